@@ -0,0 +1,158 @@
+//! Parse and write throughput on the largest fixture, `run.mid`, along with the number of heap
+//! allocations each takes since timings alone are noisy. Counting the written size is measured
+//! next to writing, since it's meant to be the cheap way to learn the length, and peeking at
+//! each fixture's header and layout next to opening it, since peeking is meant for indexing.
+//! Parsing chunks borrowed from one buffer is compared to copying each payload out first by
+//! the peak heap memory each needs on top of the buffer
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use criterion::{black_box, criterion_main, Criterion};
+use miami::{
+    chunk::ParsedChunk,
+    reader::{chunks, MidiReadable},
+    writer::{MidiWriteable, WriteOptions},
+    Midi, RawMidi,
+};
+
+/// System allocator that counts allocations and the bytes they hold
+struct Counting;
+
+/// Allocations made so far
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+/// Bytes allocated and not yet freed
+static LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+/// Most bytes live at once since the last reset
+static PEAK_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        let live = LIVE_BYTES.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        PEAK_BYTES.fetch_max(live, Ordering::Relaxed);
+        // SAFETY: Forwarded unchanged to the system allocator
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+        // SAFETY: Forwarded unchanged to the system allocator
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+/// Counts every allocation the benchmarks make
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Number of allocations `f` makes
+fn allocations<T>(f: impl FnOnce() -> T) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    black_box(f());
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+/// Most heap memory `f` holds at once, in bytes, on top of what was already allocated
+fn peak_bytes<T>(f: impl FnOnce() -> T) -> usize {
+    let before = LIVE_BYTES.load(Ordering::Relaxed);
+    PEAK_BYTES.store(before, Ordering::Relaxed);
+    black_box(f());
+    PEAK_BYTES.load(Ordering::Relaxed) - before
+}
+
+/// Reads the fixture's bytes once up front so only parsing is measured
+fn fixture() -> Vec<u8> {
+    "test/run.mid"
+        .get_midi_bytes()
+        .expect("Open `run.mid`")
+        .collect()
+}
+
+/// Parses the fixture into a sanitized `Midi`
+fn parse(bytes: &[u8]) -> Midi {
+    RawMidi::try_from_midi_stream(bytes.iter().copied())
+        .expect("Parse `run.mid`")
+        .check_into_midi()
+        .expect("Sanitize `run.mid`")
+}
+
+/// Parses every chunk of the fixture straight from its buffer
+fn parse_borrowed(bytes: &[u8]) -> Vec<ParsedChunk> {
+    chunks(bytes)
+        .map(|chunk| {
+            let (chunk, data) = chunk.expect("Split `run.mid` into chunks");
+            ParsedChunk::try_from((chunk, data)).expect("Parse a chunk of `run.mid`")
+        })
+        .collect()
+}
+
+/// Parses every chunk of the fixture from a copy of its payload, as owned parsing needs
+fn parse_owned(bytes: &[u8]) -> Vec<ParsedChunk> {
+    chunks(bytes)
+        .map(|chunk| {
+            let (chunk, data) = chunk.expect("Split `run.mid` into chunks");
+            ParsedChunk::try_from((chunk, data.to_vec())).expect("Parse a chunk of `run.mid`")
+        })
+        .collect()
+}
+
+/// Benchmarks parsing and writing the fixture
+fn parse_and_write(c: &mut Criterion) {
+    let bytes = fixture();
+    let midi = parse(&bytes);
+
+    println!(
+        "parse run.mid: {} allocations",
+        allocations(|| parse(&bytes))
+    );
+    println!(
+        "parse run.mid's chunks borrowed: {} bytes peak, copied: {} bytes peak",
+        peak_bytes(|| parse_borrowed(&bytes)),
+        peak_bytes(|| parse_owned(&bytes))
+    );
+    println!(
+        "write run.mid: {} allocations",
+        allocations(|| midi.clone().to_midi_bytes())
+    );
+    let options = WriteOptions::default();
+    println!(
+        "encoded size of run.mid: {} allocations",
+        allocations(|| midi.encoded_size(&options))
+    );
+
+    c.bench_function("parse run.mid", |b| b.iter(|| parse(black_box(&bytes))));
+    c.bench_function("parse run.mid's chunks borrowed", |b| {
+        b.iter(|| parse_borrowed(black_box(&bytes)))
+    });
+    c.bench_function("write run.mid", |b| {
+        b.iter(|| black_box(midi.clone()).to_midi_bytes())
+    });
+    c.bench_function("encoded size of run.mid", |b| {
+        b.iter(|| black_box(&midi).encoded_size(&options))
+    });
+}
+
+/// Benchmarks peeking at each fixture against opening and parsing all of it
+fn peek_and_open(c: &mut Criterion) {
+    for path in ["test/run.mid", "test/test.mid", "test/test4tracks.mid"] {
+        c.bench_function(&format!("open {path}"), |b| {
+            b.iter(|| miami::open(black_box(path)).expect("Open the fixture"))
+        });
+        c.bench_function(&format!("peek_header {path}"), |b| {
+            b.iter(|| miami::peek_header(black_box(path)).expect("Peek at the fixture"))
+        });
+        c.bench_function(&format!("peek_info {path}"), |b| {
+            b.iter(|| miami::peek_info(black_box(path)).expect("Peek at the fixture"))
+        });
+    }
+}
+
+/// Holds the generated benchmark group
+mod group {
+    criterion::criterion_group!(benches, super::parse_and_write, super::peek_and_open);
+}
+
+criterion_main!(group::benches);
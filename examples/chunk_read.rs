@@ -1,19 +1,12 @@
-//! Example program that reads the entirety of a MIDI file as raw chunks
+//! Example program that reads the entirety of a MIDI file and prints its header and tracks
 
-use miami::{reader::MidiReadable, Midi, RawMidi};
+use miami::prelude::*;
 
 fn main() {
-    let data = "test/test.mid"
-        .get_midi_bytes()
-        .expect("Get `run.midi` file and stream bytes");
+    let midi = open("test/test.mid").expect("Open and parse `test.mid`");
 
-    let midi = RawMidi::try_from_midi_stream(data).expect("Parse data as a MIDI stream");
-    let sanitized_midi: Midi = midi
-        .check_into_midi()
-        .expect("Upgrade into sanitized format");
-
-    println!("Header: {:?}", sanitized_midi.header);
-    for chunk in sanitized_midi.tracks.iter() {
+    println!("Header: {:?}", midi.header);
+    for chunk in midi.tracks.iter() {
         println!("Track: {chunk:?}");
     }
 }
@@ -0,0 +1,37 @@
+//! Example program that generates a 100 track file, writing each track as soon as it's built so
+//! only one track is ever held in memory
+
+use miami::prelude::*;
+use std::fs::File;
+
+fn main() {
+    let path = std::env::temp_dir().join("miami_generated.mid");
+    let output = File::create(&path).expect("Create new output file");
+    let mut writer = MidiFileWriter::new(output, Format::One, Division::TPQN_480)
+        .expect("Write placeholder header");
+
+    for index in 0..100u8 {
+        let channel = index % 16;
+        let key = 36 + index % 60;
+
+        let mut events = vec![];
+        for step in 0..64u8 {
+            let velocity = 64 + step % 32;
+            let on = MidiEvent::NoteOn(channel, NoteMeta::new(key, velocity).expect("Valid note"));
+            let off = MidiEvent::NoteOff(channel, NoteMeta::new(key, 0).expect("Valid note"));
+            events.push(MTrkEvent::new(
+                if step == 0 { 0 } else { 120 },
+                Event::MidiEvent(on),
+            ));
+            events.push(MTrkEvent::new(120, Event::MidiEvent(off)));
+        }
+        events.push(MTrkEvent::new(0, Event::MetaEvent(MetaEvent::EndOfTrack)));
+
+        writer
+            .write_track(&TrackChunk::new(events))
+            .expect("Write track");
+    }
+
+    writer.finish().expect("Patch the header's track count");
+    println!("Wrote 100 tracks to {}", path.display());
+}
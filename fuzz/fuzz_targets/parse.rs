@@ -0,0 +1,26 @@
+//! Feeds arbitrary bytes through every parsing entry point. Any panic is a bug, parsing must
+//! always end in a value or an error
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use miami::{
+    chunk::{track::TrackChunk, ParsedChunk},
+    reader::{chunks, ParseOptions},
+    RawMidi,
+};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(raw) = RawMidi::try_from_midi_stream(data.iter().copied()) {
+        let _ = raw.check_into_midi();
+    }
+
+    let options = ParseOptions::default().keep_raw(true);
+    let _ = RawMidi::try_from_midi_stream_lenient_with(data.iter().copied(), options);
+
+    for (chunk, payload) in chunks(data).map_while(Result::ok) {
+        let _ = ParsedChunk::parse(chunk, payload);
+    }
+
+    let _ = TrackChunk::parse(data);
+});
@@ -0,0 +1,454 @@
+//! Shared, precomputed file state for analysing tracks independently, such as on separate threads
+
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::{
+    chunk::{
+        header::Division,
+        track::{
+            event::{ControlChange, MidiEvent},
+            meta::TimeSignature,
+            Event, TrackChunk,
+        },
+    },
+    slice::NoteChange,
+    tempo::TempoMap,
+    time::Tick,
+    timeline::SignatureMap,
+    Midi,
+};
+
+/// The parts of a file every track's analysis depends on, gathered once by
+/// [`Midi::analysis_context`]. It is `Send + Sync`, so one context can be shared by reference
+/// across threads analysing different tracks, and every track converts ticks the same way
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnalysisContext {
+    /// The file's division
+    division: Division,
+    /// Tempo changes across every track
+    tempo_map: TempoMap,
+    /// Time signature changes across every track
+    time_signatures: SignatureMap<TimeSignature>,
+}
+
+impl AnalysisContext {
+    /// The file's division
+    pub fn division(&self) -> Division {
+        self.division
+    }
+
+    /// The file's [`Midi::tempo_map`]
+    pub fn tempo_map(&self) -> &TempoMap {
+        &self.tempo_map
+    }
+
+    /// The file's [`Midi::time_signature_map`]
+    pub fn time_signature_map(&self) -> &SignatureMap<TimeSignature> {
+        &self.time_signatures
+    }
+
+    /// Converts an absolute tick into seconds from the start of the file
+    pub fn seconds_at(&self, tick: impl Into<Tick>) -> f64 {
+        self.tempo_map.seconds_at(tick)
+    }
+}
+
+/// A note from its Note On to the Note Off that releases it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Note {
+    /// Channel the note plays on
+    pub channel: u8,
+    /// The note's key, where 60 is middle C
+    pub key: u8,
+    /// Velocity the note was struck with
+    pub velocity: u8,
+    /// Tick of the Note On
+    pub start: Tick,
+    /// Tick of the releasing Note Off, or the end of the track for a note that's never released
+    pub end: Tick,
+    /// Tick the note stops sounding. The same as `end` unless sustain is honored with
+    /// [`NotesOptions::honor_sustain`], in which case a note released while its channel's pedal
+    /// is down keeps sounding until the pedal is lifted
+    pub effective_end: Tick,
+}
+
+impl Note {
+    /// Ticks from the Note On to the releasing Note Off
+    pub fn duration(&self) -> u64 {
+        self.end.get() - self.start.get()
+    }
+
+    /// Ticks from the Note On until the note stops sounding, including any time it's held by the
+    /// sustain pedal
+    pub fn effective_duration(&self) -> u64 {
+        self.effective_end.get() - self.start.get()
+    }
+}
+
+/// How [`TrackChunk::notes_with`] decides when a note stops sounding
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct NotesOptions {
+    /// Extends the effective end of a note released while its channel's sustain pedal (CC64) is
+    /// down to the next pedal lift, a CC64 value below 64, or to the end of the track if the
+    /// pedal is never lifted. Off by default
+    pub honor_sustain: bool,
+}
+
+impl NotesOptions {
+    /// Sets [`NotesOptions::honor_sustain`]
+    pub fn honor_sustain(mut self, honor: bool) -> Self {
+        self.honor_sustain = honor;
+        self
+    }
+}
+
+/// A [`Note`] with its start and end converted into seconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimedNote {
+    /// The note in ticks
+    pub note: Note,
+    /// Seconds from the start of the file to the Note On
+    pub start_seconds: f64,
+    /// Seconds from the start of the file to the note's release
+    pub end_seconds: f64,
+}
+
+impl Midi {
+    /// Gathers the division, tempo map and time signature map once, for analysing tracks with
+    /// [`TrackChunk::timed_notes`] and [`TrackChunk::duration_seconds`]
+    pub fn analysis_context(&self) -> AnalysisContext {
+        AnalysisContext {
+            division: self.header.division(),
+            tempo_map: self.tempo_map(),
+            time_signatures: self.time_signature_map(),
+        }
+    }
+
+    /// The second every note in the file starts at, as `(seconds, key, channel)`, in ascending
+    /// order. Simultaneous onsets are ordered by key and then channel, so the order doesn't
+    /// depend on how the notes are split across tracks. Files with a time-code-based division
+    /// are converted by their frame rate
+    pub fn note_onsets_seconds(&self) -> Vec<(f64, u8, u8)> {
+        self.note_times_seconds(|note| note.start)
+    }
+
+    /// The second every note in the file is released by its Note Off, ordered like
+    /// [`Midi::note_onsets_seconds`]
+    pub fn note_offsets_seconds(&self) -> Vec<(f64, u8, u8)> {
+        self.note_times_seconds(|note| note.end)
+    }
+
+    /// Converts the tick `at` picks from every note into `(seconds, key, channel)`, sorted
+    fn note_times_seconds(&self, at: impl Fn(&Note) -> Tick) -> Vec<(f64, u8, u8)> {
+        let tempo_map = self.tempo_map();
+        let mut times: Vec<_> = self
+            .tracks
+            .iter()
+            .flat_map(TrackChunk::notes)
+            .map(|note| (tempo_map.seconds_at(at(&note)), note.key, note.channel))
+            .collect();
+        times.sort_by(|(a, a_key, a_channel), (b, b_key, b_channel)| {
+            a.total_cmp(b)
+                .then(a_key.cmp(b_key))
+                .then(a_channel.cmp(b_channel))
+        });
+
+        times
+    }
+}
+
+impl TrackChunk {
+    /// The tick of the track's last event
+    pub fn duration(&self) -> Tick {
+        self.absolute_events()
+            .last()
+            .map_or(Tick::ZERO, |(tick, _)| tick)
+    }
+
+    /// The tick of the track's last event in seconds from the start of the file
+    pub fn duration_seconds(&self, context: &AnalysisContext) -> f64 {
+        context.seconds_at(self.duration())
+    }
+
+    /// Pairs every Note On with the Note Off that releases it, in the order the notes start.
+    /// Repeated strikes of a key that's still sounding are released first in, first out, and a
+    /// Note On with a velocity of 0 counts as a Note Off. Each note's effective end is its Note
+    /// Off, see [`TrackChunk::notes_with`] for honoring the sustain pedal
+    pub fn notes(&self) -> Vec<Note> {
+        self.notes_with(NotesOptions::default())
+    }
+
+    /// [`TrackChunk::notes`], with the effective end of each note decided by the options. The
+    /// sustain pedal only holds notes on its own channel
+    pub fn notes_with(&self, options: NotesOptions) -> Vec<Note> {
+        let mut notes: Vec<Note> = vec![];
+        let mut sounding: BTreeMap<(u8, u8), VecDeque<usize>> = BTreeMap::new();
+        let mut pedal_down = [false; 16];
+        let mut sustained: BTreeMap<u8, Vec<usize>> = BTreeMap::new();
+        let mut end = Tick::ZERO;
+
+        for (tick, event) in self.absolute_events() {
+            end = tick;
+            if let Event::MidiEvent(MidiEvent::ControlChange(channel, change)) = event {
+                if options.honor_sustain && change.controller() == ControlChange::SUSTAIN {
+                    let down = change.value() >= 64;
+                    pedal_down[*channel as usize & 0x0F] = down;
+                    if !down {
+                        for held in sustained.remove(channel).unwrap_or_default() {
+                            notes[held].effective_end = tick;
+                        }
+                    }
+                }
+            }
+
+            match NoteChange::of(event) {
+                Some(NoteChange::On(channel, note)) => {
+                    sounding
+                        .entry((channel, note.key))
+                        .or_default()
+                        .push_back(notes.len());
+                    notes.push(Note {
+                        channel,
+                        key: note.key,
+                        velocity: note.velocity,
+                        start: tick,
+                        end: tick,
+                        effective_end: tick,
+                    });
+                }
+                Some(NoteChange::Off(channel, note)) => {
+                    if let Some(on) = sounding
+                        .get_mut(&(channel, note.key))
+                        .and_then(|ons| ons.pop_front())
+                    {
+                        notes[on].end = tick;
+                        notes[on].effective_end = tick;
+                        if pedal_down[channel as usize & 0x0F] {
+                            sustained.entry(channel).or_default().push(on);
+                        }
+                    }
+                }
+                None => {}
+            }
+        }
+
+        for on in sounding.into_values().flatten() {
+            notes[on].end = end;
+            notes[on].effective_end = end;
+        }
+        for held in sustained.into_values().flatten() {
+            notes[held].effective_end = end;
+        }
+
+        notes
+    }
+
+    /// [`TrackChunk::notes`] with their starts and ends converted into seconds from the start of
+    /// the file
+    pub fn timed_notes(&self, context: &AnalysisContext) -> Vec<TimedNote> {
+        self.notes()
+            .into_iter()
+            .map(|note| TimedNote {
+                note,
+                start_seconds: context.seconds_at(note.start),
+                end_seconds: context.seconds_at(note.end),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AnalysisContext, Note, NotesOptions};
+    use crate::{
+        chunk::{
+            header::HeaderChunk,
+            track::{
+                event::{ControlChange, MidiEvent, NoteMeta},
+                meta::{MetaEvent, TimeSignature},
+                Event, TrackChunk,
+            },
+        },
+        time::Tick,
+        Midi,
+    };
+
+    fn on(key: u8) -> Event {
+        Event::MidiEvent(MidiEvent::NoteOn(1, NoteMeta { key, velocity: 90 }))
+    }
+
+    fn off(key: u8) -> Event {
+        Event::MidiEvent(MidiEvent::NoteOff(1, NoteMeta { key, velocity: 0 }))
+    }
+
+    fn end() -> Event {
+        Event::MetaEvent(MetaEvent::EndOfTrack)
+    }
+
+    /// A conductor track with a tempo and meter change, and two note tracks
+    fn midi() -> Midi {
+        let conductor = TrackChunk::from_absolute_events([
+            (0, Event::MetaEvent(MetaEvent::Tempo(1_000_000))),
+            (
+                0,
+                Event::MetaEvent(MetaEvent::TimeSignature(TimeSignature {
+                    numerator: 3,
+                    ..TimeSignature::default()
+                })),
+            ),
+            (960, Event::MetaEvent(MetaEvent::Tempo(250_000))),
+            (960, end()),
+        ])
+        .unwrap();
+        let melody = TrackChunk::from_absolute_events([
+            (0, on(60)),
+            (480, on(60)),
+            (720, off(60)),
+            (960, off(60)),
+            (960, on(64)),
+            (1440, end()),
+        ])
+        .unwrap();
+        let bass = TrackChunk::from_absolute_events([(0, on(36)), (1920, off(36)), (1920, end())])
+            .unwrap();
+
+        Midi {
+            header: HeaderChunk::try_from((1, 3, 480)).unwrap(),
+            tracks: vec![conductor, melody, bass],
+        }
+    }
+
+    #[test]
+    fn context_is_shareable_across_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<AnalysisContext>();
+
+        let midi = midi();
+        let context = midi.analysis_context();
+
+        let parallel: Vec<_> = std::thread::scope(|scope| {
+            let handles: Vec<_> = midi
+                .tracks
+                .iter()
+                .map(|track| {
+                    let context = &context;
+                    scope.spawn(move || {
+                        (
+                            track.stats(),
+                            track.timed_notes(context),
+                            track.duration_seconds(context),
+                        )
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect()
+        });
+
+        let tempo_map = midi.tempo_map();
+        for (track, (stats, notes, duration)) in midi.tracks.iter().zip(parallel) {
+            assert_eq!(stats, track.stats());
+            assert_eq!(duration, tempo_map.seconds_at(track.duration()));
+            for timed in notes {
+                assert_eq!(timed.start_seconds, tempo_map.seconds_at(timed.note.start));
+                assert_eq!(timed.end_seconds, tempo_map.seconds_at(timed.note.end));
+            }
+        }
+
+        assert_eq!(context.division(), midi.header.division());
+        assert_eq!(context.time_signature_map(), &midi.time_signature_map());
+        assert_eq!(
+            midi.tracks.iter().map(|track| track.duration()).max(),
+            Some(midi.duration())
+        );
+        assert_eq!(midi.tracks[2].duration_seconds(&context), 2.5);
+    }
+
+    #[test]
+    fn notes_pair_first_in_first_out() {
+        let midi = midi();
+        let note = |key, start: u64, end: u64| Note {
+            channel: 1,
+            key,
+            velocity: 90,
+            start: Tick::new(start),
+            end: Tick::new(end),
+            effective_end: Tick::new(end),
+        };
+
+        assert_eq!(
+            midi.tracks[1].notes(),
+            [note(60, 0, 720), note(60, 480, 960), note(64, 960, 1440)]
+        );
+
+        let timed = midi.tracks[1].timed_notes(&midi.analysis_context());
+        assert_eq!(
+            timed
+                .iter()
+                .map(|timed| (timed.start_seconds, timed.end_seconds))
+                .collect::<Vec<_>>(),
+            [(0.0, 1.5), (1.0, 2.0), (2.0, 2.25)]
+        );
+        assert!(midi.tracks[0].notes().is_empty());
+    }
+
+    #[test]
+    fn onsets_and_offsets_follow_the_tempo_change() {
+        let mut midi = midi();
+
+        // 1 second per quarter until tick 960, a quarter second after
+        assert_eq!(
+            midi.note_onsets_seconds(),
+            [(0.0, 36, 1), (0.0, 60, 1), (1.0, 60, 1), (2.0, 64, 1)]
+        );
+        assert_eq!(
+            midi.note_offsets_seconds(),
+            [(1.5, 60, 1), (2.0, 60, 1), (2.25, 64, 1), (2.5, 36, 1)]
+        );
+
+        // 25 frames per second of 40 ticks, so 1000 ticks per second whatever the tempo
+        midi.header = HeaderChunk::try_from((1, 3, 0xE728)).unwrap();
+        assert_eq!(
+            midi.note_onsets_seconds(),
+            [(0.0, 36, 1), (0.0, 60, 1), (0.48, 60, 1), (0.96, 64, 1)]
+        );
+    }
+
+    #[test]
+    fn sustain_holds_released_notes_until_the_pedal_lifts() {
+        let pedal = |channel, value| {
+            Event::MidiEvent(MidiEvent::ControlChange(
+                channel,
+                ControlChange::new(ControlChange::SUSTAIN, value).unwrap(),
+            ))
+        };
+        let track = TrackChunk::from_absolute_events([
+            (0, on(60)),
+            (120, pedal(1, 127)),
+            (240, off(60)),
+            (240, on(64)),
+            (600, pedal(2, 0)),
+            (720, pedal(1, 0)),
+            (960, off(64)),
+            (1200, end()),
+        ])
+        .unwrap();
+
+        let raw = track.notes();
+        assert!(raw.iter().all(|note| note.effective_end == note.end));
+
+        let sustained = track.notes_with(NotesOptions {
+            honor_sustain: true,
+        });
+        let ends: Vec<_> = sustained
+            .iter()
+            .map(|note| (note.key, note.end.get(), note.effective_end.get()))
+            .collect();
+        assert_eq!(ends, [(60, 240, 720), (64, 960, 960)]);
+        assert_eq!(sustained[0].duration(), 240);
+        assert_eq!(sustained[0].effective_duration(), 720);
+    }
+}
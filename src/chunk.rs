@@ -1,10 +1,11 @@
 //! Chunk Definitions for parsed types and type headers
 
-use header::{HeaderChunk, InvalidFormat};
-use track::TrackChunk;
+use header::{HeaderChunk, HeaderError, InvalidFormat};
+use track::{meta::TextEncoding, TrackChunk};
 
 use crate::{
     chunk::chunk_types::{HEADER_CHUNK, TRACK_DATA_CHUNK},
+    reader::ParseOptions,
     writer::MidiWriteable,
     Chunk,
 };
@@ -35,16 +36,59 @@ impl MidiWriteable for ParsedChunk {
 }
 
 /// Error type for attempting to parse from a raw chunk to a parsed one
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ChunkParseError {
     /// Invalid format in parsing a header
     InvalidFormat(InvalidFormat),
+    /// Header chunk is shorter than 6 bytes, holding this many
+    HeaderTooShort(usize),
     /// Type tag is not registered
     UnknownType,
     /// Random todo during debugging
     Todo(&'static str),
     /// Error parsing track
     TrackParseError(track::TrackError),
+    /// The stream ended partway through a chunk's header or payload
+    Truncated {
+        /// Bytes the chunk needed, 8 for a chunk header
+        expected: usize,
+        /// Bytes that were actually left
+        available: usize,
+    },
+    /// A single chunk's bytes held a different type of chunk than the one asked for
+    WrongType {
+        /// Type of the chunk being parsed
+        expected: [char; 4],
+        /// Type found in the chunk header
+        found: [char; 4],
+    },
+    /// A single chunk's bytes went on this many bytes past the length its header declared
+    TrailingBytes(usize),
+    /// The file needs more memory than [`ParseOptions`] allows
+    ResourceLimitExceeded {
+        /// The limit that was crossed
+        which: ResourceLimit,
+        /// Its configured value
+        limit: usize,
+    },
+}
+
+/// A limit set in [`ParseOptions`] to bound the memory parsing untrusted input can take
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceLimit {
+    /// [`ParseOptions::max_total_bytes`]
+    TotalBytes,
+    /// [`ParseOptions::max_events_per_track`]
+    EventsPerTrack,
+}
+
+impl core::fmt::Display for ResourceLimit {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::TotalBytes => write![f, "total payload bytes"],
+            Self::EventsPerTrack => write![f, "events per track"],
+        }
+    }
 }
 
 impl core::error::Error for ChunkParseError {}
@@ -52,9 +96,29 @@ impl core::fmt::Display for ChunkParseError {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             Self::InvalidFormat(_) => write![f, "Invalid Format Specified"],
+            Self::HeaderTooShort(len) => {
+                write![f, "Header chunk is {len} bytes long, at least 6 are needed"]
+            }
             Self::UnknownType => write![f, "Unknown Chunk Type"],
             Self::Todo(s) => write![f, "Development TODO: {s}"],
             Self::TrackParseError(_) => write![f, "Track parsing error"],
+            Self::Truncated {
+                expected,
+                available,
+            } => write![
+                f,
+                "Stream ended {available} bytes into a chunk needing {expected}"
+            ],
+            Self::WrongType { expected, found } => write![
+                f,
+                "Expected a {} chunk but found {}",
+                expected.iter().collect::<String>(),
+                found.iter().collect::<String>()
+            ],
+            Self::TrailingBytes(len) => write![f, "{len} bytes follow the end of the chunk"],
+            Self::ResourceLimitExceeded { which, limit } => {
+                write![f, "File exceeds the limit of {limit} {which}"]
+            }
         }
     }
 }
@@ -63,9 +127,23 @@ impl From<InvalidFormat> for ChunkParseError {
         Self::InvalidFormat(f)
     }
 }
+impl From<HeaderError> for ChunkParseError {
+    fn from(f: HeaderError) -> Self {
+        match f {
+            HeaderError::TooShort(len) => Self::HeaderTooShort(len),
+            HeaderError::InvalidFormat(format) => Self::InvalidFormat(format),
+        }
+    }
+}
 impl From<track::TrackError> for ChunkParseError {
     fn from(f: track::TrackError) -> Self {
-        Self::TrackParseError(f)
+        match f {
+            track::TrackError::TooManyEvents(limit) => Self::ResourceLimitExceeded {
+                which: ResourceLimit::EventsPerTrack,
+                limit,
+            },
+            f => Self::TrackParseError(f),
+        }
     }
 }
 
@@ -98,30 +176,355 @@ impl From<ParsedChunk> for (Chunk, Vec<u8>) {
     }
 }
 
+impl ParsedChunk {
+    /// Parses a chunk from its header and a borrowed view of its payload
+    pub fn parse(chunk: Chunk, data: &[u8]) -> Result<Self, ChunkParseError> {
+        Self::parse_text(chunk, data, None)
+    }
+
+    /// Parses a chunk like [`ParsedChunk::parse`], decoding text meta events with the given
+    /// options instead of requiring UTF-8
+    pub fn parse_with(
+        chunk: Chunk,
+        data: &[u8],
+        options: ParseOptions,
+    ) -> Result<Self, ChunkParseError> {
+        let mut parsed = match chunk.chunk_type {
+            TRACK_DATA_CHUNK => ParsedChunk::Track(TrackChunk::parse_limited(
+                data,
+                Some(options.text_encoding),
+                options.max_events_per_track,
+                options.max_event_payload,
+                options.record_spans,
+            )?),
+            _ => Self::parse_text(chunk, data, Some(options.text_encoding))?,
+        };
+        if options.keep_raw {
+            match &mut parsed {
+                ParsedChunk::Header(header) => header.raw_payload = Some(data.to_vec()),
+                ParsedChunk::Track(track) => track.raw_payload = Some(data.to_vec()),
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    /// The exact payload the chunk was parsed from. Only kept when parsing with
+    /// [`ParseOptions::keep_raw`], and `None` for chunks built or modified in code
+    pub fn raw_payload(&self) -> Option<&[u8]> {
+        match self {
+            ParsedChunk::Header(header) => header.raw_payload(),
+            ParsedChunk::Track(track) => track.raw_payload(),
+        }
+    }
+
+    /// Shared chunk parser, where `text_encoding` is only set for lenient text decoding
+    fn parse_text(
+        chunk: Chunk,
+        data: &[u8],
+        text_encoding: Option<TextEncoding>,
+    ) -> Result<Self, ChunkParseError> {
+        match chunk.chunk_type {
+            HEADER_CHUNK => {
+                let data = data
+                    .get(..chunk.len())
+                    .ok_or(ChunkParseError::InvalidFormat(InvalidFormat))?;
+
+                Ok(ParsedChunk::Header(HeaderChunk::parse_extended(data)?))
+            }
+
+            TRACK_DATA_CHUNK => Ok(ParsedChunk::Track(match text_encoding {
+                Some(text_encoding) => TrackChunk::parse_with_text_encoding(data, text_encoding)?,
+                None => TrackChunk::parse(data)?,
+            })),
+
+            _ => Err(ChunkParseError::UnknownType),
+        }
+    }
+}
+
+/// Splits the bytes of a single chunk into its header and payload, checking that it's of the
+/// expected type and that its declared length covers exactly the rest of the bytes
+fn split_chunk(bytes: &[u8], chunk_type: [char; 4]) -> Result<(Chunk, &[u8]), ChunkParseError> {
+    let (header, payload) = bytes
+        .split_first_chunk::<8>()
+        .ok_or(ChunkParseError::Truncated {
+            expected: 8,
+            available: bytes.len(),
+        })?;
+
+    let chunk: Chunk = u64::from_be_bytes(*header).into();
+    if chunk.chunk_type != chunk_type {
+        return Err(ChunkParseError::WrongType {
+            expected: chunk_type,
+            found: chunk.chunk_type,
+        });
+    }
+    match payload.len().checked_sub(chunk.len()) {
+        Some(0) => Ok((chunk, payload)),
+        Some(trailing) => Err(ChunkParseError::TrailingBytes(trailing)),
+        None => Err(ChunkParseError::Truncated {
+            expected: chunk.len(),
+            available: payload.len(),
+        }),
+    }
+}
+
+impl TrackChunk {
+    /// The track as a complete `MTrk` chunk, its chunk header followed by its events, ready to
+    /// be spliced into a file's bytes. Events after the track's `EndOfTrack` aren't written
+    pub fn to_chunk_bytes(&self) -> Vec<u8> {
+        ParsedChunk::Track(self.clone()).to_midi_bytes()
+    }
+
+    /// Parses a complete `MTrk` chunk as written by [`TrackChunk::to_chunk_bytes`]. Fails if the
+    /// bytes hold another type of chunk or don't match the length in its header
+    pub fn from_chunk_bytes(bytes: &[u8]) -> Result<TrackChunk, ChunkParseError> {
+        let (chunk, payload) = split_chunk(bytes, TRACK_DATA_CHUNK)?;
+        match ParsedChunk::parse(chunk, payload)? {
+            ParsedChunk::Track(track) => Ok(track),
+            ParsedChunk::Header(_) => unreachable!("The chunk type was checked to be MTrk"),
+        }
+    }
+}
+
+impl HeaderChunk {
+    /// The header as a complete `MThd` chunk, its chunk header followed by its fields
+    pub fn to_chunk_bytes(&self) -> Vec<u8> {
+        ParsedChunk::Header(self.clone()).to_midi_bytes()
+    }
+
+    /// Parses a complete `MThd` chunk as written by [`HeaderChunk::to_chunk_bytes`]. Fails if
+    /// the bytes hold another type of chunk or don't match the length in its header
+    pub fn from_chunk_bytes(bytes: &[u8]) -> Result<HeaderChunk, ChunkParseError> {
+        let (chunk, payload) = split_chunk(bytes, HEADER_CHUNK)?;
+        match ParsedChunk::parse(chunk, payload)? {
+            ParsedChunk::Header(header) => Ok(header),
+            ParsedChunk::Track(_) => unreachable!("The chunk type was checked to be MThd"),
+        }
+    }
+}
+
 impl TryFrom<(Chunk, Vec<u8>)> for ParsedChunk {
     type Error = ChunkParseError;
     fn try_from(value: (Chunk, Vec<u8>)) -> Result<Self, Self::Error> {
         let (chunk, data) = value;
+        Self::parse(chunk, &data)
+    }
+}
 
-        match chunk.chunk_type {
-            HEADER_CHUNK => {
-                if chunk.len() == 6 {
-                    let format = u16::from_be_bytes([data[0], data[1]]);
-                    let ntrk = u16::from_be_bytes([data[2], data[3]]);
-                    let division = u16::from_be_bytes([data[4], data[5]]);
-                    let parsed = HeaderChunk::try_from((format, ntrk, division))?;
-                    Ok(ParsedChunk::Header(parsed))
-                } else {
-                    Err(ChunkParseError::InvalidFormat(InvalidFormat))
+impl TryFrom<(Chunk, &[u8])> for ParsedChunk {
+    type Error = ChunkParseError;
+    fn try_from(value: (Chunk, &[u8])) -> Result<Self, Self::Error> {
+        let (chunk, data) = value;
+        Self::parse(chunk, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{track::TrackChunk, ChunkParseError, ParsedChunk};
+    use crate::{
+        chunk::{
+            chunk_types::{HEADER_CHUNK, TRACK_DATA_CHUNK},
+            header::{Division, Format, HeaderChunk},
+        },
+        reader::{chunks, MidiReadable, ParseOptions},
+        Chunk, Midi, RawMidi,
+    };
+
+    #[test]
+    fn borrowed_parse_matches_owned_parse() {
+        let bytes: Vec<u8> = "test/test4tracks.mid".get_midi_bytes().unwrap().collect();
+
+        let mut offset = 0;
+        while offset + 8 <= bytes.len() {
+            let chunk: Chunk =
+                u64::from_be_bytes(bytes[offset..offset + 8].try_into().unwrap()).into();
+            let data = &bytes[offset + 8..offset + 8 + chunk.len()];
+
+            let borrowed = ParsedChunk::parse(chunk, data).unwrap();
+            let owned = ParsedChunk::try_from((chunk, data.to_vec())).unwrap();
+            assert_eq!(borrowed, owned);
+            assert_eq!(ParsedChunk::try_from((chunk, data)).unwrap(), owned);
+            match owned {
+                ParsedChunk::Header(header) => {
+                    assert_eq!(HeaderChunk::try_from(data).unwrap(), header)
                 }
+                ParsedChunk::Track(track) => assert_eq!(TrackChunk::try_from(data).unwrap(), track),
             }
 
-            TRACK_DATA_CHUNK => {
-                let parsed = TrackChunk::try_from(data)?;
-                Ok(ParsedChunk::Track(parsed))
+            offset += 8 + chunk.len();
+        }
+        assert_eq!(offset, bytes.len());
+    }
+
+    #[test]
+    fn raw_payload_kept_only_when_asked() {
+        let bytes: Vec<u8> = "test/test4tracks.mid".get_midi_bytes().unwrap().collect();
+        let keep_raw = ParseOptions {
+            keep_raw: true,
+            ..Default::default()
+        };
+
+        for (chunk, data) in chunks(&bytes).map(Result::unwrap) {
+            let kept = ParsedChunk::parse_with(chunk, data, keep_raw).unwrap();
+            assert_eq!(kept.raw_payload(), Some(data));
+
+            let dropped = ParsedChunk::parse_with(chunk, data, ParseOptions::default()).unwrap();
+            assert_eq!(dropped.raw_payload(), None);
+            assert_eq!(dropped, kept);
+
+            if let ParsedChunk::Track(mut track) = kept {
+                let rebuilt = TrackChunk::new(track.mtrk_events.clone());
+                assert_eq!(rebuilt.raw_payload(), None);
+
+                track.transpose(0);
+                assert_eq!(track.raw_payload(), None);
             }
+        }
 
-            _ => Err(ChunkParseError::UnknownType),
+        assert_eq!(
+            ParsedChunk::Track(TrackChunk::default()).raw_payload(),
+            None
+        );
+    }
+
+    #[test]
+    fn event_spans_slice_out_each_event() {
+        let record = ParseOptions::default().record_spans(true);
+        for path in ["test/run.mid", "test/test4tracks.mid"] {
+            let bytes: Vec<u8> = path.get_midi_bytes().unwrap().collect();
+
+            for (chunk, data) in chunks(&bytes).map(Result::unwrap) {
+                let ParsedChunk::Track(mut track) =
+                    ParsedChunk::parse_with(chunk, data, record).unwrap()
+                else {
+                    continue;
+                };
+
+                let mut end = 0;
+                for (idx, mtrk_event) in track.events().enumerate() {
+                    let span = track.event_span(idx).unwrap();
+                    assert_eq!(span.start, end, "{path}");
+                    end = span.end;
+
+                    let alone = TrackChunk::parse(&data[span]).unwrap();
+                    assert!(alone.events().eq([mtrk_event]), "{path}");
+                }
+                assert_eq!(end, data.len());
+                assert_eq!(track.event_span(track.events().count()), None);
+
+                track.transpose(0);
+                assert_eq!(track.event_span(0), None);
+            }
+
+            let (chunk, data) = chunks(&bytes).nth(1).unwrap().unwrap();
+            let ParsedChunk::Track(track) =
+                ParsedChunk::parse_with(chunk, data, ParseOptions::default()).unwrap()
+            else {
+                panic!("The second chunk is a track");
+            };
+            assert_eq!(track.event_span(0), None);
         }
     }
+
+    #[test]
+    fn header_shorter_than_six_is_an_error() {
+        let chunk = Chunk {
+            chunk_type: HEADER_CHUNK,
+            length: 4,
+        };
+
+        assert!(matches!(
+            ParsedChunk::parse(chunk, &[0, 1, 0, 2]),
+            Err(ChunkParseError::HeaderTooShort(4))
+        ));
+    }
+
+    #[test]
+    fn longer_header_round_trips() {
+        let chunk = Chunk {
+            chunk_type: HEADER_CHUNK,
+            length: 8,
+        };
+        let payload = [0, 0, 0, 1, 0, 96, 0x12, 0x34];
+
+        let parsed = ParsedChunk::parse(chunk, &payload).unwrap();
+        let (written_chunk, written) = parsed.into();
+        assert_eq!(written_chunk, chunk);
+        assert_eq!(written, payload);
+    }
+
+    #[test]
+    fn empty_track_parses() {
+        let chunk = Chunk {
+            chunk_type: TRACK_DATA_CHUNK,
+            length: 0,
+        };
+
+        assert!(matches!(
+            ParsedChunk::parse(chunk, &[]),
+            Ok(ParsedChunk::Track(track)) if track.mtrk_events.is_empty()
+        ));
+    }
+
+    fn parse(bytes: &[u8]) -> Midi {
+        RawMidi::try_from_midi_stream(bytes.iter().copied())
+            .unwrap()
+            .check_into_midi()
+            .unwrap()
+    }
+
+    #[test]
+    fn chunk_bytes_round_trip_and_splice() {
+        let bytes: Vec<u8> = "test/test4tracks.mid".get_midi_bytes().unwrap().collect();
+        let midi = parse(&bytes);
+        let track = &midi.tracks[0];
+
+        let track_bytes = track.to_chunk_bytes();
+        assert_eq!(&track_bytes[..4], b"MTrk");
+        assert_eq!(
+            TrackChunk::from_chunk_bytes(&track_bytes).as_ref(),
+            Ok(track)
+        );
+        let header_bytes = midi.header.to_chunk_bytes();
+        assert_eq!(
+            HeaderChunk::from_chunk_bytes(&header_bytes),
+            Ok(midi.header.clone())
+        );
+
+        let mut spliced = HeaderChunk::new(Format::One, 2, Division::TPQN_480).to_chunk_bytes();
+        spliced.extend(TrackChunk::default().to_chunk_bytes());
+        spliced.extend(&track_bytes);
+        let spliced = parse(&spliced);
+        assert_eq!(spliced.tracks.len(), 2);
+        assert_eq!(&spliced.tracks[1], track);
+
+        assert_eq!(
+            TrackChunk::from_chunk_bytes(&header_bytes),
+            Err(ChunkParseError::WrongType {
+                expected: TRACK_DATA_CHUNK,
+                found: HEADER_CHUNK
+            })
+        );
+        assert_eq!(
+            TrackChunk::from_chunk_bytes(&track_bytes[..track_bytes.len() - 1]),
+            Err(ChunkParseError::Truncated {
+                expected: track_bytes.len() - 8,
+                available: track_bytes.len() - 9
+            })
+        );
+        assert_eq!(
+            HeaderChunk::from_chunk_bytes(&[header_bytes.as_slice(), &[0]].concat()),
+            Err(ChunkParseError::TrailingBytes(1))
+        );
+        assert_eq!(
+            HeaderChunk::from_chunk_bytes(b"MThd"),
+            Err(ChunkParseError::Truncated {
+                expected: 8,
+                available: 4
+            })
+        );
+    }
 }
@@ -5,16 +5,105 @@ use serde::{Deserialize, Serialize};
 
 use crate::writer::MidiWriteable;
 
-/// Header chunk data, including format, ntrks and division as 3 16 bit unsigned integers
-#[derive(Debug, Clone, Copy, PartialEq)]
+/// Header chunk data, including format, ntrks and division as 3 16 bit unsigned integers.
+///
+/// Unlike in 0.1 this isn't `Copy`, since it owns the bytes of an extended header and the
+/// payload kept with [`crate::reader::ParseOptions::keep_raw`]
+#[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct HeaderChunk {
     /// The MIDI format
-    format: Format,
+    pub(crate) format: Format,
     /// Number of tracks
-    ntrks: u16,
+    pub(crate) ntrks: u16,
     /// Time signature/division
-    division: Division,
+    pub(crate) division: Division,
+    /// Bytes following the standard 6 in a longer header, kept so they're written back
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) extra: Vec<u8>,
+    /// The payload the header was parsed from, if it was asked to be kept
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) raw_payload: Option<Vec<u8>>,
+}
+
+/// Headers are equal when their fields are, regardless of whether a raw payload was kept
+impl PartialEq for HeaderChunk {
+    fn eq(&self, other: &Self) -> bool {
+        self.format == other.format
+            && self.ntrks == other.ntrks
+            && self.division == other.division
+            && self.extra == other.extra
+    }
+}
+
+impl HeaderChunk {
+    /// Creates a standard 6 byte header
+    pub fn new(format: Format, ntrks: u16, division: Division) -> Self {
+        Self {
+            format,
+            ntrks,
+            division,
+            extra: vec![],
+            raw_payload: None,
+        }
+    }
+
+    /// Creates a format 0 header for a file holding a single track
+    pub fn single_track(division: Division) -> Self {
+        Self::new(Format::Zero, 1, division)
+    }
+
+    /// Creates a format 1 header for a file holding `ntrks` tracks played together
+    pub fn multi_track(ntrks: u16, division: Division) -> Self {
+        Self::new(Format::One, ntrks, division)
+    }
+
+    /// Parses the 6 byte payload of an `MThd` chunk
+    pub fn parse(bytes: &[u8; 6]) -> Result<Self, InvalidFormat> {
+        let format = u16::from_be_bytes([bytes[0], bytes[1]]);
+        let ntrks = u16::from_be_bytes([bytes[2], bytes[3]]);
+        let division = u16::from_be_bytes([bytes[4], bytes[5]]);
+
+        Self::try_from((format, ntrks, division))
+    }
+
+    /// Parses an `MThd` payload of any length of at least 6 bytes. Anything past the first 6 is
+    /// kept as [`HeaderChunk::extra`] so future extensions to the header survive a round trip
+    pub fn parse_extended(bytes: &[u8]) -> Result<Self, HeaderError> {
+        let (standard, extra) = bytes
+            .split_first_chunk::<6>()
+            .ok_or(HeaderError::TooShort(bytes.len()))?;
+
+        let mut header = Self::parse(standard)?;
+        header.extra = extra.to_vec();
+        Ok(header)
+    }
+
+    /// Bytes following the standard 6 in a longer header, empty for a standard header
+    pub fn extra(&self) -> &[u8] {
+        &self.extra
+    }
+
+    /// The exact payload the header was parsed from, if it was parsed with
+    /// [`crate::reader::ParseOptions::keep_raw`] and hasn't been modified since
+    pub fn raw_payload(&self) -> Option<&[u8]> {
+        self.raw_payload.as_deref()
+    }
+
+    /// The header's MIDI format
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// The number of tracks the header declares
+    pub fn ntrks(&self) -> u16 {
+        self.ntrks
+    }
+
+    /// The header's time division
+    pub fn division(&self) -> Division {
+        self.division
+    }
 }
 
 impl MidiWriteable for HeaderChunk {
@@ -25,6 +114,7 @@ impl MidiWriteable for HeaderChunk {
 
         bytes.extend(ntrks.iter());
         bytes.extend(division.iter());
+        bytes.extend(self.extra);
 
         bytes
     }
@@ -35,11 +125,39 @@ impl TryFrom<(u16, u16, u16)> for HeaderChunk {
     fn try_from(value: (u16, u16, u16)) -> Result<Self, Self::Error> {
         let (format, ntrks, division) = value;
 
-        Ok(Self {
-            format: format.try_into()?,
-            ntrks,
-            division: division.into(),
-        })
+        Ok(Self::new(format.try_into()?, ntrks, division.into()))
+    }
+}
+
+impl TryFrom<&[u8]> for HeaderChunk {
+    type Error = HeaderError;
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        Self::parse_extended(value)
+    }
+}
+
+/// Error from parsing a header payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderError {
+    /// The header is shorter than the 6 bytes every header needs, holding this many
+    TooShort(usize),
+    /// The header's format is invalid
+    InvalidFormat(InvalidFormat),
+}
+
+impl core::error::Error for HeaderError {}
+impl core::fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::TooShort(len) => write![f, "Header is {len} bytes long, at least 6 are needed"],
+            Self::InvalidFormat(err) => write![f, "{err}"],
+        }
+    }
+}
+
+impl From<InvalidFormat> for HeaderError {
+    fn from(f: InvalidFormat) -> Self {
+        Self::InvalidFormat(f)
     }
 }
 
@@ -108,9 +226,9 @@ pub enum Division {
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SmpteTicks {
     /// 7 bits of negative timecode
-    smpte: i8,
+    pub(crate) smpte: i8,
     /// 8 bits of ticks per frame
-    tpf: u8,
+    pub(crate) tpf: u8,
 }
 
 impl MidiWriteable for SmpteTicks {
@@ -122,6 +240,41 @@ impl MidiWriteable for SmpteTicks {
     }
 }
 
+impl Division {
+    /// 96 ticks per quarter note, the resolution of many older sequencers
+    pub const TPQN_96: Self = Self::Metrical(96);
+    /// 192 ticks per quarter note
+    pub const TPQN_192: Self = Self::Metrical(192);
+    /// 480 ticks per quarter note, the most common resolution in modern software
+    pub const TPQN_480: Self = Self::Metrical(480);
+    /// 960 ticks per quarter note
+    pub const TPQN_960: Self = Self::Metrical(960);
+
+    /// Returns the ticks per quarter note for a metrical division, or `None` if the division is
+    /// time-code-based
+    pub fn ticks_per_quarter(&self) -> Option<u16> {
+        match self {
+            Self::Metrical(tpq) => Some(*tpq),
+            Self::TimeCodeBased(_) => None,
+        }
+    }
+}
+
+impl SmpteTicks {
+    /// Frames per second of the SMPTE format, with the -29 drop-frame format as 29.97
+    pub fn frames_per_second(&self) -> f64 {
+        match -(self.smpte as i16) {
+            29 => 30_000.0 / 1001.0,
+            fps => fps as f64,
+        }
+    }
+
+    /// Ticks per second, the frame rate times the ticks per frame
+    pub fn ticks_per_second(&self) -> f64 {
+        self.frames_per_second() * self.tpf as f64
+    }
+}
+
 impl From<u16> for Division {
     fn from(value: u16) -> Self {
         const MASK: u16 = 0x7FFF;
@@ -135,8 +288,8 @@ impl From<u16> for Division {
                 let tpf = remaining as u8;
                 let smpte = (remaining >> 8) as i8;
 
-                // Explicit sign extension for SMPTE
-                let smpte = if smpte & 0x8 != 0 {
+                // Explicit sign extension from bit 6 of the 7 bit SMPTE field
+                let smpte = if smpte & 0x40 != 0 {
                     smpte | !0x7F
                 } else {
                     smpte
@@ -170,12 +323,30 @@ mod tests {
     use crate::{
         chunk::{
             chunk_types::HEADER_CHUNK,
-            header::{Division, Format, HeaderChunk, SmpteTicks},
+            header::{Division, Format, HeaderChunk, HeaderError, SmpteTicks},
         },
         reader::{MidiReadable, MidiStream},
+        writer::MidiWriteable,
         Chunk,
     };
 
+    #[test]
+    fn standard_headers_written_as_expected() {
+        let single = HeaderChunk::single_track(Division::TPQN_480).to_midi_bytes();
+        assert_eq!(single, [0x00, 0x00, 0x00, 0x01, 0x01, 0xE0]);
+
+        let multi = HeaderChunk::multi_track(16, Division::TPQN_96).to_midi_bytes();
+        assert_eq!(multi, [0x00, 0x01, 0x00, 0x10, 0x00, 0x60]);
+
+        for (division, bytes) in [
+            (Division::TPQN_192, [0x00, 0xC0]),
+            (Division::TPQN_960, [0x03, 0xC0]),
+        ] {
+            assert_eq!(division.to_midi_bytes(), bytes);
+            assert_eq!(Division::from(u16::from_be_bytes(bytes)), division);
+        }
+    }
+
     #[test]
     fn parsing_division_to_metrical_works() {
         let test: Division = (0x000au16).into();
@@ -199,15 +370,41 @@ mod tests {
 
         assert_eq!(test, expected);
 
-        let test: Division = (0x8bFFu16).into();
+        let test: Division = (0x8BFFu16).into();
         let expected = Division::TimeCodeBased(SmpteTicks {
-            smpte: -117,
+            smpte: 11,
             tpf: 255,
         });
 
+        assert_eq!(test, expected);
+
+        let test: Division = (0xE250u16).into();
+        let expected = Division::TimeCodeBased(SmpteTicks {
+            smpte: -30,
+            tpf: 80,
+        });
+
         assert_eq!(test, expected)
     }
 
+    #[test]
+    fn every_timecode_division_round_trips() {
+        for code in 0..128u16 {
+            for tpf in [0u16, 1, 4, 40, 80, 100, 255] {
+                let word = 0x8000 | (code << 8) | tpf;
+                let division = Division::from(word);
+
+                let Division::TimeCodeBased(ticks) = division else {
+                    panic!("{word:#06X} parsed as metrical");
+                };
+                assert!((-64..64).contains(&ticks.smpte));
+                assert_eq!(ticks.smpte as u8 & 0x7F, code as u8);
+
+                assert_eq!(division.to_midi_bytes(), word.to_be_bytes());
+            }
+        }
+    }
+
     #[test]
     fn header_chunk_reads_properly() {
         let mut data = "test/run.mid"
@@ -216,7 +413,6 @@ mod tests {
 
         let (header, payload) = data.read_chunk_data_pair().expect("Get chunk and data");
 
-        let header: Chunk = header.into();
         assert_eq!(header, HEADER_CHUNK_RAW);
 
         // Now we try reading the next 6 bytes as [u16; 3]
@@ -234,12 +430,28 @@ mod tests {
 
         let header_chunk = HeaderChunk::try_from((packets[0], packets[1], packets[2]))
             .expect("Parse header chunk from payload packets");
-        let expected = HeaderChunk {
-            format: Format::One,
-            ntrks: 10,
-            division: Division::Metrical(384),
-        };
+        let expected = HeaderChunk::new(Format::One, 10, Division::Metrical(384));
 
         assert_eq!(expected, header_chunk)
     }
+
+    #[test]
+    fn longer_header_keeps_extra_bytes() {
+        let payload = [0, 1, 0, 2, 0x01, 0xE0, 0xAB, 0xCD];
+        let header = HeaderChunk::parse_extended(&payload).unwrap();
+
+        assert_eq!(header.format(), Format::One);
+        assert_eq!(header.ntrks(), 2);
+        assert_eq!(header.division(), Division::Metrical(480));
+        assert_eq!(header.extra(), [0xAB, 0xCD]);
+        assert_eq!(header.to_midi_bytes(), payload);
+    }
+
+    #[test]
+    fn short_header_is_an_error() {
+        assert_eq!(
+            HeaderChunk::parse_extended(&[0, 1, 0, 2]),
+            Err(HeaderError::TooShort(4))
+        );
+    }
 }
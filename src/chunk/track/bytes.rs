@@ -0,0 +1,253 @@
+//! Byte buffer for event payloads that keeps short payloads inline instead of on the heap
+
+use core::{
+    hash::{Hash, Hasher},
+    ops::{Deref, DerefMut},
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Payloads up to this many bytes are stored inline
+pub const INLINE_CAPACITY: usize = 16;
+
+/// The bytes of a meta or system exclusive event's payload. Most payloads are only a few bytes
+/// long, so anything up to [`INLINE_CAPACITY`] bytes is stored inline and only longer payloads
+/// allocate. Dereferences to `[u8]`, and serializes exactly like a `Vec<u8>`
+#[derive(Clone)]
+pub struct SmallBytes(Repr);
+
+/// Storage behind [`SmallBytes`]
+#[derive(Clone)]
+enum Repr {
+    /// The first `len` bytes of `buf`
+    Inline {
+        /// Number of bytes used
+        len: u8,
+        /// Backing storage, only the first `len` bytes are meaningful
+        buf: [u8; INLINE_CAPACITY],
+    },
+    /// Too long to store inline
+    Heap(Vec<u8>),
+}
+
+impl SmallBytes {
+    /// An empty payload
+    pub const fn new() -> Self {
+        Self(Repr::Inline {
+            len: 0,
+            buf: [0; INLINE_CAPACITY],
+        })
+    }
+
+    /// Takes up to `n` bytes from an iterator, stopping early if it runs out
+    pub(crate) fn take<ITER: Iterator<Item = u8>>(iter: &mut ITER, n: usize) -> Self {
+        iter.take(n).collect()
+    }
+
+    /// Appends a byte, moving to the heap once the payload outgrows the inline storage
+    pub fn push(&mut self, byte: u8) {
+        match &mut self.0 {
+            Repr::Inline { len, buf } if (*len as usize) < INLINE_CAPACITY => {
+                buf[*len as usize] = byte;
+                *len += 1;
+            }
+            Repr::Inline { buf, .. } => {
+                let mut heap = Vec::with_capacity(INLINE_CAPACITY * 2);
+                heap.extend_from_slice(buf);
+                heap.push(byte);
+                self.0 = Repr::Heap(heap);
+            }
+            Repr::Heap(heap) => heap.push(byte),
+        }
+    }
+
+    /// Whether the payload is stored inline rather than on the heap
+    pub fn is_inline(&self) -> bool {
+        matches!(self.0, Repr::Inline { .. })
+    }
+}
+
+impl Default for SmallBytes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for SmallBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.0 {
+            Repr::Inline { len, buf } => &buf[..*len as usize],
+            Repr::Heap(heap) => heap,
+        }
+    }
+}
+
+impl DerefMut for SmallBytes {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        match &mut self.0 {
+            Repr::Inline { len, buf } => &mut buf[..*len as usize],
+            Repr::Heap(heap) => heap,
+        }
+    }
+}
+
+impl AsRef<[u8]> for SmallBytes {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl core::fmt::Debug for SmallBytes {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&**self, f)
+    }
+}
+
+/// Payloads are equal when their bytes are, however they're stored
+impl PartialEq for SmallBytes {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for SmallBytes {}
+
+impl Hash for SmallBytes {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl PartialEq<[u8]> for SmallBytes {
+    fn eq(&self, other: &[u8]) -> bool {
+        **self == *other
+    }
+}
+
+impl PartialEq<Vec<u8>> for SmallBytes {
+    fn eq(&self, other: &Vec<u8>) -> bool {
+        **self == **other
+    }
+}
+
+impl<const N: usize> PartialEq<[u8; N]> for SmallBytes {
+    fn eq(&self, other: &[u8; N]) -> bool {
+        **self == *other
+    }
+}
+
+impl FromIterator<u8> for SmallBytes {
+    fn from_iter<T: IntoIterator<Item = u8>>(iter: T) -> Self {
+        let mut bytes = Self::new();
+        for byte in iter {
+            bytes.push(byte);
+        }
+
+        bytes
+    }
+}
+
+impl From<&[u8]> for SmallBytes {
+    fn from(value: &[u8]) -> Self {
+        if value.len() <= INLINE_CAPACITY {
+            let mut buf = [0; INLINE_CAPACITY];
+            buf[..value.len()].copy_from_slice(value);
+            Self(Repr::Inline {
+                len: value.len() as u8,
+                buf,
+            })
+        } else {
+            Self(Repr::Heap(value.to_vec()))
+        }
+    }
+}
+
+impl<const N: usize> From<[u8; N]> for SmallBytes {
+    fn from(value: [u8; N]) -> Self {
+        value.as_slice().into()
+    }
+}
+
+impl From<Vec<u8>> for SmallBytes {
+    fn from(value: Vec<u8>) -> Self {
+        if value.len() <= INLINE_CAPACITY {
+            value.as_slice().into()
+        } else {
+            Self(Repr::Heap(value))
+        }
+    }
+}
+
+impl From<SmallBytes> for Vec<u8> {
+    fn from(value: SmallBytes) -> Self {
+        match value.0 {
+            Repr::Inline { len, buf } => buf[..len as usize].to_vec(),
+            Repr::Heap(heap) => heap,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for SmallBytes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // Serialized as a sequence, exactly like the `Vec<u8>` payloads used to be
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for SmallBytes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::<u8>::deserialize(deserializer).map(Self::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SmallBytes, INLINE_CAPACITY};
+
+    #[test]
+    fn short_payloads_stay_inline() {
+        let mut bytes: SmallBytes = (0..INLINE_CAPACITY as u8).collect();
+        assert!(bytes.is_inline());
+        assert_eq!(bytes.len(), INLINE_CAPACITY);
+
+        bytes.push(0xFF);
+        assert!(!bytes.is_inline());
+        assert_eq!(bytes.len(), INLINE_CAPACITY + 1);
+        assert_eq!(bytes[INLINE_CAPACITY], 0xFF);
+
+        let inline = SmallBytes::from(vec![1, 2, 3]);
+        assert!(inline.is_inline());
+        assert_eq!(inline, [1, 2, 3]);
+        assert_eq!(Vec::from(inline), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn equality_ignores_storage() {
+        let long: Vec<u8> = (0..20).collect();
+        let heap = SmallBytes::from(long.clone());
+
+        let mut shrunk = heap.clone();
+        shrunk[..].copy_from_slice(&long);
+        assert_eq!(heap, shrunk);
+        assert_eq!(heap, long);
+        assert_ne!(heap, SmallBytes::from(&long[..16]));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serializes_like_a_vec() {
+        for len in [0, 3, INLINE_CAPACITY, 40] {
+            let vec: Vec<u8> = (0..len as u8).collect();
+            let bytes = SmallBytes::from(vec.clone());
+
+            let json = serde_json::to_string(&bytes).unwrap();
+            assert_eq!(json, serde_json::to_string(&vec).unwrap());
+            assert_eq!(serde_json::from_str::<SmallBytes>(&json).unwrap(), bytes);
+        }
+    }
+}
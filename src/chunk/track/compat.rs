@@ -0,0 +1,373 @@
+//! Hand written `Deserialize` impls for the event types whose format changes between releases.
+//!
+//! Serialization is still derived, so the format is exactly what the derives would read. Reading
+//! it back is more forgiving: a [`MetaEvent`] variant from a newer release becomes
+//! [`MetaEvent::UnknownRaw`] tagged [`UNKNOWN_VARIANT_TAG`], an [`Event`] variant from one
+//! fails with a message saying so rather than just naming the variant, and [`MetaText`] is also
+//! read in the plain string form releases before 0.2 wrote
+
+use serde::{
+    de::{
+        value::MapAccessDeserializer, EnumAccess, Error, IgnoredAny, MapAccess, SeqAccess,
+        VariantAccess, Visitor,
+    },
+    Deserialize, Deserializer,
+};
+
+use super::{
+    bytes::SmallBytes,
+    meta::{MetaEvent, MetaText, TextEncoding, UNKNOWN_VARIANT_TAG},
+    Event,
+};
+
+/// Names of the [`MetaEvent`] variants, in declaration order so binary formats can refer to them
+/// by index
+const META_VARIANTS: &[&str] = &[
+    "SequenceNumber",
+    "Text",
+    "Copyright",
+    "TrackName",
+    "InstrumentName",
+    "Lyric",
+    "Marker",
+    "CuePoint",
+    "MidiChannelPrefix",
+    "EndOfTrack",
+    "Tempo",
+    "SmpteOffset",
+    "TimeSignature",
+    "KeySignature",
+    "SequencerSpecific",
+    "UnknownRaw",
+];
+
+/// Names of the [`Event`] variants, in declaration order
+const EVENT_VARIANTS: &[&str] = &["MidiEvent", "SysexEvent", "MetaEvent"];
+
+/// The name of an enum variant, read from its name in self-describing formats or its index in
+/// binary ones. An index past the known variants reads as an empty name
+struct VariantName<const META: bool>(String);
+
+impl<'de, const META: bool> Deserialize<'de> for VariantName<META> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        /// Visits a variant identifier
+        struct NameVisitor<const META: bool>;
+
+        impl<const META: bool> Visitor<'_> for NameVisitor<META> {
+            type Value = VariantName<META>;
+
+            fn expecting(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                write![f, "a variant name or index"]
+            }
+
+            fn visit_str<E: Error>(self, name: &str) -> Result<Self::Value, E> {
+                Ok(VariantName(name.to_owned()))
+            }
+
+            fn visit_u64<E: Error>(self, index: u64) -> Result<Self::Value, E> {
+                let variants = if META { META_VARIANTS } else { EVENT_VARIANTS };
+                let name = variants.get(index as usize).copied().unwrap_or_default();
+                Ok(VariantName(name.to_owned()))
+            }
+        }
+
+        deserializer.deserialize_identifier(NameVisitor)
+    }
+}
+
+/// Visits a [`MetaEvent`], externally tagged like the derive writes it
+struct MetaEventVisitor;
+
+impl<'de> Visitor<'de> for MetaEventVisitor {
+    type Value = MetaEvent;
+
+    fn expecting(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write![f, "a meta event"]
+    }
+
+    /// A unit variant, written as just its name
+    fn visit_str<E: Error>(self, name: &str) -> Result<Self::Value, E> {
+        match name {
+            "EndOfTrack" => Ok(MetaEvent::EndOfTrack),
+            name if META_VARIANTS.contains(&name) => Err(E::custom(format!(
+                "meta event `{name}` is missing its data"
+            ))),
+            _ => Ok(MetaEvent::UnknownRaw(UNKNOWN_VARIANT_TAG, vec![].into())),
+        }
+    }
+
+    /// A variant with data, written as a map from its name to the data
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        self.visit_enum(MapAccessDeserializer::new(map))
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+        let (VariantName::<true>(name), variant) = data.variant()?;
+
+        Ok(match name.as_str() {
+            "SequenceNumber" => MetaEvent::SequenceNumber(variant.newtype_variant()?),
+            "Text" => MetaEvent::Text(variant.newtype_variant()?),
+            "Copyright" => MetaEvent::Copyright(variant.newtype_variant()?),
+            "TrackName" => MetaEvent::TrackName(variant.newtype_variant()?),
+            "InstrumentName" => MetaEvent::InstrumentName(variant.newtype_variant()?),
+            "Lyric" => MetaEvent::Lyric(variant.newtype_variant()?),
+            "Marker" => MetaEvent::Marker(variant.newtype_variant()?),
+            "CuePoint" => MetaEvent::CuePoint(variant.newtype_variant()?),
+            "MidiChannelPrefix" => MetaEvent::MidiChannelPrefix(variant.newtype_variant()?),
+            "EndOfTrack" => {
+                variant.unit_variant()?;
+                MetaEvent::EndOfTrack
+            }
+            "Tempo" => MetaEvent::Tempo(variant.newtype_variant()?),
+            "SmpteOffset" => MetaEvent::SmpteOffset(variant.newtype_variant()?),
+            "TimeSignature" => MetaEvent::TimeSignature(variant.newtype_variant()?),
+            "KeySignature" => MetaEvent::KeySignature(variant.newtype_variant()?),
+            "SequencerSpecific" => MetaEvent::SequencerSpecific(variant.newtype_variant()?),
+            "UnknownRaw" => {
+                let (tag, data) = variant.newtype_variant()?;
+                MetaEvent::UnknownRaw(tag, data)
+            }
+            _ => {
+                variant.newtype_variant::<IgnoredAny>()?;
+                MetaEvent::UnknownRaw(UNKNOWN_VARIANT_TAG, vec![].into())
+            }
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for MetaEvent {
+    /// Self-describing formats are read without knowing the variants up front, so a unit variant
+    /// from a newer release can be told apart from one with data
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(MetaEventVisitor)
+        } else {
+            deserializer.deserialize_enum("MetaEvent", META_VARIANTS, MetaEventVisitor)
+        }
+    }
+}
+
+/// Visits an [`Event`], externally tagged like the derive writes it
+struct EventVisitor;
+
+impl<'de> Visitor<'de> for EventVisitor {
+    type Value = Event;
+
+    fn expecting(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write![f, "a MidiEvent, SysexEvent or MetaEvent"]
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+        let (VariantName::<false>(name), variant) = data.variant()?;
+
+        match name.as_str() {
+            "MidiEvent" => variant.newtype_variant().map(Event::MidiEvent),
+            "SysexEvent" => variant.newtype_variant().map(Event::SysexEvent),
+            "MetaEvent" => variant.newtype_variant().map(Event::MetaEvent),
+            name => Err(A::Error::custom(format!(
+                "unknown event kind `{name}`, expected MidiEvent, SysexEvent or MetaEvent. It was \
+                 probably written by a newer version of miami"
+            ))),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Event {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_enum("Event", EVENT_VARIANTS, EventVisitor)
+    }
+}
+
+/// [`MetaText`] as it's written since 0.2, with its bytes and their encoding
+#[derive(Deserialize)]
+#[serde(rename = "MetaText")]
+struct EncodedText {
+    /// See [`MetaText::as_bytes`]
+    bytes: SmallBytes,
+    /// See [`MetaText::encoding`]
+    encoding: TextEncoding,
+}
+
+impl From<EncodedText> for MetaText {
+    fn from(text: EncodedText) -> Self {
+        MetaText::from_bytes(text.bytes, text.encoding)
+    }
+}
+
+/// Visits a [`MetaText`] written by any release
+struct MetaTextVisitor;
+
+impl<'de> Visitor<'de> for MetaTextVisitor {
+    type Value = MetaText;
+
+    fn expecting(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write![f, "meta event text"]
+    }
+
+    /// Text before 0.2, which was always UTF-8
+    fn visit_str<E: Error>(self, text: &str) -> Result<Self::Value, E> {
+        Ok(text.into())
+    }
+
+    fn visit_bytes<E: Error>(self, bytes: &[u8]) -> Result<Self::Value, E> {
+        Ok(MetaText::from_bytes(bytes, TextEncoding::Utf8))
+    }
+
+    /// A cue point before 0.2, which was kept as bytes
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+        while let Some(byte) = seq.next_element::<u8>()? {
+            bytes.push(byte);
+        }
+
+        Ok(MetaText::from_bytes(bytes, TextEncoding::Utf8))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        EncodedText::deserialize(MapAccessDeserializer::new(map)).map(MetaText::from)
+    }
+}
+
+impl<'de> Deserialize<'de> for MetaText {
+    /// Only self-describing formats can tell the forms apart, so binary ones are read in the
+    /// current form alone
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(MetaTextVisitor)
+        } else {
+            EncodedText::deserialize(deserializer).map(MetaText::from)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        chunk::track::{
+            event::{MidiEvent, NoteMeta},
+            meta::{MetaEvent, MetaText, UNKNOWN_VARIANT_TAG},
+            Event,
+        },
+        Midi, RawMidi,
+    };
+
+    /// JSON of a file using every kind of event, in the format 0.2 introduces. It must keep
+    /// deserializing to the same file in every later release
+    const MIDI_0_2_JSON: &str = include_str!("../../../test/compat/midi_0_2.json");
+
+    /// `test/test.mid` in the format 0.2 introduces
+    const TEST_MID_0_2_JSON: &str = include_str!("../../../test/compat/test_mid_0_2.json");
+
+    /// [`every_event`] as written by the released miami 0.1.3
+    const MIDI_0_1_3_JSON: &str = include_str!("../../../test/compat/midi_0_1_3.json");
+
+    /// `test/test.mid` as written by the released miami 0.1.3
+    const TEST_MID_0_1_3_JSON: &str = include_str!("../../../test/compat/test_mid_0_1_3.json");
+
+    /// A file using every event miami 0.1.3 could parse
+    fn every_event() -> Vec<u8> {
+        let track = [
+            &[0x00, 0xFF, 0x00, 0x02, 0x00, 0x07][..],
+            &[0x00, 0xFF, 0x01, 0x05, b'h', b'e', b'l', b'l', b'o'],
+            &[0x00, 0xFF, 0x02, 0x03, b'(', b'c', b')'],
+            &[0x00, 0xFF, 0x03, 0x05, b'P', b'i', b'a', b'n', b'o'],
+            &[0x00, 0xFF, 0x04, 0x04, b'K', b'e', b'y', b's'],
+            &[0x00, 0xFF, 0x05, 0x02, b'l', b'a'],
+            &[0x00, 0xFF, 0x06, 0x05, b'V', b'e', b'r', b's', b'e'],
+            &[0x00, 0xFF, 0x07, 0x03, b'c', b'u', b'e'],
+            &[0x00, 0xFF, 0x20, 0x01, 0x00],
+            &[0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20],
+            &[0x00, 0xFF, 0x54, 0x05, 0x01, 0x02, 0x03, 0x04, 0x05],
+            &[0x00, 0xFF, 0x58, 0x04, 0x06, 0x03, 0x18, 0x08],
+            &[0x00, 0xFF, 0x59, 0x02, 0xFE, 0x01],
+            &[0x00, 0xFF, 0x7F, 0x03, 0x00, 0x00, 0x41],
+            &[0x00, 0xC0, 0x05],
+            &[0x00, 0xB0, 0x07, 0x64],
+            &[0x00, 0x90, 0x3C, 0x64],
+            &[0x60, 0xD0, 0x20],
+            &[0x60, 0x80, 0x3C, 0x40],
+            &[0x00, 0xFF, 0x2F, 0x00],
+        ]
+        .concat();
+        let mut bytes = b"MThd\0\0\0\x06\0\0\0\x01\x01\xE0MTrk".to_vec();
+        bytes.extend((track.len() as u32).to_be_bytes());
+        bytes.extend(track);
+        bytes
+    }
+
+    #[test]
+    fn frozen_json_still_loads() {
+        let midi: Midi = serde_json::from_str(TEST_MID_0_2_JSON).unwrap();
+        assert_eq!(midi, crate::open("test/test.mid").unwrap());
+
+        let midi: Midi = serde_json::from_str(MIDI_0_2_JSON).unwrap();
+        let json = serde_json::to_string_pretty(&midi).unwrap();
+        assert_eq!(json.trim(), MIDI_0_2_JSON.trim());
+    }
+
+    #[test]
+    fn released_json_still_loads() {
+        let midi: Midi = serde_json::from_str(TEST_MID_0_1_3_JSON).unwrap();
+        assert_eq!(midi, crate::open("test/test.mid").unwrap());
+
+        let midi: Midi = serde_json::from_str(MIDI_0_1_3_JSON).unwrap();
+        let parsed = RawMidi::try_from_midi_stream(every_event().into_iter()).unwrap();
+        assert_eq!(midi, Midi::try_from(parsed).unwrap());
+
+        let text: MetaText = serde_json::from_str(r#""hello""#).unwrap();
+        assert_eq!(text, MetaText::from("hello"));
+        let text: MetaText = serde_json::from_str("[99, 117, 101]").unwrap();
+        assert_eq!(text, MetaText::from("cue"));
+    }
+
+    #[test]
+    fn unknown_meta_events_are_kept_as_unknown() {
+        let events: Vec<MetaEvent> = serde_json::from_str(
+            r#"[
+                "EndOfTrack",
+                "Fermata",
+                { "Tempo": 500000 },
+                { "Chord": { "root": 0, "quality": "maj7" } },
+                { "Pedal": [64, true] }
+            ]"#,
+        )
+        .unwrap();
+
+        let unknown = MetaEvent::UnknownRaw(UNKNOWN_VARIANT_TAG, vec![].into());
+        assert_eq!(
+            events,
+            [
+                MetaEvent::EndOfTrack,
+                unknown.clone(),
+                MetaEvent::Tempo(500000),
+                unknown.clone(),
+                unknown
+            ]
+        );
+
+        let err = serde_json::from_str::<MetaEvent>(r#""Tempo""#).unwrap_err();
+        assert!(err.to_string().contains("`Tempo` is missing its data"));
+    }
+
+    #[test]
+    fn unknown_events_fail_with_a_description() {
+        let err = serde_json::from_str::<Event>(r#"{ "ClockEvent": { "beat": 1 } }"#).unwrap_err();
+        assert!(err.to_string().contains("unknown event kind `ClockEvent`"));
+
+        // Fields a newer release adds to a struct are ignored
+        let event: Event = serde_json::from_str(
+            r#"{ "MidiEvent": { "NoteOn": [0, { "key": 60, "velocity": 90, "tuning": 0.5 }] } }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            event,
+            Event::MidiEvent(MidiEvent::NoteOn(
+                0,
+                NoteMeta {
+                    key: 60,
+                    velocity: 90
+                }
+            ))
+        );
+    }
+}
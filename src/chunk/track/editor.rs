@@ -0,0 +1,281 @@
+//! Absolute-time editing of track events
+
+use super::{meta::MetaEvent, Event, MTrkEvent, TrackChunk};
+use crate::{time::Tick, track_id::TrackId};
+
+/// Error from finishing a track edit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditError {
+    /// The gap before the event at this absolute tick is larger than a delta time can express
+    DeltaOverflow(Tick),
+}
+
+impl core::error::Error for EditError {}
+impl core::fmt::Display for EditError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::DeltaOverflow(tick) => {
+                write![f, "Delta time before the event at tick {tick} is too large"]
+            }
+        }
+    }
+}
+
+/// An editable view of a track as events at absolute ticks rather than delta times.
+///
+/// Events can be freely inserted, removed, reordered or retimed through [`TrackEditor::events_mut`]
+/// without worrying about delta times, and [`TrackEditor::finish`] turns them back into a valid
+/// track. The track's `EndOfTrack` is managed by the editor, so any `EndOfTrack` events added
+/// along the way only move the end of the track later
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackEditor {
+    /// Every event other than `EndOfTrack`, alongside its absolute tick
+    events: Vec<(Tick, Event)>,
+    /// Absolute tick the track ends at
+    end_of_track: Tick,
+    /// The track's [`TrackChunk::trailing_events`], carried through the edit untouched
+    trailing_events: Vec<MTrkEvent>,
+    /// The track's [`TrackChunk::id`], kept by the finished track
+    id: Option<TrackId>,
+    /// Whether the track is [`TrackChunk::is_truncated`], kept by the finished track
+    truncated: bool,
+}
+
+impl TrackEditor {
+    /// Starts editing a track
+    pub fn new(track: TrackChunk) -> Self {
+        let mut editor = Self {
+            events: vec![],
+            end_of_track: Tick::ZERO,
+            trailing_events: track.trailing_events,
+            id: track.id,
+            truncated: track.truncated,
+        };
+
+        let mut tick = Tick::ZERO;
+        for mtrk_event in track.mtrk_events {
+            tick += mtrk_event.delta_time;
+            editor.insert(tick, mtrk_event.event);
+        }
+
+        editor
+    }
+
+    /// The events being edited with their absolute ticks, excluding `EndOfTrack`
+    pub fn events(&self) -> &[(Tick, Event)] {
+        &self.events
+    }
+
+    /// Mutable access to the events being edited. They don't need to be kept in order
+    pub fn events_mut(&mut self) -> &mut Vec<(Tick, Event)> {
+        &mut self.events
+    }
+
+    /// Adds an event at an absolute tick, after any events already at that tick
+    pub fn insert(&mut self, tick: impl Into<Tick>, event: Event) {
+        let tick = tick.into();
+        match event {
+            Event::MetaEvent(MetaEvent::EndOfTrack) => {
+                self.end_of_track = self.end_of_track.max(tick)
+            }
+            event => self.events.push((tick, event)),
+        }
+    }
+
+    /// Keeps only the events the predicate returns true for
+    pub fn retain(&mut self, mut f: impl FnMut(Tick, &Event) -> bool) {
+        self.events.retain(|(tick, event)| f(*tick, event));
+    }
+
+    /// Moves every event at or after `from_tick` later by `offset` ticks, along with the end of
+    /// the track if it's at or after `from_tick`
+    pub fn shift_from(&mut self, from_tick: impl Into<Tick>, offset: u64) {
+        let from_tick = from_tick.into();
+        for (tick, _) in self
+            .events
+            .iter_mut()
+            .filter(|(tick, _)| *tick >= from_tick)
+        {
+            tick.0 += offset;
+        }
+        if self.end_of_track >= from_tick {
+            self.end_of_track.0 += offset;
+        }
+    }
+
+    /// The absolute tick the track will end at, never earlier than its last event
+    pub fn end_of_track(&self) -> Tick {
+        self.events
+            .iter()
+            .map(|(tick, _)| *tick)
+            .fold(self.end_of_track, Tick::max)
+    }
+
+    /// Sets the tick the track ends at. The track still ends no earlier than its last event
+    pub fn set_end_of_track(&mut self, tick: impl Into<Tick>) {
+        self.end_of_track = tick.into();
+    }
+
+    /// Sorts the events by tick, keeping the current order of simultaneous events, and rebuilds
+    /// the track with freshly computed delta times and a closing `EndOfTrack`
+    pub fn finish(mut self) -> Result<TrackChunk, EditError> {
+        let end_of_track = self.end_of_track();
+        self.events.sort_by_key(|(tick, _)| *tick);
+
+        let mut last = Tick::ZERO;
+        for (tick, _) in &self.events {
+            if tick.delta_since(last).is_none() {
+                return Err(EditError::DeltaOverflow(*tick));
+            }
+            last = *tick;
+        }
+        if end_of_track.delta_since(last).is_none() {
+            return Err(EditError::DeltaOverflow(end_of_track));
+        }
+
+        let events = self
+            .events
+            .into_iter()
+            .chain([(end_of_track, Event::MetaEvent(MetaEvent::EndOfTrack))]);
+
+        let track = TrackChunk::from_absolute_events(events)
+            .expect("Events are sorted and every gap fits in a delta time");
+        Ok(TrackChunk {
+            trailing_events: self.trailing_events,
+            id: self.id,
+            truncated: self.truncated,
+            ..track
+        })
+    }
+}
+
+impl From<TrackChunk> for TrackEditor {
+    fn from(value: TrackChunk) -> Self {
+        Self::new(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EditError, TrackEditor};
+    use crate::{
+        chunk::track::{
+            event::{ControlChange, MidiEvent, NoteMeta},
+            meta::MetaEvent,
+            Event, MTrkEvent, TrackChunk, MAX_DELTA_TIME,
+        },
+        chunk::ParsedChunk,
+        reader::{MidiReadable, MidiStream},
+        time::{DeltaTime, Tick},
+    };
+
+    fn note(key: u8) -> Event {
+        Event::MidiEvent(MidiEvent::NoteOn(0, NoteMeta { key, velocity: 100 }))
+    }
+
+    fn events(track: &TrackChunk) -> Vec<(u64, Event)> {
+        track
+            .absolute_events()
+            .map(|(tick, event)| (tick.get(), event.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn unedited_tracks_finish_unchanged() {
+        for path in ["test/test.mid", "test/run.mid", "test/test4tracks.mid"] {
+            let mut stream = path.get_midi_bytes().expect("Open fixture");
+
+            while let Some(chunk) = stream.read_chunk_data_pair() {
+                if let Ok(ParsedChunk::Track(track)) = ParsedChunk::try_from(chunk) {
+                    let finished = TrackEditor::new(track.clone()).finish().unwrap();
+                    assert_eq!(finished, track, "Track in {path} changed");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn generated_tracks_finish_unchanged() {
+        let mut seed = 0x2545_F491u32;
+        for len in 0..50 {
+            let mut mtrk_events = vec![];
+            for idx in 0..len {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                mtrk_events.push(MTrkEvent {
+                    delta_time: if idx % 3 == 0 { 0 } else { seed % 2000 }.into(),
+                    event: note((seed % 128) as u8),
+                });
+            }
+            mtrk_events.push(MTrkEvent {
+                delta_time: (seed % 7).into(),
+                event: Event::MetaEvent(MetaEvent::EndOfTrack),
+            });
+
+            let track = TrackChunk::new(mtrk_events);
+            assert_eq!(TrackEditor::new(track.clone()).finish(), Ok(track));
+        }
+    }
+
+    #[test]
+    fn edits_are_resorted_stably() {
+        let track = TrackChunk::from_absolute_events([
+            (0, note(60)),
+            (480, note(62)),
+            (960, Event::MetaEvent(MetaEvent::EndOfTrack)),
+        ])
+        .unwrap();
+
+        let mut editor = TrackEditor::new(track);
+        editor.insert(480, note(64));
+        editor.insert(
+            240,
+            Event::MidiEvent(MidiEvent::ControlChange(
+                0,
+                ControlChange {
+                    controller_number: 64,
+                    new_value: 127,
+                },
+            )),
+        );
+        editor.retain(|_, event| event != &note(60));
+        editor.shift_from(480, 20);
+
+        let finished = editor.finish().unwrap();
+        assert_eq!(
+            events(&finished)[1..],
+            [
+                (500, note(62)),
+                (500, note(64)),
+                (980, Event::MetaEvent(MetaEvent::EndOfTrack)),
+            ]
+        );
+        assert_eq!(finished.mtrk_events[1].delta_time, DeltaTime::new(260));
+    }
+
+    #[test]
+    fn end_of_track_follows_last_event() {
+        let mut editor = TrackEditor::new(TrackChunk::new(vec![]));
+        editor.insert(100, note(60));
+        editor.insert(50, Event::MetaEvent(MetaEvent::EndOfTrack));
+
+        assert_eq!(editor.end_of_track(), Tick::new(100));
+        assert_eq!(
+            events(&editor.finish().unwrap()),
+            vec![
+                (100, note(60)),
+                (100, Event::MetaEvent(MetaEvent::EndOfTrack))
+            ]
+        );
+    }
+
+    #[test]
+    fn oversized_gaps_error() {
+        let mut editor = TrackEditor::new(TrackChunk::new(vec![]));
+        let tick = Tick::new(MAX_DELTA_TIME as u64 + 1);
+        editor.insert(tick, note(60));
+
+        assert_eq!(editor.finish(), Err(EditError::DeltaOverflow(tick)));
+    }
+}
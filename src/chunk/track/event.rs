@@ -1,6 +1,6 @@
 //! Status parsing trait and implementation
 
-use crate::{reader::Yieldable, writer::MidiWriteable};
+use crate::{chunk::track::TrackError, writer::MidiWriteable};
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
@@ -24,10 +24,20 @@ pub enum MidiEvent {
     ControlChange(u8, ControlChange),
     /// Program change.
     /// This message is sent when the patch number changes
-    ProgramChange(u8, u8),
+    ProgramChange {
+        /// Channel the program changes on
+        channel: u8,
+        /// The new program number
+        program: u8,
+    },
     /// Channel Pressure
     /// This message is most often sent by pressing down on a key after it "bottoms out"
-    ChannelPressure(u8, u8),
+    ChannelPressure {
+        /// Channel the pressure applies to
+        channel: u8,
+        /// Pressure applied to the whole channel
+        pressure: u8,
+    },
     /// Pitch Wheel Change
     /// This message is sent to indicate a change in the pitch wheel as measured by a fourteen bit
     /// value.
@@ -44,8 +54,10 @@ impl MidiWriteable for MidiEvent {
             | Self::NoteOn(_, notemeta)
             | Self::PolyphonicKeyPressure(_, notemeta) => notemeta.to_midi_bytes(),
             Self::ControlChange(_, control_change) => control_change.to_midi_bytes(),
-            Self::ProgramChange(_, val) | Self::ChannelPressure(_, val) => val.to_midi_bytes(),
-            Self::PitchWheelChange(_, val) => val.to_midi_bytes(),
+            Self::ProgramChange { program: val, .. }
+            | Self::ChannelPressure { pressure: val, .. } => val.to_midi_bytes(),
+            // 14 bits split into two data bytes, least significant first
+            Self::PitchWheelChange(_, val) => vec![(val & 0x7F) as u8, ((val >> 7) & 0x7F) as u8],
         };
 
         bytes.extend(extra.iter());
@@ -55,20 +67,173 @@ impl MidiWriteable for MidiEvent {
 }
 
 impl MidiEvent {
-    /// Combines the channel and current type's status identifier into a single byte
-    pub fn get_status_channel_combo(&self) -> u8 {
+    /// Number of bytes the event takes up once written, its status byte and one or two data bytes
+    pub(crate) fn encoded_len(&self) -> usize {
         match self {
-            Self::NoteOff(channel, _) => 0b10000000 | channel,
-            Self::NoteOn(channel, _) => 0b10010000 | channel,
-            Self::PolyphonicKeyPressure(channel, _) => 0b10100000 | channel,
-            Self::ControlChange(channel, _) => 0b10110000 | channel,
-            Self::ProgramChange(channel, _) => 0b11000000 | channel,
-            Self::ChannelPressure(channel, _) => 0b11010000 | channel,
-            Self::PitchWheelChange(channel, _) => 0b11100000 | channel,
+            Self::ProgramChange { .. } | Self::ChannelPressure { .. } => 2,
+            _ => 3,
         }
     }
 }
 
+impl MidiEvent {
+    /// Creates a Note On, which can't be out of range
+    pub fn note_on(channel: Channel, key: U7, velocity: U7) -> Self {
+        Self::NoteOn(channel.0, NoteMeta::from_u7(key, velocity))
+    }
+
+    /// Creates a Note Off, which can't be out of range
+    pub fn note_off(channel: Channel, key: U7, velocity: U7) -> Self {
+        Self::NoteOff(channel.0, NoteMeta::from_u7(key, velocity))
+    }
+
+    /// Creates a Control Change, which can't be out of range
+    pub fn control_change(channel: Channel, controller: U7, value: U7) -> Self {
+        Self::ControlChange(
+            channel.0,
+            ControlChange {
+                controller_number: controller.0,
+                new_value: value.0,
+            },
+        )
+    }
+
+    /// Creates a Note On from raw bytes, with the channel in 0 to 15 and the key and velocity in
+    /// 0 to 127
+    pub fn try_note_on(channel: u8, key: u8, velocity: u8) -> Result<Self, DataOutOfRange> {
+        Ok(Self::note_on(
+            channel.try_into()?,
+            key.try_into()?,
+            velocity.try_into()?,
+        ))
+    }
+
+    /// Creates a Note Off from raw bytes, with the channel in 0 to 15 and the key and velocity in
+    /// 0 to 127
+    pub fn try_note_off(channel: u8, key: u8, velocity: u8) -> Result<Self, DataOutOfRange> {
+        Ok(Self::note_off(
+            channel.try_into()?,
+            key.try_into()?,
+            velocity.try_into()?,
+        ))
+    }
+
+    /// Creates a Control Change from raw bytes, with the channel in 0 to 15 and the controller
+    /// number and value in 0 to 127
+    pub fn try_control_change(
+        channel: u8,
+        controller: u8,
+        value: u8,
+    ) -> Result<Self, DataOutOfRange> {
+        Ok(Self::control_change(
+            channel.try_into()?,
+            controller.try_into()?,
+            value.try_into()?,
+        ))
+    }
+
+    /// Creates a Program Change, which can't be out of range
+    pub fn program_change(channel: Channel, program: U7) -> Self {
+        Self::ProgramChange {
+            channel: channel.0,
+            program: program.0,
+        }
+    }
+
+    /// Creates a Channel Pressure message, which can't be out of range
+    pub fn channel_pressure(channel: Channel, pressure: U7) -> Self {
+        Self::ChannelPressure {
+            channel: channel.0,
+            pressure: pressure.0,
+        }
+    }
+
+    /// Creates a Program Change from raw bytes, with the channel in 0 to 15 and the program in 0
+    /// to 127
+    pub fn try_program_change(channel: u8, program: u8) -> Result<Self, DataOutOfRange> {
+        Ok(Self::program_change(
+            channel.try_into()?,
+            program.try_into()?,
+        ))
+    }
+
+    /// Creates a Channel Pressure message from raw bytes, with the channel in 0 to 15 and the
+    /// pressure in 0 to 127
+    pub fn try_channel_pressure(channel: u8, pressure: u8) -> Result<Self, DataOutOfRange> {
+        Ok(Self::channel_pressure(
+            channel.try_into()?,
+            pressure.try_into()?,
+        ))
+    }
+
+    /// The program selected, if this is a Program Change
+    pub fn program(&self) -> Option<u8> {
+        match self {
+            Self::ProgramChange { program, .. } => Some(*program),
+            _ => None,
+        }
+    }
+
+    /// The pressure applied, if this is a Channel Pressure message
+    pub fn pressure(&self) -> Option<u8> {
+        match self {
+            Self::ChannelPressure { pressure, .. } => Some(*pressure),
+            _ => None,
+        }
+    }
+
+    /// The wheel position, if this is a Pitch Wheel Change
+    pub fn pitch_bend(&self) -> Option<PitchBend> {
+        match self {
+            Self::PitchWheelChange(_, value) => Some(PitchBend(*value & PitchBend::MAX)),
+            _ => None,
+        }
+    }
+
+    /// Returns the channel the event is sent on
+    pub fn channel(&self) -> u8 {
+        match self {
+            Self::NoteOff(channel, _)
+            | Self::NoteOn(channel, _)
+            | Self::PolyphonicKeyPressure(channel, _)
+            | Self::ControlChange(channel, _)
+            | Self::ProgramChange { channel, .. }
+            | Self::ChannelPressure { channel, .. }
+            | Self::PitchWheelChange(channel, _) => *channel,
+        }
+    }
+
+    /// Mutable access to the channel the event is sent on
+    pub(crate) fn channel_mut(&mut self) -> &mut u8 {
+        match self {
+            Self::NoteOff(channel, _)
+            | Self::NoteOn(channel, _)
+            | Self::PolyphonicKeyPressure(channel, _)
+            | Self::ControlChange(channel, _)
+            | Self::ProgramChange { channel, .. }
+            | Self::ChannelPressure { channel, .. }
+            | Self::PitchWheelChange(channel, _) => channel,
+        }
+    }
+
+    /// Combines the channel and current type's status identifier into a single byte, with the
+    /// message kind in the high nibble and the channel in the low one. Channels above 15 are
+    /// truncated to their low 4 bits rather than spilling into the message kind
+    pub fn get_status_channel_combo(&self) -> u8 {
+        let kind: u8 = match self {
+            Self::NoteOff(..) => 0b1000,
+            Self::NoteOn(..) => 0b1001,
+            Self::PolyphonicKeyPressure(..) => 0b1010,
+            Self::ControlChange(..) => 0b1011,
+            Self::ProgramChange { .. } => 0b1100,
+            Self::ChannelPressure { .. } => 0b1101,
+            Self::PitchWheelChange(..) => 0b1110,
+        };
+
+        (kind << 4) | (self.channel() & 0x0F)
+    }
+}
+
 /// Error type for an unsupported error type
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct UnsupportedStatusCode(u8);
@@ -85,61 +250,69 @@ impl<ITER> TryFrom<IteratorWrapper<&mut ITER>> for MidiEvent
 where
     ITER: Iterator<Item = u8>,
 {
-    type Error = UnsupportedStatusCode;
+    type Error = TrackError;
     fn try_from(value: IteratorWrapper<&mut ITER>) -> Result<Self, Self::Error> {
-        let value = value.0;
-        let status = value.get(1)[0];
-        let channel = status & 0x0F;
-        let status = status >> 4;
-
-        match status {
-            0b1000 => {
-                let reads = value.get(2);
-                Ok(Self::NoteOff(
-                    channel,
-                    NoteMeta {
-                        key: reads[0],
-                        velocity: reads[1],
-                    },
-                ))
-            }
-
-            0b1001 => {
-                let reads = value.get(2);
-                Ok(Self::NoteOn(
-                    channel,
-                    NoteMeta {
-                        key: reads[0],
-                        velocity: reads[1],
-                    },
-                ))
-            }
+        Self::parse(value.0)
+    }
+}
 
-            0b1011 => {
-                let reads = value.get(2);
-                Ok(Self::ControlChange(
-                    channel,
-                    ControlChange {
-                        controller_number: reads[0],
-                        new_value: reads[1],
-                    },
-                ))
-            }
+impl MidiEvent {
+    /// Parses a channel message from its status byte and data bytes, failing with
+    /// [`TrackError::OutOfSpace`] if the bytes run out partway through
+    pub(crate) fn parse<ITER: Iterator<Item = u8>>(iter: &mut ITER) -> Result<Self, TrackError> {
+        let mut next = || iter.next().ok_or(TrackError::OutOfSpace);
 
-            0b1100 => {
-                let reads = value.get(1);
-                Ok(Self::ProgramChange(channel, reads[0]))
-            }
+        let status = next()?;
+        let channel = Channel::new_unchecked(status).get();
+        let status = status >> 4;
 
-            0b1101 => {
-                let reads = value.get(1);
-                Ok(Self::ChannelPressure(channel, reads[0]))
-            }
+        match status {
+            0b1000 => Ok(Self::NoteOff(
+                channel,
+                NoteMeta {
+                    key: next()?,
+                    velocity: next()?,
+                },
+            )),
+
+            0b1001 => Ok(Self::NoteOn(
+                channel,
+                NoteMeta {
+                    key: next()?,
+                    velocity: next()?,
+                },
+            )),
+
+            0b1010 => Ok(Self::PolyphonicKeyPressure(
+                channel,
+                NoteMeta {
+                    key: next()?,
+                    velocity: next()?,
+                },
+            )),
+
+            0b1011 => Ok(Self::ControlChange(
+                channel,
+                ControlChange {
+                    controller_number: next()?,
+                    new_value: next()?,
+                },
+            )),
+
+            0b1100 => Ok(Self::ProgramChange {
+                channel,
+                program: next()?,
+            }),
+
+            0b1101 => Ok(Self::ChannelPressure {
+                channel,
+                pressure: next()?,
+            }),
 
             0b1110 => {
-                let reads = value.get(2);
+                let reads = [next()?, next()?];
 
-                const MASK: u8 = 0x7;
+                const MASK: u8 = 0x7F;
 
                 let mut result: u16 = 0;
                 for byte in reads.iter().rev() {
@@ -150,19 +323,266 @@ where
                 Ok(Self::PitchWheelChange(channel, result))
             }
 
-            code => Err(UnsupportedStatusCode(code)),
+            code => Err(UnsupportedStatusCode(code).into()),
         }
     }
 }
 
-/// Metadata for a note's relative info. Including channel, key and velocity
+/// Error for a MIDI data byte outside of the 7 bit 0 to 127 range, or a channel outside of 0 to
+/// 15
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DataOutOfRange(pub u8);
+
+impl core::error::Error for DataOutOfRange {}
+impl core::fmt::Display for DataOutOfRange {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write![f, "Value {} is outside of its valid range", self.0]
+    }
+}
+
+/// Error for a pitch bend further from center than the bend range allows
 #[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BendOutOfRange {
+    /// The requested bend in semitones
+    pub semitones: f32,
+    /// The bend range it had to fit, in semitones either way
+    pub range: f32,
+}
+
+impl core::error::Error for BendOutOfRange {}
+impl core::fmt::Display for BendOutOfRange {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write![
+            f,
+            "Bend of {} semitones is outside of a range of ±{}",
+            self.semitones, self.range
+        ]
+    }
+}
+
+/// A 14 bit pitch wheel position, as sent by a Pitch Wheel Change. What it means in semitones
+/// depends on the channel's bend range, ±2 semitones unless changed through RPN 0, see
+/// [`crate::Midi::bend_range_timeline`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PitchBend(u16);
+
+impl PitchBend {
+    /// The wheel at rest, bending nothing
+    pub const CENTER: Self = Self(0x2000);
+    /// The highest 14 bit position
+    pub const MAX: u16 = 0x3FFF;
+
+    /// A wheel position from its 14 bit value. Fails for a value past 0x3FFF, with the
+    /// [`DataOutOfRange`] holding its high byte
+    pub fn new(value: u16) -> Result<Self, DataOutOfRange> {
+        if value <= Self::MAX {
+            Ok(Self(value))
+        } else {
+            Err(DataOutOfRange((value >> 8) as u8))
+        }
+    }
+
+    /// The 14 bit value
+    pub fn value(self) -> u16 {
+        self.0
+    }
+
+    /// The bend in semitones for a bend range of `range` semitones either way. Each step is
+    /// `range / 8192` semitones, so the lowest position is exactly `-range` and the highest one
+    /// step short of `range`
+    pub fn to_semitones(&self, range: f32) -> f32 {
+        (self.0 as f32 - Self::CENTER.0 as f32) * range / Self::CENTER.0 as f32
+    }
+
+    /// The position closest to a bend of `semitones` for a bend range of `range` semitones either
+    /// way, where a full bend up lands on the highest position. Fails if the bend is outside of
+    /// the range
+    pub fn from_semitones(semitones: f32, range: f32) -> Result<Self, BendOutOfRange> {
+        if semitones.is_nan() || range.is_nan() || semitones.abs() > range {
+            return Err(BendOutOfRange { semitones, range });
+        }
+
+        if range == 0.0 {
+            return Ok(Self::CENTER);
+        }
+
+        let offset = (semitones / range * Self::CENTER.0 as f32).round();
+        let value = (Self::CENTER.0 as f32 + offset).clamp(0.0, Self::MAX as f32);
+        Ok(Self(value as u16))
+    }
+}
+
+/// A 7 bit MIDI data byte, such as a key, velocity or controller value, which can't hold the
+/// 128 to 255 that would corrupt a file. Raw bytes convert with [`TryFrom<u8>`], or with
+/// [`U7::saturating`] and [`U7::new_unchecked`] where clamping or masking is wanted
+///
+/// ```rust
+/// use miami::chunk::track::event::{Channel, MidiEvent, U7};
+///
+/// let key = U7::try_from(60).unwrap();
+/// let note = MidiEvent::note_on(Channel::new_unchecked(0), key, U7::MAX);
+/// assert_eq!(note, MidiEvent::try_note_on(0, 60, 127).unwrap());
+/// assert!(U7::try_from(200).is_err());
+/// ```
+///
+/// Plain bytes aren't accepted where a `U7` is expected:
+///
+/// ```rust,compile_fail
+/// use miami::chunk::track::event::{Channel, MidiEvent, U7};
+///
+/// let note = MidiEvent::note_on(Channel::new_unchecked(0), 200u8, U7::MAX);
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct U7(u8);
+
+impl U7 {
+    /// The lowest value, 0
+    pub const MIN: Self = Self(0);
+    /// The highest value, 127
+    pub const MAX: Self = Self(0x7F);
+
+    /// Keeps only the low 7 bits of the byte without checking it, for bytes already known to
+    /// fit such as ones masked off the wire
+    pub const fn new_unchecked(value: u8) -> Self {
+        Self(value & 0x7F)
+    }
+
+    /// Clamps the byte to 127
+    pub const fn saturating(value: u8) -> Self {
+        if value > 0x7F {
+            Self::MAX
+        } else {
+            Self(value)
+        }
+    }
+
+    /// The value as a byte
+    pub const fn get(self) -> u8 {
+        self.0
+    }
+}
+
+impl TryFrom<u8> for U7 {
+    type Error = DataOutOfRange;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        data_byte(value).map(Self)
+    }
+}
+
+impl From<U7> for u8 {
+    fn from(value: U7) -> Self {
+        value.0
+    }
+}
+
+/// A 4 bit MIDI channel from 0 to 15, where 9 is the General MIDI percussion channel
+///
+/// ```rust,compile_fail
+/// use miami::chunk::track::event::{MidiEvent, U7};
+///
+/// let note = MidiEvent::note_on(3u8, U7::MIN, U7::MAX);
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Channel(u8);
+
+impl Channel {
+    /// The highest channel, 15
+    pub const MAX: Self = Self(0x0F);
+
+    /// Keeps only the low 4 bits of the byte without checking it, for bytes already known to
+    /// fit such as the low nibble of a status byte
+    pub const fn new_unchecked(channel: u8) -> Self {
+        Self(channel & 0x0F)
+    }
+
+    /// Clamps the byte to 15
+    pub const fn saturating(channel: u8) -> Self {
+        if channel > 0x0F {
+            Self::MAX
+        } else {
+            Self(channel)
+        }
+    }
+
+    /// The channel as a byte
+    pub const fn get(self) -> u8 {
+        self.0
+    }
+}
+
+impl TryFrom<u8> for Channel {
+    type Error = DataOutOfRange;
+    fn try_from(channel: u8) -> Result<Self, Self::Error> {
+        channel_nibble(channel).map(Self)
+    }
+}
+
+impl From<Channel> for u8 {
+    fn from(channel: Channel) -> Self {
+        channel.0
+    }
+}
+
+/// Checks that a data byte fits in 7 bits
+fn data_byte(value: u8) -> Result<u8, DataOutOfRange> {
+    if value <= 0x7F {
+        Ok(value)
+    } else {
+        Err(DataOutOfRange(value))
+    }
+}
+
+/// Checks that a channel fits in 4 bits
+pub(crate) fn channel_nibble(channel: u8) -> Result<u8, DataOutOfRange> {
+    if channel <= 0x0F {
+        Ok(channel)
+    } else {
+        Err(DataOutOfRange(channel))
+    }
+}
+
+/// Metadata for a note's relative info. Including channel, key and velocity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct NoteMeta {
     /// Note key
-    key: u8,
+    pub(crate) key: u8,
     /// Note velocity
-    velocity: u8,
+    pub(crate) velocity: u8,
+}
+
+impl NoteMeta {
+    /// Creates a note's key and velocity, both of which must be in 0 to 127
+    pub fn new(key: u8, velocity: u8) -> Result<Self, DataOutOfRange> {
+        Ok(Self {
+            key: data_byte(key)?,
+            velocity: data_byte(velocity)?,
+        })
+    }
+
+    /// Creates a note's key and velocity, which can't be out of range
+    pub fn from_u7(key: U7, velocity: U7) -> Self {
+        Self {
+            key: key.0,
+            velocity: velocity.0,
+        }
+    }
+
+    /// The note's key, where 60 is middle C
+    pub fn key(&self) -> u8 {
+        self.key
+    }
+
+    /// The note's velocity
+    pub fn velocity(&self) -> u8 {
+        self.velocity
+    }
+}
+
+impl core::fmt::Display for NoteMeta {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write![f, "key {} velocity {}", self.key, self.velocity]
+    }
 }
 
 impl MidiWriteable for NoteMeta {
@@ -172,13 +592,56 @@ impl MidiWriteable for NoteMeta {
 }
 
 /// Metadata for changing a controller
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ControlChange {
     /// Controller number
-    controller_number: u8,
+    pub(crate) controller_number: u8,
     /// New value
-    new_value: u8,
+    pub(crate) new_value: u8,
+}
+
+impl ControlChange {
+    /// Controller number for Channel Volume
+    pub const VOLUME: u8 = 7;
+    /// Controller number for Pan
+    pub const PAN: u8 = 10;
+    /// Controller number for Expression
+    pub const EXPRESSION: u8 = 11;
+    /// Controller number for the Sustain pedal
+    pub const SUSTAIN: u8 = 64;
+    /// Controller number for the Reset All Controllers channel mode message
+    pub const RESET_ALL_CONTROLLERS: u8 = 121;
+    /// Controller number for the All Notes Off channel mode message
+    pub const ALL_NOTES_OFF: u8 = 123;
+
+    /// Creates a controller change, with the controller number and value both in 0 to 127
+    pub fn new(controller: u8, value: u8) -> Result<Self, DataOutOfRange> {
+        Ok(Self {
+            controller_number: data_byte(controller)?,
+            new_value: data_byte(value)?,
+        })
+    }
+
+    /// The controller number being changed
+    pub fn controller(&self) -> u8 {
+        self.controller_number
+    }
+
+    /// The controller's new value
+    pub fn value(&self) -> u8 {
+        self.new_value
+    }
+}
+
+impl core::fmt::Display for ControlChange {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write![
+            f,
+            "controller {} value {}",
+            self.controller_number, self.new_value
+        ]
+    }
 }
 
 impl MidiWriteable for ControlChange {
@@ -189,9 +652,133 @@ impl MidiWriteable for ControlChange {
 
 #[cfg(test)]
 mod tests {
-    use crate::{chunk::track::event::UnsupportedStatusCode, writer::MidiWriteable};
+    use crate::{
+        chunk::track::{event::UnsupportedStatusCode, TrackError},
+        writer::MidiWriteable,
+    };
+
+    use super::{
+        BendOutOfRange, Channel, ControlChange, DataOutOfRange, IteratorWrapper, MidiEvent,
+        NoteMeta, PitchBend, U7,
+    };
 
-    use super::{IteratorWrapper, MidiEvent, NoteMeta};
+    #[test]
+    fn narrow_types_validate_clamp_and_mask() {
+        assert_eq!(U7::try_from(127).map(u8::from), Ok(127));
+        assert_eq!(U7::try_from(128), Err(DataOutOfRange(128)));
+        assert_eq!(U7::saturating(200), U7::MAX);
+        assert_eq!(U7::new_unchecked(0x90).get(), 0x10);
+        assert_eq!(Channel::try_from(15).map(u8::from), Ok(15));
+        assert_eq!(Channel::try_from(16), Err(DataOutOfRange(16)));
+        assert_eq!(Channel::saturating(40), Channel::MAX);
+        assert_eq!(Channel::new_unchecked(0x93).get(), 3);
+
+        let typed = MidiEvent::note_on(Channel::MAX, U7::new_unchecked(60), U7::MAX);
+        assert_eq!(typed.to_midi_bytes(), [0x9F, 60, 127]);
+        assert_eq!(MidiEvent::try_note_on(15, 60, 127), Ok(typed));
+        assert_eq!(MidiEvent::try_note_on(16, 60, 127), Err(DataOutOfRange(16)));
+        assert_eq!(MidiEvent::try_note_off(0, 128, 0), Err(DataOutOfRange(128)));
+        assert_eq!(
+            MidiEvent::try_control_change(2, ControlChange::SUSTAIN, 127),
+            Ok(MidiEvent::ControlChange(
+                2,
+                ControlChange::new(ControlChange::SUSTAIN, 127).unwrap()
+            ))
+        );
+        assert_eq!(
+            MidiEvent::try_control_change(2, 64, 255),
+            Err(DataOutOfRange(255))
+        );
+    }
+
+    #[test]
+    fn pitch_bends_convert_to_semitones() {
+        let step = 2.0 / 8192.0;
+        assert_eq!(PitchBend::CENTER.to_semitones(2.0), 0.0);
+        assert_eq!(PitchBend::new(0).unwrap().to_semitones(2.0), -2.0);
+        let max = PitchBend::new(PitchBend::MAX).unwrap();
+        assert!((max.to_semitones(2.0) - 2.0).abs() <= step);
+
+        assert_eq!(PitchBend::from_semitones(0.0, 12.0), Ok(PitchBend::CENTER));
+        assert_eq!(PitchBend::from_semitones(2.0, 2.0), Ok(max));
+        assert_eq!(PitchBend::from_semitones(-2.0, 2.0).unwrap().value(), 0);
+        assert_eq!(PitchBend::from_semitones(1.0, 2.0).unwrap().value(), 0x3000);
+        assert_eq!(
+            PitchBend::from_semitones(2.5, 2.0),
+            Err(BendOutOfRange {
+                semitones: 2.5,
+                range: 2.0
+            })
+        );
+        assert!(PitchBend::from_semitones(f32::NAN, 2.0).is_err());
+
+        let bend = MidiEvent::PitchWheelChange(0, 0x3000);
+        assert_eq!(bend.pitch_bend().unwrap().to_semitones(12.0), 6.0);
+        assert_eq!(PitchBend::new(0x4000), Err(DataOutOfRange(0x40)));
+    }
+
+    #[test]
+    fn program_change_and_channel_pressure_constructors_validate() {
+        let program = MidiEvent::try_program_change(3, 40).unwrap();
+        assert_eq!(
+            program,
+            MidiEvent::program_change(Channel::new_unchecked(3), U7::new_unchecked(40))
+        );
+        assert_eq!(
+            program,
+            MidiEvent::ProgramChange {
+                channel: 3,
+                program: 40
+            }
+        );
+        assert_eq!((program.channel(), program.program()), (3, Some(40)));
+        assert_eq!(program.pressure(), None);
+        assert_eq!(program.to_midi_bytes(), [0xC3, 40]);
+        assert_eq!(
+            MidiEvent::try_program_change(16, 0),
+            Err(DataOutOfRange(16))
+        );
+        assert_eq!(
+            MidiEvent::try_program_change(0, 128),
+            Err(DataOutOfRange(128))
+        );
+
+        let pressure = MidiEvent::try_channel_pressure(9, 127).unwrap();
+        assert_eq!(
+            pressure,
+            MidiEvent::channel_pressure(Channel::new_unchecked(9), U7::MAX)
+        );
+        assert_eq!((pressure.channel(), pressure.pressure()), (9, Some(127)));
+        assert_eq!(pressure.to_midi_bytes(), [0xD9, 127]);
+        assert_eq!(
+            MidiEvent::try_from(IteratorWrapper(&mut [0xD9, 127].into_iter())),
+            Ok(pressure)
+        );
+        assert_eq!(
+            MidiEvent::try_channel_pressure(0, 0x80),
+            Err(DataOutOfRange(0x80))
+        );
+    }
+
+    #[test]
+    fn note_meta_and_control_change_constructors_validate() {
+        let note = NoteMeta::new(60, 127).unwrap();
+        assert_eq!((note.key(), note.velocity()), (60, 127));
+        assert_eq!(note.to_string(), "key 60 velocity 127");
+        assert_eq!(NoteMeta::new(128, 0), Err(DataOutOfRange(128)));
+        assert_eq!(NoteMeta::new(0, 200), Err(DataOutOfRange(200)));
+
+        let cc = ControlChange::new(7, 0).unwrap();
+        assert_eq!((cc.controller(), cc.value()), (7, 0));
+        assert_eq!(cc.to_string(), "controller 7 value 0");
+        assert_eq!(ControlChange::new(0x80, 0), Err(DataOutOfRange(0x80)));
+        assert_eq!(ControlChange::new(0, 0xFF), Err(DataOutOfRange(0xFF)));
+
+        let keys: std::collections::HashSet<_> = [note, note, NoteMeta::new(61, 127).unwrap()]
+            .into_iter()
+            .collect();
+        assert_eq!(keys.len(), 2);
+    }
 
     #[test]
     fn midi_event_status_parsing() {
@@ -216,7 +803,93 @@ mod tests {
 
         let mut stream = [status_channel, key, velocity].into_iter();
         let status = MidiEvent::try_from(IteratorWrapper(&mut stream));
-        assert_eq!(status, Err(UnsupportedStatusCode(0b0010)));
+        assert_eq!(
+            status,
+            Err(TrackError::UnsupportedStatusCode(UnsupportedStatusCode(
+                0b0010
+            )))
+        );
+    }
+
+    #[test]
+    fn every_message_round_trips_on_every_channel() {
+        const DATA: [u8; 5] = [0, 1, 0x40, 0x7E, 0x7F];
+        const BENDS: [u16; 7] = [0, 1, 0x7F, 0x80, 0x2000, 0x3F80, 0x3FFF];
+
+        for channel in 0..16 {
+            let mut events = vec![];
+            for a in DATA {
+                for b in DATA {
+                    let note = NoteMeta {
+                        key: a,
+                        velocity: b,
+                    };
+                    events.push(MidiEvent::NoteOff(channel, note));
+                    events.push(MidiEvent::NoteOn(channel, note));
+                    events.push(MidiEvent::PolyphonicKeyPressure(channel, note));
+                    events.push(MidiEvent::ControlChange(
+                        channel,
+                        ControlChange {
+                            controller_number: a,
+                            new_value: b,
+                        },
+                    ));
+                }
+                events.push(MidiEvent::ProgramChange {
+                    channel,
+                    program: a,
+                });
+                events.push(MidiEvent::ChannelPressure {
+                    channel,
+                    pressure: a,
+                });
+            }
+            events.extend(BENDS.map(|bend| MidiEvent::PitchWheelChange(channel, bend)));
+
+            for event in events {
+                let bytes = event.to_midi_bytes();
+                assert_eq!(bytes[0] & 0x0F, channel);
+                assert!(bytes[1..].iter().all(|byte| *byte <= 0x7F), "{event:?}");
+
+                let mut stream = bytes.into_iter();
+                assert_eq!(MidiEvent::parse(&mut stream), Ok(event));
+                assert_eq!(stream.next(), None);
+            }
+        }
+    }
+
+    #[test]
+    fn status_and_pitch_bend_bytes_follow_the_spec() {
+        let note = NoteMeta {
+            key: 60,
+            velocity: 100,
+        };
+        assert_eq!(MidiEvent::NoteOff(0, note).to_midi_bytes(), [0x80, 60, 100]);
+        assert_eq!(MidiEvent::NoteOn(9, note).to_midi_bytes(), [0x99, 60, 100]);
+        assert_eq!(
+            MidiEvent::PolyphonicKeyPressure(15, note).to_midi_bytes(),
+            [0xAF, 60, 100]
+        );
+
+        // Centre, minimum and maximum bends, least significant 7 bits first
+        assert_eq!(
+            MidiEvent::PitchWheelChange(2, 0x2000).to_midi_bytes(),
+            [0xE2, 0x00, 0x40]
+        );
+        assert_eq!(
+            MidiEvent::PitchWheelChange(0, 0).to_midi_bytes(),
+            [0xE0, 0x00, 0x00]
+        );
+        assert_eq!(
+            MidiEvent::PitchWheelChange(0, 0x3FFF).to_midi_bytes(),
+            [0xE0, 0x7F, 0x7F]
+        );
+
+        // An out of range channel can't change the message kind
+        assert_eq!(
+            MidiEvent::NoteOff(0x13, note).get_status_channel_combo(),
+            0x83
+        );
     }
 
     #[test]
@@ -226,7 +899,7 @@ mod tests {
 
         let expected = MidiEvent::NoteOff(0x0F, NoteMeta { key, velocity });
 
-        let mut stream = expected.clone().to_midi_bytes().into_iter();
+        let mut stream = expected.to_midi_bytes().into_iter();
         let bytes =
             MidiEvent::try_from(IteratorWrapper(&mut stream)).expect("Parse from serialized bytes");
 
@@ -0,0 +1,413 @@
+//! Payload-free discriminants of events, for filtering and counting events by kind. Both enums
+//! are `#[non_exhaustive]` so new kinds can be added without breaking downstream matches, and
+//! parse from and display as the names used on command lines, such as `note_on` or `meta:lyric`
+
+use core::str::FromStr;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::{event::MidiEvent, meta::MetaEvent, Event, TrackChunk};
+use crate::time::DeltaTimeOverflow;
+
+/// The kind of a [`MetaEvent`], without its payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum MetaKind {
+    /// [`MetaEvent::SequenceNumber`]
+    SequenceNumber,
+    /// [`MetaEvent::Text`]
+    Text,
+    /// [`MetaEvent::Copyright`]
+    Copyright,
+    /// [`MetaEvent::TrackName`]
+    TrackName,
+    /// [`MetaEvent::InstrumentName`]
+    InstrumentName,
+    /// [`MetaEvent::Lyric`]
+    Lyric,
+    /// [`MetaEvent::Marker`]
+    Marker,
+    /// [`MetaEvent::CuePoint`]
+    CuePoint,
+    /// [`MetaEvent::MidiChannelPrefix`]
+    MidiChannelPrefix,
+    /// [`MetaEvent::EndOfTrack`]
+    EndOfTrack,
+    /// [`MetaEvent::Tempo`]
+    Tempo,
+    /// [`MetaEvent::SmpteOffset`]
+    SmpteOffset,
+    /// [`MetaEvent::TimeSignature`]
+    TimeSignature,
+    /// [`MetaEvent::KeySignature`]
+    KeySignature,
+    /// [`MetaEvent::SequencerSpecific`]
+    SequencerSpecific,
+    /// [`MetaEvent::UnknownRaw`]
+    Unknown,
+}
+
+/// Every meta kind alongside the name it displays and parses as
+const META_NAMES: [(MetaKind, &str); 16] = [
+    (MetaKind::SequenceNumber, "sequence_number"),
+    (MetaKind::Text, "text"),
+    (MetaKind::Copyright, "copyright"),
+    (MetaKind::TrackName, "track_name"),
+    (MetaKind::InstrumentName, "instrument_name"),
+    (MetaKind::Lyric, "lyric"),
+    (MetaKind::Marker, "marker"),
+    (MetaKind::CuePoint, "cue_point"),
+    (MetaKind::MidiChannelPrefix, "channel_prefix"),
+    (MetaKind::EndOfTrack, "end_of_track"),
+    (MetaKind::Tempo, "tempo"),
+    (MetaKind::SmpteOffset, "smpte_offset"),
+    (MetaKind::TimeSignature, "time_signature"),
+    (MetaKind::KeySignature, "key_signature"),
+    (MetaKind::SequencerSpecific, "sequencer_specific"),
+    (MetaKind::Unknown, "unknown"),
+];
+
+/// The kind of an [`Event`], without its payload or channel. With the `serde` feature it
+/// serializes as its name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(into = "String", try_from = "String")
+)]
+#[non_exhaustive]
+pub enum EventKind {
+    /// [`MidiEvent::NoteOff`]
+    NoteOff,
+    /// [`MidiEvent::NoteOn`], including a velocity of 0
+    NoteOn,
+    /// [`MidiEvent::PolyphonicKeyPressure`]
+    PolyphonicKeyPressure,
+    /// [`MidiEvent::ControlChange`]
+    ControlChange,
+    /// [`MidiEvent::ProgramChange`]
+    ProgramChange,
+    /// [`MidiEvent::ChannelPressure`]
+    ChannelPressure,
+    /// [`MidiEvent::PitchWheelChange`]
+    PitchWheelChange,
+    /// [`Event::SysexEvent`]
+    Sysex,
+    /// [`Event::MetaEvent`] of the given kind
+    Meta(MetaKind),
+}
+
+/// Every kind of event other than meta events alongside the name it displays and parses as
+const EVENT_NAMES: [(EventKind, &str); 8] = [
+    (EventKind::NoteOff, "note_off"),
+    (EventKind::NoteOn, "note_on"),
+    (EventKind::PolyphonicKeyPressure, "poly_pressure"),
+    (EventKind::ControlChange, "control_change"),
+    (EventKind::ProgramChange, "program_change"),
+    (EventKind::ChannelPressure, "channel_pressure"),
+    (EventKind::PitchWheelChange, "pitch_bend"),
+    (EventKind::Sysex, "sysex"),
+];
+
+/// Prefix of the name of an [`EventKind::Meta`]
+const META_PREFIX: &str = "meta:";
+
+/// Error parsing a name that isn't any [`EventKind`] or [`MetaKind`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownKind(pub String);
+
+impl core::error::Error for UnknownKind {}
+impl core::fmt::Display for UnknownKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write![f, "Unknown event kind {:?}", self.0]
+    }
+}
+
+impl core::fmt::Display for MetaKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let (_, name) = META_NAMES
+            .iter()
+            .find(|(kind, _)| kind == self)
+            .expect("Every meta kind is named");
+        write![f, "{name}"]
+    }
+}
+
+impl FromStr for MetaKind {
+    type Err = UnknownKind;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        META_NAMES
+            .iter()
+            .find(|(_, name)| *name == s)
+            .map(|(kind, _)| *kind)
+            .ok_or_else(|| UnknownKind(s.into()))
+    }
+}
+
+impl core::fmt::Display for EventKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Meta(kind) => write![f, "{META_PREFIX}{kind}"],
+            _ => {
+                let (_, name) = EVENT_NAMES
+                    .iter()
+                    .find(|(kind, _)| kind == self)
+                    .expect("Every event kind is named");
+                write![f, "{name}"]
+            }
+        }
+    }
+}
+
+impl FromStr for EventKind {
+    type Err = UnknownKind;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(meta) = s.strip_prefix(META_PREFIX) {
+            return meta
+                .parse()
+                .map(Self::Meta)
+                .map_err(|_| UnknownKind(s.into()));
+        }
+
+        EVENT_NAMES
+            .iter()
+            .find(|(_, name)| *name == s)
+            .map(|(kind, _)| *kind)
+            .ok_or_else(|| UnknownKind(s.into()))
+    }
+}
+
+impl From<EventKind> for String {
+    fn from(value: EventKind) -> Self {
+        value.to_string()
+    }
+}
+
+impl TryFrom<String> for EventKind {
+    type Error = UnknownKind;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl MetaEvent {
+    /// The kind of meta event this is
+    pub fn kind(&self) -> MetaKind {
+        match self {
+            Self::SequenceNumber(_) => MetaKind::SequenceNumber,
+            Self::Text(_) => MetaKind::Text,
+            Self::Copyright(_) => MetaKind::Copyright,
+            Self::TrackName(_) => MetaKind::TrackName,
+            Self::InstrumentName(_) => MetaKind::InstrumentName,
+            Self::Lyric(_) => MetaKind::Lyric,
+            Self::Marker(_) => MetaKind::Marker,
+            Self::CuePoint(_) => MetaKind::CuePoint,
+            Self::MidiChannelPrefix(_) => MetaKind::MidiChannelPrefix,
+            Self::EndOfTrack => MetaKind::EndOfTrack,
+            Self::Tempo(_) => MetaKind::Tempo,
+            Self::SmpteOffset(_) => MetaKind::SmpteOffset,
+            Self::TimeSignature(_) => MetaKind::TimeSignature,
+            Self::KeySignature(_) => MetaKind::KeySignature,
+            Self::SequencerSpecific(_) => MetaKind::SequencerSpecific,
+            Self::UnknownRaw(..) => MetaKind::Unknown,
+        }
+    }
+}
+
+impl Event {
+    /// The kind of event this is
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Self::MidiEvent(event) => match event {
+                MidiEvent::NoteOff(..) => EventKind::NoteOff,
+                MidiEvent::NoteOn(..) => EventKind::NoteOn,
+                MidiEvent::PolyphonicKeyPressure(..) => EventKind::PolyphonicKeyPressure,
+                MidiEvent::ControlChange(..) => EventKind::ControlChange,
+                MidiEvent::ProgramChange { .. } => EventKind::ProgramChange,
+                MidiEvent::ChannelPressure { .. } => EventKind::ChannelPressure,
+                MidiEvent::PitchWheelChange(..) => EventKind::PitchWheelChange,
+            },
+            Self::SysexEvent(_) => EventKind::Sysex,
+            Self::MetaEvent(meta) => EventKind::Meta(meta.kind()),
+        }
+    }
+}
+
+impl TrackChunk {
+    /// Keeps only the events of the given kinds, like [`TrackChunk::retain_events`]. The track's
+    /// `EndOfTrack` is always kept
+    pub fn retain_kinds(&mut self, keep: &[EventKind]) -> Result<(), DeltaTimeOverflow> {
+        self.retain_events(|event| {
+            matches!(event, Event::MetaEvent(MetaEvent::EndOfTrack)) || keep.contains(&event.kind())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EventKind, MetaKind, UnknownKind, EVENT_NAMES, META_NAMES};
+    use crate::chunk::track::{
+        event::{ControlChange, MidiEvent, NoteMeta},
+        meta::{KeySignature, MetaEvent, SmpteOffset, TimeSignature},
+        sysex::{ManufactureId, SysexEvent},
+        Event, TrackChunk,
+    };
+
+    #[test]
+    fn every_variant_has_its_kind() {
+        let note = NoteMeta {
+            key: 60,
+            velocity: 100,
+        };
+        let midi = [
+            (MidiEvent::NoteOff(0, note), EventKind::NoteOff),
+            (MidiEvent::NoteOn(0, note), EventKind::NoteOn),
+            (
+                MidiEvent::PolyphonicKeyPressure(0, note),
+                EventKind::PolyphonicKeyPressure,
+            ),
+            (
+                MidiEvent::ControlChange(0, ControlChange::new(1, 2).unwrap()),
+                EventKind::ControlChange,
+            ),
+            (
+                MidiEvent::ProgramChange {
+                    channel: 0,
+                    program: 3,
+                },
+                EventKind::ProgramChange,
+            ),
+            (
+                MidiEvent::ChannelPressure {
+                    channel: 0,
+                    pressure: 4,
+                },
+                EventKind::ChannelPressure,
+            ),
+            (
+                MidiEvent::PitchWheelChange(0, 0x2000),
+                EventKind::PitchWheelChange,
+            ),
+        ];
+        for (event, kind) in midi {
+            assert_eq!(Event::MidiEvent(event).kind(), kind);
+        }
+
+        let sysex = Event::SysexEvent(SysexEvent {
+            manufacture_id: ManufactureId::OneByte(0x41),
+            payload: vec![0x10].into(),
+        });
+        assert_eq!(sysex.kind(), EventKind::Sysex);
+
+        let meta = [
+            (MetaEvent::SequenceNumber(1), MetaKind::SequenceNumber),
+            (MetaEvent::Text("a".into()), MetaKind::Text),
+            (MetaEvent::Copyright("a".into()), MetaKind::Copyright),
+            (MetaEvent::TrackName("a".into()), MetaKind::TrackName),
+            (
+                MetaEvent::InstrumentName("a".into()),
+                MetaKind::InstrumentName,
+            ),
+            (MetaEvent::Lyric("a".into()), MetaKind::Lyric),
+            (MetaEvent::Marker("a".into()), MetaKind::Marker),
+            (MetaEvent::CuePoint(vec![b'a'].into()), MetaKind::CuePoint),
+            (MetaEvent::MidiChannelPrefix(2), MetaKind::MidiChannelPrefix),
+            (MetaEvent::EndOfTrack, MetaKind::EndOfTrack),
+            (MetaEvent::DEFAULT_TEMPO, MetaKind::Tempo),
+            (
+                MetaEvent::SmpteOffset(SmpteOffset {
+                    hours: 1,
+                    minutes: 0,
+                    seconds: 0,
+                    frames: 0,
+                    subframes: 0,
+                }),
+                MetaKind::SmpteOffset,
+            ),
+            (
+                MetaEvent::TimeSignature(TimeSignature::default()),
+                MetaKind::TimeSignature,
+            ),
+            (
+                MetaEvent::KeySignature(KeySignature::default()),
+                MetaKind::KeySignature,
+            ),
+            (
+                MetaEvent::SequencerSpecific(vec![0x00].into()),
+                MetaKind::SequencerSpecific,
+            ),
+            (
+                MetaEvent::UnknownRaw(0x60, vec![0x00].into()),
+                MetaKind::Unknown,
+            ),
+        ];
+        assert_eq!(meta.len(), META_NAMES.len());
+        for (event, kind) in meta {
+            assert_eq!(event.kind(), kind);
+            assert_eq!(Event::MetaEvent(event).kind(), EventKind::Meta(kind));
+        }
+    }
+
+    #[test]
+    fn names_round_trip() {
+        let kinds = EVENT_NAMES
+            .iter()
+            .map(|(kind, _)| *kind)
+            .chain(META_NAMES.iter().map(|(kind, _)| EventKind::Meta(*kind)));
+        for kind in kinds {
+            assert_eq!(kind.to_string().parse(), Ok(kind));
+        }
+
+        assert_eq!("meta:lyric".parse(), Ok(EventKind::Meta(MetaKind::Lyric)));
+        assert_eq!("pitch_bend".parse(), Ok(EventKind::PitchWheelChange));
+        assert_eq!(
+            "meta:note_on".parse::<EventKind>(),
+            Err(UnknownKind("meta:note_on".into()))
+        );
+        assert_eq!(
+            "lyric".parse::<EventKind>(),
+            Err(UnknownKind("lyric".into()))
+        );
+    }
+
+    #[test]
+    fn retain_kinds_keeps_the_end_of_track() {
+        let mut track = TrackChunk::from_absolute_events([
+            (0, Event::MetaEvent(MetaEvent::TrackName("Vox".into()))),
+            (0, Event::MetaEvent(MetaEvent::Lyric("la".into()))),
+            (
+                240,
+                Event::MidiEvent(MidiEvent::NoteOn(
+                    0,
+                    NoteMeta {
+                        key: 60,
+                        velocity: 100,
+                    },
+                )),
+            ),
+            (480, Event::MetaEvent(MetaEvent::Lyric("lo".into()))),
+            (960, Event::MetaEvent(MetaEvent::EndOfTrack)),
+        ])
+        .unwrap();
+
+        track
+            .retain_kinds(&[EventKind::Meta(MetaKind::Lyric)])
+            .unwrap();
+        let kinds: Vec<_> = track
+            .absolute_events()
+            .map(|(tick, event)| (tick.get(), event.kind()))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (0, EventKind::Meta(MetaKind::Lyric)),
+                (480, EventKind::Meta(MetaKind::Lyric)),
+                (960, EventKind::Meta(MetaKind::EndOfTrack)),
+            ]
+        );
+    }
+}
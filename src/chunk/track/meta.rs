@@ -1,31 +1,41 @@
 //! Meta Event Structs and Parsing
 
-use super::{event::IteratorWrapper, TrackError};
-use crate::{chunk::track::MTrkEvent, reader::Yieldable, writer::MidiWriteable};
+use std::borrow::Cow;
+
+use super::{bytes::SmallBytes, event::IteratorWrapper, TrackError};
+use crate::{
+    vlq::{self, VlqError},
+    writer::MidiWriteable,
+};
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-/// A meta level event
+/// A meta level event.
+///
+/// The payloads of events this crate doesn't interpret, such as [`MetaEvent::SequencerSpecific`]
+/// and [`MetaEvent::UnknownRaw`], are kept and written back byte for byte. Payload lengths are
+/// always written in the shortest variable length encoding, so a length padded with leading
+/// `0x80` bytes parses fine but comes back shorter
 #[derive(Debug, Clone, PartialEq)]
-#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub enum MetaEvent {
     /// Sequence Number, tag 0x00
     SequenceNumber(u16),
     /// Text metadata, tag 0x01
-    Text(String),
+    Text(MetaText),
     /// Copyright, tag 0x02
-    Copyright(String),
+    Copyright(MetaText),
     /// Track name, tag 0x03
-    TrackName(String),
+    TrackName(MetaText),
     /// Instrucment name, tag 0x04
-    InstrumentName(String),
+    InstrumentName(MetaText),
     /// Lyric, tag 0x05
-    Lyric(String),
+    Lyric(MetaText),
     /// Marker, tag 0x06
-    Marker(String),
+    Marker(MetaText),
     /// Cue Point, tag 0x07
-    CuePoint(Vec<u8>),
+    CuePoint(SmallBytes),
     /// Midi Channel Prefix, tag 0x20
     MidiChannelPrefix(u8),
     /// End of Track Identifier, tag 0x2F
@@ -39,12 +49,167 @@ pub enum MetaEvent {
     /// Key Signature, tag 0x59
     KeySignature(KeySignature),
     /// Sequencer Specific, tag 0x7f
-    SequencerSpecific(Vec<u8>),
+    SequencerSpecific(SmallBytes),
     /// An unknown meta event
-    UnknownRaw(u8, Vec<u8>),
+    UnknownRaw(u8, SmallBytes),
+}
+
+/// The tag of the empty [`MetaEvent::UnknownRaw`] a meta event variant from a newer release of
+/// this crate deserializes into, since its data can't be recovered. No standard meta event uses it
+pub const UNKNOWN_VARIANT_TAG: u8 = 0x7E;
+
+/// How the bytes of text bearing meta events are decoded when parsing and encoded when writing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TextEncoding {
+    /// UTF-8, the only encoding strict parsing accepts
+    Utf8,
+    /// ISO-8859-1, where every byte is the code point of the same value
+    Latin1,
+    /// Bytes are passed through untouched, and only decoded lossily as UTF-8 for display
+    Raw,
+}
+
+/// The payload of a text bearing meta event. The original bytes are always kept alongside the
+/// encoding they're decoded with, so writing them back out is lossless
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct MetaText {
+    /// The text exactly as it appears in the file
+    bytes: SmallBytes,
+    /// Encoding used to decode `bytes`
+    encoding: TextEncoding,
+}
+
+impl MetaText {
+    /// Wraps raw text bytes that are in the given encoding
+    pub fn from_bytes(bytes: impl Into<SmallBytes>, encoding: TextEncoding) -> Self {
+        Self {
+            bytes: bytes.into(),
+            encoding,
+        }
+    }
+
+    /// The text's bytes, as they'll be written
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The encoding the bytes are decoded with
+    pub fn encoding(&self) -> TextEncoding {
+        self.encoding
+    }
+
+    /// Decodes the text, replacing anything that isn't valid in its encoding
+    pub fn text(&self) -> Cow<'_, str> {
+        match self.encoding {
+            TextEncoding::Utf8 | TextEncoding::Raw => String::from_utf8_lossy(&self.bytes),
+            TextEncoding::Latin1 => Cow::Owned(self.bytes.iter().map(|&b| b as char).collect()),
+        }
+    }
+
+    /// Re-encodes the text into another encoding. Characters Latin-1 can't represent become
+    /// `?`, and `Raw` keeps the current bytes untouched
+    pub fn encode(&self, encoding: TextEncoding) -> Self {
+        let bytes = match encoding {
+            TextEncoding::Raw => return self.clone(),
+            TextEncoding::Utf8 => self.text().as_bytes().into(),
+            TextEncoding::Latin1 => self
+                .text()
+                .chars()
+                .map(|c| u8::try_from(c).unwrap_or(b'?'))
+                .collect(),
+        };
+
+        Self { bytes, encoding }
+    }
+
+    /// Length of the bytes [`MetaText::encode`] would produce for the encoding, counted without
+    /// decoding into a new string
+    pub(crate) fn encoded_len(&self, encoding: TextEncoding) -> usize {
+        match (encoding, self.encoding) {
+            (TextEncoding::Raw, _) | (TextEncoding::Latin1, TextEncoding::Latin1) => {
+                self.bytes.len()
+            }
+            // Code points from 0x80 up take two bytes in UTF-8
+            (TextEncoding::Utf8, TextEncoding::Latin1) => self
+                .bytes
+                .iter()
+                .map(|&b| if b < 0x80 { 1 } else { 2 })
+                .sum(),
+            // Lossy decoding turns each invalid run into a single U+FFFD, which takes three
+            // bytes in UTF-8 and becomes a single `?` in Latin-1
+            (TextEncoding::Utf8, _) => self
+                .bytes
+                .utf8_chunks()
+                .map(|chunk| chunk.valid().len() + 3 * usize::from(!chunk.invalid().is_empty()))
+                .sum(),
+            (TextEncoding::Latin1, _) => self
+                .bytes
+                .utf8_chunks()
+                .map(|chunk| {
+                    chunk.valid().chars().count() + usize::from(!chunk.invalid().is_empty())
+                })
+                .sum(),
+        }
+    }
+}
+
+impl From<String> for MetaText {
+    fn from(value: String) -> Self {
+        Self::from_bytes(value.into_bytes(), TextEncoding::Utf8)
+    }
+}
+
+impl From<&str> for MetaText {
+    fn from(value: &str) -> Self {
+        value.to_string().into()
+    }
+}
+
+impl core::fmt::Display for MetaText {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write![f, "{}", self.text()]
+    }
+}
+
+impl MidiWriteable for MetaText {
+    fn to_midi_bytes(self) -> Vec<u8> {
+        self.bytes.into()
+    }
 }
 
 impl MetaEvent {
+    /// A tempo of 120 BPM, the tempo players assume before any tempo event, see
+    /// [`crate::tempo::DEFAULT_TEMPO`]
+    pub const DEFAULT_TEMPO: Self = Self::Tempo(crate::tempo::DEFAULT_TEMPO);
+
+    /// Returns the text of a text bearing event (text, copyright, names, lyrics and markers)
+    pub fn text(&self) -> Option<&MetaText> {
+        match self {
+            Self::Text(text)
+            | Self::Copyright(text)
+            | Self::TrackName(text)
+            | Self::InstrumentName(text)
+            | Self::Lyric(text)
+            | Self::Marker(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Mutable access to the text of a text bearing event
+    pub(crate) fn text_mut(&mut self) -> Option<&mut MetaText> {
+        match self {
+            Self::Text(text)
+            | Self::Copyright(text)
+            | Self::TrackName(text)
+            | Self::InstrumentName(text)
+            | Self::Lyric(text)
+            | Self::Marker(text) => Some(text),
+            _ => None,
+        }
+    }
+
     /// Returns the specific event's tag
     pub fn get_tag(&self) -> u8 {
         match self {
@@ -81,7 +246,7 @@ impl MidiWriteable for MetaEvent {
             Self::InstrumentName(val) => val.to_midi_bytes(),
             Self::Lyric(val) => val.to_midi_bytes(),
             Self::Marker(val) => val.to_midi_bytes(),
-            Self::CuePoint(val) => val,
+            Self::CuePoint(val) => val.into(),
             Self::MidiChannelPrefix(val) => val.to_midi_bytes(),
             Self::EndOfTrack => vec![],
             Self::Tempo(val) => {
@@ -91,28 +256,63 @@ impl MidiWriteable for MetaEvent {
             Self::SmpteOffset(val) => val.to_midi_bytes(),
             Self::TimeSignature(val) => val.to_midi_bytes(),
             Self::KeySignature(val) => val.to_midi_bytes(),
-            Self::SequencerSpecific(val) => val,
-            Self::UnknownRaw(_, val) => val,
+            Self::SequencerSpecific(val) => val.into(),
+            Self::UnknownRaw(_, val) => val.into(),
         };
 
-        let length = payload_bytes.len() as u32;
-        let len_vlq = MTrkEvent::to_midi_vlq(length);
-
-        bytes.extend(len_vlq.iter());
+        vlq::write(payload_bytes.len() as u32, &mut bytes);
         bytes.extend(payload_bytes.iter());
 
         bytes
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+impl MetaEvent {
+    /// Number of bytes the event takes up once written with its text in the given encoding,
+    /// counting the 0xFF prefix, tag and length
+    pub(crate) fn encoded_len(&self, encoding: TextEncoding) -> usize {
+        let payload = match self {
+            Self::SequenceNumber(_) | Self::KeySignature(_) => 2,
+            Self::Text(text)
+            | Self::Copyright(text)
+            | Self::TrackName(text)
+            | Self::InstrumentName(text)
+            | Self::Lyric(text)
+            | Self::Marker(text) => text.encoded_len(encoding),
+            Self::CuePoint(bytes) | Self::SequencerSpecific(bytes) | Self::UnknownRaw(_, bytes) => {
+                bytes.len()
+            }
+            Self::MidiChannelPrefix(_) => 1,
+            Self::EndOfTrack => 0,
+            Self::Tempo(_) => 3,
+            Self::SmpteOffset(_) => 5,
+            Self::TimeSignature(_) => 4,
+        };
+
+        2 + vlq::encode(payload as u32).len() + payload
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-/// A key signature
+/// A key signature, defaulting to C major
 pub struct KeySignature {
     /// Sharps and flats
-    sharps_flats: i8,
+    pub(crate) sharps_flats: i8,
     /// True if in major false if in minor
-    major_minor: bool,
+    pub(crate) major_minor: bool,
+}
+
+impl KeySignature {
+    /// Number of sharps, or flats if negative
+    pub fn sharps_flats(&self) -> i8 {
+        self.sharps_flats
+    }
+
+    /// True for a major key, false for a minor one
+    pub fn is_major(&self) -> bool {
+        !self.major_minor
+    }
 }
 
 impl MidiWriteable for KeySignature {
@@ -142,15 +342,15 @@ impl MidiWriteable for KeySignature {
 /// An SMPTE Offset
 pub struct SmpteOffset {
     /// Hours of offset
-    hours: u8,
+    pub(crate) hours: u8,
     /// Minutes of offset
-    minutes: u8,
+    pub(crate) minutes: u8,
     /// Seconds of offset
-    seconds: u8,
+    pub(crate) seconds: u8,
     /// Frames of offset
-    frames: u8,
+    pub(crate) frames: u8,
     /// Subframes of offset
-    subframes: u8,
+    pub(crate) subframes: u8,
 }
 
 impl MidiWriteable for SmpteOffset {
@@ -171,13 +371,47 @@ impl MidiWriteable for SmpteOffset {
 /// A Time Signature
 pub struct TimeSignature {
     /// The time signature's numerator
-    numerator: u8,
+    pub(crate) numerator: u8,
     /// The time signature's denominator
-    denominator: u32,
+    pub(crate) denominator: u32,
     /// Clocks per tick
-    clocks_per_tick: u8,
+    pub(crate) clocks_per_tick: u8,
     /// Thirty second notes per quarter
-    thirty_second_notes_per_quarter: u8,
+    pub(crate) thirty_second_notes_per_quarter: u8,
+}
+
+impl Default for TimeSignature {
+    /// Common time, 4/4 with a metronome click every quarter note
+    fn default() -> Self {
+        Self {
+            numerator: 4,
+            denominator: 4,
+            clocks_per_tick: 24,
+            thirty_second_notes_per_quarter: 8,
+        }
+    }
+}
+
+impl TimeSignature {
+    /// Beats in a bar
+    pub fn numerator(&self) -> u8 {
+        self.numerator
+    }
+
+    /// Note value of a beat, 4 for quarter notes, 8 for eighths
+    pub fn denominator(&self) -> u32 {
+        self.denominator
+    }
+
+    /// MIDI clocks between metronome clicks
+    pub fn clocks_per_tick(&self) -> u8 {
+        self.clocks_per_tick
+    }
+
+    /// Thirty second notes in a MIDI quarter note, normally 8
+    pub fn thirty_second_notes_per_quarter(&self) -> u8 {
+        self.thirty_second_notes_per_quarter
+    }
 }
 
 impl MidiWriteable for TimeSignature {
@@ -188,11 +422,15 @@ impl MidiWriteable for TimeSignature {
             clocks_per_tick,
             thirty_second_notes_per_quarter,
         } = self;
-        let mut bytes = vec![numerator];
-        bytes.extend(denominator.to_midi_bytes().iter());
-        bytes.extend([clocks_per_tick, thirty_second_notes_per_quarter]);
+        // The denominator is stored as a power of two, the way it's parsed
+        let power = denominator.trailing_zeros() as u8;
 
-        bytes
+        vec![
+            numerator,
+            power,
+            clocks_per_tick,
+            thirty_second_notes_per_quarter,
+        ]
     }
 }
 
@@ -202,77 +440,115 @@ where
 {
     type Error = TrackError;
     fn try_from(value: IteratorWrapper<&mut ITER>) -> Result<Self, Self::Error> {
-        let prefix = value.0.next().ok_or(TrackError::OutOfSpace)?;
+        Self::parse(value.0, None, u32::MAX)
+    }
+}
+
+impl MetaEvent {
+    /// Parses a meta event. With no `text_encoding` text must be valid UTF-8, otherwise any bytes
+    /// are accepted and tagged with the given encoding. A declared length above `max_payload`
+    /// fails before any of the payload is read
+    pub(crate) fn parse<ITER: Iterator<Item = u8>>(
+        iter: &mut ITER,
+        text_encoding: Option<TextEncoding>,
+        max_payload: u32,
+    ) -> Result<Self, TrackError> {
+        let prefix = iter.next().ok_or(TrackError::OutOfSpace)?;
         if prefix != 0xFF {
             return Err(TrackError::InvalidMetaEventData);
         }
 
-        let event_tag = value.0.next().ok_or(TrackError::OutOfSpace)?;
+        let event_tag = iter.next().ok_or(TrackError::OutOfSpace)?;
+
+        let length = vlq::read(iter).map_err(|err| match err {
+            VlqError::TooLong => TrackError::InvalidMetaEventData,
+            err => err.into(),
+        })?;
+        if length > max_payload {
+            return Err(TrackError::EventTooLarge {
+                declared: length,
+                limit: max_payload,
+            });
+        }
 
-        let length = MTrkEvent::try_get_delta_time(value.0).ok_or(TrackError::OutOfSpace)?;
+        let data = SmallBytes::take(iter, length as usize);
 
-        let data = value.0.get(length as usize);
+        let text = |data: SmallBytes| -> Result<MetaText, TrackError> {
+            match text_encoding {
+                Some(encoding) => Ok(MetaText::from_bytes(data, encoding)),
+                None if core::str::from_utf8(&data).is_ok() => {
+                    Ok(MetaText::from_bytes(data, TextEncoding::Utf8))
+                }
+                // Only invalid text pays for the copy needed to build the error
+                None => Err(String::from_utf8(data.into())
+                    .err()
+                    .map_or(TrackError::InvalidMetaEventData, TrackError::from)),
+            }
+        };
 
+        // Fixed size events destructure their payload, so a wrong length is an error rather than
+        // an out of bounds index
         macro_rules! meta_event {
-            ($len: expr_2021, $name: expr_2021, $value: expr_2021) => {{
-                if data.len() != $len {
-                    return Err(TrackError::InvalidMetaEventData);
+            (|[$($byte: ident),+]| $value: expr_2021) => {{
+                match data[..] {
+                    [$($byte),+] => Ok($value),
+                    _ => Err(TrackError::InvalidMetaEventData),
                 }
-                Ok($name($value))
             }};
         }
 
         match event_tag {
-            0x00 => meta_event!(
-                2,
-                MetaEvent::SequenceNumber,
-                u16::from_be_bytes([data[0], data[1]])
-            ),
-            0x01 => Ok(MetaEvent::Text(String::from_utf8(data)?)),
-            0x02 => Ok(MetaEvent::Copyright(String::from_utf8(data)?)),
-            0x03 => Ok(MetaEvent::TrackName(String::from_utf8(data)?)),
-            0x04 => Ok(MetaEvent::InstrumentName(String::from_utf8(data)?)),
-            0x05 => Ok(MetaEvent::Lyric(String::from_utf8(data)?)),
-            0x06 => Ok(MetaEvent::Marker(String::from_utf8(data)?)),
+            0x00 => {
+                meta_event!(|[msb, lsb]| MetaEvent::SequenceNumber(u16::from_be_bytes([msb, lsb])))
+            }
+            0x01 => Ok(MetaEvent::Text(text(data)?)),
+            0x02 => Ok(MetaEvent::Copyright(text(data)?)),
+            0x03 => Ok(MetaEvent::TrackName(text(data)?)),
+            0x04 => Ok(MetaEvent::InstrumentName(text(data)?)),
+            0x05 => Ok(MetaEvent::Lyric(text(data)?)),
+            0x06 => Ok(MetaEvent::Marker(text(data)?)),
             0x07 => Ok(MetaEvent::CuePoint(data)),
 
-            0x20 => meta_event!(1, MetaEvent::MidiChannelPrefix, data[0]),
+            0x20 => meta_event!(|[channel]| MetaEvent::MidiChannelPrefix(channel)),
             0x2F => Ok(MetaEvent::EndOfTrack),
 
-            0x51 => meta_event!(
-                3,
-                MetaEvent::Tempo,
-                ((data[0] as u32) << 16) | ((data[1] as u32) << 8) | (data[2] as u32)
-            ),
-            0x54 => meta_event!(
-                5,
-                MetaEvent::SmpteOffset,
-                SmpteOffset {
-                    hours: data[0],
-                    minutes: data[1],
-                    seconds: data[2],
-                    frames: data[3],
-                    subframes: data[4]
-                }
-            ),
-            0x58 => meta_event!(
-                4,
-                MetaEvent::TimeSignature,
-                TimeSignature {
-                    numerator: data[0],
-                    denominator: 2u32.pow(data[1] as u32),
-                    clocks_per_tick: data[2],
-                    thirty_second_notes_per_quarter: data[3],
-                }
-            ),
-            0x59 => meta_event!(
-                2,
-                MetaEvent::KeySignature,
+            0x51 => meta_event!(|[high, mid, low]| MetaEvent::Tempo(u32::from_be_bytes([
+                0, high, mid, low
+            ]))),
+            0x54 => {
+                meta_event!(
+                    |[hours, minutes, seconds, frames, subframes]| MetaEvent::SmpteOffset(
+                        SmpteOffset {
+                            hours,
+                            minutes,
+                            seconds,
+                            frames,
+                            subframes,
+                        }
+                    )
+                )
+            }
+            0x58 => {
+                meta_event!(
+                    |[numerator, power, clocks_per_tick, thirty_second_notes_per_quarter]| {
+                        MetaEvent::TimeSignature(TimeSignature {
+                            numerator,
+                            // The denominator is stored as a power of two, which only fits up to 2^31
+                            denominator: 2u32
+                                .checked_pow(power as u32)
+                                .ok_or(TrackError::InvalidMetaEventData)?,
+                            clocks_per_tick,
+                            thirty_second_notes_per_quarter,
+                        })
+                    }
+                )
+            }
+            0x59 => meta_event!(|[sharps_flats, major_minor]| MetaEvent::KeySignature(
                 KeySignature {
-                    sharps_flats: data[0] as i8,
-                    major_minor: data[1] != 0
+                    sharps_flats: sharps_flats as i8,
+                    major_minor: major_minor != 0,
                 }
-            ),
+            )),
 
             0x7F => Ok(MetaEvent::SequencerSpecific(data)),
 
@@ -286,12 +562,49 @@ mod tests {
     use crate::{
         chunk::track::{
             event::IteratorWrapper,
-            meta::{KeySignature, MetaEvent, SmpteOffset, TimeSignature},
+            meta::{KeySignature, MetaEvent, MetaText, SmpteOffset, TextEncoding, TimeSignature},
             TrackError,
         },
         writer::MidiWriteable,
     };
 
+    #[test]
+    fn meta_text_decodes_and_encodes() {
+        let latin1 = MetaText::from_bytes(vec![b'n', 0xE9, b'e'], TextEncoding::Latin1);
+        assert_eq!(latin1.text(), "née");
+        assert_eq!(latin1.encode(TextEncoding::Raw), latin1);
+
+        let utf8 = latin1.encode(TextEncoding::Utf8);
+        assert_eq!(utf8.as_bytes(), "née".as_bytes());
+        assert_eq!(utf8.encode(TextEncoding::Latin1), latin1);
+
+        let kana = MetaText::from("曲");
+        assert_eq!(kana.encode(TextEncoding::Latin1).as_bytes(), b"?");
+    }
+
+    #[test]
+    fn non_utf8_text_kept_as_bytes_when_lenient() {
+        let data = vec![0xFF, 0x03, 0x02, 0x93, 0xFA];
+        assert!(matches!(
+            MetaEvent::try_from(IteratorWrapper(&mut data.clone().into_iter())),
+            Err(TrackError::UtfParseError(_))
+        ));
+
+        let parsed = MetaEvent::parse(
+            &mut data.clone().into_iter(),
+            Some(TextEncoding::Raw),
+            u32::MAX,
+        );
+        assert_eq!(
+            parsed,
+            Ok(MetaEvent::TrackName(MetaText::from_bytes(
+                vec![0x93, 0xFA],
+                TextEncoding::Raw
+            )))
+        );
+        assert_eq!(parsed.unwrap().to_midi_bytes(), data);
+    }
+
     #[test]
     fn test_sequence_number() {
         let data = vec![0xFF, 0x00, 0x02, 0x00, 0x01]; // Tag: 0x00, Length: 2, Value: [0x00, 0x01]
@@ -303,7 +616,7 @@ mod tests {
     fn test_text_event() {
         let data = vec![0xFF, 0x01, 0x05, b'H', b'e', b'l', b'l', b'o']; // Tag: 0x01, Length: 5, Value: "Hello"
         let result = MetaEvent::try_from(IteratorWrapper(&mut data.into_iter())).unwrap();
-        assert_eq!(result, MetaEvent::Text("Hello".to_string()));
+        assert_eq!(result, MetaEvent::Text("Hello".into()));
     }
 
     #[test]
@@ -312,7 +625,7 @@ mod tests {
             0xFF, 0x02, 0x0A, b'C', b'o', b'p', b'y', b'r', b'i', b'g', b'h', b't',
         ];
         let result = MetaEvent::try_from(IteratorWrapper(&mut data.into_iter())).unwrap();
-        assert_eq!(result, MetaEvent::Copyright("Copyright".to_string()));
+        assert_eq!(result, MetaEvent::Copyright("Copyright".into()));
     }
 
     #[test]
@@ -325,7 +638,7 @@ mod tests {
     #[test]
     fn test_time_signature_event() {
         let data = vec![0xFF, 0x58, 0x04, 0x04, 0x02, 0x18, 0x08]; // Tag: 0x58, Length: 4
-        let result = MetaEvent::try_from(IteratorWrapper(&mut data.into_iter())).unwrap();
+        let result = MetaEvent::try_from(IteratorWrapper(&mut data.clone().into_iter())).unwrap();
         assert_eq!(
             result,
             MetaEvent::TimeSignature(TimeSignature {
@@ -335,6 +648,7 @@ mod tests {
                 thirty_second_notes_per_quarter: 8,
             })
         );
+        assert_eq!(result.to_midi_bytes(), data);
     }
 
     #[test]
@@ -377,7 +691,10 @@ mod tests {
     fn test_unknown_event() {
         let data = vec![0xFF, 0x99, 0x03, 0x01, 0x02, 0x03]; // Unknown Tag: 0x99
         let result = MetaEvent::try_from(IteratorWrapper(&mut data.into_iter())).unwrap();
-        assert_eq!(result, MetaEvent::UnknownRaw(0x99, vec![0x01, 0x02, 0x03]));
+        assert_eq!(
+            result,
+            MetaEvent::UnknownRaw(0x99, vec![0x01, 0x02, 0x03].into())
+        );
     }
 
     #[test]
@@ -394,6 +711,27 @@ mod tests {
         assert_eq!(result, Err(TrackError::OutOfSpace));
     }
 
+    #[test]
+    fn signatures_read_back_through_accessors() {
+        let parse = |bytes: Vec<u8>| MetaEvent::try_from(IteratorWrapper(&mut bytes.into_iter()));
+
+        let Ok(MetaEvent::TimeSignature(time)) = parse(vec![0xFF, 0x58, 0x04, 6, 3, 36, 8]) else {
+            panic!("expected a time signature");
+        };
+        assert_eq!(time.numerator(), 6);
+        assert_eq!(time.denominator(), 8);
+        assert_eq!(time.clocks_per_tick(), 36);
+        assert_eq!(time.thirty_second_notes_per_quarter(), 8);
+
+        let Ok(MetaEvent::KeySignature(key)) = parse(vec![0xFF, 0x59, 0x02, (-3i8) as u8, 1])
+        else {
+            panic!("expected a key signature");
+        };
+        assert_eq!(key.sharps_flats(), -3);
+        assert!(!key.is_major());
+        assert!(KeySignature::default().is_major());
+    }
+
     #[test]
     fn meta_event_backwards_parses_to_bytes() {
         let expected = MetaEvent::KeySignature(KeySignature {
@@ -431,43 +769,43 @@ mod tests {
 
     meta_event_test!(
         text_event,
-        MetaEvent::Text("Hello".to_string()),
+        MetaEvent::Text("Hello".into()),
         vec![0xFF, 0x01, 0x05, b'H', b'e', b'l', b'l', b'o']
     );
 
     meta_event_test!(
         copyright_event,
-        MetaEvent::Copyright("Copyright".to_string()),
+        MetaEvent::Copyright("Copyright".into()),
         vec![0xFF, 0x02, 0x09, b'C', b'o', b'p', b'y', b'r', b'i', b'g', b'h', b't']
     );
 
     meta_event_test!(
         track_name_event,
-        MetaEvent::TrackName("Track 1".to_string()),
+        MetaEvent::TrackName("Track 1".into()),
         vec![0xFF, 0x03, 0x07, b'T', b'r', b'a', b'c', b'k', b' ', b'1']
     );
 
     meta_event_test!(
         instrument_name_event,
-        MetaEvent::InstrumentName("Piano".to_string()),
+        MetaEvent::InstrumentName("Piano".into()),
         vec![0xFF, 0x04, 0x05, b'P', b'i', b'a', b'n', b'o']
     );
 
     meta_event_test!(
         lyric_event,
-        MetaEvent::Lyric("Lyrics".to_string()),
+        MetaEvent::Lyric("Lyrics".into()),
         vec![0xFF, 0x05, 0x06, b'L', b'y', b'r', b'i', b'c', b's']
     );
 
     meta_event_test!(
         marker_event,
-        MetaEvent::Marker("Marker".to_string()),
+        MetaEvent::Marker("Marker".into()),
         vec![0xFF, 0x06, 0x06, b'M', b'a', b'r', b'k', b'e', b'r']
     );
 
     meta_event_test!(
         cue_point_event,
-        MetaEvent::CuePoint(vec![0x01, 0x02]),
+        MetaEvent::CuePoint(vec![0x01, 0x02].into()),
         vec![0xFF, 0x07, 0x02, 0x01, 0x02]
     );
 
@@ -506,13 +844,35 @@ mod tests {
 
     meta_event_test!(
         sequencer_specific_event,
-        MetaEvent::SequencerSpecific(vec![0x01, 0x02, 0x03]),
+        MetaEvent::SequencerSpecific(vec![0x01, 0x02, 0x03].into()),
         vec![0xFF, 0x7F, 0x03, 0x01, 0x02, 0x03]
     );
 
     meta_event_test!(
         unknown_raw_event,
-        MetaEvent::UnknownRaw(0x99, vec![0x01, 0x02, 0x03]),
+        MetaEvent::UnknownRaw(0x99, vec![0x01, 0x02, 0x03].into()),
         vec![0xFF, 0x99, 0x03, 0x01, 0x02, 0x03]
     );
+
+    #[test]
+    fn raw_payloads_round_trip_with_minimal_lengths() {
+        let payload: Vec<u8> = (0..=255).collect();
+        for event in [
+            MetaEvent::UnknownRaw(0x60, payload.clone().into()),
+            MetaEvent::SequencerSpecific(payload.clone().into()),
+        ] {
+            let bytes = event.clone().to_midi_bytes();
+            assert_eq!(bytes[2..4], [0x82, 0x00]);
+            assert_eq!(bytes[4..], payload);
+
+            let parsed = MetaEvent::try_from(IteratorWrapper(&mut bytes.into_iter())).unwrap();
+            assert_eq!(parsed, event);
+        }
+
+        // A length padded to 3 bytes is read, and written back in 1
+        let padded = [0xFF, 0x60, 0x80, 0x80, 0x02, 0xAB, 0xCD];
+        let parsed = MetaEvent::try_from(IteratorWrapper(&mut padded.into_iter())).unwrap();
+        assert_eq!(parsed, MetaEvent::UnknownRaw(0x60, vec![0xAB, 0xCD].into()));
+        assert_eq!(parsed.to_midi_bytes(), [0xFF, 0x60, 0x02, 0xAB, 0xCD]);
+    }
 }
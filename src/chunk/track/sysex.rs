@@ -2,19 +2,21 @@
 
 use crate::writer::MidiWriteable;
 
-use super::{event::IteratorWrapper, TrackError};
+use super::{bytes::SmallBytes, event::IteratorWrapper, TrackError};
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
+pub mod universal;
+
 /// A midi system exclusize event message
 #[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SysexEvent {
     /// The manufacture ID of the System Exclusize message
-    manufacture_id: ManufactureId,
+    pub(crate) manufacture_id: ManufactureId,
     /// Data payload to be parsed on a per-system basis
-    payload: Vec<u8>,
+    pub(crate) payload: SmallBytes,
 }
 
 impl MidiWriteable for SysexEvent {
@@ -74,19 +76,46 @@ where
     ITER: Iterator<Item = u8>,
 {
     type Error = TrackError;
-    fn try_from(mut value: IteratorWrapper<&mut ITER>) -> Result<Self, Self::Error> {
+    fn try_from(value: IteratorWrapper<&mut ITER>) -> Result<Self, Self::Error> {
+        Self::parse(value.0, u32::MAX)
+    }
+}
+
+impl SysexEvent {
+    /// Number of bytes the event takes up once written, including the 0xF0 and 0xF7 around it
+    pub(crate) fn encoded_len(&self) -> usize {
+        let id = match self.manufacture_id {
+            ManufactureId::OneByte(_) => 1,
+            ManufactureId::ThreeByte(_) => 3,
+        };
+
+        2 + id + self.payload.len()
+    }
+
+    /// Parses a system exclusive event, failing with [`TrackError::EventTooLarge`] as soon as its
+    /// payload runs past `max_payload` bytes without an end of exclusive byte
+    pub(crate) fn parse<ITER: Iterator<Item = u8>>(
+        iter: &mut ITER,
+        max_payload: u32,
+    ) -> Result<Self, TrackError> {
+        let mut value = IteratorWrapper(iter);
         let prefix = value.0.next().ok_or(TrackError::OutOfSpace)?;
         if prefix != 0xF0 {
             return Err(TrackError::InvalidSysExMessage);
         }
 
         let manufacture_id = ManufactureId::try_from(&mut value)?;
-        let mut payload = vec![];
+        let mut payload = SmallBytes::new();
 
         loop {
             let byte = value.0.next().ok_or(TrackError::MissingEndOfExclusive)?;
             if byte == 0xF7 {
                 break;
+            } else if payload.len() as u32 == max_payload {
+                return Err(TrackError::EventTooLarge {
+                    declared: max_payload.saturating_add(1),
+                    limit: max_payload,
+                });
             } else {
                 payload.push(byte);
             }
@@ -143,7 +172,7 @@ mod tests {
         let sysex = SysexEvent::try_from(wrapper).expect("Parse sysex message from bytes");
         let expected = SysexEvent {
             manufacture_id: ManufactureId::OneByte(0x01),
-            payload: vec![0xFF, 0x00, 0x21],
+            payload: [0xFF, 0x00, 0x21].into(),
         };
 
         assert_eq!(sysex, expected)
@@ -174,4 +203,20 @@ mod tests {
 
         assert_eq!(sysex, expected)
     }
+
+    #[test]
+    fn payload_past_the_limit_is_rejected() {
+        let data = [0xF0, 0x01, 0x10, 0x20, 0x30, 0xF7];
+
+        let sysex = SysexEvent::parse(&mut data.into_iter(), 3).expect("Payload fits the limit");
+        assert_eq!(&sysex.payload[..], [0x10, 0x20, 0x30]);
+
+        assert_eq!(
+            SysexEvent::parse(&mut data.into_iter(), 2),
+            Err(TrackError::EventTooLarge {
+                declared: 3,
+                limit: 2
+            })
+        );
+    }
 }
@@ -0,0 +1,420 @@
+//! Decoding of Universal System Exclusive messages, the manufacturer independent messages sent
+//! with ID `0x7E` (non real time) or `0x7F` (real time)
+
+use crate::time::SmpteTime;
+
+use super::{ManufactureId, SysexEvent};
+
+/// Manufacturer ID of Universal Non Real Time messages
+pub const NON_REAL_TIME: u8 = 0x7E;
+/// Manufacturer ID of Universal Real Time messages
+pub const REAL_TIME: u8 = 0x7F;
+/// Real time sub-ID of MIDI Show Control
+const SHOW_CONTROL: u8 = 0x02;
+/// Real time sub-ID of MIDI Machine Control commands
+const MACHINE_CONTROL: u8 = 0x06;
+
+/// A Universal System Exclusive message, see [`SysexEvent::interpret`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UniversalSysex {
+    /// A MIDI Show Control message, for lighting, sound and stage machinery cues
+    ShowControl {
+        /// Device the message is addressed to, with `0x7F` meaning all devices
+        device: u8,
+        /// Kind of equipment addressed, such as `0x01` for lighting or `0x10` for sound
+        command_format: u8,
+        /// The command
+        command: MscCommand,
+    },
+    /// A MIDI Machine Control command, for transport control of tape machines and recorders
+    MachineControl {
+        /// Device the message is addressed to, with `0x7F` meaning all devices
+        device: u8,
+        /// The command
+        command: MmcCommand,
+    },
+    /// A universal message that isn't decoded
+    Raw {
+        /// Whether this is a real time message, rather than non real time
+        real_time: bool,
+        /// Device the message is addressed to, with `0x7F` meaning all devices
+        device: u8,
+        /// The first sub-ID, naming the kind of message
+        sub_id: u8,
+        /// Everything after the first sub-ID
+        data: Vec<u8>,
+    },
+}
+
+/// A cue number as sent by MIDI Show Control, with each part kept as the ASCII digits and
+/// decimal points it was sent as, such as `"235.6"`. Parts that weren't sent are `None`, and a
+/// command with no cue number applies to the current or next cue
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CueNumber {
+    /// The cue itself
+    pub number: Option<String>,
+    /// The cue list the cue belongs to
+    pub list: Option<String>,
+    /// The cue path the list belongs to
+    pub path: Option<String>,
+}
+
+impl CueNumber {
+    /// Parses up to three `0x00` separated parts made of ASCII digits and decimal points,
+    /// returning `None` if anything else is found
+    fn parse(data: &[u8]) -> Option<Self> {
+        if data.is_empty() {
+            return Some(Self::default());
+        }
+
+        let mut parts = vec![];
+        for part in data.split(|byte| *byte == 0x00) {
+            if !part
+                .iter()
+                .all(|byte| byte.is_ascii_digit() || *byte == b'.')
+            {
+                return None;
+            }
+            parts.push(String::from_utf8_lossy(part).into_owned());
+        }
+        if parts.len() > 3 {
+            return None;
+        }
+
+        let mut parts = parts.into_iter();
+        Some(Self {
+            number: parts.next(),
+            list: parts.next(),
+            path: parts.next(),
+        })
+    }
+}
+
+/// A MIDI Show Control command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MscCommand {
+    /// Starts a cue
+    Go(CueNumber),
+    /// Stops a running cue
+    Stop(CueNumber),
+    /// Resumes a stopped cue
+    Resume(CueNumber),
+    /// Any other command, or one of the above with a malformed cue number
+    Other {
+        /// The command byte
+        command: u8,
+        /// Everything after the command byte
+        data: Vec<u8>,
+    },
+}
+
+impl MscCommand {
+    /// Decodes a command from its command byte and data
+    fn parse(command: u8, data: &[u8]) -> Self {
+        let cue = match command {
+            0x01 => CueNumber::parse(data).map(Self::Go),
+            0x02 => CueNumber::parse(data).map(Self::Stop),
+            0x03 => CueNumber::parse(data).map(Self::Resume),
+            _ => None,
+        };
+
+        cue.unwrap_or_else(|| Self::Other {
+            command,
+            data: data.to_vec(),
+        })
+    }
+}
+
+/// The frame rate an MMC or MIDI Time Code position is counted in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimecodeRate {
+    /// 24 frames per second
+    Fps24,
+    /// 25 frames per second
+    Fps25,
+    /// 29.97 frames per second drop-frame
+    Fps30Drop,
+    /// 30 frames per second
+    Fps30,
+}
+
+/// A MIDI Machine Control command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MmcCommand {
+    /// Stops playback
+    Stop,
+    /// Starts playback
+    Play,
+    /// Starts playback once the current locate finishes
+    DeferredPlay,
+    /// Winds forward
+    FastForward,
+    /// Winds backward
+    Rewind,
+    /// Punches in to record
+    RecordStrobe,
+    /// Punches out of recording
+    RecordExit,
+    /// Pauses recording
+    RecordPause,
+    /// Pauses playback
+    Pause,
+    /// Ejects the media
+    Eject,
+    /// Follows an external time code
+    Chase,
+    /// Resets the device's MMC state
+    Reset,
+    /// Moves to a time code position
+    Locate {
+        /// Frame rate the position is counted in
+        rate: TimecodeRate,
+        /// The position, with subframes in hundredths of a frame
+        target: SmpteTime,
+    },
+    /// Any other command, or a message holding more than one command
+    Other {
+        /// The first command byte
+        command: u8,
+        /// Everything after the command byte
+        data: Vec<u8>,
+    },
+}
+
+impl MmcCommand {
+    /// Locate sub-command that moves to an explicit time code
+    const LOCATE_TARGET: u8 = 0x01;
+
+    /// Decodes a command from its command byte and data
+    fn parse(command: u8, data: &[u8]) -> Self {
+        let decoded = match (command, data) {
+            (0x01, []) => Some(Self::Stop),
+            (0x02, []) => Some(Self::Play),
+            (0x03, []) => Some(Self::DeferredPlay),
+            (0x04, []) => Some(Self::FastForward),
+            (0x05, []) => Some(Self::Rewind),
+            (0x06, []) => Some(Self::RecordStrobe),
+            (0x07, []) => Some(Self::RecordExit),
+            (0x08, []) => Some(Self::RecordPause),
+            (0x09, []) => Some(Self::Pause),
+            (0x0A, []) => Some(Self::Eject),
+            (0x0B, []) => Some(Self::Chase),
+            (0x0D, []) => Some(Self::Reset),
+            (0x44, &[0x06, Self::LOCATE_TARGET, hours, minutes, seconds, frames, subframes]) => {
+                // The hours byte is 0rrhhhhh, with the frame rate in the r bits
+                let rate = match (hours >> 5) & 0b11 {
+                    0 => TimecodeRate::Fps24,
+                    1 => TimecodeRate::Fps25,
+                    2 => TimecodeRate::Fps30Drop,
+                    _ => TimecodeRate::Fps30,
+                };
+
+                Some(Self::Locate {
+                    rate,
+                    target: SmpteTime {
+                        hours: (hours & 0x1F) as u64,
+                        minutes: minutes & 0x3F,
+                        seconds: seconds & 0x3F,
+                        frames: frames & 0x1F,
+                        subframes: subframes & 0x7F,
+                    },
+                })
+            }
+            _ => None,
+        };
+
+        decoded.unwrap_or_else(|| Self::Other {
+            command,
+            data: data.to_vec(),
+        })
+    }
+}
+
+impl SysexEvent {
+    /// Decodes a Universal System Exclusive message, or returns `None` if the message is
+    /// manufacturer specific or too short to hold a device ID and sub-ID. MIDI Show Control and
+    /// MIDI Machine Control commands are decoded, and every other universal message is returned
+    /// as [`UniversalSysex::Raw`]
+    pub fn interpret(&self) -> Option<UniversalSysex> {
+        let real_time = match self.manufacture_id {
+            ManufactureId::OneByte(REAL_TIME) => true,
+            ManufactureId::OneByte(NON_REAL_TIME) => false,
+            _ => return None,
+        };
+        let (&device, rest) = self.payload.split_first()?;
+        let (&sub_id, data) = rest.split_first()?;
+
+        let decoded = match (real_time, sub_id, data) {
+            (true, SHOW_CONTROL, [command_format, command, data @ ..]) => {
+                Some(UniversalSysex::ShowControl {
+                    device,
+                    command_format: *command_format,
+                    command: MscCommand::parse(*command, data),
+                })
+            }
+            (true, MACHINE_CONTROL, [command, data @ ..]) => Some(UniversalSysex::MachineControl {
+                device,
+                command: MmcCommand::parse(*command, data),
+            }),
+            _ => None,
+        };
+
+        Some(decoded.unwrap_or_else(|| UniversalSysex::Raw {
+            real_time,
+            device,
+            sub_id,
+            data: data.to_vec(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CueNumber, MmcCommand, MscCommand, TimecodeRate, UniversalSysex};
+    use crate::{
+        chunk::track::{event::IteratorWrapper, sysex::SysexEvent},
+        time::SmpteTime,
+    };
+
+    fn interpret(bytes: &[u8]) -> Option<UniversalSysex> {
+        SysexEvent::try_from(IteratorWrapper(&mut bytes.iter().copied()))
+            .unwrap()
+            .interpret()
+    }
+
+    fn cue(number: &str, list: Option<&str>, path: Option<&str>) -> CueNumber {
+        CueNumber {
+            number: Some(number.into()),
+            list: list.map(Into::into),
+            path: path.map(Into::into),
+        }
+    }
+
+    #[test]
+    fn show_control_cues_decode() {
+        // GO cue 235.6 in list 36.6 on lighting device 1
+        assert_eq!(
+            interpret(&[
+                0xF0, 0x7F, 0x01, 0x02, 0x01, 0x01, 0x32, 0x33, 0x35, 0x2E, 0x36, 0x00, 0x33, 0x36,
+                0x2E, 0x36, 0xF7
+            ]),
+            Some(UniversalSysex::ShowControl {
+                device: 0x01,
+                command_format: 0x01,
+                command: MscCommand::Go(cue("235.6", Some("36.6"), None)),
+            })
+        );
+
+        // STOP cue 5 in list 1 and path 2, to every sound device
+        assert_eq!(
+            interpret(&[0xF0, 0x7F, 0x7F, 0x02, 0x10, 0x02, 0x35, 0x00, 0x31, 0x00, 0x32, 0xF7]),
+            Some(UniversalSysex::ShowControl {
+                device: 0x7F,
+                command_format: 0x10,
+                command: MscCommand::Stop(cue("5", Some("1"), Some("2"))),
+            })
+        );
+
+        // RESUME with no cue number resumes everything
+        assert_eq!(
+            interpret(&[0xF0, 0x7F, 0x01, 0x02, 0x01, 0x03, 0xF7]),
+            Some(UniversalSysex::ShowControl {
+                device: 0x01,
+                command_format: 0x01,
+                command: MscCommand::Resume(CueNumber::default()),
+            })
+        );
+
+        // SET and a GO with a cue number that isn't digits are kept as they were sent
+        assert_eq!(
+            interpret(&[0xF0, 0x7F, 0x01, 0x02, 0x01, 0x06, 0x01, 0x00, 0x7F, 0x00, 0xF7]),
+            Some(UniversalSysex::ShowControl {
+                device: 0x01,
+                command_format: 0x01,
+                command: MscCommand::Other {
+                    command: 0x06,
+                    data: vec![0x01, 0x00, 0x7F, 0x00],
+                },
+            })
+        );
+        assert!(matches!(
+            interpret(&[0xF0, 0x7F, 0x01, 0x02, 0x01, 0x01, 0x41, 0xF7]),
+            Some(UniversalSysex::ShowControl {
+                command: MscCommand::Other { command: 0x01, .. },
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn machine_control_commands_decode() {
+        let command = |bytes: &[u8]| match interpret(bytes) {
+            Some(UniversalSysex::MachineControl {
+                device: 0x7F,
+                command,
+            }) => command,
+            other => panic!("Not a machine control command: {other:?}"),
+        };
+
+        assert_eq!(
+            command(&[0xF0, 0x7F, 0x7F, 0x06, 0x02, 0xF7]),
+            MmcCommand::Play
+        );
+        assert_eq!(
+            command(&[0xF0, 0x7F, 0x7F, 0x06, 0x01, 0xF7]),
+            MmcCommand::Stop
+        );
+
+        // LOCATE to 01:02:03:04.05 at 30 fps drop-frame
+        assert_eq!(
+            command(&[
+                0xF0, 0x7F, 0x7F, 0x06, 0x44, 0x06, 0x01, 0x41, 0x02, 0x03, 0x04, 0x05, 0xF7
+            ]),
+            MmcCommand::Locate {
+                rate: TimecodeRate::Fps30Drop,
+                target: SmpteTime {
+                    hours: 1,
+                    minutes: 2,
+                    seconds: 3,
+                    frames: 4,
+                    subframes: 5,
+                },
+            }
+        );
+
+        // Locating to a stored register isn't decoded
+        assert_eq!(
+            command(&[0xF0, 0x7F, 0x7F, 0x06, 0x44, 0x02, 0x00, 0x08, 0xF7]),
+            MmcCommand::Other {
+                command: 0x44,
+                data: vec![0x02, 0x00, 0x08],
+            }
+        );
+    }
+
+    #[test]
+    fn other_messages_fall_through() {
+        // General MIDI System On
+        assert_eq!(
+            interpret(&[0xF0, 0x7E, 0x7F, 0x09, 0x01, 0xF7]),
+            Some(UniversalSysex::Raw {
+                real_time: false,
+                device: 0x7F,
+                sub_id: 0x09,
+                data: vec![0x01],
+            })
+        );
+        // MIDI Time Code full frame
+        assert!(matches!(
+            interpret(&[0xF0, 0x7F, 0x7F, 0x01, 0x01, 0x20, 0x00, 0x00, 0x00, 0xF7]),
+            Some(UniversalSysex::Raw {
+                real_time: true,
+                sub_id: 0x01,
+                ..
+            })
+        ));
+
+        assert_eq!(interpret(&[0xF0, 0x43, 0x10, 0x4C, 0xF7]), None);
+        assert_eq!(interpret(&[0xF0, 0x7F, 0x7F, 0xF7]), None);
+    }
+}
@@ -0,0 +1,251 @@
+//! Metronome click tracks generated from a file's meter
+
+use crate::{
+    chunk::track::{
+        event::{MidiEvent, NoteMeta},
+        meta::MetaEvent,
+        Event, TrackChunk,
+    },
+    timeline::PositionError,
+    Midi,
+};
+
+/// How the click track added by [`Midi::add_click_track`] sounds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ClickConfig {
+    /// Channel the clicks play on, counted from 0. Defaults to 9, the General MIDI percussion
+    /// channel
+    pub channel: u8,
+    /// Key played on the first beat of every bar. Defaults to 76, Hi Wood Block
+    pub downbeat_key: u8,
+    /// Key played on every other beat. Defaults to 77, Low Wood Block
+    pub beat_key: u8,
+    /// Velocity of every click. Defaults to 100
+    pub velocity: u8,
+}
+
+impl Default for ClickConfig {
+    fn default() -> Self {
+        Self {
+            channel: 9,
+            downbeat_key: 76,
+            beat_key: 77,
+            velocity: 100,
+        }
+    }
+}
+
+impl ClickConfig {
+    /// Sets [`ClickConfig::channel`]
+    pub fn channel(mut self, channel: u8) -> Self {
+        self.channel = channel;
+        self
+    }
+
+    /// Sets [`ClickConfig::downbeat_key`]
+    pub fn downbeat_key(mut self, key: u8) -> Self {
+        self.downbeat_key = key;
+        self
+    }
+
+    /// Sets [`ClickConfig::beat_key`]
+    pub fn beat_key(mut self, key: u8) -> Self {
+        self.beat_key = key;
+        self
+    }
+
+    /// Sets [`ClickConfig::velocity`]
+    pub fn velocity(mut self, velocity: u8) -> Self {
+        self.velocity = velocity;
+        self
+    }
+}
+
+impl Midi {
+    /// Appends a track named `Click` that plays a note on every beat from the start of the file
+    /// to its [`Midi::duration`], following every time signature change. Each click lasts half a
+    /// beat, cut short if the file ends first, and the track ends exactly where the file does.
+    /// The header's track count is updated, and a format 0 file becomes format 1.
+    ///
+    /// Fails with [`PositionError::TimeCodeDivision`] for time-code-based files, which have no
+    /// beats
+    pub fn add_click_track(&mut self, config: ClickConfig) -> Result<(), PositionError> {
+        let segments = self
+            .meter_segments()
+            .ok_or(PositionError::TimeCodeDivision)?;
+        let duration = self.duration().get();
+        let channel = config.channel & 0x0F;
+
+        let mut events = vec![(0, Event::MetaEvent(MetaEvent::TrackName("Click".into())))];
+        for segment in &segments {
+            let end = segment.end.unwrap_or(duration).min(duration);
+
+            for (beat, tick) in (segment.start..end)
+                .step_by(segment.beat_len as usize)
+                .enumerate()
+            {
+                let key = if (beat as u64).is_multiple_of(segment.beats) {
+                    config.downbeat_key
+                } else {
+                    config.beat_key
+                };
+                let release = (tick + (segment.beat_len / 2).max(1)).min(duration);
+
+                events.push((
+                    tick,
+                    Event::MidiEvent(MidiEvent::NoteOn(
+                        channel,
+                        NoteMeta {
+                            key,
+                            velocity: config.velocity,
+                        },
+                    )),
+                ));
+                events.push((
+                    release,
+                    Event::MidiEvent(MidiEvent::NoteOff(channel, NoteMeta { key, velocity: 0 })),
+                ));
+            }
+        }
+
+        // A release can land after the next segment's first click when a beat is a single tick
+        events.sort_by_key(|(tick, _)| *tick);
+        events.push((duration, Event::MetaEvent(MetaEvent::EndOfTrack)));
+
+        self.tracks.push(
+            TrackChunk::from_absolute_events(events)
+                .expect("Clicks are at most a beat apart, which fits in a delta time"),
+        );
+        self.header = self.consistent_header();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClickConfig;
+    use crate::{
+        chunk::{
+            header::{Format, HeaderChunk},
+            track::{
+                event::{MidiEvent, NoteMeta},
+                meta::{MetaEvent, TimeSignature},
+                Event, TrackChunk,
+            },
+        },
+        time::Tick,
+        timeline::PositionError,
+        Midi,
+    };
+
+    fn time_signature(numerator: u8) -> Event {
+        Event::MetaEvent(MetaEvent::TimeSignature(TimeSignature {
+            numerator,
+            denominator: 4,
+            clocks_per_tick: 24,
+            thirty_second_notes_per_quarter: 8,
+        }))
+    }
+
+    /// Two bars of 4/4 then two bars of 3/4 at 480 ticks per quarter
+    fn meter_change_midi() -> Midi {
+        let track = TrackChunk::from_absolute_events([
+            (0, time_signature(4)),
+            (3840, time_signature(3)),
+            (6720, Event::MetaEvent(MetaEvent::EndOfTrack)),
+        ])
+        .unwrap();
+
+        Midi {
+            header: HeaderChunk::try_from((0, 1, 480)).unwrap(),
+            tracks: vec![track],
+        }
+    }
+
+    #[test]
+    fn clicks_follow_meter_changes() {
+        let mut midi = meter_change_midi();
+        midi.add_click_track(ClickConfig::default()).unwrap();
+
+        assert_eq!(midi.header.format(), Format::One);
+        assert_eq!(midi.header.ntrks(), 2);
+        assert_eq!(midi.duration(), Tick::new(6720));
+
+        let click = &midi.tracks[1];
+        let notes: Vec<(u64, u8, u8)> = click
+            .notes_on()
+            .map(|(tick, channel, note)| (tick.get(), channel, note.key))
+            .collect();
+        assert_eq!(notes.len(), 14);
+        assert!(notes.iter().all(|(_, channel, _)| *channel == 9));
+
+        let downbeats: Vec<u64> = notes
+            .iter()
+            .filter(|(_, _, key)| *key == 76)
+            .map(|(tick, _, _)| *tick)
+            .collect();
+        assert_eq!(downbeats, [0, 1920, 3840, 5280]);
+        assert_eq!(notes[13], (6240, 9, 77));
+
+        assert_eq!(
+            click.absolute_events().next(),
+            Some((
+                Tick::ZERO,
+                &Event::MetaEvent(MetaEvent::TrackName("Click".into()))
+            ))
+        );
+        assert_eq!(
+            click.absolute_events().last(),
+            Some((Tick::new(6720), &Event::MetaEvent(MetaEvent::EndOfTrack)))
+        );
+    }
+
+    #[test]
+    fn last_click_released_at_the_end() {
+        let mut midi = meter_change_midi();
+        midi.tracks[0] = TrackChunk::from_absolute_events([
+            (0, time_signature(4)),
+            (500, Event::MetaEvent(MetaEvent::EndOfTrack)),
+        ])
+        .unwrap();
+
+        let config = ClickConfig {
+            channel: 3,
+            velocity: 64,
+            ..ClickConfig::default()
+        };
+        midi.add_click_track(config).unwrap();
+
+        let events: Vec<_> = midi.tracks[1]
+            .absolute_events()
+            .skip(1)
+            .map(|(tick, event)| (tick.get(), event.clone()))
+            .collect();
+        let on = |key| Event::MidiEvent(MidiEvent::NoteOn(3, NoteMeta { key, velocity: 64 }));
+        let off = |key| Event::MidiEvent(MidiEvent::NoteOff(3, NoteMeta { key, velocity: 0 }));
+        assert_eq!(
+            events,
+            [
+                (0, on(76)),
+                (240, off(76)),
+                (480, on(77)),
+                (500, off(77)),
+                (500, Event::MetaEvent(MetaEvent::EndOfTrack)),
+            ]
+        );
+    }
+
+    #[test]
+    fn time_code_files_have_no_beats() {
+        let mut midi = meter_change_midi();
+        midi.header = HeaderChunk::try_from((0, 1, 0xE728)).unwrap();
+
+        assert_eq!(
+            midi.add_click_track(ClickConfig::default()),
+            Err(PositionError::TimeCodeDivision)
+        );
+        assert_eq!(midi.tracks.len(), 1);
+    }
+}
@@ -0,0 +1,211 @@
+//! Readable `Debug` style output for large files, keeping only the start and end of long event
+//! lists so `dbg!` and logs stay usable
+
+use core::fmt::{Display, Formatter, Result};
+
+use crate::{
+    chunk::{header::HeaderChunk, track::TrackChunk, ParsedChunk},
+    Midi, RawMidi,
+};
+
+/// Number of events shown from each end of a track by default
+pub const DEFAULT_EVENT_LIMIT: usize = 3;
+
+/// Displays a value like its pretty `Debug` output, but only the first and last few events of
+/// each track, with a count of the events left out in between. Created with
+/// [`Midi::compact_debug`], [`RawMidi::compact_debug`] or [`TrackChunk::compact_debug`]
+#[derive(Debug, Clone, Copy)]
+pub struct CompactDebug<'a, T> {
+    /// The value being displayed
+    value: &'a T,
+    /// Events shown from each end of a track
+    limit: usize,
+}
+
+impl<'a, T> CompactDebug<'a, T> {
+    /// Wraps a value with the default limit
+    fn new(value: &'a T) -> Self {
+        Self {
+            value,
+            limit: DEFAULT_EVENT_LIMIT,
+        }
+    }
+
+    /// Shows `limit` events from each end of a track instead of [`DEFAULT_EVENT_LIMIT`]
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+}
+
+impl Midi {
+    /// A compact, deterministic rendering of the file for diagnostics, see [`CompactDebug`]
+    pub fn compact_debug(&self) -> CompactDebug<'_, Midi> {
+        CompactDebug::new(self)
+    }
+}
+
+impl RawMidi {
+    /// A compact, deterministic rendering of the chunks for diagnostics, see [`CompactDebug`]
+    pub fn compact_debug(&self) -> CompactDebug<'_, RawMidi> {
+        CompactDebug::new(self)
+    }
+}
+
+impl TrackChunk {
+    /// A compact, deterministic rendering of the track for diagnostics, see [`CompactDebug`]
+    pub fn compact_debug(&self) -> CompactDebug<'_, TrackChunk> {
+        CompactDebug::new(self)
+    }
+}
+
+/// Writes a header on one line, leaving out any raw payload that was kept
+fn write_header(f: &mut Formatter<'_>, header: &HeaderChunk) -> Result {
+    write![
+        f,
+        "HeaderChunk {{ format: {:?}, ntrks: {}, division: {:?}",
+        header.format, header.ntrks, header.division
+    ]?;
+    if !header.extra.is_empty() {
+        write![f, ", extra: {:02X?}", header.extra]?;
+    }
+    write![f, " }}"]
+}
+
+/// Writes a track's event count and its first and last `limit` events, one per line, indented
+/// by `indent` levels
+fn write_track(f: &mut Formatter<'_>, track: &TrackChunk, limit: usize, indent: usize) -> Result {
+    let pad = "    ".repeat(indent);
+    let events = &track.mtrk_events;
+    writeln![f, "TrackChunk ({} events) [", events.len()]?;
+
+    let skipped = events.len().saturating_sub(2 * limit);
+    let (head, tail) = if skipped == 0 {
+        (&events[..], &events[events.len()..])
+    } else {
+        (&events[..limit], &events[events.len() - limit..])
+    };
+
+    for event in head {
+        writeln![f, "{pad}    {event:?},"]?;
+    }
+    if skipped > 0 {
+        writeln![f, "{pad}    ... {skipped} more events ..."]?;
+    }
+    for event in tail {
+        writeln![f, "{pad}    {event:?},"]?;
+    }
+
+    write![f, "{pad}]"]
+}
+
+impl Display for CompactDebug<'_, TrackChunk> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write_track(f, self.value, self.limit, 0)
+    }
+}
+
+impl Display for CompactDebug<'_, Midi> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        writeln![f, "Midi {{"]?;
+        write![f, "    header: "]?;
+        write_header(f, &self.value.header)?;
+        writeln![f, ","]?;
+
+        writeln![f, "    tracks: ["]?;
+        for track in &self.value.tracks {
+            write![f, "        "]?;
+            write_track(f, track, self.limit, 2)?;
+            writeln![f, ","]?;
+        }
+        writeln![f, "    ],"]?;
+        write![f, "}}"]
+    }
+}
+
+impl Display for CompactDebug<'_, RawMidi> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        writeln![f, "RawMidi {{"]?;
+        writeln![f, "    chunks: ["]?;
+        for chunk in &self.value.chunks {
+            write![f, "        "]?;
+            match chunk {
+                ParsedChunk::Header(header) => {
+                    write![f, "Header("]?;
+                    write_header(f, header)?;
+                }
+                ParsedChunk::Track(track) => {
+                    write![f, "Track("]?;
+                    write_track(f, track, self.limit, 2)?;
+                }
+            }
+            writeln![f, "),"]?;
+        }
+        writeln![f, "    ],"]?;
+        write![f, "}}"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{reader::MidiReadable, RawMidi};
+
+    fn fixture() -> RawMidi {
+        RawMidi::try_from_midi_stream("test/test4tracks.mid".get_midi_bytes().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn midi_compact_debug_is_stable() {
+        let midi = fixture().check_into_midi().unwrap();
+
+        assert_eq!(
+            midi.compact_debug().limit(1).to_string(),
+            "Midi {
+    header: HeaderChunk { format: Zero, ntrks: 1, division: Metrical(384) },
+    tracks: [
+        TrackChunk (17 events) [
+            MTrkEvent { delta_time: DeltaTime(0), event: MetaEvent(TimeSignature(TimeSignature { numerator: 4, denominator: 4, clocks_per_tick: 24, thirty_second_notes_per_quarter: 8 })) },
+            ... 15 more events ...
+            MTrkEvent { delta_time: DeltaTime(0), event: MetaEvent(EndOfTrack) },
+        ],
+    ],
+}"
+        );
+    }
+
+    #[test]
+    fn raw_midi_compact_debug_is_stable() {
+        assert_eq!(
+            fixture().compact_debug().to_string(),
+            "RawMidi {
+    chunks: [
+        Header(HeaderChunk { format: Zero, ntrks: 1, division: Metrical(384) }),
+        Track(TrackChunk (17 events) [
+            MTrkEvent { delta_time: DeltaTime(0), event: MetaEvent(TimeSignature(TimeSignature { numerator: 4, denominator: 4, clocks_per_tick: 24, thirty_second_notes_per_quarter: 8 })) },
+            MTrkEvent { delta_time: DeltaTime(0), event: MetaEvent(Tempo(545454)) },
+            MTrkEvent { delta_time: DeltaTime(0), event: MetaEvent(TrackName(MetaText { bytes: [69, 108, 101, 99, 116, 114, 105, 99, 32, 80, 105, 97, 110, 111], encoding: Utf8 })) },
+            ... 11 more events ...
+            MTrkEvent { delta_time: DeltaTime(96), event: MidiEvent(NoteOff(0, NoteMeta { key: 64, velocity: 0 })) },
+            MTrkEvent { delta_time: DeltaTime(0), event: MidiEvent(NoteOff(0, NoteMeta { key: 61, velocity: 0 })) },
+            MTrkEvent { delta_time: DeltaTime(0), event: MetaEvent(EndOfTrack) },
+        ]),
+    ],
+}"
+        );
+    }
+
+    #[test]
+    fn short_tracks_shown_in_full() {
+        let midi = fixture().check_into_midi().unwrap();
+        let track = &midi.tracks[0];
+
+        let full = track.compact_debug().limit(9).to_string();
+        assert_eq!(full.lines().count(), 19);
+        assert!(!full.contains("more events"));
+
+        assert_eq!(
+            track.compact_debug().limit(0).to_string(),
+            "TrackChunk (17 events) [\n    ... 17 more events ...\n]"
+        );
+    }
+}
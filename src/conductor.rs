@@ -0,0 +1,726 @@
+//! Conductor track identification and normalization
+
+use std::collections::BTreeSet;
+
+use crate::{
+    chunk::{
+        header::{Format, HeaderChunk},
+        track::{
+            editor::{EditError, TrackEditor},
+            kind::MetaKind,
+            meta::MetaEvent,
+            Event, TrackChunk,
+        },
+    },
+    time::Tick,
+    Midi,
+};
+
+/// Error from extracting tracks into their own file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtractError {
+    /// The requested track doesn't exist
+    TrackOutOfRange {
+        /// The requested track index
+        index: usize,
+        /// How many tracks the file has
+        track_count: usize,
+    },
+    /// The conductor events gathered from the tracks left out are too far apart for a delta
+    /// time, ending the gap at the contained tick
+    DeltaOverflow(Tick),
+}
+
+impl core::error::Error for ExtractError {}
+impl core::fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::TrackOutOfRange { index, track_count } => write![
+                f,
+                "Track {index} is out of range for a file with {track_count} tracks"
+            ],
+            Self::DeltaOverflow(tick) => write![
+                f,
+                "Conductor events before tick {tick} are too far apart for a delta time"
+            ],
+        }
+    }
+}
+
+/// How [`Midi::extract_tracks_with`] lays out the file it builds
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ExtractOptions {
+    /// When a single track is selected, merges it with any conductor events it needs into a
+    /// format 0 file instead of keeping them as separate tracks. Off by default
+    pub single_track_format0: bool,
+    /// Leaves out the conductor events of the tracks that aren't kept rather than gathering
+    /// them into a new conductor track. Off by default, so tempo and meter can't be lost by
+    /// accident
+    pub discard_conductor: bool,
+}
+
+impl ExtractOptions {
+    /// Sets [`ExtractOptions::single_track_format0`]
+    pub fn single_track_format0(mut self, format0: bool) -> Self {
+        self.single_track_format0 = format0;
+        self
+    }
+
+    /// Sets [`ExtractOptions::discard_conductor`]
+    pub fn discard_conductor(mut self, discard: bool) -> Self {
+        self.discard_conductor = discard;
+        self
+    }
+}
+
+/// Returns true for the meta events that belong in a conductor track: tempo, time signature, key
+/// signature and markers
+fn is_conductor_event(event: &MetaEvent) -> bool {
+    matches!(
+        event,
+        MetaEvent::Tempo(_)
+            | MetaEvent::TimeSignature(_)
+            | MetaEvent::KeySignature(_)
+            | MetaEvent::Marker(_)
+    )
+}
+
+/// Returns true for the conductor events that change how the rest of the file is timed or
+/// notated: tempo, time signature and key signature
+fn is_meter_event(event: &MetaEvent) -> bool {
+    matches!(
+        event,
+        MetaEvent::Tempo(_) | MetaEvent::TimeSignature(_) | MetaEvent::KeySignature(_)
+    )
+}
+
+impl Midi {
+    /// Every tempo, time signature and key signature event outside track 0 of a format 1 file, as
+    /// its track index, absolute tick and kind, in the order of [`Midi::merged_events`]. Players
+    /// may only read these from track 0, so such files can play back differently in different
+    /// players. Always empty for format 0 and format 2 files, where every track has its own
+    /// timing. [`Midi::consolidate_conductor_track`] moves them into track 0
+    pub fn misplaced_conductor_events(&self) -> Vec<(usize, Tick, MetaKind)> {
+        if self.header.format != Format::One {
+            return vec![];
+        }
+
+        self.merged_events()
+            .into_iter()
+            .filter_map(|found| match found.event {
+                Event::MetaEvent(meta) if found.track > 0 && is_meter_event(meta) => {
+                    Some((found.track, found.tick, meta.kind()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Gathers every tempo, time signature, key signature and marker event from all tracks,
+    /// ordered by absolute tick. Simultaneous events keep their track order
+    pub fn conductor_events(&self) -> Vec<(Tick, &MetaEvent)> {
+        let mut events: Vec<(Tick, &MetaEvent)> = self
+            .tracks
+            .iter()
+            .flat_map(|track| track.meta_events())
+            .filter(|(_, meta)| is_conductor_event(meta))
+            .collect();
+
+        events.sort_by_key(|(tick, _)| *tick);
+        events
+    }
+
+    /// Moves every conductor event (see [`Midi::conductor_events`]) into track 0 at its original
+    /// absolute tick, creating track 0 if the file has no tracks. The moved events are removed
+    /// from their original tracks, with their delta times folded into the events that follow so
+    /// the timing of everything else is unchanged.
+    ///
+    /// A moved tempo, time signature or key signature is dropped if track 0 already ends up with
+    /// one of the same kind at the same tick, so conflicting meters resolve to track 0's, or
+    /// otherwise to the lowest track's.
+    ///
+    /// Fails without changing anything if a track is left with a gap too large for a delta time,
+    /// either where an event was removed or between the events track 0 ends up with
+    pub fn consolidate_conductor_track(&mut self) -> Result<(), EditError> {
+        if self.tracks.is_empty() {
+            self.tracks.push(TrackChunk::default());
+            self.header.ntrks = 1;
+            self.header.raw_payload = None;
+        }
+
+        let mut moved = vec![];
+        let mut rest = vec![];
+        for track in self.tracks.iter().skip(1) {
+            let mut editor = TrackEditor::new(track.clone());
+            editor.retain(|tick, event| match event {
+                Event::MetaEvent(meta) if is_conductor_event(meta) => {
+                    moved.push((tick, event.clone()));
+                    false
+                }
+                _ => true,
+            });
+
+            rest.push(editor.finish()?);
+        }
+
+        let mut conductor = TrackEditor::new(self.tracks[0].clone());
+        for (tick, event) in moved {
+            let Event::MetaEvent(meta) = &event else {
+                continue;
+            };
+            let settled = is_meter_event(meta)
+                && conductor.events().iter().any(|(existing_tick, existing)| {
+                    *existing_tick == tick
+                        && matches!(existing, Event::MetaEvent(existing) if existing.kind() == meta.kind())
+                });
+            if !settled {
+                conductor.insert(tick, event);
+            }
+        }
+
+        let conductor = conductor.finish()?;
+        self.tracks.truncate(1);
+        self.tracks[0] = conductor;
+        self.tracks.extend(rest);
+
+        Ok(())
+    }
+
+    /// A new file with only the tracks at `indices`, in file order, and the conductor events
+    /// needed to keep their timing. See [`Midi::extract_tracks_with`]
+    pub fn with_tracks(&self, indices: &[usize]) -> Result<Midi, ExtractError> {
+        self.extract_tracks_with(indices, ExtractOptions::default())
+    }
+
+    /// A new file with every track except the ones at `indices`, and the conductor events
+    /// needed to keep their timing. See [`Midi::extract_tracks_with`]
+    pub fn without_tracks(&self, indices: &[usize]) -> Result<Midi, ExtractError> {
+        let excluded = self.checked_track_indices(indices)?;
+        let kept: Vec<usize> = (0..self.tracks.len())
+            .filter(|idx| !excluded.contains(idx))
+            .collect();
+
+        self.extract_tracks_with(&kept, ExtractOptions::default())
+    }
+
+    /// Builds a new file from the tracks at `indices`, kept in file order with repeated indices
+    /// ignored. Unless discarded, conductor events (see [`Midi::conductor_events`]) found in the
+    /// tracks left out are gathered into a new first track at their original ticks, so the
+    /// selection plays with the same tempo and meter, leaving out any past the end of the
+    /// selected tracks. No conductor track is added if nothing needs one, if nothing is
+    /// selected, or for a format 2 file, whose patterns each carry their own timing.
+    ///
+    /// The header's track count is synced, and format 0 becomes format 1 if there's more than
+    /// one track. Fails if any index is out of range, or if the gathered conductor events are
+    /// too far apart to share a track
+    pub fn extract_tracks_with(
+        &self,
+        indices: &[usize],
+        options: ExtractOptions,
+    ) -> Result<Midi, ExtractError> {
+        let selected = self.checked_track_indices(indices)?;
+        let mut tracks: Vec<TrackChunk> = selected
+            .iter()
+            .map(|idx| self.tracks[*idx].clone())
+            .collect();
+
+        if !options.discard_conductor && !tracks.is_empty() && self.header.format != Format::Two {
+            let end = tracks
+                .iter()
+                .map(TrackChunk::duration)
+                .max()
+                .unwrap_or(Tick::ZERO);
+
+            let mut conductor = TrackEditor::new(TrackChunk::default());
+            for (idx, track) in self.tracks.iter().enumerate() {
+                if selected.contains(&idx) {
+                    continue;
+                }
+                for (tick, meta) in track.meta_events() {
+                    if tick <= end && is_conductor_event(meta) {
+                        conductor.insert(tick, Event::MetaEvent(meta.clone()));
+                    }
+                }
+            }
+
+            if !conductor.events().is_empty() {
+                let conductor = conductor.finish().map_err(|error| match error {
+                    EditError::DeltaOverflow(tick) => ExtractError::DeltaOverflow(tick),
+                })?;
+                tracks.insert(0, conductor);
+            }
+        }
+
+        let mut midi = Midi {
+            header: self.header.clone(),
+            tracks,
+        };
+        midi.header = midi.consistent_header();
+        if options.single_track_format0 && selected.len() == 1 {
+            midi.to_format0();
+        }
+
+        Ok(midi)
+    }
+
+    /// Keeps only the tracks the predicate returns true for, given each track's index. See
+    /// [`Midi::retain_tracks_with`]
+    pub fn retain_tracks(
+        &mut self,
+        f: impl FnMut(usize, &TrackChunk) -> bool,
+    ) -> Result<(), ExtractError> {
+        self.retain_tracks_with(f, ExtractOptions::default())
+    }
+
+    /// Keeps only the tracks the predicate returns true for, given each track's index, laying
+    /// out the result like [`Midi::extract_tracks_with`]. Unless the options discard them, the
+    /// conductor events of the removed tracks move into a new first track. Fails without
+    /// changing anything if they're too far apart to share one
+    pub fn retain_tracks_with(
+        &mut self,
+        mut f: impl FnMut(usize, &TrackChunk) -> bool,
+        options: ExtractOptions,
+    ) -> Result<(), ExtractError> {
+        let kept: Vec<usize> = self
+            .tracks
+            .iter()
+            .enumerate()
+            .filter(|(idx, track)| f(*idx, track))
+            .map(|(idx, _)| idx)
+            .collect();
+
+        *self = self.extract_tracks_with(&kept, options)?;
+        Ok(())
+    }
+
+    /// Calls `f` on every track with its index, then syncs the header with
+    /// [`Midi::consistent_header`]
+    pub fn map_tracks(&mut self, mut f: impl FnMut(usize, &mut TrackChunk)) {
+        for (idx, track) in self.tracks.iter_mut().enumerate() {
+            f(idx, track);
+        }
+        self.header = self.consistent_header();
+    }
+
+    /// The given track indices without repeats, or an error for the first that's out of range
+    fn checked_track_indices(&self, indices: &[usize]) -> Result<BTreeSet<usize>, ExtractError> {
+        indices
+            .iter()
+            .map(|&index| {
+                if index < self.tracks.len() {
+                    Ok(index)
+                } else {
+                    Err(ExtractError::TrackOutOfRange {
+                        index,
+                        track_count: self.tracks.len(),
+                    })
+                }
+            })
+            .collect()
+    }
+
+    /// Builds a standalone format 0 file from a single track, merging in the conductor events
+    /// from every other track at their original ticks so the stem keeps the file's tempo and
+    /// meter. Conductor events after the stem ends are left out, and conductor events at the
+    /// same tick as the stem's own events are placed before them
+    pub fn extract_track(&self, index: usize) -> Result<Midi, ExtractError> {
+        let track = self
+            .tracks
+            .get(index)
+            .ok_or(ExtractError::TrackOutOfRange {
+                index,
+                track_count: self.tracks.len(),
+            })?;
+
+        let mut editor = TrackEditor::new(track.clone());
+        let end = editor.end_of_track();
+
+        let mut events: Vec<(Tick, Event)> = self
+            .tracks
+            .iter()
+            .enumerate()
+            .filter(|(other, _)| *other != index)
+            .flat_map(|(_, other)| other.meta_events())
+            .filter(|(tick, meta)| *tick <= end && is_conductor_event(meta))
+            .map(|(tick, meta)| (tick, Event::MetaEvent(meta.clone())))
+            .collect();
+        events.sort_by_key(|(tick, _)| *tick);
+        events.append(editor.events_mut());
+        *editor.events_mut() = events;
+
+        let header = HeaderChunk::new(Format::Zero, 1, self.header.division);
+
+        Ok(Midi {
+            header,
+            // Nothing was placed past the track's original end
+            tracks: vec![editor
+                .finish()
+                .expect("Conductor events are limited to the stem's own span")],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ExtractError, ExtractOptions};
+    use crate::{
+        chunk::{
+            header::{Format, HeaderChunk},
+            track::{
+                editor::EditError,
+                event::{MidiEvent, NoteMeta},
+                kind::MetaKind,
+                meta::{MetaEvent, TimeSignature},
+                Event, TrackChunk,
+            },
+        },
+        time::Tick,
+        Midi,
+    };
+
+    fn note(key: u8) -> Event {
+        Event::MidiEvent(MidiEvent::NoteOn(0, NoteMeta { key, velocity: 100 }))
+    }
+
+    fn end() -> Event {
+        Event::MetaEvent(MetaEvent::EndOfTrack)
+    }
+
+    fn fixture() -> Midi {
+        let conductor = vec![(0, Event::MetaEvent(MetaEvent::Tempo(500_000))), (0, end())];
+        let melody = |key| vec![(0, note(key)), (960, note(key + 1)), (1920, end())];
+        let hidden = vec![
+            (0, note(40)),
+            (480, Event::MetaEvent(MetaEvent::Tempo(250_000))),
+            (480, Event::MetaEvent(MetaEvent::TrackName("Bass".into()))),
+            (960, note(41)),
+            (1920, end()),
+        ];
+
+        Midi {
+            header: HeaderChunk::try_from((1, 4, 480)).unwrap(),
+            tracks: [conductor, melody(60), melody(70), hidden]
+                .into_iter()
+                .map(|events| TrackChunk::from_absolute_events(events).unwrap())
+                .collect(),
+        }
+    }
+
+    fn events(track: &TrackChunk) -> Vec<(u64, Event)> {
+        track
+            .absolute_events()
+            .map(|(tick, event)| (tick.get(), event.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn conductor_events_found_in_any_track() {
+        let midi = fixture();
+
+        assert_eq!(
+            midi.conductor_events(),
+            vec![
+                (Tick::ZERO, &MetaEvent::Tempo(500_000)),
+                (Tick::new(480), &MetaEvent::Tempo(250_000))
+            ]
+        );
+    }
+
+    #[test]
+    fn hidden_tempo_change_moved_to_track_zero() {
+        let mut midi = fixture();
+        midi.consolidate_conductor_track().unwrap();
+
+        assert_eq!(
+            events(&midi.tracks[0]),
+            vec![
+                (0, Event::MetaEvent(MetaEvent::Tempo(500_000))),
+                (480, Event::MetaEvent(MetaEvent::Tempo(250_000))),
+                (480, end()),
+            ]
+        );
+        assert_eq!(
+            events(&midi.tracks[3]),
+            vec![
+                (0, note(40)),
+                (480, Event::MetaEvent(MetaEvent::TrackName("Bass".into()))),
+                (960, note(41)),
+                (1920, end()),
+            ]
+        );
+        assert_eq!(midi.conductor_events().len(), 2);
+    }
+
+    #[test]
+    fn consolidating_fails_rather_than_overflow_a_delta_time() {
+        const MAX: u64 = 0x0FFF_FFFF;
+        let tempo = |tempo| Event::MetaEvent(MetaEvent::Tempo(tempo));
+        let midi = |track: Vec<(u64, Event)>| Midi {
+            header: HeaderChunk::try_from((1, 2, 480)).unwrap(),
+            tracks: vec![
+                TrackChunk::from_absolute_events([(0, end())]).unwrap(),
+                TrackChunk::from_absolute_events(track).unwrap(),
+            ],
+        };
+
+        // Removing the tempo joins two maximal gaps in track 1
+        let mut joined = midi(vec![
+            (0, note(60)),
+            (MAX, tempo(500_000)),
+            (2 * MAX, note(62)),
+            (2 * MAX, end()),
+        ]);
+        // The tempos are too far apart once the note between them stays behind
+        let mut spread = midi(vec![
+            (0, tempo(500_000)),
+            (MAX, note(60)),
+            (2 * MAX, tempo(400_000)),
+            (2 * MAX, end()),
+        ]);
+
+        for midi in [&mut joined, &mut spread] {
+            let original = midi.clone();
+            assert_eq!(
+                midi.consolidate_conductor_track(),
+                Err(EditError::DeltaOverflow(Tick::new(2 * MAX)))
+            );
+            assert_eq!(*midi, original);
+        }
+    }
+
+    #[test]
+    fn misplaced_meters_only_flagged_in_format_1() {
+        let mut midi = fixture();
+        assert_eq!(
+            midi.misplaced_conductor_events(),
+            [(3, Tick::new(480), MetaKind::Tempo)]
+        );
+
+        midi.header.format = Format::Two;
+        assert_eq!(midi.misplaced_conductor_events(), []);
+
+        let single = Midi {
+            header: HeaderChunk::try_from((0, 1, 480)).unwrap(),
+            tracks: vec![midi.tracks[3].clone()],
+        };
+        assert_eq!(single.misplaced_conductor_events(), []);
+    }
+
+    #[test]
+    fn conflicting_meters_resolve_to_the_conductor() {
+        let signature = |numerator| {
+            Event::MetaEvent(MetaEvent::TimeSignature(TimeSignature {
+                numerator,
+                ..Default::default()
+            }))
+        };
+        let mut midi = Midi {
+            header: HeaderChunk::try_from((1, 3, 480)).unwrap(),
+            tracks: [
+                vec![(0, signature(3)), (0, end())],
+                vec![(0, signature(5)), (960, signature(7)), (960, end())],
+                vec![(960, signature(6)), (960, note(60)), (960, end())],
+            ]
+            .into_iter()
+            .map(|events| TrackChunk::from_absolute_events(events).unwrap())
+            .collect(),
+        };
+        assert_eq!(midi.validate().len(), 5);
+
+        midi.consolidate_conductor_track().unwrap();
+        assert_eq!(
+            events(&midi.tracks[0]),
+            vec![(0, signature(3)), (960, signature(7)), (960, end())]
+        );
+        assert_eq!(midi.misplaced_conductor_events(), []);
+        assert_eq!(midi.validate(), []);
+    }
+
+    #[test]
+    fn extracted_stem_keeps_conductor_and_span() {
+        let midi = Midi {
+            tracks: vec![
+                TrackChunk::from_absolute_events(vec![
+                    (0, Event::MetaEvent(MetaEvent::Tempo(500_000))),
+                    (960, Event::MetaEvent(MetaEvent::Tempo(400_000))),
+                    (4800, end()),
+                ])
+                .unwrap(),
+                TrackChunk::from_absolute_events(vec![
+                    (0, note(60)),
+                    (960, note(62)),
+                    (1920, end()),
+                ])
+                .unwrap(),
+            ],
+            header: HeaderChunk::try_from((1, 2, 480)).unwrap(),
+        };
+
+        let stem = midi.extract_track(1).unwrap();
+        assert_eq!(stem.header.format, Format::Zero);
+        assert_eq!(stem.header.ntrks, 1);
+        assert_eq!(stem.header.division, midi.header.division);
+        assert_eq!(stem.duration(), Tick::new(1920));
+        assert_eq!(
+            events(&stem.tracks[0]),
+            vec![
+                (0, Event::MetaEvent(MetaEvent::Tempo(500_000))),
+                (0, note(60)),
+                (960, Event::MetaEvent(MetaEvent::Tempo(400_000))),
+                (960, note(62)),
+                (1920, end()),
+            ]
+        );
+
+        assert_eq!(
+            midi.extract_track(2),
+            Err(ExtractError::TrackOutOfRange {
+                index: 2,
+                track_count: 2
+            })
+        );
+    }
+
+    #[test]
+    fn conductor_track_created_when_missing() {
+        let mut midi = Midi {
+            header: HeaderChunk::try_from((1, 0, 480)).unwrap(),
+            tracks: vec![],
+        };
+        midi.consolidate_conductor_track().unwrap();
+
+        assert_eq!(midi.tracks.len(), 1);
+        assert_eq!(events(&midi.tracks[0]), vec![(0, end())]);
+    }
+
+    #[test]
+    fn selected_tracks_keep_their_tempo() {
+        let midi = fixture();
+        let tempo_map = midi.tempo_map();
+        let melody = midi.tracks[1].clone();
+
+        let selection = midi.with_tracks(&[2, 1, 2]).unwrap();
+        assert_eq!(selection.header.ntrks, 3);
+        assert_eq!(
+            selection.tracks[1..],
+            [melody.clone(), midi.tracks[2].clone()]
+        );
+        assert_eq!(selection.tempo_map(), tempo_map);
+        assert_eq!(selection.duration_seconds(), midi.duration_seconds());
+        assert_eq!(
+            events(&selection.tracks[0]),
+            vec![
+                (0, Event::MetaEvent(MetaEvent::Tempo(500_000))),
+                (480, Event::MetaEvent(MetaEvent::Tempo(250_000))),
+                (480, end()),
+            ]
+        );
+
+        let stem = midi
+            .extract_tracks_with(
+                &[1],
+                ExtractOptions {
+                    single_track_format0: true,
+                    ..ExtractOptions::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(stem.header.format, Format::Zero);
+        assert_eq!(stem.tracks.len(), 1);
+        assert_eq!(stem.tempo_map(), tempo_map);
+        assert_eq!(stem.tracks[0].notes(), melody.notes());
+
+        // Only the conductor track is left out, and the tempo change in the last track stays put
+        let muted = midi.without_tracks(&[0]).unwrap();
+        assert_eq!(muted.header.ntrks, 4);
+        assert_eq!(muted.tracks[1..], midi.tracks[1..]);
+        assert_eq!(muted.tempo_map(), tempo_map);
+
+        assert_eq!(
+            midi.with_tracks(&[0]).unwrap().tracks,
+            [midi.tracks[0].clone()]
+        );
+        assert_eq!(
+            midi.without_tracks(&[4]),
+            Err(ExtractError::TrackOutOfRange {
+                index: 4,
+                track_count: 4
+            })
+        );
+    }
+
+    #[test]
+    fn retaining_tracks_keeps_the_tempo() {
+        let midi = fixture();
+        let is_playing = |_, track: &TrackChunk| !track.notes().is_empty();
+
+        let mut retained = midi.clone();
+        retained.retain_tracks(is_playing).unwrap();
+        assert_eq!(retained.header.ntrks, 4);
+        assert_eq!(retained.tracks[1..], midi.tracks[1..]);
+        assert_eq!(retained.tempo_map(), midi.tempo_map());
+        assert_eq!(retained.tempo_map().tempo_at(0), 500_000);
+
+        let mut discarded = midi.clone();
+        discarded
+            .retain_tracks_with(
+                is_playing,
+                ExtractOptions {
+                    discard_conductor: true,
+                    ..ExtractOptions::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(discarded.tracks, midi.tracks[1..]);
+        assert_eq!(discarded.header.ntrks, 3);
+        assert_eq!(discarded.tempo_map().changes(), [(Tick::new(480), 250_000)]);
+
+        let mut unnamed = midi;
+        unnamed.header.ntrks = 0;
+        unnamed.map_tracks(|idx, track| {
+            if idx == 3 {
+                track
+                    .retain_events(|event| {
+                        !matches!(event, Event::MetaEvent(MetaEvent::TrackName(_)))
+                    })
+                    .unwrap();
+            }
+        });
+        assert_eq!(unnamed.tracks[3].name(), None);
+        assert_eq!(unnamed.header.ntrks, 4);
+    }
+
+    #[test]
+    fn gathering_distant_conductor_events_fails() {
+        const MAX: u64 = 0x0FFF_FFFF;
+        let tempo = |tempo| Event::MetaEvent(MetaEvent::Tempo(tempo));
+        let midi = Midi {
+            header: HeaderChunk::try_from((1, 2, 480)).unwrap(),
+            tracks: vec![
+                TrackChunk::from_absolute_events([
+                    (0, tempo(500_000)),
+                    (MAX, note(60)),
+                    (2 * MAX, tempo(400_000)),
+                    (2 * MAX, end()),
+                ])
+                .unwrap(),
+                TrackChunk::from_absolute_events([
+                    (0, note(62)),
+                    (MAX, note(64)),
+                    (2 * MAX, end()),
+                ])
+                .unwrap(),
+            ],
+        };
+        let overflow = Err(ExtractError::DeltaOverflow(Tick::new(2 * MAX)));
+
+        assert_eq!(midi.with_tracks(&[1]), overflow);
+        let mut retained = midi.clone();
+        assert_eq!(
+            retained.retain_tracks(|idx, _| idx == 1),
+            overflow.map(|_| ())
+        );
+        assert_eq!(retained, midi);
+    }
+}
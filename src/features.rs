@@ -0,0 +1,426 @@
+//! Reports of which MIDI features a file relies on, checked against what a device can handle
+
+use std::collections::BTreeMap;
+
+use crate::{
+    chunk::{
+        header::Division,
+        track::{event::MidiEvent, meta::MetaEvent, Event},
+    },
+    slice::NoteChange,
+    Midi,
+};
+
+/// The features a file uses that constrained hardware might not support
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FeatureReport {
+    /// Whether any track contains a system exclusive event
+    pub uses_sysex: bool,
+    /// Whether the header's division is time-code-based rather than ticks per quarter note
+    pub smpte_division: bool,
+    /// Whether any track sends a Pitch Wheel Change
+    pub uses_pitch_bend: bool,
+    /// The most notes sounding at once across every track and channel
+    pub max_polyphony: usize,
+    /// The highest channel any channel message is sent on, counted from 0, or `None` if there
+    /// are no channel messages
+    pub max_channel: Option<u8>,
+    /// Length in bytes of the longest text, name, lyric, marker or cue point meta event
+    pub max_meta_text_len: usize,
+}
+
+/// The limits of a device a file will be played on, checked by [`FeatureReport::compatible_with`].
+///
+/// The default profile allows everything
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DeviceProfile {
+    /// Whether the device accepts system exclusive messages
+    pub sysex: bool,
+    /// Whether the device can play files timed in SMPTE frames
+    pub smpte_division: bool,
+    /// Whether the device responds to pitch bends
+    pub pitch_bend: bool,
+    /// The most notes the device can sound at once
+    pub max_polyphony: usize,
+    /// The highest channel the device listens on, counted from 0
+    pub max_channel: u8,
+    /// The longest meta event text in bytes the device can display or store
+    pub max_meta_text_len: usize,
+}
+
+impl Default for DeviceProfile {
+    fn default() -> Self {
+        Self {
+            sysex: true,
+            smpte_division: true,
+            pitch_bend: true,
+            max_polyphony: usize::MAX,
+            max_channel: 15,
+            max_meta_text_len: usize::MAX,
+        }
+    }
+}
+
+impl DeviceProfile {
+    /// Sets [`DeviceProfile::sysex`]
+    pub fn sysex(mut self, sysex: bool) -> Self {
+        self.sysex = sysex;
+        self
+    }
+
+    /// Sets [`DeviceProfile::smpte_division`]
+    pub fn smpte_division(mut self, smpte: bool) -> Self {
+        self.smpte_division = smpte;
+        self
+    }
+
+    /// Sets [`DeviceProfile::pitch_bend`]
+    pub fn pitch_bend(mut self, pitch_bend: bool) -> Self {
+        self.pitch_bend = pitch_bend;
+        self
+    }
+
+    /// Sets [`DeviceProfile::max_polyphony`]
+    pub fn max_polyphony(mut self, notes: usize) -> Self {
+        self.max_polyphony = notes;
+        self
+    }
+
+    /// Sets [`DeviceProfile::max_channel`]
+    pub fn max_channel(mut self, channel: u8) -> Self {
+        self.max_channel = channel;
+        self
+    }
+
+    /// Sets [`DeviceProfile::max_meta_text_len`]
+    pub fn max_meta_text_len(mut self, len: usize) -> Self {
+        self.max_meta_text_len = len;
+        self
+    }
+}
+
+/// A feature a file uses that a [`DeviceProfile`] can't handle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Incompatibility {
+    /// The file contains system exclusive events
+    Sysex,
+    /// The file is timed in SMPTE frames
+    SmpteDivision,
+    /// The file bends pitch
+    PitchBend,
+    /// More notes sound at once than the device can play
+    Polyphony {
+        /// Notes sounding at once in the file
+        used: usize,
+        /// The device's limit
+        limit: usize,
+    },
+    /// A channel message is sent on a channel the device doesn't listen on
+    Channel {
+        /// Highest channel in the file
+        used: u8,
+        /// Highest channel the device listens on
+        limit: u8,
+    },
+    /// A meta event's text is longer than the device allows
+    MetaTextLength {
+        /// Length of the longest text in the file
+        used: usize,
+        /// The device's limit
+        limit: usize,
+    },
+}
+
+impl core::fmt::Display for Incompatibility {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Sysex => write![f, "Uses system exclusive events"],
+            Self::SmpteDivision => write![f, "Uses a time-code-based division"],
+            Self::PitchBend => write![f, "Uses pitch bends"],
+            Self::Polyphony { used, limit } => write![
+                f,
+                "Plays {used} notes at once but the device can play {limit}"
+            ],
+            Self::Channel { used, limit } => write![
+                f,
+                "Uses channel {used} but the device only listens up to channel {limit}"
+            ],
+            Self::MetaTextLength { used, limit } => write![
+                f,
+                "Has {used} bytes of meta text but the device allows {limit}"
+            ],
+        }
+    }
+}
+
+impl FeatureReport {
+    /// Lists every feature in the report the device can't handle, or nothing if the file is
+    /// compatible
+    pub fn compatible_with(&self, profile: &DeviceProfile) -> Vec<Incompatibility> {
+        let mut incompatibilities = vec![];
+
+        if self.uses_sysex && !profile.sysex {
+            incompatibilities.push(Incompatibility::Sysex);
+        }
+        if self.smpte_division && !profile.smpte_division {
+            incompatibilities.push(Incompatibility::SmpteDivision);
+        }
+        if self.uses_pitch_bend && !profile.pitch_bend {
+            incompatibilities.push(Incompatibility::PitchBend);
+        }
+        if self.max_polyphony > profile.max_polyphony {
+            incompatibilities.push(Incompatibility::Polyphony {
+                used: self.max_polyphony,
+                limit: profile.max_polyphony,
+            });
+        }
+        if let Some(channel) = self.max_channel.filter(|used| *used > profile.max_channel) {
+            incompatibilities.push(Incompatibility::Channel {
+                used: channel,
+                limit: profile.max_channel,
+            });
+        }
+        if self.max_meta_text_len > profile.max_meta_text_len {
+            incompatibilities.push(Incompatibility::MetaTextLength {
+                used: self.max_meta_text_len,
+                limit: profile.max_meta_text_len,
+            });
+        }
+
+        incompatibilities
+    }
+}
+
+impl Midi {
+    /// Reports which features the file uses, to check it against a [`DeviceProfile`] before
+    /// sending it to hardware.
+    ///
+    /// Polyphony counts notes across every track together, since they all play at the same time.
+    /// A note released on the same tick another starts doesn't count as overlapping it
+    pub fn feature_report(&self) -> FeatureReport {
+        let mut report = FeatureReport {
+            smpte_division: matches!(self.header.division(), Division::TimeCodeBased(_)),
+            ..FeatureReport::default()
+        };
+        let mut notes = vec![];
+
+        for track in &self.tracks {
+            for (tick, event) in track.absolute_events() {
+                match event {
+                    Event::SysexEvent(_) => report.uses_sysex = true,
+                    Event::MetaEvent(MetaEvent::CuePoint(bytes)) => {
+                        report.max_meta_text_len = report.max_meta_text_len.max(bytes.len());
+                    }
+                    Event::MetaEvent(meta) => {
+                        if let Some(text) = meta.text() {
+                            report.max_meta_text_len =
+                                report.max_meta_text_len.max(text.as_bytes().len());
+                        }
+                    }
+                    Event::MidiEvent(midi) => {
+                        report.max_channel = report.max_channel.max(Some(midi.channel()));
+                        if matches!(midi, MidiEvent::PitchWheelChange(..)) {
+                            report.uses_pitch_bend = true;
+                        }
+                        if let Some(change) = NoteChange::of(event) {
+                            notes.push((tick, change));
+                        }
+                    }
+                }
+            }
+        }
+
+        // Releases sort before starts on the same tick
+        notes.sort_by_key(|(tick, change)| (*tick, matches!(change, NoteChange::On(..))));
+
+        let mut sounding: BTreeMap<(u8, u8), usize> = BTreeMap::new();
+        let mut polyphony = 0;
+        for (_, change) in notes {
+            match change {
+                NoteChange::On(channel, note) => {
+                    *sounding.entry((channel, note.key)).or_default() += 1;
+                    polyphony += 1;
+                    report.max_polyphony = report.max_polyphony.max(polyphony);
+                }
+                NoteChange::Off(channel, note) => {
+                    if let Some(count) = sounding
+                        .get_mut(&(channel, note.key))
+                        .filter(|count| **count > 0)
+                    {
+                        *count -= 1;
+                        polyphony -= 1;
+                    }
+                }
+            }
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeviceProfile, FeatureReport, Incompatibility};
+    use crate::{
+        chunk::{
+            header::HeaderChunk,
+            track::{
+                event::{MidiEvent, NoteMeta},
+                meta::MetaEvent,
+                sysex::{ManufactureId, SysexEvent},
+                Event, TrackChunk,
+            },
+        },
+        Midi,
+    };
+
+    fn on(channel: u8, key: u8) -> Event {
+        Event::MidiEvent(MidiEvent::NoteOn(channel, NoteMeta { key, velocity: 100 }))
+    }
+
+    fn off(channel: u8, key: u8) -> Event {
+        Event::MidiEvent(MidiEvent::NoteOff(channel, NoteMeta { key, velocity: 0 }))
+    }
+
+    fn midi(division: u16, tracks: Vec<Vec<(u64, Event)>>) -> Midi {
+        let tracks: Vec<TrackChunk> = tracks
+            .into_iter()
+            .map(|mut events| {
+                let end = events.last().map_or(0, |(tick, _)| *tick);
+                events.push((end, Event::MetaEvent(MetaEvent::EndOfTrack)));
+                TrackChunk::from_absolute_events(events).unwrap()
+            })
+            .collect();
+
+        Midi {
+            header: HeaderChunk::try_from((1, tracks.len() as u16, division)).unwrap(),
+            tracks,
+        }
+    }
+
+    #[test]
+    fn plain_file_uses_nothing_special() {
+        let midi = midi(480, vec![vec![(0, on(0, 60)), (480, off(0, 60))]]);
+
+        assert_eq!(
+            midi.feature_report(),
+            FeatureReport {
+                max_polyphony: 1,
+                max_channel: Some(0),
+                ..FeatureReport::default()
+            }
+        );
+        assert!(midi
+            .feature_report()
+            .compatible_with(&DeviceProfile {
+                sysex: false,
+                smpte_division: false,
+                pitch_bend: false,
+                max_polyphony: 1,
+                max_channel: 0,
+                max_meta_text_len: 0,
+            })
+            .is_empty());
+    }
+
+    #[test]
+    fn each_feature_is_detected() {
+        let sysex = midi(
+            480,
+            vec![vec![(
+                0,
+                Event::SysexEvent(SysexEvent {
+                    manufacture_id: ManufactureId::OneByte(0x7E),
+                    payload: vec![0x7F, 0x09, 0x01].into(),
+                }),
+            )]],
+        );
+        assert!(sysex.feature_report().uses_sysex);
+
+        let smpte = midi(0xE728, vec![vec![]]);
+        assert!(smpte.feature_report().smpte_division);
+
+        let bend = midi(
+            480,
+            vec![vec![(
+                0,
+                Event::MidiEvent(MidiEvent::PitchWheelChange(2, 0x2000)),
+            )]],
+        );
+        let report = bend.feature_report();
+        assert!(report.uses_pitch_bend);
+        assert_eq!(report.max_channel, Some(2));
+
+        let text = midi(
+            480,
+            vec![vec![
+                (0, Event::MetaEvent(MetaEvent::TrackName("Piano".into()))),
+                (0, Event::MetaEvent(MetaEvent::Lyric("la la la la".into()))),
+                (
+                    0,
+                    Event::MetaEvent(MetaEvent::CuePoint(vec![b'x'; 8].into())),
+                ),
+            ]],
+        );
+        assert_eq!(text.feature_report().max_meta_text_len, 11);
+    }
+
+    #[test]
+    fn polyphony_spans_tracks_and_ignores_back_to_back_notes() {
+        let midi = midi(
+            480,
+            vec![
+                vec![
+                    (0, on(0, 60)),
+                    (0, on(0, 64)),
+                    (480, off(0, 60)),
+                    (480, off(0, 64)),
+                    (480, on(0, 62)),
+                    (960, off(0, 62)),
+                ],
+                vec![(240, on(9, 36)), (720, off(9, 36))],
+            ],
+        );
+
+        let report = midi.feature_report();
+        assert_eq!(report.max_polyphony, 3);
+        assert_eq!(report.max_channel, Some(9));
+    }
+
+    #[test]
+    fn incompatibilities_are_listed() {
+        let report = FeatureReport {
+            uses_sysex: true,
+            smpte_division: true,
+            uses_pitch_bend: true,
+            max_polyphony: 12,
+            max_channel: Some(9),
+            max_meta_text_len: 40,
+        };
+        let profile = DeviceProfile {
+            sysex: false,
+            smpte_division: false,
+            pitch_bend: false,
+            max_polyphony: 8,
+            max_channel: 3,
+            max_meta_text_len: 32,
+        };
+
+        assert_eq!(
+            report.compatible_with(&profile),
+            [
+                Incompatibility::Sysex,
+                Incompatibility::SmpteDivision,
+                Incompatibility::PitchBend,
+                Incompatibility::Polyphony { used: 12, limit: 8 },
+                Incompatibility::Channel { used: 9, limit: 3 },
+                Incompatibility::MetaTextLength {
+                    used: 40,
+                    limit: 32
+                },
+            ]
+        );
+        assert!(report.compatible_with(&DeviceProfile::default()).is_empty());
+    }
+}
@@ -0,0 +1,457 @@
+//! Opening and saving whole MIDI files on disk in a single call. These are where the crate's
+//! defaults live: files are parsed strictly and left exactly as they were read, with the `_with`
+//! variants for anything else
+
+use std::{
+    fs::File,
+    io::{BufWriter, Cursor, Seek, Write},
+    path::Path,
+};
+
+use crate::{
+    normalize::NormalizeOptions,
+    outcome::ParseOutcome,
+    reader::ParseProfile,
+    signature::GENERATOR,
+    writer::{MidiFileWriter, WriteError, WriteOptions},
+    Midi, MidiError,
+};
+
+/// How [`open_with`] reads a file
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct OpenOptions {
+    /// How forgiving the parse is of damaged files, along with its text decoding and memory
+    /// limits. [`ParseProfile::strict`] by default
+    pub profile: ParseProfile,
+    /// Runs [`Midi::normalize`] on the parsed file. Off by default
+    pub normalize: Option<NormalizeOptions>,
+}
+
+impl OpenOptions {
+    /// Sets [`OpenOptions::profile`]
+    pub fn profile(mut self, profile: ParseProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Sets [`OpenOptions::normalize`]
+    pub fn normalize(mut self, normalize: Option<NormalizeOptions>) -> Self {
+        self.normalize = normalize;
+        self
+    }
+}
+
+/// How [`save_with`] writes a file
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SaveOptions {
+    /// Layout of the written chunks
+    pub write: WriteOptions,
+    /// Writes a [`Midi::normalize`]d copy of the file rather than the file as it is. Off by
+    /// default
+    pub normalize: Option<NormalizeOptions>,
+}
+
+impl SaveOptions {
+    /// Sets [`SaveOptions::write`]
+    pub fn write(mut self, write: WriteOptions) -> Self {
+        self.write = write;
+        self
+    }
+
+    /// Sets [`SaveOptions::normalize`]
+    pub fn normalize(mut self, normalize: Option<NormalizeOptions>) -> Self {
+        self.normalize = normalize;
+        self
+    }
+}
+
+/// Reads and strictly parses the MIDI file at `path`
+///
+/// ```rust
+/// let midi = miami::open("test/test.mid").expect("Open test file");
+/// assert_eq!(midi.tracks.len() as u16, midi.header.ntrks());
+/// ```
+pub fn open(path: impl AsRef<Path>) -> Result<Midi, MidiError> {
+    open_with(path, OpenOptions::default())
+}
+
+/// Reads and parses the MIDI file at `path` according to the given options
+pub fn open_with(path: impl AsRef<Path>, options: OpenOptions) -> Result<Midi, MidiError> {
+    let mut midi = Midi::from_path_with(path, options.profile)?.midi;
+
+    if let Some(normalize) = options.normalize {
+        midi.normalize(normalize);
+    }
+
+    Ok(midi)
+}
+
+impl Midi {
+    /// Reads and parses the MIDI file at `path` like [`Midi::try_from_midi_stream_with`],
+    /// reporting everything the [`ParseProfile`] skipped or tolerated
+    /// reporting everything the [`ParseProfile`] skipped or tolerated. A gzip-compressed file is
+    /// decompressed first with the `flate` feature, and fails with
+    /// [`MidiError::CompressedInputUnsupported`] without it
+    pub fn from_path_with(
+        path: impl AsRef<Path>,
+        profile: ParseProfile,
+    ) -> Result<ParseOutcome, MidiError> {
+        let bytes = decompress(std::fs::read(path)?)?;
+        Ok(Self::try_from_midi_stream_with(bytes.into_iter(), profile)?)
+    }
+}
+
+/// The first three bytes of every gzip stream: its two magic bytes and the deflate method. A MIDI
+/// file starts with `MThd`, so these never match one
+const GZIP_MAGIC: [u8; 3] = [0x1F, 0x8B, 0x08];
+
+/// Decompresses `bytes` if they're gzip-compressed, and hands them back untouched otherwise
+#[cfg(feature = "flate")]
+fn decompress(bytes: Vec<u8>) -> Result<Vec<u8>, MidiError> {
+    use std::io::Read;
+
+    if !bytes.starts_with(&GZIP_MAGIC) {
+        return Ok(bytes);
+    }
+
+    let mut decompressed = vec![];
+    flate2::read::MultiGzDecoder::new(&bytes[..]).read_to_end(&mut decompressed)?;
+    Ok(decompressed)
+}
+
+/// Hands `bytes` back untouched, or fails with [`MidiError::CompressedInputUnsupported`] if
+/// they're gzip-compressed, since decompressing them needs the `flate` feature
+#[cfg(not(feature = "flate"))]
+fn decompress(bytes: Vec<u8>) -> Result<Vec<u8>, MidiError> {
+    if bytes.starts_with(&GZIP_MAGIC) {
+        return Err(MidiError::CompressedInputUnsupported);
+    }
+
+    Ok(bytes)
+}
+
+/// Writes `midi` to a new file at `path`, replacing anything already there. Tracks are streamed
+/// to the file one at a time, and the header written is [`Midi::consistent_header`]. With
+/// [`WriteOptions::verify_roundtrip`], the default in debug builds, the file is written in memory
+/// and read back first, and `path` is left alone if it fails with [`MidiError::Write`]
+pub fn save(midi: &Midi, path: impl AsRef<Path>) -> Result<(), MidiError> {
+    save_with(midi, path, SaveOptions::default())
+}
+
+/// Writes `midi` to a new file at `path` like [`save`], according to the given options
+pub fn save_with(
+    midi: &Midi,
+    path: impl AsRef<Path>,
+    options: SaveOptions,
+) -> Result<(), MidiError> {
+    write_to(midi, || Ok(BufWriter::new(File::create(path)?)), options)?;
+
+    Ok(())
+}
+
+/// Writes `midi` to a new gzip-compressed file at `path`, replacing anything already there. See
+/// [`save`]
+#[cfg(feature = "flate")]
+pub fn save_gz(midi: &Midi, path: impl AsRef<Path>) -> Result<(), MidiError> {
+    save_gz_with(midi, path, SaveOptions::default())
+}
+
+/// Writes `midi` to a new gzip-compressed file at `path` like [`save_gz`], according to the given
+/// options. The file is written in memory first, since its track count is only known at the end,
+/// then compressed to disk
+#[cfg(feature = "flate")]
+pub fn save_gz_with(
+    midi: &Midi,
+    path: impl AsRef<Path>,
+    options: SaveOptions,
+) -> Result<(), MidiError> {
+    let bytes = write_to(midi, || Ok(Cursor::new(vec![])), options)?.into_inner();
+
+    let sink = BufWriter::new(File::create(path)?);
+    let mut encoder = flate2::write::GzEncoder::new(sink, flate2::Compression::default());
+    encoder.write_all(&bytes)?;
+    encoder.finish()?.flush()?;
+
+    Ok(())
+}
+
+/// Streams `midi` to the sink `open` creates one track at a time, normalizing or stamping a copy
+/// first if the options ask for it, and hands the sink back. A file that's verified is written in
+/// memory first, and the sink is only created once it reads back the same
+fn write_to<W: Write + Seek>(
+    midi: &Midi,
+    open: impl FnOnce() -> Result<W, MidiError>,
+    options: SaveOptions,
+) -> Result<W, MidiError> {
+    let prepared;
+    let midi = if options.normalize.is_some() || options.write.stamp_generator {
+        let mut copy = midi.clone();
+        if let Some(normalize) = options.normalize {
+            copy.normalize(normalize);
+        }
+        if options.write.stamp_generator {
+            copy.set_generator(GENERATOR).map_err(WriteError::Stamp)?;
+        }
+        prepared = copy;
+        &prepared
+    } else {
+        midi
+    };
+
+    if !options.write.verify_roundtrip {
+        return stream_to(midi, open()?, options.write);
+    }
+
+    let bytes = stream_to(midi, Cursor::new(vec![]), options.write)?.into_inner();
+    options.write.check_roundtrip(midi, &bytes)?;
+    let mut sink = open()?;
+    sink.write_all(&bytes)?;
+    sink.flush()?;
+
+    Ok(sink)
+}
+
+/// Streams `midi` to `sink` one track at a time under [`Midi::consistent_header`]
+fn stream_to<W: Write + Seek>(midi: &Midi, sink: W, options: WriteOptions) -> Result<W, MidiError> {
+    let header = midi.consistent_header();
+    let mut writer =
+        MidiFileWriter::with_options(sink, header.format(), header.division(), options)?;
+    for track in &midi.tracks {
+        writer.write_track(track)?;
+    }
+
+    Ok(writer.finish()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{open, open_with, save, save_with, OpenOptions, SaveOptions};
+    use crate::{
+        chunk::track::{
+            meta::{MetaEvent, TextEncoding},
+            Event, MTrkEvent,
+        },
+        normalize::NormalizeOptions,
+        reader::{MidiReadable, ParseOptions, ParseProfile},
+        writer::{MidiWriteable, WriteError, WriteOptions},
+        Midi, MidiError, MidiSanitizerError,
+    };
+
+    /// A directory of its own under the system temp directory, removed when dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        /// Creates a fresh directory named after the test using it
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("miami_{name}_{}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&path);
+            std::fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_file_round_trips_through_disk() {
+        let dir = TempDir::new("round_trip");
+        let path = dir.0.join("copy.mid");
+
+        let midi = open("test/test.mid").unwrap();
+        save(&midi, &path).unwrap();
+
+        let original: Vec<u8> = "test/test.mid".get_midi_bytes().unwrap().collect();
+        assert_eq!(std::fs::read(&path).unwrap(), original);
+        assert_eq!(open(&path).unwrap(), midi);
+    }
+
+    #[test]
+    fn options_apply_on_open_and_save() {
+        let dir = TempDir::new("options");
+        let path = dir.0.join("normalized.mid");
+
+        let mut expected = open("test/test.mid").unwrap();
+        expected.normalize(NormalizeOptions::default());
+
+        let normalize = Some(NormalizeOptions::default());
+        let opened =
+            open_with("test/test.mid", OpenOptions::default().normalize(normalize)).unwrap();
+        assert_eq!(opened, expected);
+
+        let midi = open("test/test.mid").unwrap();
+        save_with(&midi, &path, SaveOptions::default().normalize(normalize)).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), expected.to_midi_bytes());
+    }
+
+    #[test]
+    fn saving_refuses_a_file_that_reads_back_differently() {
+        let dir = TempDir::new("verify");
+        let path = dir.0.join("mismatch.mid");
+
+        // An unknown meta event with the Text tag is written fine but reads back as Text
+        let mut midi = open("test/test.mid").unwrap();
+        let bad = Event::MetaEvent(MetaEvent::UnknownRaw(0x01, b"hi".to_vec().into()));
+        midi.tracks[0].mtrk_events.insert(0, MTrkEvent::new(0, bad));
+
+        let verify =
+            |verify| SaveOptions::default().write(WriteOptions::default().verify_roundtrip(verify));
+        let Err(MidiError::Write(WriteError::RoundTripMismatch { detail })) =
+            save_with(&midi, &path, verify(true))
+        else {
+            panic!("expected a round trip mismatch");
+        };
+        assert!(
+            detail.starts_with("track 0, event 0: wrote MetaEvent(UnknownRaw"),
+            "{detail}"
+        );
+        assert!(!path.exists());
+
+        save_with(&midi, &path, verify(false)).unwrap();
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn verifying_reads_back_events_of_any_size() {
+        let dir = TempDir::new("verify_large");
+        let path = dir.0.join("large.mid");
+
+        // Twice the largest event payload parsing allows by default
+        let mut midi = open("test/test.mid").unwrap();
+        let lyric = Event::MetaEvent(MetaEvent::Lyric("la".repeat(1 << 20).into()));
+        midi.tracks[0]
+            .mtrk_events
+            .insert(0, MTrkEvent::new(0, lyric));
+
+        save(&midi, &path).unwrap();
+        let profile =
+            ParseProfile::strict().options(ParseOptions::default().max_event_payload(u32::MAX));
+        let read = Midi::from_path_with(&path, profile).unwrap().midi;
+        assert!(read.tracks[0].semantic_eq(&midi.tracks[0]));
+    }
+
+    #[test]
+    fn builders_compose_with_the_defaults() {
+        let write = WriteOptions::default()
+            .pad_odd_chunks(true)
+            .encoding(TextEncoding::Latin1);
+        let save = SaveOptions::default().write(write).normalize(Some(
+            NormalizeOptions::default().recover_trailing_notes(true),
+        ));
+        assert!(save.write.pad_odd_chunks);
+        assert_eq!(save.write.encoding, TextEncoding::Latin1);
+        assert!(save
+            .normalize
+            .is_some_and(|normalize| normalize.recover_trailing_notes
+                && !normalize.canonicalize_simultaneous));
+
+        let parse = ParseOptions::default()
+            .max_total_bytes(1 << 20)
+            .keep_raw(true);
+        let open = OpenOptions::default().profile(ParseProfile::recovering().options(parse));
+        assert_eq!(open.normalize, None);
+        assert_eq!(open.profile, ParseProfile::recovering().options(parse));
+        assert_eq!(
+            parse.max_event_payload,
+            ParseOptions::default().max_event_payload
+        );
+
+        // A later call overrides an earlier one, and nothing else changes
+        assert_eq!(
+            WriteOptions::default()
+                .pad_odd_chunks(true)
+                .pad_odd_chunks(false),
+            WriteOptions::default()
+        );
+    }
+
+    #[test]
+    fn strict_open_rejects_what_lenient_open_recovers() {
+        let dir = TempDir::new("lenient");
+        let path = dir.0.join("headless.mid");
+
+        let midi = open("test/test.mid").unwrap();
+        // Padding before the header, which a lenient parse skips over
+        let mut bytes = vec![0x00; 3];
+        bytes.extend(midi.clone().to_midi_bytes());
+        std::fs::write(&path, bytes).unwrap();
+
+        assert!(open(&path).is_err());
+        let lenient = OpenOptions::default().profile(ParseProfile::permissive());
+        assert_eq!(open_with(&path, lenient).unwrap(), midi);
+
+        assert!(matches!(
+            open(dir.0.join("missing.mid")),
+            Err(MidiError::Io(_))
+        ));
+
+        std::fs::write(&path, []).unwrap();
+        assert!(matches!(
+            open(&path),
+            Err(MidiError::Structure(MidiSanitizerError::NoChunks))
+        ));
+    }
+
+    #[cfg(feature = "flate")]
+    #[test]
+    fn gzipped_files_open_and_save_transparently() {
+        use std::io::Write;
+
+        let dir = TempDir::new("gzip");
+        let path = dir.0.join("test.mid.gz");
+
+        let original = std::fs::read("test/test.mid").unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(&original).unwrap();
+        let gzipped = encoder.finish().unwrap();
+        assert!(gzipped.starts_with(&[0x1F, 0x8B]));
+        std::fs::write(&path, &gzipped).unwrap();
+
+        let midi = open("test/test.mid").unwrap();
+        assert_eq!(open(&path).unwrap(), midi);
+
+        let copy = dir.0.join("copy.mid.gz");
+        super::save_gz(&midi, &copy).unwrap();
+        let mut decompressed = vec![];
+        std::io::Read::read_to_end(
+            &mut flate2::read::GzDecoder::new(&std::fs::read(&copy).unwrap()[..]),
+            &mut decompressed,
+        )
+        .unwrap();
+        assert_eq!(decompressed, original);
+        assert_eq!(open(&copy).unwrap(), midi);
+
+        // A damaged stream is an I/O error rather than a chunk error
+        std::fs::write(&path, &gzipped[..gzipped.len() / 2]).unwrap();
+        assert!(matches!(open(&path), Err(MidiError::Io(_))));
+    }
+
+    #[cfg(not(feature = "flate"))]
+    #[test]
+    fn gzipped_files_need_the_flate_feature() {
+        let dir = TempDir::new("gzip_unsupported");
+        let path = dir.0.join("test.mid.gz");
+
+        // A gzip header with an empty stored block, compressing nothing
+        let gzipped = [
+            0x1F, 0x8B, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x01, 0x00, 0x00, 0xFF,
+            0xFF, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        std::fs::write(&path, gzipped).unwrap();
+        assert!(matches!(
+            open(&path),
+            Err(MidiError::CompressedInputUnsupported)
+        ));
+
+        // Files merely starting with the same byte aren't mistaken for gzip
+        std::fs::write(&path, [0x1F, 0x8B, 0x00]).unwrap();
+        assert!(matches!(open(&path), Err(MidiError::Parse(_))));
+    }
+}
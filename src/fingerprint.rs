@@ -0,0 +1,227 @@
+//! Encoding independent fingerprints of MIDI files for deduplication
+
+use std::collections::BTreeMap;
+
+use crate::{
+    chunk::track::{event::MidiEvent, meta::MetaEvent, Event, TrackChunk},
+    time::Tick,
+    writer::MidiWriteable,
+    Midi,
+};
+
+/// 64 bit FNV-1a offset basis
+const FNV_OFFSET: u64 = 0xCBF2_9CE4_8422_2325;
+/// 64 bit FNV-1a prime
+const FNV_PRIME: u64 = 0x0000_0100_0000_01B3;
+
+/// Hashes bytes with 64 bit FNV-1a, which is tiny, dependency free and fully specified
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// Encodes an event for comparison, or `None` if it doesn't affect playback. Note Offs lose
+/// their release velocity and Note Ons with a velocity of 0 become Note Offs, since DAWs
+/// disagree on both
+fn content_bytes(event: &Event) -> Option<Vec<u8>> {
+    match event {
+        Event::MidiEvent(MidiEvent::NoteOff(channel, note)) => {
+            Some(vec![0x80 | channel, note.key, 0])
+        }
+        Event::MidiEvent(MidiEvent::NoteOn(channel, note)) if note.velocity == 0 => {
+            Some(vec![0x80 | channel, note.key, 0])
+        }
+        Event::MidiEvent(event) => Some(event.to_midi_bytes()),
+        Event::SysexEvent(sysex) => Some(sysex.clone().to_midi_bytes()),
+        Event::MetaEvent(
+            MetaEvent::EndOfTrack
+            | MetaEvent::CuePoint(_)
+            | MetaEvent::Text(_)
+            | MetaEvent::Copyright(_)
+            | MetaEvent::TrackName(_)
+            | MetaEvent::InstrumentName(_)
+            | MetaEvent::Lyric(_)
+            | MetaEvent::Marker(_),
+        ) => None,
+        Event::MetaEvent(meta) => Some(meta.clone().to_midi_bytes()),
+    }
+}
+
+impl TrackChunk {
+    /// Every event in the track that affects playback as `(absolute tick, encoding)` pairs,
+    /// sorted like [`Midi::content_stream`]
+    fn content_stream(&self) -> Vec<(Tick, Vec<u8>)> {
+        let mut stream: Vec<_> = self
+            .absolute_events()
+            .filter_map(|(tick, event)| Some((tick, content_bytes(event)?)))
+            .collect();
+        stream.sort();
+
+        stream
+    }
+}
+
+impl Midi {
+    /// Every event that affects playback, merged across tracks as `(absolute tick, encoding)`
+    /// pairs and sorted so that neither track layout nor the order of simultaneous events matter
+    fn content_stream(&self) -> Vec<(Tick, Vec<u8>)> {
+        let mut stream: Vec<_> = self
+            .tracks
+            .iter()
+            .flat_map(TrackChunk::content_stream)
+            .collect();
+        stream.sort();
+
+        stream
+    }
+
+    /// Hashes what the file plays, ignoring how it's encoded. The division and every event
+    /// other than text meta events and `EndOfTrack` are merged across tracks by absolute tick
+    /// and hashed, so splitting tracks differently, reordering simultaneous events, renaming
+    /// tracks, padding delta times or choosing a different Note Off style all give the same
+    /// fingerprint.
+    ///
+    /// The hash is 64 bit FNV-1a over a fixed encoding, and is only ever changed in a major
+    /// release, so fingerprints can be stored and compared across minor versions
+    pub fn fingerprint(&self) -> u64 {
+        let division = self.header.division().to_midi_bytes();
+        let mut hash = fnv1a(FNV_OFFSET, &division);
+
+        for (tick, bytes) in self.content_stream() {
+            hash = fnv1a(hash, &tick.get().to_le_bytes());
+            hash = fnv1a(hash, &(bytes.len() as u32).to_le_bytes());
+            hash = fnv1a(hash, &bytes);
+        }
+
+        hash
+    }
+
+    /// Exactly compares what two files play, using the same normalization as
+    /// [`Midi::fingerprint`] but without the chance of a hash collision
+    pub fn content_eq(&self, other: &Midi) -> bool {
+        self.header.division() == other.header.division()
+            && self.content_stream() == other.content_stream()
+    }
+
+    /// Groups the indices of tracks that play the same thing, compared with the same
+    /// normalization as [`Midi::content_eq`], so tracks differing only in their names, text
+    /// events, Note Off style or the order of simultaneous events count as duplicates. Only
+    /// groups of two or more are returned, each in ascending order and ordered by their first
+    /// track. Tracks that play nothing at all are never reported
+    pub fn find_duplicate_tracks(&self) -> Vec<Vec<usize>> {
+        let mut groups: BTreeMap<Vec<(Tick, Vec<u8>)>, Vec<usize>> = BTreeMap::new();
+        for (idx, track) in self.tracks.iter().enumerate() {
+            let stream = track.content_stream();
+            if !stream.is_empty() {
+                groups.entry(stream).or_default().push(idx);
+            }
+        }
+
+        let mut duplicates: Vec<Vec<usize>> = groups
+            .into_values()
+            .filter(|group| group.len() > 1)
+            .collect();
+        duplicates.sort();
+
+        duplicates
+    }
+
+    /// Removes every track reported by [`Midi::find_duplicate_tracks`] except the first of its
+    /// group, and syncs the header's track count
+    pub fn dedup_tracks(&mut self) {
+        let mut removed: Vec<usize> = self
+            .find_duplicate_tracks()
+            .into_iter()
+            .flat_map(|group| group.into_iter().skip(1))
+            .collect();
+        removed.sort_unstable();
+
+        for idx in removed.into_iter().rev() {
+            self.tracks.remove(idx);
+        }
+        self.header = self.consistent_header();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        chunk::track::{
+            editor::TrackEditor,
+            event::{MidiEvent, NoteMeta},
+            meta::MetaEvent,
+            Event,
+        },
+        reader::MidiReadable,
+        Midi, RawMidi,
+    };
+
+    fn fixture() -> Midi {
+        RawMidi::try_from_midi_stream("test/test4tracks.mid".get_midi_bytes().unwrap())
+            .unwrap()
+            .check_into_midi()
+            .unwrap()
+    }
+
+    #[test]
+    fn re_encoded_fixture_fingerprints_equal() {
+        let midi = fixture();
+
+        let mut editor = TrackEditor::new(midi.tracks[0].clone());
+        for (_, event) in editor.events_mut().iter_mut() {
+            match event {
+                Event::MidiEvent(MidiEvent::NoteOff(channel, note)) => {
+                    *event = Event::MidiEvent(MidiEvent::NoteOn(
+                        *channel,
+                        NoteMeta {
+                            key: note.key,
+                            velocity: 0,
+                        },
+                    ))
+                }
+                Event::MetaEvent(MetaEvent::TrackName(name)) => *name = "Renamed".into(),
+                _ => {}
+            }
+        }
+        editor.events_mut().reverse();
+        let end = editor.end_of_track();
+        editor.set_end_of_track(end.get() + 960);
+
+        let mut re_encoded = midi.clone();
+        re_encoded.tracks[0] = editor.finish().unwrap();
+        assert_ne!(re_encoded, midi);
+
+        assert_eq!(re_encoded.fingerprint(), midi.fingerprint());
+        assert!(re_encoded.content_eq(&midi));
+    }
+
+    #[test]
+    fn transposed_fixture_fingerprint_differs() {
+        let midi = fixture();
+        let mut transposed = midi.clone();
+        transposed.transpose(2);
+
+        assert_ne!(transposed.fingerprint(), midi.fingerprint());
+        assert!(!transposed.content_eq(&midi));
+    }
+
+    #[test]
+    fn duplicated_track_is_found_and_removed() {
+        let midi = fixture();
+        assert!(midi.find_duplicate_tracks().is_empty());
+
+        let mut editor = TrackEditor::new(midi.tracks[0].clone());
+        editor.retain(|_, event| !matches!(event, Event::MetaEvent(MetaEvent::TrackName(_))));
+        editor.insert(0, Event::MetaEvent(MetaEvent::TrackName("Copy".into())));
+        let mut duplicated = midi.clone();
+        duplicated.push_track(editor.finish().unwrap());
+
+        let copy = midi.tracks.len();
+        assert_eq!(duplicated.find_duplicate_tracks(), [vec![0, copy]]);
+
+        duplicated.dedup_tracks();
+        assert_eq!(duplicated.tracks, midi.tracks);
+        assert_eq!(duplicated.header.ntrks as usize, copy);
+    }
+}
@@ -0,0 +1,629 @@
+//! A flat, list of records form of a file for exchanging events with other tools, where every
+//! event is a [`FlatEvent`] with the same handful of fields whatever its kind. With the `serde`
+//! feature each record serializes as a plain object, and its kind as the name it displays as,
+//! such as `"note_on"` or `"meta:lyric"`.
+//!
+//! How each kind of event fills in the fields, where unlisted fields are `None`, zero or empty:
+//!
+//! | Kind | `channel` | `data` | `text` | `aux` |
+//! |------|-----------|--------|--------|-------|
+//! | Note Off, Note On, polyphonic key pressure | channel | key, velocity or pressure | | |
+//! | Control Change | channel | controller, value | | |
+//! | Program Change | channel | program | | |
+//! | Channel pressure | channel | pressure | | |
+//! | Pitch bend | channel | the 14 bit value, centered at 8192 | | |
+//! | System exclusive | | | | manufacturer ID then payload |
+//! | Sequence number | | number | | |
+//! | Text, copyright, names, lyric, marker and cue point | | | text | |
+//! | Channel prefix | | channel | | |
+//! | End of track | | | | |
+//! | Tempo | | microseconds per quarter note | | |
+//! | SMPTE offset | | hours, minutes, seconds | | frames, subframes |
+//! | Time signature | | numerator, denominator, clocks per click | | 32nd notes per quarter |
+//! | Key signature | | sharps or flats as a signed byte, 1 for minor | | |
+//! | Sequencer specific | | | | payload |
+//! | Unknown meta event | | tag | | payload |
+//!
+//! Converting to flat events and back gives back the same events with two exceptions: text
+//! comes back as UTF-8 with anything that wasn't valid UTF-8 replaced, and a system exclusive
+//! event's manufacturer ID is read back as three bytes whenever it starts with 0x00, like it is
+//! when parsing
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chunk::{
+        header::Division,
+        track::{
+            event::{ControlChange, MidiEvent, NoteMeta, PitchBend},
+            kind::{EventKind, MetaKind},
+            meta::{KeySignature, MetaEvent, SmpteOffset, TimeSignature},
+            sysex::{ManufactureId, SysexEvent},
+            Event, TrackChunk,
+        },
+    },
+    time::Tick,
+    Midi,
+};
+
+/// One event of a file as a flat record, see the [module docs](self) for how each kind of event
+/// fills in the fields
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FlatEvent {
+    /// Index of the track holding the event
+    pub track: u16,
+    /// Absolute tick of the event
+    pub tick: Tick,
+    /// What kind of event this is
+    pub kind: EventKind,
+    /// Channel of a channel message
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub channel: Option<u8>,
+    /// Up to three numbers describing the event
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub data: [u32; 3],
+    /// Text of a text bearing meta event
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub text: Option<String>,
+    /// Raw bytes of the event that don't fit in `data`
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub aux: Vec<u8>,
+}
+
+/// Error converting flat events back into a [`Midi`] with [`Midi::from_flat`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlatError {
+    /// An event's field is missing or out of range for its kind
+    InvalidField {
+        /// Index of the event in the input
+        index: usize,
+        /// Name of the field
+        field: &'static str,
+    },
+    /// Two consecutive events of a track are further apart than a delta time can hold
+    TickGapTooLarge {
+        /// Index of the track
+        track: u16,
+    },
+}
+
+impl core::error::Error for FlatError {}
+impl core::fmt::Display for FlatError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::InvalidField { index, field } => {
+                write![f, "Event {index} has a missing or out of range `{field}`"]
+            }
+            Self::TickGapTooLarge { track } => {
+                write![f, "Track {track} has events too far apart for a delta time"]
+            }
+        }
+    }
+}
+
+impl FlatEvent {
+    /// A record of the given kind with every other field empty
+    fn new(track: u16, tick: Tick, kind: EventKind) -> Self {
+        Self {
+            track,
+            tick,
+            kind,
+            channel: None,
+            data: [0; 3],
+            text: None,
+            aux: vec![],
+        }
+    }
+
+    /// Flattens a single event
+    fn from_event(track: u16, tick: Tick, event: &Event) -> Self {
+        let mut flat = Self::new(track, tick, event.kind());
+
+        match event {
+            Event::MidiEvent(midi) => {
+                flat.channel = Some(midi.channel());
+                flat.data = match midi {
+                    MidiEvent::NoteOff(_, note)
+                    | MidiEvent::NoteOn(_, note)
+                    | MidiEvent::PolyphonicKeyPressure(_, note) => {
+                        [note.key.into(), note.velocity.into(), 0]
+                    }
+                    MidiEvent::ControlChange(_, change) => {
+                        [change.controller().into(), change.value().into(), 0]
+                    }
+                    MidiEvent::ProgramChange { program: value, .. }
+                    | MidiEvent::ChannelPressure {
+                        pressure: value, ..
+                    } => [(*value).into(), 0, 0],
+                    MidiEvent::PitchWheelChange(_, value) => [(*value).into(), 0, 0],
+                };
+            }
+            Event::SysexEvent(sysex) => {
+                flat.aux = match sysex.manufacture_id {
+                    ManufactureId::OneByte(id) => vec![id],
+                    ManufactureId::ThreeByte(id) => id.to_vec(),
+                };
+                flat.aux.extend_from_slice(&sysex.payload);
+            }
+            Event::MetaEvent(meta) => match meta {
+                MetaEvent::SequenceNumber(number) => flat.data[0] = (*number).into(),
+                MetaEvent::CuePoint(bytes) => {
+                    flat.text = Some(String::from_utf8_lossy(bytes).into_owned())
+                }
+                MetaEvent::MidiChannelPrefix(channel) => flat.data[0] = (*channel).into(),
+                MetaEvent::EndOfTrack => {}
+                MetaEvent::Tempo(tempo) => flat.data[0] = *tempo,
+                MetaEvent::SmpteOffset(offset) => {
+                    flat.data = [
+                        offset.hours.into(),
+                        offset.minutes.into(),
+                        offset.seconds.into(),
+                    ];
+                    flat.aux = vec![offset.frames, offset.subframes];
+                }
+                MetaEvent::TimeSignature(signature) => {
+                    flat.data = [
+                        signature.numerator.into(),
+                        signature.denominator,
+                        signature.clocks_per_tick.into(),
+                    ];
+                    flat.aux = vec![signature.thirty_second_notes_per_quarter];
+                }
+                MetaEvent::KeySignature(key) => {
+                    flat.data = [(key.sharps_flats as u8).into(), key.major_minor.into(), 0]
+                }
+                MetaEvent::SequencerSpecific(bytes) => flat.aux = bytes.to_vec(),
+                MetaEvent::UnknownRaw(tag, bytes) => {
+                    flat.data[0] = (*tag).into();
+                    flat.aux = bytes.to_vec();
+                }
+                text => flat.text = text.text().map(|text| text.text().into_owned()),
+            },
+        }
+
+        flat
+    }
+
+    /// Rebuilds the event, with `index` the record's position for errors
+    fn to_event(&self, index: usize) -> Result<Event, FlatError> {
+        let invalid = |field| FlatError::InvalidField { index, field };
+        let byte = |slot: usize, max: u8| {
+            u8::try_from(self.data[slot])
+                .ok()
+                .filter(|value| *value <= max)
+                .ok_or(invalid("data"))
+        };
+        let text = || self.text.clone().ok_or(invalid("text"));
+
+        let meta = match self.kind {
+            EventKind::Sysex => {
+                let (manufacture_id, payload) = match self.aux.as_slice() {
+                    [0x00, second, third, payload @ ..] => {
+                        (ManufactureId::ThreeByte([0x00, *second, *third]), payload)
+                    }
+                    [first, payload @ ..] if *first != 0x00 => {
+                        (ManufactureId::OneByte(*first), payload)
+                    }
+                    _ => return Err(invalid("aux")),
+                };
+                return Ok(Event::SysexEvent(SysexEvent {
+                    manufacture_id,
+                    payload: payload.into(),
+                }));
+            }
+            EventKind::Meta(kind) => kind,
+            kind => {
+                let channel = self
+                    .channel
+                    .filter(|channel| *channel <= 0x0F)
+                    .ok_or(invalid("channel"))?;
+                let note =
+                    || NoteMeta::new(byte(0, 0x7F)?, byte(1, 0x7F)?).map_err(|_| invalid("data"));
+                let midi = match kind {
+                    EventKind::NoteOff => MidiEvent::NoteOff(channel, note()?),
+                    EventKind::NoteOn => MidiEvent::NoteOn(channel, note()?),
+                    EventKind::PolyphonicKeyPressure => {
+                        MidiEvent::PolyphonicKeyPressure(channel, note()?)
+                    }
+                    EventKind::ControlChange => MidiEvent::ControlChange(
+                        channel,
+                        ControlChange::new(byte(0, 0x7F)?, byte(1, 0x7F)?)
+                            .map_err(|_| invalid("data"))?,
+                    ),
+                    EventKind::ProgramChange => MidiEvent::ProgramChange {
+                        channel,
+                        program: byte(0, 0x7F)?,
+                    },
+                    EventKind::ChannelPressure => MidiEvent::ChannelPressure {
+                        channel,
+                        pressure: byte(0, 0x7F)?,
+                    },
+                    _ => MidiEvent::PitchWheelChange(
+                        channel,
+                        u16::try_from(self.data[0])
+                            .ok()
+                            .filter(|value| *value <= PitchBend::MAX)
+                            .ok_or(invalid("data"))?,
+                    ),
+                };
+                return Ok(Event::MidiEvent(midi));
+            }
+        };
+
+        let meta = match meta {
+            MetaKind::SequenceNumber => {
+                MetaEvent::SequenceNumber(u16::try_from(self.data[0]).map_err(|_| invalid("data"))?)
+            }
+            MetaKind::Text => MetaEvent::Text(text()?.into()),
+            MetaKind::Copyright => MetaEvent::Copyright(text()?.into()),
+            MetaKind::TrackName => MetaEvent::TrackName(text()?.into()),
+            MetaKind::InstrumentName => MetaEvent::InstrumentName(text()?.into()),
+            MetaKind::Lyric => MetaEvent::Lyric(text()?.into()),
+            MetaKind::Marker => MetaEvent::Marker(text()?.into()),
+            MetaKind::CuePoint => MetaEvent::CuePoint(text()?.into_bytes().into()),
+            MetaKind::MidiChannelPrefix => MetaEvent::MidiChannelPrefix(byte(0, 0x0F)?),
+            MetaKind::EndOfTrack => MetaEvent::EndOfTrack,
+            MetaKind::Tempo => MetaEvent::Tempo(
+                Some(self.data[0])
+                    .filter(|tempo| *tempo <= 0xFF_FFFF)
+                    .ok_or(invalid("data"))?,
+            ),
+            MetaKind::SmpteOffset => {
+                let [frames, subframes] = self.aux[..].try_into().map_err(|_| invalid("aux"))?;
+                MetaEvent::SmpteOffset(SmpteOffset {
+                    hours: byte(0, u8::MAX)?,
+                    minutes: byte(1, u8::MAX)?,
+                    seconds: byte(2, u8::MAX)?,
+                    frames,
+                    subframes,
+                })
+            }
+            MetaKind::TimeSignature => {
+                let [thirty_second_notes_per_quarter] =
+                    self.aux[..].try_into().map_err(|_| invalid("aux"))?;
+                MetaEvent::TimeSignature(TimeSignature {
+                    numerator: byte(0, u8::MAX)?,
+                    denominator: Some(self.data[1])
+                        .filter(|denominator| denominator.is_power_of_two())
+                        .ok_or(invalid("data"))?,
+                    clocks_per_tick: byte(2, u8::MAX)?,
+                    thirty_second_notes_per_quarter,
+                })
+            }
+            MetaKind::KeySignature => MetaEvent::KeySignature(KeySignature {
+                sharps_flats: byte(0, u8::MAX)? as i8,
+                major_minor: byte(1, 1)? == 1,
+            }),
+            MetaKind::SequencerSpecific => MetaEvent::SequencerSpecific(self.aux.clone().into()),
+            MetaKind::Unknown => MetaEvent::UnknownRaw(byte(0, u8::MAX)?, self.aux.clone().into()),
+        };
+
+        Ok(Event::MetaEvent(meta))
+    }
+}
+
+impl Midi {
+    /// Every event in the file as a flat record, in track order and then in order within each
+    /// track
+    pub fn to_flat(&self) -> Vec<FlatEvent> {
+        self.tracks
+            .iter()
+            .enumerate()
+            .flat_map(|(track, chunk)| {
+                chunk
+                    .absolute_events()
+                    .map(move |(tick, event)| FlatEvent::from_event(track as u16, tick, event))
+            })
+            .collect()
+    }
+
+    /// Rebuilds a file from flat records, with as many tracks as the highest track index needs.
+    /// Records don't need to be in order: each track's events are sorted by tick, keeping the
+    /// order of events at the same tick. The header is format 0 for a single track and format 1
+    /// otherwise
+    pub fn from_flat(division: Division, events: &[FlatEvent]) -> Result<Midi, FlatError> {
+        let ntracks = events
+            .iter()
+            .map(|event| usize::from(event.track) + 1)
+            .max()
+            .unwrap_or(0);
+        let mut per_track = vec![vec![]; ntracks];
+        for (index, flat) in events.iter().enumerate() {
+            per_track[usize::from(flat.track)].push((flat.tick, flat.to_event(index)?));
+        }
+
+        let tracks = per_track
+            .into_iter()
+            .enumerate()
+            .map(|(track, mut events)| {
+                events.sort_by_key(|(tick, _)| *tick);
+                TrackChunk::from_absolute_events(events).ok_or(FlatError::TickGapTooLarge {
+                    track: track as u16,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Midi::new(tracks, division))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FlatError, FlatEvent};
+    use crate::{
+        chunk::{
+            header::{Division, HeaderChunk},
+            track::{
+                event::{ControlChange, MidiEvent, NoteMeta},
+                kind::{EventKind, MetaKind},
+                meta::{
+                    KeySignature, MetaEvent, MetaText, SmpteOffset, TextEncoding, TimeSignature,
+                },
+                sysex::{ManufactureId, SysexEvent},
+                Event, TrackChunk,
+            },
+        },
+        reader::MidiReadable,
+        Midi, RawMidi,
+    };
+
+    /// A file with every kind of event
+    fn diverse() -> Midi {
+        let meta = Event::MetaEvent;
+        let conductor = TrackChunk::from_absolute_events([
+            (0, meta(MetaEvent::SequenceNumber(7))),
+            (0, meta(MetaEvent::TrackName("Conductor".into()))),
+            (0, meta(MetaEvent::Copyright("© Somebody".into()))),
+            (0, meta(MetaEvent::DEFAULT_TEMPO)),
+            (
+                0,
+                meta(MetaEvent::SmpteOffset(SmpteOffset {
+                    hours: 1,
+                    minutes: 2,
+                    seconds: 3,
+                    frames: 4,
+                    subframes: 5,
+                })),
+            ),
+            (
+                0,
+                meta(MetaEvent::TimeSignature(TimeSignature {
+                    numerator: 6,
+                    denominator: 8,
+                    clocks_per_tick: 36,
+                    thirty_second_notes_per_quarter: 8,
+                })),
+            ),
+            (
+                0,
+                meta(MetaEvent::KeySignature(KeySignature {
+                    sharps_flats: -3,
+                    major_minor: true,
+                })),
+            ),
+            (480, meta(MetaEvent::Marker("Verse".into()))),
+            (480, meta(MetaEvent::CuePoint(b"Lights".to_vec().into()))),
+            (960, meta(MetaEvent::Text("Any text".into()))),
+            (
+                960,
+                meta(MetaEvent::SequencerSpecific(vec![0x41, 1, 2].into())),
+            ),
+            (960, meta(MetaEvent::UnknownRaw(0x60, vec![9, 9].into()))),
+            (1920, meta(MetaEvent::EndOfTrack)),
+        ])
+        .unwrap();
+        let part = TrackChunk::from_absolute_events([
+            (0, meta(MetaEvent::MidiChannelPrefix(3))),
+            (0, meta(MetaEvent::InstrumentName("Piano".into()))),
+            (
+                0,
+                Event::MidiEvent(MidiEvent::ProgramChange {
+                    channel: 3,
+                    program: 5,
+                }),
+            ),
+            (
+                0,
+                Event::MidiEvent(MidiEvent::ControlChange(
+                    3,
+                    ControlChange::new(ControlChange::SUSTAIN, 127).unwrap(),
+                )),
+            ),
+            (
+                0,
+                Event::MidiEvent(MidiEvent::NoteOn(3, NoteMeta::new(60, 90).unwrap())),
+            ),
+            (0, meta(MetaEvent::Lyric("Hel".into()))),
+            (
+                240,
+                Event::MidiEvent(MidiEvent::PolyphonicKeyPressure(
+                    3,
+                    NoteMeta::new(60, 40).unwrap(),
+                )),
+            ),
+            (
+                240,
+                Event::MidiEvent(MidiEvent::ChannelPressure {
+                    channel: 3,
+                    pressure: 30,
+                }),
+            ),
+            (
+                360,
+                Event::MidiEvent(MidiEvent::PitchWheelChange(3, 0x3FFF)),
+            ),
+            (
+                480,
+                Event::MidiEvent(MidiEvent::NoteOff(3, NoteMeta::new(60, 64).unwrap())),
+            ),
+            (
+                480,
+                Event::SysexEvent(SysexEvent {
+                    manufacture_id: ManufactureId::OneByte(0x43),
+                    payload: vec![0x10, 0x4C].into(),
+                }),
+            ),
+            (
+                480,
+                Event::SysexEvent(SysexEvent {
+                    manufacture_id: ManufactureId::ThreeByte([0x00, 0x20, 0x29]),
+                    payload: vec![].into(),
+                }),
+            ),
+            (1920, meta(MetaEvent::EndOfTrack)),
+        ])
+        .unwrap();
+
+        Midi {
+            header: HeaderChunk::try_from((1, 2, 480)).unwrap(),
+            tracks: vec![conductor, part],
+        }
+    }
+
+    #[test]
+    fn every_kind_round_trips() {
+        let midi = diverse();
+        let flat = midi.to_flat();
+
+        assert_eq!(flat.len(), 26);
+        let tempo = &flat[3];
+        assert_eq!(tempo.kind, EventKind::Meta(MetaKind::Tempo));
+        assert_eq!(tempo.data, [500_000, 0, 0]);
+        let bend = flat
+            .iter()
+            .find(|flat| flat.kind == EventKind::PitchWheelChange)
+            .unwrap();
+        assert_eq!(
+            (bend.track, bend.tick.get(), bend.channel),
+            (1, 360, Some(3))
+        );
+        assert_eq!(bend.data, [0x3FFF, 0, 0]);
+
+        let rebuilt = Midi::from_flat(Division::TPQN_480, &flat).unwrap();
+        assert_eq!(rebuilt, midi);
+
+        // Records of different tracks can be interleaved in any order
+        let mut shuffled = flat.clone();
+        shuffled.sort_by_key(|flat| (flat.tick, core::cmp::Reverse(flat.track)));
+        assert_eq!(
+            Midi::from_flat(Division::TPQN_480, &shuffled).unwrap(),
+            midi
+        );
+
+        for path in ["test/test.mid", "test/run.mid"] {
+            let bytes = path.get_midi_bytes().unwrap();
+            let fixture = RawMidi::try_from_midi_stream(bytes)
+                .unwrap()
+                .check_into_midi()
+                .unwrap();
+            let rebuilt = Midi::from_flat(fixture.header.division, &fixture.to_flat()).unwrap();
+            assert_eq!(rebuilt.tracks, fixture.tracks, "{path}");
+        }
+    }
+
+    #[test]
+    fn only_documented_losses() {
+        let latin = Event::MetaEvent(MetaEvent::Lyric(MetaText::from_bytes(
+            vec![b'C', 0xE9],
+            TextEncoding::Latin1,
+        )));
+        let midi = Midi::new(
+            vec![TrackChunk::from_absolute_events([(0, latin)]).unwrap()],
+            Division::TPQN_96,
+        );
+
+        let flat = midi.to_flat();
+        assert_eq!(flat[0].text.as_deref(), Some("Cé"));
+        let rebuilt = Midi::from_flat(Division::TPQN_96, &flat).unwrap();
+        assert_eq!(
+            rebuilt.tracks[0].meta_events().next().unwrap().1,
+            &MetaEvent::Lyric("Cé".into())
+        );
+    }
+
+    #[test]
+    fn out_of_range_fields_are_rejected() {
+        let note = || FlatEvent {
+            channel: Some(0),
+            data: [60, 90, 0],
+            ..FlatEvent::new(0, 0.into(), EventKind::NoteOn)
+        };
+        assert!(Midi::from_flat(Division::TPQN_480, &[note()]).is_ok());
+
+        for (index, flat, field) in [
+            (
+                0,
+                FlatEvent {
+                    data: [128, 90, 0],
+                    ..note()
+                },
+                "data",
+            ),
+            (
+                0,
+                FlatEvent {
+                    channel: Some(16),
+                    ..note()
+                },
+                "channel",
+            ),
+            (
+                0,
+                FlatEvent {
+                    channel: None,
+                    ..note()
+                },
+                "channel",
+            ),
+            (
+                0,
+                FlatEvent::new(0, 0.into(), EventKind::Meta(MetaKind::Lyric)),
+                "text",
+            ),
+            (0, FlatEvent::new(0, 0.into(), EventKind::Sysex), "aux"),
+            (
+                0,
+                FlatEvent {
+                    data: [4, 3, 24],
+                    aux: vec![8],
+                    ..FlatEvent::new(0, 0.into(), EventKind::Meta(MetaKind::TimeSignature))
+                },
+                "data",
+            ),
+        ] {
+            assert_eq!(
+                Midi::from_flat(Division::TPQN_480, &[flat]),
+                Err(FlatError::InvalidField { index, field })
+            );
+        }
+
+        let far = [
+            note(),
+            FlatEvent {
+                tick: u64::MAX.into(),
+                ..note()
+            },
+        ];
+        assert_eq!(
+            Midi::from_flat(Division::TPQN_480, &far),
+            Err(FlatError::TickGapTooLarge { track: 0 })
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn records_serialize_as_plain_objects() {
+        let flat = diverse().to_flat();
+        let json = serde_json::to_string(&flat).unwrap();
+        assert!(json.contains(r#""kind":"meta:tempo""#), "{json}");
+        assert!(json.contains(r#""kind":"note_on""#), "{json}");
+
+        let back: Vec<FlatEvent> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, flat);
+    }
+}
@@ -0,0 +1,392 @@
+//! Preflight checks of a file against General MIDI level 1, for content meant to play the same
+//! on any GM device
+
+use std::collections::BTreeMap;
+
+use crate::{
+    chunk::track::{event::MidiEvent, Event},
+    slice::NoteChange,
+    stats::PERCUSSION_CHANNEL,
+    time::Tick,
+    timeline::{
+        BANK_SELECT_LSB, BANK_SELECT_MSB, DATA_ENTRY_LSB, DATA_ENTRY_MSB, NRPN_LSB, NRPN_MSB,
+        RPN_LSB, RPN_MSB,
+    },
+    Midi,
+};
+
+/// The number of notes a General MIDI device has to be able to play at once
+pub const GM_POLYPHONY: usize = 24;
+
+/// The controllers a General MIDI device has to respond to, other than Bank Select to bank 0:
+/// Modulation, Data Entry, Volume, Pan, Expression, Sustain, the RPN numbers, Reset All
+/// Controllers and All Notes Off
+pub const GM_CONTROLLERS: [u8; 11] = [1, 6, 7, 10, 11, 38, 64, 100, 101, 121, 123];
+
+/// The Bank Select MSB values General MIDI 2 and XG use for drum kits
+const DRUM_BANKS: [u8; 2] = [120, 127];
+
+/// A General MIDI level 1 rule broken by a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GmRule {
+    /// A Bank Select to a bank other than 0. GM has a single bank, so programs should only be
+    /// chosen by Program Change
+    NonZeroBank {
+        /// The Bank Select controller, MSB or LSB
+        controller: u8,
+        /// The bank selected
+        bank: u8,
+    },
+    /// A Bank Select MSB to a drum kit bank on a channel other than channel 10, the only channel
+    /// GM plays percussion on
+    PercussionOutsideChannel10 {
+        /// The drum kit bank selected
+        bank: u8,
+    },
+    /// More notes sound at once than [`GM_POLYPHONY`], so a GM device may cut some off
+    Polyphony {
+        /// The most notes sounding at once before dropping back under the limit
+        peak: usize,
+    },
+    /// A controller outside [`GM_CONTROLLERS`], which a GM device may ignore
+    NonGmController(u8),
+    /// A Data Entry sent while no RPN is selected in its track, so it sets a parameter GM
+    /// doesn't know about, or none at all
+    DataEntryWithoutRpn,
+}
+
+impl core::fmt::Display for GmRule {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::NonZeroBank { controller, bank } => {
+                write![f, "Bank Select (CC {controller}) to bank {bank}"]
+            }
+            Self::PercussionOutsideChannel10 { bank } => {
+                write![f, "Drum kit bank {bank} selected outside channel 10"]
+            }
+            Self::Polyphony { peak } => write![
+                f,
+                "{peak} notes sound at once, more than the {GM_POLYPHONY} GM guarantees"
+            ],
+            Self::NonGmController(controller) => {
+                write![f, "Controller {controller} isn't part of General MIDI"]
+            }
+            Self::DataEntryWithoutRpn => write![f, "Data Entry without an RPN selected"],
+        }
+    }
+}
+
+/// Where a file breaks a [`GmRule`], from [`Midi::gm_lint`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GmIssue {
+    /// Absolute tick of the offending event
+    pub tick: Tick,
+    /// Index of the track holding it
+    pub track: usize,
+    /// Channel it was sent on, counting from 0
+    pub channel: u8,
+    /// The rule it breaks
+    pub rule: GmRule,
+}
+
+impl GmIssue {
+    /// How to bring the file back within General MIDI
+    pub fn suggestion(&self) -> &'static str {
+        match self.rule {
+            GmRule::NonZeroBank { .. } => {
+                "Remove the Bank Select and pick the closest GM program with a Program Change"
+            }
+            GmRule::PercussionOutsideChannel10 { .. } => {
+                "Move the percussion to channel 10 and remove the Bank Select"
+            }
+            GmRule::Polyphony { .. } => {
+                "Shorten overlapping notes or drop doubled ones to stay within 24 voices"
+            }
+            GmRule::NonGmController(_) => {
+                "Remove the controller, or express it with a GM controller such as CC 1 or CC 11"
+            }
+            GmRule::DataEntryWithoutRpn => {
+                "Select RPN 0 with CC 101 and CC 100 before setting the pitch bend range"
+            }
+        }
+    }
+}
+
+impl core::fmt::Display for GmIssue {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write![
+            f,
+            "Track {}, channel {} at tick {}: {}",
+            self.track,
+            self.channel + 1,
+            self.tick,
+            self.rule
+        ]
+    }
+}
+
+impl Midi {
+    /// Checks the file against General MIDI level 1 for distributing to GM players, returning
+    /// every rule broken in tick order:
+    ///
+    /// - Programs are only chosen by Program Change, so any Bank Select to a bank other than 0
+    ///   is [`GmRule::NonZeroBank`], unless it's a drum kit bank outside channel 10, which is
+    ///   [`GmRule::PercussionOutsideChannel10`]
+    /// - No more than [`GM_POLYPHONY`] notes sound at once across all tracks, counted like
+    ///   [`Midi::feature_report`], with one [`GmRule::Polyphony`] each time the limit is passed
+    /// - Only [`GM_CONTROLLERS`] are used, besides Bank Select
+    /// - The pitch bend range is left alone or set through an RPN, so a Data Entry is only
+    ///   sent once an RPN is selected with CC 101 and CC 100 in the same track, like
+    ///   [`Midi::bend_range_timeline`] expects. Selecting an NRPN or the null RPN deselects it
+    pub fn gm_lint(&self) -> Vec<GmIssue> {
+        let mut issues = vec![];
+        let mut notes = vec![];
+
+        for (track_idx, track) in self.tracks.iter().enumerate() {
+            // Selected RPN as (MSB, LSB) for each channel
+            let mut selected: BTreeMap<u8, (Option<u8>, Option<u8>)> = BTreeMap::new();
+
+            for (tick, event) in track.absolute_events() {
+                if let Some(change) = NoteChange::of(event) {
+                    notes.push((tick, track_idx, change));
+                }
+                let Event::MidiEvent(MidiEvent::ControlChange(channel, cc)) = event else {
+                    continue;
+                };
+
+                let issue = |rule| GmIssue {
+                    tick,
+                    track: track_idx,
+                    channel: *channel,
+                    rule,
+                };
+                let rpn = selected.entry(*channel).or_default();
+                match (cc.controller_number, cc.new_value) {
+                    (BANK_SELECT_MSB | BANK_SELECT_LSB, 0) => {}
+                    (BANK_SELECT_MSB, bank)
+                        if DRUM_BANKS.contains(&bank) && *channel != PERCUSSION_CHANNEL =>
+                    {
+                        issues.push(issue(GmRule::PercussionOutsideChannel10 { bank }));
+                    }
+                    (controller @ (BANK_SELECT_MSB | BANK_SELECT_LSB), bank) => {
+                        issues.push(issue(GmRule::NonZeroBank { controller, bank }));
+                    }
+                    (RPN_MSB, value) => rpn.0 = Some(value),
+                    (RPN_LSB, value) => rpn.1 = Some(value),
+                    (DATA_ENTRY_MSB | DATA_ENTRY_LSB, _) => {
+                        if !matches!(*rpn, (Some(msb), Some(lsb)) if (msb, lsb) != (127, 127)) {
+                            issues.push(issue(GmRule::DataEntryWithoutRpn));
+                        }
+                    }
+                    (controller, _) => {
+                        if matches!(controller, NRPN_MSB | NRPN_LSB) {
+                            *rpn = (None, None);
+                        }
+                        if !GM_CONTROLLERS.contains(&controller) {
+                            issues.push(issue(GmRule::NonGmController(controller)));
+                        }
+                    }
+                }
+            }
+        }
+
+        // Releases sort before starts on the same tick
+        notes.sort_by_key(|(tick, _, change)| (*tick, matches!(change, NoteChange::On(..))));
+
+        let mut sounding: BTreeMap<(u8, u8), usize> = BTreeMap::new();
+        let mut polyphony = 0;
+        let mut over = None;
+        for (tick, track, change) in notes {
+            match change {
+                NoteChange::On(channel, note) => {
+                    *sounding.entry((channel, note.key)).or_default() += 1;
+                    polyphony += 1;
+                    if polyphony <= GM_POLYPHONY {
+                        continue;
+                    }
+
+                    match over {
+                        Some(idx) => {
+                            if let GmIssue {
+                                rule: GmRule::Polyphony { peak },
+                                ..
+                            } = &mut issues[idx]
+                            {
+                                *peak = (*peak).max(polyphony);
+                            }
+                        }
+                        None => {
+                            over = Some(issues.len());
+                            issues.push(GmIssue {
+                                tick,
+                                track,
+                                channel,
+                                rule: GmRule::Polyphony { peak: polyphony },
+                            });
+                        }
+                    }
+                }
+                NoteChange::Off(channel, note) => {
+                    if let Some(count) = sounding
+                        .get_mut(&(channel, note.key))
+                        .filter(|count| **count > 0)
+                    {
+                        *count -= 1;
+                        polyphony -= 1;
+                        if polyphony <= GM_POLYPHONY {
+                            over = None;
+                        }
+                    }
+                }
+            }
+        }
+
+        // Stable, so issues at the same tick stay in track order
+        issues.sort_by_key(|issue| issue.tick);
+        issues
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GmIssue, GmRule};
+    use crate::{
+        chunk::{
+            header::HeaderChunk,
+            track::{
+                event::{ControlChange, MidiEvent, NoteMeta},
+                meta::MetaEvent,
+                Event, TrackChunk,
+            },
+        },
+        time::Tick,
+        Midi,
+    };
+
+    fn cc(channel: u8, controller_number: u8, new_value: u8) -> Event {
+        Event::MidiEvent(MidiEvent::ControlChange(
+            channel,
+            ControlChange {
+                controller_number,
+                new_value,
+            },
+        ))
+    }
+
+    fn note(on: bool, channel: u8, key: u8) -> Event {
+        let meta = NoteMeta { key, velocity: 90 };
+        Event::MidiEvent(if on {
+            MidiEvent::NoteOn(channel, meta)
+        } else {
+            MidiEvent::NoteOff(channel, meta)
+        })
+    }
+
+    /// A GM-clean file: a bend range set through RPN 0, a bank select to bank 0, a program
+    /// change and a chord of 24 notes on channel 1, and a drum hit on channel 10
+    fn clean() -> Vec<(u64, Event)> {
+        let mut events = vec![
+            (0, cc(0, 101, 0)),
+            (0, cc(0, 100, 0)),
+            (0, cc(0, 6, 12)),
+            (0, cc(0, 38, 0)),
+            (0, cc(0, 101, 127)),
+            (0, cc(0, 100, 127)),
+            (0, cc(0, 0, 0)),
+            (0, cc(0, 32, 0)),
+            (
+                0,
+                Event::MidiEvent(MidiEvent::ProgramChange {
+                    channel: 0,
+                    program: 40,
+                }),
+            ),
+            (0, cc(0, 7, 100)),
+            (0, note(true, 9, 36)),
+            (120, note(false, 9, 36)),
+        ];
+        for key in 40..64 {
+            events.push((240, note(true, 0, key)));
+            events.push((480, note(false, 0, key)));
+        }
+        events.sort_by_key(|(tick, _)| *tick);
+        events
+    }
+
+    fn lint(events: Vec<(u64, Event)>) -> Vec<GmIssue> {
+        let end = (960, Event::MetaEvent(MetaEvent::EndOfTrack));
+        let track = TrackChunk::from_absolute_events(events.into_iter().chain([end])).unwrap();
+        Midi {
+            header: HeaderChunk::try_from((0, 1, 480)).unwrap(),
+            tracks: vec![track],
+        }
+        .gm_lint()
+    }
+
+    #[test]
+    fn a_clean_file_has_no_issues() {
+        assert_eq!(lint(clean()), []);
+    }
+
+    #[test]
+    fn each_rule_is_reported_once() {
+        let violations = [
+            (
+                cc(0, 0, 1),
+                GmRule::NonZeroBank {
+                    controller: 0,
+                    bank: 1,
+                },
+                0,
+            ),
+            (
+                cc(2, 0, 127),
+                GmRule::PercussionOutsideChannel10 { bank: 127 },
+                2,
+            ),
+            (note(true, 1, 70), GmRule::Polyphony { peak: 25 }, 1),
+            (cc(0, 74, 64), GmRule::NonGmController(74), 0),
+            (cc(3, 6, 12), GmRule::DataEntryWithoutRpn, 3),
+        ];
+
+        for (event, rule, channel) in violations {
+            let mut events = clean();
+            events.push((300, event));
+            events.sort_by_key(|(tick, _)| *tick);
+
+            let issues = lint(events);
+            assert_eq!(
+                issues,
+                [GmIssue {
+                    tick: Tick::new(300),
+                    track: 0,
+                    channel,
+                    rule
+                }],
+                "{rule}"
+            );
+            assert!(!issues[0].suggestion().is_empty());
+        }
+    }
+
+    #[test]
+    fn drum_banks_on_channel_10_are_still_banks() {
+        let mut events = clean();
+        events.push((0, cc(9, 0, 127)));
+        events.sort_by_key(|(tick, _)| *tick);
+
+        let issues = lint(events);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(
+            issues[0].rule,
+            GmRule::NonZeroBank {
+                controller: 0,
+                bank: 127
+            }
+        );
+        assert_eq!(
+            issues[0].to_string(),
+            "Track 0, channel 10 at tick 0: Bank Select (CC 0) to bank 127"
+        );
+    }
+}
@@ -0,0 +1,260 @@
+//! Reproducible random variation of note timing and velocity, for making generated tracks sound
+//! less mechanical
+
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::{
+    chunk::track::{editor::TrackEditor, event::MidiEvent, Event, TrackChunk},
+    slice::NoteChange,
+    time::Tick,
+};
+
+/// How far [`TrackChunk::humanize`] may move each note
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct HumanizeOptions {
+    /// Largest number of ticks a note may start earlier or later. 0 by default
+    pub timing: u32,
+    /// Largest amount a note's velocity may be raised or lowered. 0 by default
+    pub velocity: u8,
+}
+
+impl HumanizeOptions {
+    /// Sets [`HumanizeOptions::timing`]
+    pub fn timing(mut self, ticks: u32) -> Self {
+        self.timing = ticks;
+        self
+    }
+
+    /// Sets [`HumanizeOptions::velocity`]
+    pub fn velocity(mut self, amount: u8) -> Self {
+        self.velocity = amount;
+        self
+    }
+}
+
+/// Xorshift64* generator, small and fast enough for musical randomness
+struct XorShift(u64);
+
+impl XorShift {
+    /// Seeds the generator. Every seed is valid, including 0
+    fn new(seed: u64) -> Self {
+        // Spread the seed's bits so nearby seeds don't start out alike, and avoid the all zero
+        // state xorshift can't leave
+        let mut state = seed ^ 0x9E37_79B9_7F4A_7C15;
+        state = (state ^ (state >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        state = (state ^ (state >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        Self((state ^ (state >> 31)).max(1))
+    }
+
+    /// The next 64 random bits
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// A value in `-max..=max`
+    fn offset(&mut self, max: u64) -> i64 {
+        (self.next_u64() % (2 * max + 1)) as i64 - max as i64
+    }
+}
+
+impl TrackChunk {
+    /// Moves every note's start by up to `opts.timing` ticks either way and changes its velocity
+    /// by up to `opts.velocity`, keeping velocities between 1 and 127. The same `seed` always
+    /// gives the same result.
+    ///
+    /// Each note's Note Off moves with it, so durations are unchanged. A note never starts before
+    /// tick 0, ends past the end of the track, or starts on the other side of any event other than
+    /// a note, so controllers, program changes and meta events still apply to the same notes.
+    /// Moves that would cross one are clamped to its tick instead
+    pub fn humanize(&mut self, opts: HumanizeOptions, seed: u64) {
+        let mut rng = XorShift::new(seed);
+        let mut editor = TrackEditor::new(core::mem::take(self));
+        let end = editor.end_of_track().get();
+        let events = editor.events_mut();
+
+        let mut notes: Vec<(usize, Option<usize>)> = vec![];
+        let mut sounding: BTreeMap<(u8, u8), VecDeque<usize>> = BTreeMap::new();
+        let mut fixed = vec![];
+        for (idx, (tick, event)) in events.iter().enumerate() {
+            match NoteChange::of(event) {
+                Some(NoteChange::On(channel, note)) => {
+                    sounding
+                        .entry((channel, note.key))
+                        .or_default()
+                        .push_back(notes.len());
+                    notes.push((idx, None));
+                }
+                Some(NoteChange::Off(channel, note)) => {
+                    if let Some(on) = sounding
+                        .get_mut(&(channel, note.key))
+                        .and_then(|ons| ons.pop_front())
+                    {
+                        notes[on].1 = Some(idx);
+                    }
+                }
+                None => fixed.push((idx, tick.get())),
+            }
+        }
+
+        for (on, off) in notes {
+            let start = events[on].0.get();
+            let length = off.map_or(0, |off| events[off].0.get() - start);
+
+            let idx = fixed.partition_point(|(fixed, _)| *fixed < on);
+            let earliest = idx.checked_sub(1).map_or(0, |idx| fixed[idx].1);
+            let latest = fixed
+                .get(idx)
+                .map_or(end, |(_, tick)| *tick)
+                .min(end - length);
+
+            let moved = (start as i64 + rng.offset(opts.timing as u64))
+                .clamp(earliest as i64, latest as i64) as u64;
+            let velocity = rng.offset(opts.velocity as u64);
+
+            events[on].0 = Tick(moved);
+            if let Some(off) = off {
+                events[off].0 = Tick(moved + length);
+            }
+            if let Event::MidiEvent(MidiEvent::NoteOn(_, note)) = &mut events[on].1 {
+                note.velocity = (note.velocity as i64 + velocity).clamp(1, 127) as u8;
+            }
+        }
+
+        *self = editor
+            .finish()
+            .expect("Notes only move within the track's existing span");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HumanizeOptions;
+    use crate::chunk::track::{
+        event::{ControlChange, MidiEvent, NoteMeta},
+        meta::MetaEvent,
+        Event, TrackChunk,
+    };
+
+    const OPTIONS: HumanizeOptions = HumanizeOptions {
+        timing: 20,
+        velocity: 10,
+    };
+
+    fn on(key: u8, velocity: u8) -> Event {
+        Event::MidiEvent(MidiEvent::NoteOn(0, NoteMeta { key, velocity }))
+    }
+
+    fn off(key: u8) -> Event {
+        Event::MidiEvent(MidiEvent::NoteOff(0, NoteMeta { key, velocity: 0 }))
+    }
+
+    fn sustain(value: u8) -> Event {
+        Event::MidiEvent(MidiEvent::ControlChange(
+            0,
+            ControlChange {
+                controller_number: 64,
+                new_value: value,
+            },
+        ))
+    }
+
+    fn track() -> TrackChunk {
+        TrackChunk::from_absolute_events([
+            (0, on(60, 100)),
+            (96, off(60)),
+            (100, sustain(127)),
+            (100, on(62, 90)),
+            (196, off(62)),
+            (200, on(64, 127)),
+            (290, sustain(0)),
+            (296, off(64)),
+            (300, Event::MetaEvent(MetaEvent::EndOfTrack)),
+        ])
+        .unwrap()
+    }
+
+    fn events(track: &TrackChunk) -> Vec<(u64, Event)> {
+        track
+            .absolute_events()
+            .map(|(tick, event)| (tick.get(), event.clone()))
+            .collect()
+    }
+
+    fn starts(event: &Event, key: u8) -> bool {
+        matches!(event, Event::MidiEvent(MidiEvent::NoteOn(_, note)) if note.key == key)
+    }
+
+    fn position(events: &[(u64, Event)], matches: impl Fn(&Event) -> bool) -> usize {
+        events.iter().position(|(_, event)| matches(event)).unwrap()
+    }
+
+    #[test]
+    fn fixed_seed_is_reproducible() {
+        let mut humanized = track();
+        humanized.humanize(OPTIONS, 7);
+
+        assert_eq!(
+            events(&humanized),
+            [
+                (8, on(60, 105)),
+                (100, sustain(127)),
+                (100, on(62, 92)),
+                (104, off(60)),
+                (195, on(64, 127)),
+                (196, off(62)),
+                (290, sustain(0)),
+                (291, off(64)),
+                (300, Event::MetaEvent(MetaEvent::EndOfTrack)),
+            ]
+        );
+
+        let mut again = track();
+        again.humanize(OPTIONS, 7);
+        assert_eq!(again, humanized);
+
+        let mut other = track();
+        other.humanize(OPTIONS, 8);
+        assert_ne!(other, humanized);
+    }
+
+    #[test]
+    fn notes_keep_their_length_and_bounds() {
+        for seed in 0..200 {
+            let mut humanized = track();
+            humanized.humanize(OPTIONS, seed);
+            let events = events(&humanized);
+
+            let note_on = |key| position(&events, |event| starts(event, key));
+
+            for key in [60, 62, 64] {
+                let note_off = position(&events, |event| event == &off(key));
+                assert_eq!(events[note_off].0 - events[note_on(key)].0, 96);
+            }
+
+            // The pedal still goes down after the first note starts and before the second, and
+            // comes up after the third starts
+            let down = position(&events, |event| event == &sustain(127));
+            let up = position(&events, |event| event == &sustain(0));
+            assert!(note_on(60) < down && down < note_on(62));
+            assert!(note_on(64) < up);
+
+            assert_eq!(events.last().unwrap().0, 300);
+            for (_, event) in &events {
+                if let Event::MidiEvent(MidiEvent::NoteOn(_, note)) = event {
+                    assert!((1..=127).contains(&note.velocity));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn zero_options_change_nothing() {
+        let mut humanized = track();
+        humanized.humanize(HumanizeOptions::default(), 42);
+        assert_eq!(humanized, track());
+    }
+}
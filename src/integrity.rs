@@ -0,0 +1,364 @@
+//! Chunk level checksums for checking archived MIDI files haven't changed
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{reader::find_signature, Chunk, RawMidi};
+
+/// Lookup table for the reflected IEEE CRC-32 polynomial, built at compile time
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut idx = 0;
+    while idx < 256 {
+        let mut crc = idx as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[idx] = crc;
+        idx += 1;
+    }
+    table
+};
+
+/// Computes the CRC-32 of some bytes, the same checksum zip, gzip and PNG use
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, byte| {
+        CRC32_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+/// What a manifest records about one chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ChunkRecord {
+    /// Byte offset of the chunk's header in the file
+    pub offset: usize,
+    /// The chunk's four byte type, such as `MThd` or `MTrk`
+    pub chunk_type: [u8; 4],
+    /// Payload length the chunk's header declares
+    pub declared_len: usize,
+    /// Payload length actually present, shorter than declared if the file is truncated
+    pub actual_len: usize,
+    /// CRC-32 of the payload actually present
+    pub crc32: u32,
+}
+
+/// Sizes and checksums of every chunk in a file, taken when it's archived and checked with
+/// [`Manifest::verify`] later on
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Manifest {
+    /// Length of the whole file in bytes
+    pub file_len: usize,
+    /// CRC-32 of the whole file, covering bytes outside every chunk such as padding too
+    pub file_crc32: u32,
+    /// Every chunk in the file, in order
+    pub chunks: Vec<ChunkRecord>,
+}
+
+/// A way in which a file differs from its [`Manifest`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum IntegrityViolation {
+    /// The file isn't the length it was
+    FileLength {
+        /// Length recorded in the manifest
+        expected: usize,
+        /// Length of the file now
+        found: usize,
+    },
+    /// A chunk's payload has the same layout but different contents
+    Checksum {
+        /// Index of the chunk in the file
+        index: usize,
+        /// The chunk as recorded
+        expected: ChunkRecord,
+        /// CRC-32 of the chunk's payload now
+        found: u32,
+    },
+    /// A chunk moved, changed type or changed length
+    Layout {
+        /// Index of the chunk in the file
+        index: usize,
+        /// The chunk as recorded
+        expected: ChunkRecord,
+        /// The chunk now
+        found: ChunkRecord,
+    },
+    /// A chunk recorded in the manifest is no longer in the file
+    MissingChunk {
+        /// Index of the chunk in the file
+        index: usize,
+        /// The chunk as recorded
+        expected: ChunkRecord,
+    },
+    /// The file has a chunk the manifest doesn't record
+    ExtraChunk {
+        /// Index of the chunk in the file
+        index: usize,
+        /// The chunk now
+        found: ChunkRecord,
+    },
+    /// Every chunk matches, but bytes outside them, such as padding between chunks, changed
+    FileChecksum {
+        /// CRC-32 recorded in the manifest
+        expected: u32,
+        /// CRC-32 of the file now
+        found: u32,
+    },
+}
+
+impl core::fmt::Display for IntegrityViolation {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let kind = |record: &ChunkRecord| String::from_utf8_lossy(&record.chunk_type).into_owned();
+        match self {
+            Self::FileLength { expected, found } => {
+                write![f, "File is {found} bytes long, expected {expected}"]
+            }
+            Self::Checksum {
+                index,
+                expected,
+                found,
+            } => write![
+                f,
+                "{} chunk {index} at offset {} has CRC-32 {found:08x}, expected {:08x}",
+                kind(expected),
+                expected.offset,
+                expected.crc32
+            ],
+            Self::Layout {
+                index,
+                expected,
+                found,
+            } => write![
+                f,
+                "Chunk {index} is a {} chunk of {} bytes at offset {}, expected a {} chunk of {} \
+                 bytes at offset {}",
+                kind(found),
+                found.actual_len,
+                found.offset,
+                kind(expected),
+                expected.actual_len,
+                expected.offset
+            ],
+            Self::MissingChunk { index, expected } => write![
+                f,
+                "{} chunk {index} at offset {} is missing",
+                kind(expected),
+                expected.offset
+            ],
+            Self::ExtraChunk { index, found } => write![
+                f,
+                "Unexpected {} chunk {index} at offset {}",
+                kind(found),
+                found.offset
+            ],
+            Self::FileChecksum { expected, found } => write![
+                f,
+                "Bytes outside the chunks changed, file has CRC-32 {found:08x}, expected \
+                 {expected:08x}"
+            ],
+        }
+    }
+}
+
+impl Manifest {
+    /// Records every chunk in `bytes`. A chunk header is anything with a printable type, so
+    /// unrecognized chunks are recorded as they are. Bytes that can't start one, such as padding
+    /// between chunks, are skipped up to the next `MThd` or `MTrk` like [`crate::reader::inspect`]
+    /// does, and are only covered by [`Manifest::file_crc32`]
+    pub fn of(bytes: &[u8]) -> Self {
+        let mut chunks = vec![];
+        let mut offset = 0;
+        while let Some(header) = bytes.get(offset..offset + 8) {
+            // UNWRAP Safety: The slice is exactly 8 bytes
+            if Chunk::try_from_bytes(header.try_into().unwrap()).is_err() {
+                match find_signature(bytes, offset + 1, bytes.len()) {
+                    Some(next) => offset = next,
+                    None => break,
+                }
+                continue;
+            }
+
+            let chunk_type = [header[0], header[1], header[2], header[3]];
+            let declared_len = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+            let declared_len = declared_len as usize;
+            let payload = &bytes[offset + 8..];
+            let payload = &payload[..declared_len.min(payload.len())];
+
+            chunks.push(ChunkRecord {
+                offset,
+                chunk_type,
+                declared_len,
+                actual_len: payload.len(),
+                crc32: crc32(payload),
+            });
+            offset += 8 + payload.len();
+        }
+
+        Self {
+            file_len: bytes.len(),
+            file_crc32: crc32(bytes),
+            chunks,
+        }
+    }
+
+    /// Checks `bytes` against the manifest, returning every difference found. An empty list
+    /// means the file is unchanged. Chunks are compared by index, so once one changes length
+    /// every chunk after it is reported too. A change outside every chunk is only reported when
+    /// nothing else is
+    pub fn verify(&self, bytes: &[u8]) -> Vec<IntegrityViolation> {
+        let now = Self::of(bytes);
+        let mut violations = vec![];
+
+        if now.file_len != self.file_len {
+            violations.push(IntegrityViolation::FileLength {
+                expected: self.file_len,
+                found: now.file_len,
+            });
+        }
+
+        for index in 0..self.chunks.len().max(now.chunks.len()) {
+            let violation = match (self.chunks.get(index), now.chunks.get(index)) {
+                (Some(expected), Some(found)) if expected == found => continue,
+                (Some(&expected), Some(&found))
+                    if ChunkRecord {
+                        crc32: expected.crc32,
+                        ..found
+                    } == expected =>
+                {
+                    IntegrityViolation::Checksum {
+                        index,
+                        expected,
+                        found: found.crc32,
+                    }
+                }
+                (Some(&expected), Some(&found)) => IntegrityViolation::Layout {
+                    index,
+                    expected,
+                    found,
+                },
+                (Some(&expected), None) => IntegrityViolation::MissingChunk { index, expected },
+                (None, Some(&found)) => IntegrityViolation::ExtraChunk { index, found },
+                (None, None) => unreachable!("index is below one of the chunk counts"),
+            };
+            violations.push(violation);
+        }
+
+        if violations.is_empty() && now.file_crc32 != self.file_crc32 {
+            violations.push(IntegrityViolation::FileChecksum {
+                expected: self.file_crc32,
+                found: now.file_crc32,
+            });
+        }
+
+        violations
+    }
+}
+
+impl RawMidi {
+    /// Records the sizes and checksums of every chunk in `original_bytes`, the bytes this file
+    /// was parsed from, so an archived copy can be checked with [`Manifest::verify`]. This is
+    /// [`Manifest::of`] those bytes: chunks the parse skipped are recorded too, since changes to
+    /// them are changes to the file all the same
+    pub fn integrity_manifest(&self, original_bytes: &[u8]) -> Manifest {
+        Manifest::of(original_bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc32, IntegrityViolation, Manifest};
+    use crate::RawMidi;
+
+    #[test]
+    fn crc32_matches_the_standard_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(&[]), 0);
+    }
+
+    #[test]
+    fn a_flipped_byte_is_pinned_to_its_chunk() {
+        let bytes = std::fs::read("test/test4tracks.mid").unwrap();
+        let midi = RawMidi::try_from_midi_stream(bytes.iter().copied()).unwrap();
+        let manifest = midi.integrity_manifest(&bytes);
+
+        assert_eq!(manifest.file_len, bytes.len());
+        assert_eq!(manifest.chunks.len(), midi.chunks.len());
+        assert!(manifest.verify(&bytes).is_empty());
+
+        let second = manifest.chunks[1];
+        let mut corrupted = bytes.clone();
+        corrupted[second.offset + 8 + second.actual_len / 2] ^= 0x01;
+
+        let violations = manifest.verify(&corrupted);
+        assert_eq!(violations.len(), 1);
+        let IntegrityViolation::Checksum {
+            index, expected, ..
+        } = violations[0]
+        else {
+            panic!("expected a checksum violation, got {violations:?}");
+        };
+        assert_eq!(index, 1);
+        assert_eq!(expected, second);
+    }
+
+    #[test]
+    fn padding_between_chunks_is_skipped() {
+        let bytes = std::fs::read("test/test4tracks.mid").unwrap();
+        let plain = Manifest::of(&bytes);
+
+        // One padding byte before every track
+        let mut padded = vec![];
+        for (idx, chunk) in plain.chunks.iter().enumerate() {
+            if idx > 0 {
+                padded.push(0x00);
+            }
+            padded.extend(&bytes[chunk.offset..chunk.offset + 8 + chunk.actual_len]);
+        }
+        let midi = RawMidi::try_from_midi_stream_lenient(padded.iter().copied()).unwrap();
+        let manifest = midi.integrity_manifest(&padded);
+
+        assert_eq!(manifest.chunks.len(), midi.chunks.len());
+        for (idx, (padded, plain)) in manifest.chunks.iter().zip(&plain.chunks).enumerate() {
+            assert_eq!(padded.offset, plain.offset + idx);
+            assert_eq!(padded.crc32, plain.crc32);
+        }
+        assert!(manifest.verify(&padded).is_empty());
+
+        let mut corrupted = padded.clone();
+        corrupted[manifest.chunks[1].offset - 1] = 0x01;
+        assert!(matches!(
+            manifest.verify(&corrupted)[..],
+            [IntegrityViolation::FileChecksum { .. }]
+        ));
+    }
+
+    #[test]
+    fn truncation_is_reported_as_layout_changes() {
+        let bytes = std::fs::read("test/test4tracks.mid").unwrap();
+        let manifest = Manifest::of(&bytes);
+
+        let violations = manifest.verify(&bytes[..bytes.len() - 3]);
+        let last = manifest.chunks.len() - 1;
+        assert_eq!(
+            violations[0],
+            IntegrityViolation::FileLength {
+                expected: bytes.len(),
+                found: bytes.len() - 3
+            }
+        );
+        assert!(matches!(
+            violations[1],
+            IntegrityViolation::Layout { index, found, .. }
+                if index == last && found.declared_len == found.actual_len + 3
+        ));
+        assert_eq!(violations.len(), 2);
+    }
+}
@@ -0,0 +1,284 @@
+//! The Soft Karaoke conventions of `.kar` files, where Text meta events starting with an `@`
+//! code carry the song's metadata and the rest of the Text events in the "Words" track are its
+//! lyrics
+
+use crate::{
+    chunk::track::{
+        editor::{EditError, TrackEditor},
+        meta::MetaEvent,
+        Event, TrackChunk,
+    },
+    time::Tick,
+    Midi,
+};
+
+/// Name of the track holding the file's identification and `@I` info lines
+pub const SOFT_KARAOKE_TRACK: &str = "Soft Karaoke";
+/// Name of the track holding the language, `@T` title lines and lyrics
+pub const WORDS_TRACK: &str = "Words";
+
+/// Text of the `@K` line that marks a file as Soft Karaoke
+const KARAOKE_ID: &str = "@KMIDI KARAOKE FILE";
+/// Text of the `@V` line giving the Soft Karaoke version
+const KARAOKE_VERSION: &str = "@V0100";
+
+/// A file's Soft Karaoke metadata and lyrics, as read by [`Midi::karaoke_metadata`] and written
+/// by [`Midi::set_karaoke_metadata`]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct KaraokeMeta {
+    /// Every `@T` line, which by convention hold the song's title, then its artist and then
+    /// whoever sequenced it
+    pub title: Vec<String>,
+    /// The `@L` language code, such as `ENGL`
+    pub language: Option<String>,
+    /// Every `@I` line of free form information
+    pub info: Vec<String>,
+    /// Every Text event of the Words track without an `@` code, at its absolute tick. A
+    /// syllable starting with `\` starts a new paragraph and one starting with `/` a new line
+    pub lyrics: Vec<(Tick, String)>,
+}
+
+impl Midi {
+    /// Reads the Soft Karaoke metadata from every track's Text events, and the lyrics from the
+    /// Words track. Text starting with an `@` code is metadata wherever it appears, so it's never
+    /// mistaken for a lyric. The first `@L` line wins, and the `@K` and `@V` lines are skipped
+    pub fn karaoke_metadata(&self) -> KaraokeMeta {
+        let mut meta = KaraokeMeta::default();
+
+        for track in &self.tracks {
+            let is_words = track.name().is_some_and(|name| name == WORDS_TRACK);
+            for (tick, event) in track.meta_events() {
+                let MetaEvent::Text(text) = event else {
+                    continue;
+                };
+                let text = text.text();
+
+                let mut chars = text.chars();
+                match (chars.next(), chars.next()) {
+                    (Some('@'), Some('T')) => meta.title.push(text[2..].to_string()),
+                    (Some('@'), Some('L')) if meta.language.is_none() => {
+                        meta.language = Some(text[2..].to_string())
+                    }
+                    (Some('@'), Some('I')) => meta.info.push(text[2..].to_string()),
+                    (Some('@'), _) => {}
+                    _ if is_words => meta.lyrics.push((tick, text.into_owned())),
+                    _ => {}
+                }
+            }
+        }
+
+        meta
+    }
+
+    /// Writes Soft Karaoke metadata and lyrics into the conventional tracks, adding whichever of
+    /// the "Soft Karaoke" and "Words" tracks the file doesn't have yet. The Soft Karaoke track
+    /// gets the `@K` and `@V` lines and the info at tick 0, replacing its old `@` lines. The
+    /// Words track gets the language and title at tick 0 and the lyrics at their ticks, replacing
+    /// every Text event it had. Events other than Text events are left alone. Fails without
+    /// changing anything if a lyric is too far from the events around it for a delta time, or
+    /// if removing the old Text events leaves such a gap
+    pub fn set_karaoke_metadata(&mut self, meta: &KaraokeMeta) -> Result<(), EditError> {
+        let mut edited = self.clone();
+        let header_lines = [KARAOKE_ID.to_string(), KARAOKE_VERSION.to_string()]
+            .into_iter()
+            .chain(meta.info.iter().map(|info| format!("@I{info}")));
+        edited.edit_named_track(SOFT_KARAOKE_TRACK, |editor| {
+            editor.retain(|_, event| !is_code_text(event));
+            for line in header_lines {
+                editor.insert(0, text(line));
+            }
+        })?;
+
+        let header_lines = meta
+            .language
+            .iter()
+            .map(|language| format!("@L{language}"))
+            .chain(meta.title.iter().map(|title| format!("@T{title}")));
+        edited.edit_named_track(WORDS_TRACK, |editor| {
+            editor.retain(|_, event| !matches!(event, Event::MetaEvent(MetaEvent::Text(_))));
+            for line in header_lines {
+                editor.insert(0, text(line));
+            }
+            for (tick, lyric) in &meta.lyrics {
+                editor.insert(*tick, text(lyric.clone()));
+            }
+        })?;
+
+        *self = edited;
+        Ok(())
+    }
+
+    /// Edits the first track named `name`, adding a new one holding only the name if there is
+    /// none
+    fn edit_named_track(
+        &mut self,
+        name: &str,
+        edit: impl FnOnce(&mut TrackEditor),
+    ) -> Result<(), EditError> {
+        if self.track_by_name(name).is_none() {
+            let mut editor = TrackEditor::new(TrackChunk::default());
+            editor.insert(0, Event::MetaEvent(MetaEvent::TrackName(name.into())));
+            self.push_track(
+                editor
+                    .finish()
+                    .expect("A single event at tick 0 always fits"),
+            );
+        }
+
+        // UNWRAP Safety: The track was found or just added
+        let track = self.track_mut_by_name(name).unwrap();
+        let mut editor = TrackEditor::new(core::mem::take(track));
+        edit(&mut editor);
+        *track = editor.finish()?;
+        Ok(())
+    }
+}
+
+/// A Text meta event
+fn text(text: String) -> Event {
+    Event::MetaEvent(MetaEvent::Text(text.into()))
+}
+
+/// Whether the event is a Text meta event starting with an `@` code
+fn is_code_text(event: &Event) -> bool {
+    matches!(event, Event::MetaEvent(MetaEvent::Text(text)) if text.as_bytes().first() == Some(&b'@'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::KaraokeMeta;
+    use crate::{
+        chunk::{
+            header::HeaderChunk,
+            track::{
+                editor::EditError,
+                event::{MidiEvent, NoteMeta},
+                meta::MetaEvent,
+                Event, TrackChunk,
+            },
+        },
+        time::Tick,
+        Midi,
+    };
+
+    fn text(text: &str) -> Event {
+        Event::MetaEvent(MetaEvent::Text(text.into()))
+    }
+
+    fn name(name: &str) -> Event {
+        Event::MetaEvent(MetaEvent::TrackName(name.into()))
+    }
+
+    fn end() -> Event {
+        Event::MetaEvent(MetaEvent::EndOfTrack)
+    }
+
+    /// A miniature .kar with a melody, the Soft Karaoke track and the Words track
+    fn kar() -> Midi {
+        let melody = TrackChunk::from_absolute_events([
+            (0, name("Melody")),
+            (
+                0,
+                Event::MidiEvent(MidiEvent::NoteOn(
+                    0,
+                    NoteMeta {
+                        key: 60,
+                        velocity: 90,
+                    },
+                )),
+            ),
+            (
+                960,
+                Event::MidiEvent(MidiEvent::NoteOff(
+                    0,
+                    NoteMeta {
+                        key: 60,
+                        velocity: 0,
+                    },
+                )),
+            ),
+            (1440, end()),
+        ])
+        .unwrap();
+        let soft_karaoke = TrackChunk::from_absolute_events([
+            (0, name("Soft Karaoke")),
+            (0, text("@KMIDI KARAOKE FILE")),
+            (0, text("@V0100")),
+            (0, text("@IMade for a test")),
+            (0, end()),
+        ])
+        .unwrap();
+        let words = TrackChunk::from_absolute_events([
+            (0, name("Words")),
+            (0, text("@LENGL")),
+            (0, text("@TTiny Song")),
+            (0, text("@TSomebody")),
+            (0, text("\\Hel")),
+            (480, text("lo")),
+            (960, text("/World")),
+            (1440, end()),
+        ])
+        .unwrap();
+
+        Midi {
+            header: HeaderChunk::try_from((1, 3, 480)).unwrap(),
+            tracks: vec![melody, soft_karaoke, words],
+        }
+    }
+
+    fn expected() -> KaraokeMeta {
+        KaraokeMeta {
+            title: vec!["Tiny Song".into(), "Somebody".into()],
+            language: Some("ENGL".into()),
+            info: vec!["Made for a test".into()],
+            lyrics: vec![
+                (Tick::new(0), "\\Hel".into()),
+                (Tick::new(480), "lo".into()),
+                (Tick::new(960), "/World".into()),
+            ],
+        }
+    }
+
+    #[test]
+    fn codes_are_metadata_and_the_rest_are_lyrics() {
+        assert_eq!(kar().karaoke_metadata(), expected());
+    }
+
+    #[test]
+    fn metadata_is_written_into_the_conventional_tracks() {
+        let mut midi = kar();
+        let mut meta = expected();
+        meta.title[0] = "Renamed Song".into();
+        meta.lyrics.pop();
+        midi.set_karaoke_metadata(&meta).unwrap();
+
+        assert_eq!(midi.tracks.len(), 3);
+        assert_eq!(midi.karaoke_metadata(), meta);
+        assert_eq!(midi.tracks[1], kar().tracks[1]);
+        assert_eq!(midi.tracks[2].duration().get(), 1440);
+
+        let mut plain = kar();
+        plain.tracks.truncate(1);
+        plain.header = plain.consistent_header();
+        plain.set_karaoke_metadata(&expected()).unwrap();
+
+        assert_eq!(plain.tracks.len(), 3);
+        assert_eq!(plain.tracks[1].name().as_deref(), Some("Soft Karaoke"));
+        assert_eq!(plain.tracks[2].name().as_deref(), Some("Words"));
+        assert_eq!(plain.karaoke_metadata(), expected());
+        assert!(plain.tracks[1].events().eq(kar().tracks[1].events()));
+    }
+
+    #[test]
+    fn distant_lyrics_fail_without_changing_anything() {
+        let mut midi = kar();
+        let mut meta = expected();
+        meta.lyrics.push((Tick::new(1 << 40), "Too late".into()));
+
+        assert_eq!(
+            midi.set_karaoke_metadata(&meta),
+            Err(EditError::DeltaOverflow(Tick::new(1 << 40)))
+        );
+        assert_eq!(midi, kar());
+    }
+}
@@ -12,14 +12,31 @@
 //! parse these sections of a MIDI file.
 //!
 //! - **Minimal dependencies**: Keeps your application lightweight and minimizes build complexity.
-//!     Opt in to serde support.
+//!   Opt in to serde support, or to `tracing` instrumentation of the parser.
 //! - **Streaming-friendly**: Exposes traits and functions that can parse MIDI data from any
 //!   implementor of [`reader::MidiStream`], making it easier to handle data on the fly.
 //!
 //! ## Example Usage
 //!
 //! ```rust
-//! use miami::{reader::MidiReadable, Midi, RawMidi};
+//! // Read and parse a whole file
+//! let midi = miami::open("test/test.mid").expect("Open and parse `test.mid`");
+//!
+//! println!("Header: {:?}", midi.header);
+//! for chunk in midi.tracks.iter() {
+//!     println!("Track: {:?}", chunk);
+//! }
+//!
+//! # let path = std::env::temp_dir().join("miami_doc_example.mid");
+//! miami::save(&midi, &path).expect("Write the file back out");
+//! # std::fs::remove_file(path).unwrap();
+//! ```
+//!
+//! [`open_with`] and [`save_with`] take options for lenient parsing, text decoding and
+//! normalization. To parse from any other source, read it as a stream of chunks:
+//!
+//! ```rust
+//! use miami::prelude::*;
 //!
 //! // Load MIDI bytes (replace with your own source as needed).
 //! let mut data = "test/test.mid"
@@ -30,11 +47,6 @@
 //!     .expect("Parse data as a MIDI stream")
 //!     .check_into_midi()
 //!     .expect("Sanitize MIDI into formatted MIDI");
-//!
-//! println!("Header: {:?}", midi.header);
-//! for chunk in midi.tracks.iter() {
-//!     println!("Track: {:?}", chunk);
-//! }
 //! ```
 //!
 //!
@@ -43,6 +55,7 @@
 //!
 //! ## Library Structure
 //!
+//! - **[`prelude`]**: Re-exports the commonly used types and traits for `use miami::prelude::*`.
 //! - **[`chunk`]**: Contains the [`Chunk`] struct and associated utilities for identifying
 //!   chunk types and lengths.
 //! - **[`reader`]**: Provides traits and types for streaming MIDI data. The [`MidiStream`]
@@ -51,6 +64,15 @@
 //!   chunk types (e.g., `MThd` for the header and `MTrk` for track data) and the logic for
 //!   parsing their contents.
 //!
+//! ## Robustness
+//!
+//! Parsing never panics, no matter how malformed or malicious the input is. Truncated events,
+//! impossible lengths and out of range values all come back as errors. The `fuzz` directory
+//! holds a `cargo fuzz` target (`cargo fuzz run parse`) that checks this against arbitrary bytes.
+//!
+//! For damaged files, [`Midi::try_from_midi_stream_lenient`] recovers what it can and returns an
+//! [`outcome::ParseOutcome`] listing everything it skipped, along with chunk and event counts.
+//!
 //! ## Extensibility
 //!
 //! While this crate focuses on parsing the structural aspects of MIDI files (chunks and headers),
@@ -59,15 +81,67 @@
 //! control of the MIDI event parsing layer.
 //!
 
+pub mod analysis;
 pub mod chunk;
+pub mod click;
+pub mod compact;
+pub mod conductor;
+pub mod features;
+pub mod file;
+pub mod fingerprint;
+pub mod flat;
+pub mod gm;
+pub mod humanize;
+pub mod integrity;
+pub mod karaoke;
+pub mod merge;
+pub mod mix;
+pub mod normalize;
+pub mod notation;
+pub mod outcome;
+pub mod patch;
+pub mod pattern;
+pub mod peek;
+#[cfg(feature = "serde")]
+pub mod persist;
+pub mod pipeline;
+pub mod prelude;
+pub mod query;
 pub mod reader;
+pub mod retime;
+pub mod reverse;
+pub mod select;
+pub mod sequencer;
+pub mod signature;
+pub mod skeleton;
+pub mod slice;
+pub mod stats;
+pub mod summary;
+pub mod tempo;
+pub mod time;
+pub mod timeline;
+pub mod track_id;
+pub mod transform;
+pub mod validate;
+pub mod vlq;
 pub mod writer;
 
-use chunk::{header::HeaderChunk, track::TrackChunk, ChunkParseError, ParsedChunk};
-use reader::MidiStream;
+use chunk::{
+    chunk_types::{HEADER_CHUNK, TRACK_DATA_CHUNK},
+    header::{Division, Format, HeaderChunk},
+    track::TrackChunk,
+    ChunkParseError, ParsedChunk,
+};
+use outcome::LenientParseError;
+use reader::{MidiStream, ParseOptions, ParseProfile};
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
-use writer::MidiWriteable;
+use writer::{MidiWriteable, WriteError, WriteOptions};
+
+pub use file::{open, open_with, save, save_with, OpenOptions, SaveOptions};
+#[cfg(feature = "flate")]
+pub use file::{save_gz, save_gz_with};
+pub use peek::{peek_header, peek_header_from, peek_info, peek_info_from, FileInfo};
 
 /// An entire MIDI file as a raw sequence of parsed chunks
 #[derive(Debug, Clone, PartialEq)]
@@ -86,11 +160,121 @@ impl RawMidi {
         Self::try_from(StreamWrapper(stream))
     }
 
+    /// Constructs a new MIDI instance like [`RawMidi::try_from_midi_stream`], as forgivingly as
+    /// the given [`ParseProfile`] allows. Plain [`ParseOptions`] parse strictly, decoding text
+    /// meta events and limiting memory use as they specify. Whatever the profile skips is
+    /// dropped without a report, see [`Midi::try_from_midi_stream_with`] for one
+    pub fn try_from_midi_stream_with<STREAM>(
+        mut stream: STREAM,
+        profile: impl Into<ParseProfile>,
+    ) -> Result<Self, ChunkParseError>
+    where
+        STREAM: MidiStream,
+    {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("parse_midi").entered();
+
+        let profile = profile.into();
+        let options = profile.options;
+        let mut chunks = vec![];
+        let mut tracks = 0;
+        let mut warnings = vec![];
+        let mut remaining = options.max_total_bytes;
+        loop {
+            let read = if profile.skip_garbage {
+                stream.read_chunk_data_pair_lenient_within(remaining)
+            } else {
+                stream.read_chunk_data_pair_within(remaining)
+            };
+            let Some(read) = read else {
+                break;
+            };
+            let (chunk, data) = read.map_err(|_| options.total_bytes_exceeded())?;
+            let known = matches!(chunk.chunk_type, HEADER_CHUNK | TRACK_DATA_CHUNK);
+            options.charge(&mut remaining, data.len(), known)?;
+
+            #[cfg(feature = "tracing")]
+            let _span = chunk_span(chunks.len(), &chunk);
+            match profile.parse_chunk(chunk, &data, true, tracks, &mut warnings)? {
+                Some(ParsedChunk::Header(_))
+                    if profile.skip_extra_headers
+                        && chunks
+                            .iter()
+                            .any(|chunk| matches!(chunk, ParsedChunk::Header(_))) => {}
+                Some(parsed) => {
+                    tracks += matches!(parsed, ParsedChunk::Track(_)) as usize;
+                    chunks.push(parsed);
+                }
+                None => {}
+            }
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(chunks = chunks.len(), "parsed file");
+        Ok(Self { chunks })
+    }
+
+    /// Constructs a new MIDI instance from a stream of MIDI bytes, skipping any padding or
+    /// garbage between chunks instead of misreading it as a chunk header
+    pub fn try_from_midi_stream_lenient<STREAM>(stream: STREAM) -> Result<Self, ChunkParseError>
+    where
+        STREAM: MidiStream,
+    {
+        Self::try_from_midi_stream_lenient_with(stream, ParseOptions::default())
+    }
+
+    /// Constructs a new MIDI instance like [`RawMidi::try_from_midi_stream_lenient`], decoding
+    /// text meta events and limiting memory use according to the given options
+    pub fn try_from_midi_stream_lenient_with<STREAM>(
+        mut stream: STREAM,
+        options: ParseOptions,
+    ) -> Result<Self, ChunkParseError>
+    where
+        STREAM: MidiStream,
+    {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("parse_midi_lenient").entered();
+
+        let mut chunks = vec![];
+        let mut remaining = options.max_total_bytes;
+        while let Some(read) = stream.read_chunk_data_pair_lenient_within(remaining) {
+            let (chunk, data) = read.map_err(|_| options.total_bytes_exceeded())?;
+            options.charge(&mut remaining, data.len(), true)?;
+
+            #[cfg(feature = "tracing")]
+            let _span = chunk_span(chunks.len(), &chunk);
+            chunks.push(ParsedChunk::parse_with(chunk, &data, options)?);
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(chunks = chunks.len(), "parsed file");
+        Ok(Self { chunks })
+    }
+
     /// Attempts to upgrade a `RawMidi` stream into a sanitized `Midi` struct. This means there
     /// must be a single starting header and only track chunks afterwards
     pub fn check_into_midi(self) -> Result<Midi, MidiSanitizerError> {
         self.try_into()
     }
+
+    /// Splits the chunks before every header after the first, for files that are really several
+    /// files glued together and fail [`RawMidi::check_into_midi`] with
+    /// [`MidiSanitizerError::TooManyHeaders`]. Each part starts with its header, except for a
+    /// part holding any chunks found before the first header. To keep a single file and drop
+    /// the later headers instead, parse with [`reader::ParseProfile::skip_extra_headers`]
+    pub fn split_on_headers(self) -> Vec<RawMidi> {
+        let mut parts: Vec<RawMidi> = vec![];
+        for chunk in self.chunks {
+            match parts.last_mut() {
+                Some(part) if !matches!(chunk, ParsedChunk::Header(_)) => part.chunks.push(chunk),
+                _ => parts.push(RawMidi {
+                    chunks: vec![chunk],
+                }),
+            }
+        }
+
+        parts
+    }
 }
 
 impl MidiWriteable for RawMidi {
@@ -115,17 +299,118 @@ pub struct Midi {
     pub tracks: Vec<TrackChunk>,
 }
 
-impl MidiWriteable for Midi {
-    fn to_midi_bytes(self) -> Vec<u8> {
-        let mut res = vec![];
-        res.extend(ParsedChunk::Header(self.header).to_midi_bytes());
+impl Midi {
+    /// Creates a file from its tracks, with a format 0 header for a single track and a format 1
+    /// header otherwise. Like [`Midi::consistent_header`], the header counts at most 65535 tracks
+    ///
+    /// ```rust
+    /// use miami::{
+    ///     chunk::{
+    ///         header::Division,
+    ///         track::{meta::MetaEvent, Event, MTrkEvent, TrackChunk},
+    ///     },
+    ///     writer::MidiWriteable,
+    ///     Midi,
+    /// };
+    ///
+    /// let track = TrackChunk::new(vec![
+    ///     MTrkEvent::new(0, Event::MetaEvent(MetaEvent::DEFAULT_TEMPO)),
+    ///     MTrkEvent::new(0, Event::MetaEvent(MetaEvent::EndOfTrack)),
+    /// ]);
+    /// let midi = Midi::new(vec![track], Division::TPQN_480);
+    ///
+    /// let bytes = midi.to_midi_bytes();
+    /// assert_eq!(bytes[8..14], [0x00, 0x00, 0x00, 0x01, 0x01, 0xE0]);
+    /// ```
+    pub fn new(tracks: Vec<TrackChunk>, division: Division) -> Self {
+        let header = match tracks.len() {
+            1 => HeaderChunk::single_track(division),
+            ntrks => HeaderChunk::multi_track(saturating_ntrks(ntrks), division),
+        };
+
+        Self { header, tracks }
+    }
+
+    /// Returns the header with its track count synced to the actual number of tracks, and with
+    /// format 0 upgraded to format 1 if there is more than one track. A header can't count more
+    /// than 65535 tracks, so the count stops there
+    pub fn consistent_header(&self) -> HeaderChunk {
+        let mut header = self.header.clone();
+        header.ntrks = saturating_ntrks(self.tracks.len());
+        if header.format == Format::Zero && self.tracks.len() > 1 {
+            header.format = Format::One;
+        }
+        if header != self.header {
+            header.raw_payload = None;
+        }
+
+        header
+    }
+
+    /// Serializes the file like [`MidiWriteable::to_midi_bytes`], but first replaces the header
+    /// with [`Midi::consistent_header`] so the written header always matches the tracks.
+    /// `to_midi_bytes` itself writes the header exactly as it is set
+    pub fn to_midi_bytes_autofix(mut self) -> Vec<u8> {
+        self.header = self.consistent_header();
+        self.to_midi_bytes()
+    }
+
+    /// Serializes the file like [`MidiWriteable::to_midi_bytes`], laid out according to the
+    /// given [`WriteOptions`]
+    pub fn to_midi_bytes_with(mut self, options: WriteOptions) -> Vec<u8> {
+        if options.stamp_generator {
+            // A file that can't be signed is left unchanged and written unsigned
+            let _ = self.set_generator(signature::GENERATOR);
+        }
+
+        let mut res = options.write_chunk(ParsedChunk::Header(self.header).into());
         for track in self.tracks {
-            let wrapped = ParsedChunk::Track(track);
-            res.extend(wrapped.to_midi_bytes());
+            let track = options.encode_track(track);
+            res.extend(options.write_chunk(ParsedChunk::Track(track).into()));
         }
 
         res
     }
+
+    /// Exact number of bytes [`Midi::to_midi_bytes_with`] would produce with the same options,
+    /// counted from the events without serializing anything, or cloning anything unless the
+    /// options [`WriteOptions::stamp_generator`]. Useful for checking a file fits a size budget,
+    /// or for sizing a buffer, before writing it
+    pub fn encoded_size(&self, options: &WriteOptions) -> usize {
+        if options.stamp_generator {
+            let mut stamped = self.clone();
+            let _ = stamped.set_generator(signature::GENERATOR);
+            return stamped.encoded_size(&options.stamp_generator(false));
+        }
+
+        let header = options.chunk_len(6 + self.header.extra.len());
+        let tracks: usize = self
+            .tracks
+            .iter()
+            .map(|track| {
+                options.chunk_len(
+                    track
+                        .mtrk_events
+                        .iter()
+                        .map(|mtrk_event| mtrk_event.encoded_len(options.encoding))
+                        .sum(),
+                )
+            })
+            .sum();
+
+        header + tracks
+    }
+}
+
+/// The track count a header records for `len` tracks, which stops at the largest it can hold
+fn saturating_ntrks(len: usize) -> u16 {
+    u16::try_from(len).unwrap_or(u16::MAX)
+}
+
+impl MidiWriteable for Midi {
+    fn to_midi_bytes(self) -> Vec<u8> {
+        self.to_midi_bytes_with(WriteOptions::default())
+    }
 }
 
 /// An error that may occur when verifying that a Raw Midi struct is sanitized into a clean MIDI
@@ -138,6 +423,16 @@ pub enum MidiSanitizerError {
     TooManyHeaders,
     /// No chunks at all
     NoChunks,
+    /// A format 1 file has a tempo, time signature or key signature outside track 0, only
+    /// reported with [`ParseProfile::reject_misplaced_conductor_events`] set
+    MisplacedConductorEvent {
+        /// Index of the track holding the event
+        track: usize,
+        /// Absolute tick of the event
+        tick: time::Tick,
+        /// Which kind of event it is
+        kind: chunk::track::kind::MetaKind,
+    },
 }
 impl core::error::Error for MidiSanitizerError {}
 impl core::fmt::Display for MidiSanitizerError {
@@ -146,6 +441,74 @@ impl core::fmt::Display for MidiSanitizerError {
             Self::NoStartHeader => write![f, "First ParsedChunk in sequence isn't a header"],
             Self::TooManyHeaders => write![f, "More than one header chunk identified"],
             Self::NoChunks => write![f, "No chunks present"],
+            Self::MisplacedConductorEvent { track, tick, kind } => write![
+                f,
+                "Track {track} has a {kind} event at tick {tick} outside the conductor track"
+            ],
+        }
+    }
+}
+
+/// Any error from reading, parsing or writing a whole MIDI file
+#[derive(Debug)]
+pub enum MidiError {
+    /// Reading or writing the underlying file failed
+    Io(std::io::Error),
+    /// A header or track chunk failed to parse
+    Parse(ChunkParseError),
+    /// The chunks don't form a file with a single leading header
+    Structure(MidiSanitizerError),
+    /// The file is gzip-compressed, and reading it needs the `flate` feature
+    CompressedInputUnsupported,
+    /// The written file failed its check
+    Write(WriteError),
+}
+
+impl core::error::Error for MidiError {}
+impl core::fmt::Display for MidiError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Io(err) => write![f, "{err}"],
+            Self::Parse(err) => write![f, "{err}"],
+            Self::Structure(err) => write![f, "{err}"],
+            Self::CompressedInputUnsupported => write![
+                f,
+                "The file is gzip-compressed, enable the `flate` feature to read it"
+            ],
+            Self::Write(err) => write![f, "{err}"],
+        }
+    }
+}
+
+impl From<std::io::Error> for MidiError {
+    fn from(f: std::io::Error) -> Self {
+        Self::Io(f)
+    }
+}
+
+impl From<ChunkParseError> for MidiError {
+    fn from(f: ChunkParseError) -> Self {
+        Self::Parse(f)
+    }
+}
+
+impl From<MidiSanitizerError> for MidiError {
+    fn from(f: MidiSanitizerError) -> Self {
+        Self::Structure(f)
+    }
+}
+
+impl From<WriteError> for MidiError {
+    fn from(f: WriteError) -> Self {
+        Self::Write(f)
+    }
+}
+
+impl From<LenientParseError> for MidiError {
+    fn from(f: LenientParseError) -> Self {
+        match f {
+            LenientParseError::Chunk(err) => Self::Parse(err),
+            LenientParseError::Structure(err) => Self::Structure(err),
         }
     }
 }
@@ -182,18 +545,36 @@ where
 {
     type Error = ChunkParseError;
     fn try_from(value: StreamWrapper<STREAM>) -> Result<Self, Self::Error> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("parse_midi").entered();
+
         let mut data = value.0;
         let mut chunks = vec![];
 
-        while let Some(parsed) = data.read_chunk_data_pair().map(ParsedChunk::try_from) {
-            let parsed = parsed?;
-            chunks.push(parsed);
+        while let Some((chunk, payload)) = data.read_chunk_data_pair() {
+            #[cfg(feature = "tracing")]
+            let _span = chunk_span(chunks.len(), &chunk);
+            chunks.push(ParsedChunk::try_from((chunk, payload))?);
         }
 
+        #[cfg(feature = "tracing")]
+        tracing::debug!(chunks = chunks.len(), "parsed file");
         Ok(Self { chunks })
     }
 }
 
+/// Enters a span covering the parse of the chunk at `index`
+#[cfg(feature = "tracing")]
+fn chunk_span(index: usize, chunk: &Chunk) -> tracing::span::EnteredSpan {
+    tracing::debug_span!(
+        "chunk",
+        index,
+        kind = %String::from_iter(chunk.chunk_type),
+        length = chunk.len()
+    )
+    .entered()
+}
+
 /// Represents a raw MIDI Chunk.
 /// A MIDI Chunk consists of a 4-character ASCII type identifier and a 32-bit unsigned integer specifying the length of its data.
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -206,6 +587,17 @@ pub struct Chunk {
 }
 
 impl Chunk {
+    /// Parses an 8 byte chunk header, rejecting it unless all four type bytes are printable
+    /// ASCII. Unlike the lossy `From<u64>` conversion this catches binary garbage before its
+    /// bogus length is trusted
+    pub fn try_from_bytes(bytes: [u8; 8]) -> Result<Self, InvalidChunkHeader> {
+        if !bytes[..4].iter().all(|byte| (0x20..=0x7E).contains(byte)) {
+            return Err(InvalidChunkHeader { bytes });
+        }
+
+        Ok(u64::from_be_bytes(bytes).into())
+    }
+
     /// Gets the length of the chunk as a usize
     pub fn len(&self) -> usize {
         self.length as usize
@@ -217,6 +609,23 @@ impl Chunk {
     }
 }
 
+/// Error for 8 bytes that can't be a chunk header because the type isn't printable ASCII
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidChunkHeader {
+    /// The rejected header bytes
+    pub bytes: [u8; 8],
+}
+
+impl core::error::Error for InvalidChunkHeader {}
+impl core::fmt::Display for InvalidChunkHeader {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write![f, "Invalid chunk header bytes {:02X?}", self.bytes]
+    }
+}
+
+/// Lossy conversion from a big endian chunk header, mapping every type byte to a `char` as is.
+/// Arbitrary data converts to an arbitrary chunk, so prefer [`Chunk::try_from_bytes`] for
+/// untrusted input
 impl From<u64> for Chunk {
     fn from(value: u64) -> Self {
         let high = (value >> 32) as u32;
@@ -236,7 +645,414 @@ impl From<u64> for Chunk {
 
 #[cfg(test)]
 mod tests {
-    use crate::Chunk;
+    use crate::{
+        chunk::{
+            header::{Division, Format, HeaderChunk},
+            track::{
+                event::{MidiEvent, NoteMeta},
+                meta::{MetaEvent, MetaText, TextEncoding},
+                sysex::{ManufactureId, SysexEvent},
+                Event, MTrkEvent, TrackChunk,
+            },
+        },
+        outcome::ParseWarning,
+        reader::{MidiReadable, ParseOptions, ParseProfile},
+        vlq,
+        writer::{MidiWriteable, WriteOptions},
+        Chunk, InvalidChunkHeader, Midi, MidiSanitizerError, RawMidi,
+    };
+
+    #[test]
+    fn fixtures_survive_a_write_round_trip() {
+        let parse = |bytes: Vec<u8>| {
+            RawMidi::try_from_midi_stream(bytes.into_iter())
+                .unwrap()
+                .check_into_midi()
+                .unwrap()
+        };
+
+        // Copies written by the examples with an older writer, which aren't valid input
+        let written = ["test_out.mid", "test_run.mid"];
+
+        let mut fixtures = 0;
+        for entry in std::fs::read_dir("test").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|extension| extension != "mid")
+                || path
+                    .file_name()
+                    .is_some_and(|name| written.iter().any(|written| name == *written))
+            {
+                continue;
+            }
+
+            let midi = parse(std::fs::read(&path).unwrap());
+            let written = midi.clone().to_midi_bytes();
+            let reparsed = parse(written.clone());
+            assert_eq!(reparsed, midi, "{}", path.display());
+            assert_eq!(reparsed.to_midi_bytes(), written, "{}", path.display());
+            fixtures += 1;
+        }
+
+        assert!(fixtures > 0);
+    }
+
+    /// Every combination of write options, for checking a layout holds however the file's written
+    fn every_write_option() -> impl Iterator<Item = WriteOptions> {
+        [false, true].into_iter().flat_map(|pad_odd_chunks| {
+            [TextEncoding::Raw, TextEncoding::Utf8, TextEncoding::Latin1]
+                .into_iter()
+                .flat_map(move |encoding| {
+                    [false, true]
+                        .into_iter()
+                        .map(move |stamp_generator| WriteOptions {
+                            pad_odd_chunks,
+                            encoding,
+                            stamp_generator,
+                            ..WriteOptions::default()
+                        })
+                })
+        })
+    }
+
+    #[test]
+    fn encoded_size_matches_written_length() {
+        let assert_size = |midi: &Midi, what: &str| {
+            for options in every_write_option() {
+                assert_eq!(
+                    midi.encoded_size(&options),
+                    midi.clone().to_midi_bytes_with(options).len(),
+                    "{what} with {options:?}"
+                );
+            }
+        };
+
+        let written = ["test_out.mid", "test_run.mid"];
+        for entry in std::fs::read_dir("test").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|extension| extension != "mid")
+                || path
+                    .file_name()
+                    .is_some_and(|name| written.iter().any(|written| name == *written))
+            {
+                continue;
+            }
+
+            let midi = RawMidi::try_from_midi_stream(std::fs::read(&path).unwrap().into_iter())
+                .unwrap()
+                .check_into_midi()
+                .unwrap();
+            assert_size(&midi, &path.display().to_string());
+        }
+
+        // Generated files covering every event shape, delta times of every encoded length and
+        // text that changes length when re-encoded, including invalid UTF-8
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        let mut next = move |below: u64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state % below
+        };
+
+        for file in 0..50 {
+            let mut tracks = vec![];
+            for _ in 0..=next(3) {
+                let mut track = TrackChunk::default();
+                for _ in 0..next(40) {
+                    let byte = next(256) as u8;
+                    let bytes: Vec<u8> = (0..next(8)).map(|_| next(256) as u8).collect();
+                    let text = MetaText::from_bytes(
+                        bytes.clone(),
+                        [TextEncoding::Utf8, TextEncoding::Latin1, TextEncoding::Raw]
+                            [next(3) as usize],
+                    );
+                    let event = match next(12) {
+                        0 => Event::MidiEvent(MidiEvent::NoteOn(
+                            byte & 0x0F,
+                            NoteMeta {
+                                key: 60,
+                                velocity: 90,
+                            },
+                        )),
+                        1 => Event::MidiEvent(MidiEvent::ProgramChange {
+                            channel: byte & 0x0F,
+                            program: byte & 0x7F,
+                        }),
+                        2 => Event::MidiEvent(MidiEvent::PitchWheelChange(0, 0x2000)),
+                        3 => Event::SysexEvent(SysexEvent {
+                            manufacture_id: ManufactureId::OneByte(0x41),
+                            payload: bytes.into(),
+                        }),
+                        4 => Event::SysexEvent(SysexEvent {
+                            manufacture_id: ManufactureId::ThreeByte([0x00, 0x20, 0x29]),
+                            payload: bytes.into(),
+                        }),
+                        5 => Event::MetaEvent(MetaEvent::Lyric(text)),
+                        6 => Event::MetaEvent(MetaEvent::TrackName(text)),
+                        7 => Event::MetaEvent(MetaEvent::DEFAULT_TEMPO),
+                        8 => Event::MetaEvent(MetaEvent::TimeSignature(Default::default())),
+                        9 => Event::MetaEvent(MetaEvent::SequencerSpecific(
+                            // Long enough that its length takes two bytes
+                            vec![byte; 100 + bytes.len() * 10].into(),
+                        )),
+                        10 => Event::MetaEvent(MetaEvent::SequenceNumber(file)),
+                        _ => Event::MetaEvent(MetaEvent::UnknownRaw(0x60, bytes.into())),
+                    };
+                    let delta = (next(u64::from(vlq::MAX)) as u32) >> next(28);
+                    track.mtrk_events.push(MTrkEvent::new(delta, event));
+                }
+                track
+                    .mtrk_events
+                    .push(MTrkEvent::new(0, Event::MetaEvent(MetaEvent::EndOfTrack)));
+                tracks.push(track);
+            }
+
+            let mut header = HeaderChunk::new(Format::One, tracks.len() as u16, Division::TPQN_480);
+            header.extra = vec![0; next(3) as usize];
+            assert_size(&Midi { header, tracks }, &format!("generated file {file}"));
+        }
+    }
+
+    #[test]
+    fn autofix_write_syncs_inconsistent_header() {
+        let track = TrackChunk::from_absolute_events([
+            (0, Event::MetaEvent(MetaEvent::Tempo(500_000))),
+            (96, Event::MetaEvent(MetaEvent::EndOfTrack)),
+        ])
+        .unwrap();
+
+        let midi = Midi {
+            header: HeaderChunk::try_from((0, 1, 96)).unwrap(),
+            tracks: vec![track.clone(), track.clone(), track],
+        };
+
+        // The plain writer keeps the header verbatim
+        let verbatim = midi.clone().to_midi_bytes();
+        assert_eq!(verbatim[8..12], [0, 0, 0, 1]);
+
+        let fixed = midi.clone().to_midi_bytes_autofix();
+        assert_eq!(fixed[8..12], [0, 1, 0, 3]);
+        assert_eq!(fixed[12..], verbatim[12..]);
+
+        let reparsed = RawMidi::try_from_midi_stream(fixed.into_iter())
+            .unwrap()
+            .check_into_midi()
+            .unwrap();
+        assert_eq!(reparsed.header.format, Format::One);
+        assert_eq!(reparsed.tracks, midi.tracks);
+    }
+
+    #[test]
+    fn new_picks_format_from_track_count() {
+        let track =
+            TrackChunk::from_absolute_events([(0, Event::MetaEvent(MetaEvent::EndOfTrack))])
+                .unwrap();
+
+        let single = Midi::new(vec![track.clone()], Division::TPQN_480);
+        assert_eq!(single.header, HeaderChunk::single_track(Division::TPQN_480));
+        assert_eq!(
+            single.clone().to_midi_bytes_autofix(),
+            single.to_midi_bytes()
+        );
+
+        let multi = Midi::new(vec![track.clone(), track], Division::TPQN_96);
+        assert_eq!(
+            multi.clone().to_midi_bytes()[8..14],
+            [0x00, 0x01, 0x00, 0x02, 0x00, 0x60]
+        );
+        assert_eq!(multi.header, multi.consistent_header());
+
+        let crowded = Midi::new(vec![TrackChunk::default(); 70_000], Division::TPQN_96);
+        assert_eq!(crowded.header.ntrks(), u16::MAX);
+        assert_eq!(crowded.consistent_header().ntrks(), u16::MAX);
+    }
+
+    #[test]
+    fn padded_chunks_parse_leniently() {
+        // Tempo (7 bytes) and EndOfTrack (4 bytes) make an odd length payload
+        let odd = TrackChunk::from_absolute_events([
+            (0, Event::MetaEvent(MetaEvent::Tempo(500_000))),
+            (0, Event::MetaEvent(MetaEvent::EndOfTrack)),
+        ])
+        .unwrap();
+        let midi = Midi {
+            header: HeaderChunk::try_from((1, 2, 96)).unwrap(),
+            tracks: vec![odd.clone(), odd],
+        };
+
+        let plain = midi.clone().to_midi_bytes_with(WriteOptions::default());
+        assert_eq!(plain, midi.clone().to_midi_bytes());
+        let strict = RawMidi::try_from_midi_stream(plain.into_iter())
+            .unwrap()
+            .check_into_midi()
+            .unwrap();
+        assert_eq!(strict, midi);
+
+        let padded = midi.clone().to_midi_bytes_with(WriteOptions {
+            pad_odd_chunks: true,
+            ..Default::default()
+        });
+        assert_eq!(padded.len(), 14 + 2 * (8 + 11 + 1));
+        assert_eq!(padded[14 + 4..14 + 8], [0, 0, 0, 11]);
+        assert_eq!(padded[14 + 8 + 11], 0x00);
+
+        let lenient = RawMidi::try_from_midi_stream_lenient(padded.into_iter())
+            .unwrap()
+            .check_into_midi()
+            .unwrap();
+        assert_eq!(lenient, midi);
+    }
+
+    #[test]
+    fn latin1_text_round_trips_raw_and_transcodes_to_utf8() {
+        let name = MetaText::from_bytes(b"Caf\xE9".to_vec(), TextEncoding::Raw);
+        let track = TrackChunk::from_absolute_events([
+            (0, Event::MetaEvent(MetaEvent::TrackName(name))),
+            (0, Event::MetaEvent(MetaEvent::EndOfTrack)),
+        ])
+        .unwrap();
+        let original = Midi {
+            header: HeaderChunk::try_from((0, 1, 96)).unwrap(),
+            tracks: vec![track],
+        }
+        .to_midi_bytes();
+
+        assert!(RawMidi::try_from_midi_stream(original.clone().into_iter()).is_err());
+
+        let lenient = |encoding| {
+            RawMidi::try_from_midi_stream_lenient_with(
+                original.clone().into_iter(),
+                ParseOptions {
+                    text_encoding: encoding,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .check_into_midi()
+            .unwrap()
+        };
+
+        let raw = lenient(TextEncoding::Raw).to_midi_bytes_with(WriteOptions {
+            encoding: TextEncoding::Raw,
+            ..Default::default()
+        });
+        assert_eq!(raw, original);
+
+        let latin1 = lenient(TextEncoding::Latin1);
+        let Event::MetaEvent(meta) = latin1.tracks[0].absolute_events().next().unwrap().1 else {
+            unreachable!()
+        };
+        assert_eq!(meta.text().unwrap().text(), "Café");
+
+        let utf8 = latin1.to_midi_bytes_with(WriteOptions {
+            encoding: TextEncoding::Utf8,
+            ..Default::default()
+        });
+        let strict = RawMidi::try_from_midi_stream(utf8.into_iter())
+            .unwrap()
+            .check_into_midi()
+            .unwrap();
+        let Event::MetaEvent(meta) = strict.tracks[0].absolute_events().next().unwrap().1 else {
+            unreachable!()
+        };
+        assert_eq!(meta.text().unwrap().as_bytes(), "Café".as_bytes());
+    }
+
+    #[test]
+    fn autofix_keeps_single_track_format_zero() {
+        let midi = Midi {
+            header: HeaderChunk::try_from((0, 4, 96)).unwrap(),
+            tracks: vec![TrackChunk::default()],
+        };
+
+        let header = midi.consistent_header();
+        assert_eq!(header.format, Format::Zero);
+        assert_eq!(header.ntrks, 1);
+    }
+
+    #[test]
+    fn chunk_header_requires_printable_type() {
+        let chunk = Chunk::try_from_bytes(*b"MTrk\0\0\0\x10").unwrap();
+        assert_eq!(chunk.chunk_type, ['M', 'T', 'r', 'k']);
+        assert_eq!(chunk.len(), 16);
+
+        let garbage = [0x00, 0xFF, 0x12, 0x90, 0xFF, 0xFF, 0xFF, 0xF0];
+        assert_eq!(
+            Chunk::try_from_bytes(garbage),
+            Err(InvalidChunkHeader { bytes: garbage })
+        );
+    }
+
+    /// Parses bytes through both the strict and lenient readers, which must never panic
+    fn parse_everything(bytes: &[u8]) {
+        if let Ok(raw) = RawMidi::try_from_midi_stream(bytes.iter().copied()) {
+            let _ = raw.check_into_midi();
+        }
+        let _ = RawMidi::try_from_midi_stream_lenient(bytes.iter().copied());
+    }
+
+    #[test]
+    fn crafted_files_error_instead_of_panicking() {
+        // Header declaring 2 bytes
+        assert!(RawMidi::try_from_midi_stream(b"MThd\0\0\0\x02\0\x01".iter().copied()).is_err());
+
+        // Track declaring a 4GB payload
+        let huge = b"MThd\0\0\0\x06\0\x01\0\x01\x01\xE0MTrk\xFF\xFF\xFF\xFF\0\xFF\x2F\0";
+        let raw = RawMidi::try_from_midi_stream(huge.iter().copied()).unwrap();
+        assert_eq!(raw.chunks.len(), 1);
+
+        // Track cut off partway through a note
+        let cut = b"MThd\0\0\0\x06\0\x01\0\x01\x01\xE0MTrk\0\0\0\x03\0\x90\x3C";
+        assert!(RawMidi::try_from_midi_stream(cut.iter().copied()).is_err());
+    }
+
+    #[test]
+    fn mutated_fixture_never_panics() {
+        let bytes: Vec<u8> = "test/test4tracks.mid".get_midi_bytes().unwrap().collect();
+
+        for len in 0..bytes.len() {
+            parse_everything(&bytes[..len]);
+        }
+
+        let mut mutated = bytes.clone();
+        for idx in 0..bytes.len() {
+            for value in 0..=u8::MAX {
+                mutated[idx] = value;
+                parse_everything(&mutated);
+            }
+            mutated[idx] = bytes[idx];
+        }
+    }
+
+    #[test]
+    fn second_header_splits_or_is_skipped() {
+        let file = std::fs::read("test/test.mid").unwrap();
+        let single = RawMidi::try_from_midi_stream(file.clone().into_iter())
+            .unwrap()
+            .check_into_midi()
+            .unwrap();
+        let glued = [file.clone(), file].concat();
+
+        let raw = RawMidi::try_from_midi_stream(glued.clone().into_iter()).unwrap();
+        assert_eq!(
+            raw.clone().check_into_midi(),
+            Err(MidiSanitizerError::TooManyHeaders)
+        );
+        let parts = raw.split_on_headers();
+        assert_eq!(parts.len(), 2);
+        for part in parts {
+            assert_eq!(part.check_into_midi().unwrap(), single);
+        }
+
+        let outcome =
+            Midi::try_from_midi_stream_with(glued.into_iter(), ParseProfile::recovering()).unwrap();
+        assert!(outcome.warnings.contains(&ParseWarning::ExtraHeader));
+        assert_eq!(outcome.midi.header, single.header);
+        assert_eq!(
+            outcome.midi.tracks,
+            [single.tracks.clone(), single.tracks].concat()
+        );
+    }
 
     #[test]
     fn chunk_from_raw_u64_behaves_normally() {
@@ -248,4 +1064,148 @@ mod tests {
 
         assert_eq!(expected, message.into())
     }
+
+    #[cfg(feature = "tracing")]
+    mod tracing {
+        use std::sync::{Arc, Mutex};
+
+        use tracing::{
+            field::{Field, Visit},
+            span::{Attributes, Id, Record},
+            subscriber::with_default,
+            Event, Metadata, Subscriber,
+        };
+
+        use crate::{reader::MidiReadable, RawMidi};
+
+        /// Every span and event seen, as the path of span names they happened in
+        #[derive(Default)]
+        struct Log {
+            /// Name and parent of every span created, indexed by id - 1
+            spans: Vec<(String, Option<u64>)>,
+            /// Currently entered spans
+            stack: Vec<u64>,
+            /// One line per span or event, prefixed with its span path
+            lines: Vec<String>,
+        }
+
+        impl Log {
+            /// Names of the span and its parents, outermost first
+            fn path(&self, mut id: Option<u64>) -> String {
+                let mut names = vec![];
+                while let Some(current) = id {
+                    let (name, parent) = &self.spans[current as usize - 1];
+                    names.push(name.clone());
+                    id = *parent;
+                }
+                names.reverse();
+                names.join("/")
+            }
+        }
+
+        /// Subscriber that records into a shared [`Log`]
+        struct Recorder(Arc<Mutex<Log>>);
+
+        /// Collects fields as `name=value` pairs
+        struct Fields(Vec<String>);
+
+        impl Visit for Fields {
+            fn record_debug(&mut self, field: &Field, value: &dyn core::fmt::Debug) {
+                self.0.push(format!("{}={value:?}", field.name()));
+            }
+        }
+
+        impl Subscriber for Recorder {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                let mut log = self.0.lock().unwrap();
+                let parent = log.stack.last().copied();
+                log.spans.push((span.metadata().name().to_string(), parent));
+                let id = log.spans.len() as u64;
+
+                let mut fields = Fields(vec![]);
+                span.record(&mut fields);
+                let line = format!("{} {}", log.path(Some(id)), fields.0.join(" "));
+                log.lines.push(line.trim_end().to_string());
+
+                Id::from_u64(id)
+            }
+
+            fn record(&self, _: &Id, _: &Record<'_>) {}
+
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+            fn event(&self, event: &Event<'_>) {
+                let mut log = self.0.lock().unwrap();
+                let mut fields = Fields(vec![]);
+                event.record(&mut fields);
+                let line = format!(
+                    "{}: {} {}",
+                    log.path(log.stack.last().copied()),
+                    event.metadata().level(),
+                    fields.0.join(" ")
+                );
+                log.lines.push(line);
+            }
+
+            fn enter(&self, span: &Id) {
+                self.0.lock().unwrap().stack.push(span.into_u64());
+            }
+
+            fn exit(&self, _: &Id) {
+                self.0.lock().unwrap().stack.pop();
+            }
+        }
+
+        /// Runs `f` with a recording subscriber and returns what it logged
+        fn record(f: impl FnOnce()) -> Vec<String> {
+            let log = Arc::new(Mutex::new(Log::default()));
+            with_default(Recorder(log.clone()), f);
+
+            let lines = core::mem::take(&mut log.lock().unwrap().lines);
+            lines
+        }
+
+        #[test]
+        fn parse_spans_follow_chunks() {
+            let lines = record(|| {
+                let stream = "test/test.mid".get_midi_bytes().unwrap();
+                RawMidi::try_from_midi_stream(stream).unwrap();
+            });
+
+            assert_eq!(
+                lines,
+                [
+                    "parse_midi",
+                    "parse_midi/chunk index=0 kind=MThd length=6",
+                    "parse_midi/chunk index=1 kind=MTrk length=50",
+                    "parse_midi/chunk/track length=50",
+                    "parse_midi/chunk/track: DEBUG message=parsed track events=7",
+                    "parse_midi: DEBUG message=parsed file chunks=2",
+                ]
+            );
+        }
+
+        #[test]
+        fn lenient_skips_are_reported() {
+            let mut bytes: Vec<u8> = "test/test.mid".get_midi_bytes().unwrap().collect();
+            bytes.splice(14..14, [0x00, 0x00]);
+
+            let lines = record(|| {
+                RawMidi::try_from_midi_stream_lenient(bytes.into_iter()).unwrap();
+            });
+
+            assert_eq!(
+                lines[..3],
+                [
+                    "parse_midi_lenient",
+                    "parse_midi_lenient/chunk index=0 kind=MThd length=6",
+                    "parse_midi_lenient: WARN message=skipped bytes that can't start a chunk header skipped=2",
+                ]
+            );
+        }
+    }
 }
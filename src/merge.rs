@@ -0,0 +1,727 @@
+//! Merging tracks into one by absolute time
+
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, BinaryHeap},
+};
+
+use crate::{
+    analysis::Note,
+    chunk::track::{
+        editor::TrackEditor,
+        event::{ControlChange, MidiEvent},
+        meta::MetaEvent,
+        Event, TrackChunk,
+    },
+    query::EventRef,
+    stats::PERCUSSION_CHANNEL,
+    time::Tick,
+    timeline::{BANK_SELECT_LSB, BANK_SELECT_MSB},
+    Midi,
+};
+
+/// Separator [`TrackChunk::merge_with`] joins names with when concatenating them
+const NAME_SEPARATOR: &str = " + ";
+
+/// How [`TrackChunk::merge_with`] treats the names of the tracks it merges
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct MergeOptions {
+    /// When both tracks are named at tick 0, names the merged track `"own + other"` instead of
+    /// keeping only the receiver's name. Off by default
+    pub concatenate_names: bool,
+}
+
+impl MergeOptions {
+    /// Sets [`MergeOptions::concatenate_names`]
+    pub fn concatenate_names(mut self, concatenate: bool) -> Self {
+        self.concatenate_names = concatenate;
+        self
+    }
+}
+
+/// How [`Midi::to_format0_with`] handles notes from different tracks that sound the same key on
+/// the same channel at once, see [`Midi::note_collisions`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Merges the tracks as they are, so the later note retriggers the key and the first Note
+    /// Off cuts both short
+    #[default]
+    Keep,
+    /// Moves every channel a track collides on with an earlier track to a channel no track
+    /// uses, taking all of that track's events on the channel along. The bank, program, volume
+    /// and pan other tracks set on the old channel by the time the track first uses it are
+    /// restated on the new one at tick 0, so the moved notes keep their sound. Later changes
+    /// other tracks make to the old channel aren't followed. Channel 10 is never moved to or
+    /// from, since percussion only plays there
+    RechannelizeSpill,
+    /// Fails with every colliding pair of notes instead of merging
+    ErrorOnCollision,
+}
+
+/// Two notes from different tracks sounding the same key on the same channel at once
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoteCollision {
+    /// Index of the earlier track
+    pub first_track: usize,
+    /// The earlier track's note
+    pub first: Note,
+    /// Index of the later track
+    pub second_track: usize,
+    /// The later track's note
+    pub second: Note,
+}
+
+/// Error from merging tracks with a [`MergePolicy`] that doesn't accept collisions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeError {
+    /// Notes collide under [`MergePolicy::ErrorOnCollision`]
+    Collisions(Vec<NoteCollision>),
+    /// Every channel is in use, so a colliding channel of a track has nowhere to move under
+    /// [`MergePolicy::RechannelizeSpill`]
+    NoFreeChannel {
+        /// Index of the track
+        track: usize,
+        /// The channel it collides on
+        channel: u8,
+    },
+}
+
+impl core::error::Error for MergeError {}
+impl core::fmt::Display for MergeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Collisions(collisions) => {
+                write![
+                    f,
+                    "{} pairs of notes collide across tracks",
+                    collisions.len()
+                ]
+            }
+            Self::NoFreeChannel { track, channel } => write![
+                f,
+                "No free channel to move track {track}'s channel {channel} to"
+            ],
+        }
+    }
+}
+
+impl TrackChunk {
+    /// Merges another track's events into this one, keeping only this track's name if both are
+    /// named. See [`TrackChunk::merge_with`]
+    pub fn merge(&mut self, other: &TrackChunk) {
+        self.merge_with(other, MergeOptions::default());
+    }
+
+    /// Interleaves another track's events with this one's by absolute tick and recomputes the
+    /// delta times. Simultaneous events keep their order, with this track's first, and the
+    /// merged track ends at the later of the two `EndOfTrack`s.
+    ///
+    /// A Track Name at tick 0 in `other` is dropped if this track is already named at tick 0,
+    /// unless the names are concatenated, and otherwise becomes the merged track's first event. The other track's events after its `EndOfTrack` are
+    /// left out, while this track's are kept
+    pub fn merge_with(&mut self, other: &TrackChunk, options: MergeOptions) {
+        let mut editor = TrackEditor::new(core::mem::take(self));
+        let named = editor.events().iter().position(|(tick, event)| {
+            *tick == Tick::ZERO && matches!(event, Event::MetaEvent(MetaEvent::TrackName(_)))
+        });
+
+        for (tick, event) in other.absolute_events() {
+            match (named, event) {
+                (Some(idx), Event::MetaEvent(MetaEvent::TrackName(name))) if tick == Tick::ZERO => {
+                    if options.concatenate_names {
+                        if let Event::MetaEvent(MetaEvent::TrackName(own)) =
+                            &mut editor.events_mut()[idx].1
+                        {
+                            *own = format!("{}{NAME_SEPARATOR}{}", own.text(), name.text()).into();
+                        }
+                    }
+                }
+                (None, Event::MetaEvent(MetaEvent::TrackName(_))) if tick == Tick::ZERO => {
+                    editor.events_mut().insert(0, (tick, event.clone()))
+                }
+                _ => editor.insert(tick, event.clone()),
+            }
+        }
+
+        // Each event follows one from its own track, so merging only ever narrows the gaps
+        *self = editor
+            .finish()
+            .expect("Merged events are no further apart than in their source tracks");
+    }
+}
+
+impl Midi {
+    /// Every event of every track merged into one stream by absolute tick. The order is total:
+    /// by tick, then by track index, then by position within the track. It only depends on the
+    /// file's contents, so the same file always gives the same stream, and
+    /// [`Midi::to_format0`] merges tracks in the same order
+    pub fn merged_events(&self) -> Vec<EventRef<'_>> {
+        merge_tracks(self.tracks.iter().enumerate())
+    }
+}
+
+impl Midi {
+    /// Every pair of notes from different tracks that sound the same key on the same channel at
+    /// once, which merging the tracks would turn into retriggers, with the earlier track first.
+    /// Notes are paired within each track like [`TrackChunk::notes`], and a note released on
+    /// the tick another starts doesn't collide with it
+    pub fn note_collisions(&self) -> Vec<NoteCollision> {
+        let mut sounded: BTreeMap<(u8, u8), Vec<(usize, Note)>> = BTreeMap::new();
+        let mut collisions = vec![];
+
+        for (track, chunk) in self.tracks.iter().enumerate() {
+            let notes = chunk.notes();
+            for note in &notes {
+                collisions.extend(colliding(&sounded, note).map(|(first_track, first)| {
+                    NoteCollision {
+                        first_track,
+                        first,
+                        second_track: track,
+                        second: *note,
+                    }
+                }));
+            }
+            for note in notes {
+                sounded
+                    .entry((note.channel, note.key))
+                    .or_default()
+                    .push((track, note));
+            }
+        }
+
+        collisions
+    }
+
+    /// Moves each channel a track collides on with an earlier track to an unused channel, for
+    /// [`MergePolicy::RechannelizeSpill`]. Nothing changes unless every collision finds a channel
+    pub(crate) fn spill_collisions(&mut self) -> Result<(), MergeError> {
+        let mut free: BTreeSet<u8> = (0..16)
+            .filter(|channel| *channel != PERCUSSION_CHANNEL)
+            .collect();
+        for (_, event) in self.tracks.iter().flat_map(TrackChunk::absolute_events) {
+            if let Event::MidiEvent(midi) = event {
+                free.remove(&midi.channel());
+            }
+        }
+
+        let mut sounded: BTreeMap<(u8, u8), Vec<(usize, Note)>> = BTreeMap::new();
+        let mut moves: Vec<BTreeMap<u8, u8>> = vec![];
+        for (track, chunk) in self.tracks.iter().enumerate() {
+            let mut notes = chunk.notes();
+            let mut moved = BTreeMap::new();
+            for note in &notes {
+                if note.channel == PERCUSSION_CHANNEL
+                    || moved.contains_key(&note.channel)
+                    || colliding(&sounded, note).next().is_none()
+                {
+                    continue;
+                }
+
+                let to = free.pop_first().ok_or(MergeError::NoFreeChannel {
+                    track,
+                    channel: note.channel,
+                })?;
+                moved.insert(note.channel, to);
+            }
+
+            for note in &mut notes {
+                if let Some(to) = moved.get(&note.channel) {
+                    note.channel = *to;
+                }
+                sounded
+                    .entry((note.channel, note.key))
+                    .or_default()
+                    .push((track, *note));
+            }
+            moves.push(moved);
+        }
+
+        let setups: Vec<Vec<Event>> = moves
+            .iter()
+            .enumerate()
+            .map(|(track, moved)| {
+                moved
+                    .iter()
+                    .flat_map(|(&from, &to)| self.channel_setup(track, from, to, &moves))
+                    .collect()
+            })
+            .collect();
+
+        for ((chunk, moved), setup) in self.tracks.iter_mut().zip(moves).zip(setups) {
+            if moved.is_empty() {
+                continue;
+            }
+            if !setup.is_empty() {
+                let mut editor = TrackEditor::new(core::mem::take(chunk));
+                editor
+                    .events_mut()
+                    .splice(0..0, setup.into_iter().map(|event| (Tick::ZERO, event)));
+                // Only events at tick 0 were added, in front of every other
+                *chunk = editor
+                    .finish()
+                    .expect("Events at the start of a track never lengthen a delta time");
+            }
+            for mtrk_event in chunk.events_mut() {
+                if let Event::MidiEvent(midi) = mtrk_event.event_mut() {
+                    if let Some(to) = moved.get(&midi.channel()) {
+                        *midi.channel_mut() = *to;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The bank, program, volume and pan that tracks other than `track` set for `from` by the
+    /// time `track` first uses it, restated on `to`. Tracks that move `from` themselves don't
+    /// count, since their settings go along with them
+    fn channel_setup(
+        &self,
+        track: usize,
+        from: u8,
+        to: u8,
+        moves: &[BTreeMap<u8, u8>],
+    ) -> Vec<Event> {
+        let Some(start) = self.tracks[track]
+            .absolute_events()
+            .find(|(_, event)| matches!(event, Event::MidiEvent(midi) if midi.channel() == from))
+            .map(|(tick, _)| tick)
+        else {
+            return vec![];
+        };
+
+        let others = self
+            .tracks
+            .iter()
+            .enumerate()
+            .filter(|(other, _)| *other != track && !moves[*other].contains_key(&from));
+        let mut setup = BTreeMap::new();
+        for found in merge_tracks(others) {
+            if found.tick > start {
+                break;
+            }
+            let Event::MidiEvent(midi) = found.event else {
+                continue;
+            };
+            if midi.channel() != from {
+                continue;
+            }
+            if let Some(slot) = setup_slot(midi) {
+                let mut midi = *midi;
+                *midi.channel_mut() = to;
+                setup.insert(slot, Event::MidiEvent(midi));
+            }
+        }
+
+        setup.into_values().collect()
+    }
+}
+
+/// Where a message that sets up a channel's sound goes in the restated setup, so banks are
+/// selected before the program that needs them
+fn setup_slot(event: &MidiEvent) -> Option<u8> {
+    match event {
+        MidiEvent::ControlChange(_, cc) => match cc.controller() {
+            BANK_SELECT_MSB => Some(0),
+            BANK_SELECT_LSB => Some(1),
+            ControlChange::VOLUME => Some(3),
+            ControlChange::PAN => Some(4),
+            _ => None,
+        },
+        MidiEvent::ProgramChange { .. } => Some(2),
+        _ => None,
+    }
+}
+
+/// The notes already sounded by earlier tracks that overlap `note` on its channel and key
+fn colliding<'a>(
+    sounded: &'a BTreeMap<(u8, u8), Vec<(usize, Note)>>,
+    note: &'a Note,
+) -> impl Iterator<Item = (usize, Note)> + 'a {
+    sounded
+        .get(&(note.channel, note.key))
+        .into_iter()
+        .flatten()
+        .filter(|(_, other)| other.start < note.end && note.start < other.end)
+        .copied()
+}
+
+/// K-way merges `(track index, track)` pairs given in any order into the total order of
+/// [`Midi::merged_events`]. Each track's events are already in tick order, so a heap of the
+/// next event from every track keyed by `(tick, track, index)` never has to compare two events
+/// with the same key
+fn merge_tracks<'a>(
+    tracks: impl IntoIterator<Item = (usize, &'a TrackChunk)>,
+) -> Vec<EventRef<'a>> {
+    let mut streams: Vec<_> = tracks
+        .into_iter()
+        .map(|(track, chunk)| (track, chunk.absolute_events().enumerate().peekable()))
+        .collect();
+    let mut heap = BinaryHeap::new();
+    for (stream, (track, events)) in streams.iter_mut().enumerate() {
+        if let Some((index, (tick, _))) = events.peek() {
+            heap.push(Reverse((*tick, *track, *index, stream)));
+        }
+    }
+
+    let mut merged = vec![];
+    while let Some(Reverse((tick, track, index, stream))) = heap.pop() {
+        let events = &mut streams[stream].1;
+        // UNWRAP Safety: The key was pushed from a peeked event of this stream
+        let (_, (_, event)) = events.next().unwrap();
+        merged.push(EventRef {
+            track,
+            index,
+            tick,
+            event,
+        });
+        if let Some((index, (tick, _))) = events.peek() {
+            heap.push(Reverse((*tick, track, *index, stream)));
+        }
+    }
+
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge_tracks, MergeError, MergeOptions, MergePolicy, NoteCollision};
+    use crate::{
+        chunk::{
+            header::HeaderChunk,
+            track::{
+                event::{ControlChange, MidiEvent, NoteMeta},
+                meta::MetaEvent,
+                Event, TrackChunk,
+            },
+        },
+        time::Tick,
+        Midi,
+    };
+
+    fn note(key: u8, velocity: u8) -> Event {
+        Event::MidiEvent(MidiEvent::NoteOn(0, NoteMeta { key, velocity }))
+    }
+
+    fn name(name: &str) -> Event {
+        Event::MetaEvent(MetaEvent::TrackName(name.into()))
+    }
+
+    fn end() -> Event {
+        Event::MetaEvent(MetaEvent::EndOfTrack)
+    }
+
+    fn hands() -> (TrackChunk, TrackChunk) {
+        let right = TrackChunk::from_absolute_events([
+            (0, name("Right")),
+            (0, note(72, 90)),
+            (240, note(72, 0)),
+            (240, note(74, 90)),
+            (480, note(74, 0)),
+            (480, end()),
+        ])
+        .unwrap();
+        let left = TrackChunk::from_absolute_events([
+            (0, name("Left")),
+            (0, note(48, 80)),
+            (360, note(48, 0)),
+            (480, note(43, 80)),
+            (960, note(43, 0)),
+            (960, end()),
+        ])
+        .unwrap();
+
+        (right, left)
+    }
+
+    fn events(track: &TrackChunk) -> Vec<(u32, Event)> {
+        track
+            .events()
+            .map(|mtrk_event| (mtrk_event.delta_time().get(), mtrk_event.event().clone()))
+            .collect()
+    }
+
+    #[test]
+    fn tracks_interleave_by_tick() {
+        let (mut right, left) = hands();
+        right.merge(&left);
+
+        assert_eq!(
+            events(&right),
+            [
+                (0, name("Right")),
+                (0, note(72, 90)),
+                (0, note(48, 80)),
+                (240, note(72, 0)),
+                (0, note(74, 90)),
+                (120, note(48, 0)),
+                (120, note(74, 0)),
+                (0, note(43, 80)),
+                (480, note(43, 0)),
+                (0, end()),
+            ]
+        );
+    }
+
+    #[test]
+    fn names_can_be_concatenated() {
+        let (mut right, left) = hands();
+        right.merge_with(
+            &left,
+            MergeOptions {
+                concatenate_names: true,
+            },
+        );
+        assert_eq!(right.name().as_deref(), Some("Right + Left"));
+        assert_eq!(right.events().count(), 10);
+
+        // An unnamed receiver takes the other track's name
+        let (mut right, left) = hands();
+        right
+            .retain_events(|event| *event != name("Right"))
+            .unwrap();
+        right.merge(&left);
+        assert_eq!(events(&right)[0], (0, name("Left")));
+    }
+
+    #[test]
+    fn merged_order_ignores_track_insertion_order() {
+        let (right, left) = hands();
+        let bass = TrackChunk::from_absolute_events([
+            (0, note(36, 70)),
+            (240, note(36, 0)),
+            (240, note(38, 70)),
+            (960, end()),
+        ])
+        .unwrap();
+        let midi = Midi {
+            header: HeaderChunk::try_from((1, 3, 480)).unwrap(),
+            tracks: vec![right, left, bass],
+        };
+
+        let merged = midi.merged_events();
+        let order: Vec<_> = merged
+            .iter()
+            .map(|found| (found.tick.get(), found.track, found.index))
+            .collect();
+        let mut sorted = order.clone();
+        sorted.sort();
+        assert_eq!(order, sorted);
+        assert_eq!(&order[..4], [(0, 0, 0), (0, 0, 1), (0, 1, 0), (0, 1, 1)]);
+        assert_eq!(order[4], (0, 2, 0));
+
+        let indexed: Vec<_> = midi.tracks.iter().enumerate().collect();
+        for shuffle in [[2, 1, 0], [1, 2, 0], [0, 2, 1], [2, 0, 1]] {
+            let shuffled = merge_tracks(shuffle.iter().map(|&idx| indexed[idx]));
+            assert_eq!(shuffled, merged);
+        }
+
+        let mut format0 = midi.clone();
+        format0.to_format0();
+        let flattened: Vec<_> = format0.tracks[0]
+            .absolute_events()
+            .filter(|(_, event)| !matches!(event, Event::MetaEvent(_)))
+            .collect();
+        let expected: Vec<_> = merged
+            .iter()
+            .filter(|found| !matches!(found.event, Event::MetaEvent(_)))
+            .map(|found| (found.tick, found.event))
+            .collect();
+        assert_eq!(flattened, expected);
+    }
+
+    /// Two tracks on channel 1 that both hold middle C over ticks 240 to 480, the second with
+    /// its own volume and a note that doesn't collide
+    fn colliding() -> Midi {
+        let on = |key| Event::MidiEvent(MidiEvent::NoteOn(0, NoteMeta { key, velocity: 90 }));
+        let off = |key| Event::MidiEvent(MidiEvent::NoteOff(0, NoteMeta { key, velocity: 0 }));
+        let volume = Event::MidiEvent(MidiEvent::ControlChange(
+            0,
+            ControlChange {
+                controller_number: 7,
+                new_value: 80,
+            },
+        ));
+
+        let first =
+            TrackChunk::from_absolute_events([(0, on(60)), (480, off(60)), (960, end())]).unwrap();
+        let second = TrackChunk::from_absolute_events([
+            (0, volume),
+            (0, on(64)),
+            (240, on(60)),
+            (720, off(60)),
+            (720, off(64)),
+            (960, end()),
+        ])
+        .unwrap();
+
+        Midi {
+            header: HeaderChunk::try_from((1, 2, 480)).unwrap(),
+            tracks: vec![first, second],
+        }
+    }
+
+    /// The merged track's notes as `(channel, key, start)`
+    fn merged_notes(midi: &Midi) -> Vec<(u8, u8, u64)> {
+        let mut notes: Vec<_> = midi.tracks[0]
+            .notes()
+            .iter()
+            .map(|note| (note.channel, note.key, note.start.get()))
+            .collect();
+        notes.sort();
+        notes
+    }
+
+    #[test]
+    fn merge_policies_handle_collisions() {
+        let midi = colliding();
+        let collisions = midi.note_collisions();
+        assert_eq!(collisions.len(), 1);
+        let NoteCollision {
+            first_track,
+            first,
+            second_track,
+            second,
+        } = collisions[0];
+        assert_eq!((first_track, second_track), (0, 1));
+        assert_eq!(
+            (first.key, first.start, first.end),
+            (60, Tick::ZERO, Tick::new(480))
+        );
+        assert_eq!(
+            (second.key, second.start, second.end),
+            (60, Tick::new(240), Tick::new(720))
+        );
+
+        let mut kept = midi.clone();
+        kept.to_format0_with(MergePolicy::Keep).unwrap();
+        assert_eq!(merged_notes(&kept), [(0, 60, 0), (0, 60, 240), (0, 64, 0)]);
+
+        let mut refused = midi.clone();
+        assert_eq!(
+            refused.to_format0_with(MergePolicy::ErrorOnCollision),
+            Err(MergeError::Collisions(collisions))
+        );
+        assert_eq!(refused, midi);
+
+        // The second track moves to channel 2 with its volume and its other note
+        let mut spilled = midi.clone();
+        spilled
+            .to_format0_with(MergePolicy::RechannelizeSpill)
+            .unwrap();
+        assert_eq!(
+            merged_notes(&spilled),
+            [(0, 60, 0), (1, 60, 240), (1, 64, 0)]
+        );
+        assert!(spilled.tracks[0]
+            .absolute_events()
+            .any(|(_, event)| matches!(
+                event,
+                Event::MidiEvent(MidiEvent::ControlChange(1, cc)) if cc.controller() == 7
+            )));
+
+        // Merging tracks that don't collide works under every policy
+        let mut clean = midi.clone();
+        clean.tracks[1].transpose(1);
+        assert!(clean.note_collisions().is_empty());
+        clean
+            .to_format0_with(MergePolicy::ErrorOnCollision)
+            .unwrap();
+    }
+
+    #[test]
+    fn spilled_channels_keep_their_setup() {
+        let cc = |controller_number, new_value| {
+            Event::MidiEvent(MidiEvent::ControlChange(
+                0,
+                ControlChange {
+                    controller_number,
+                    new_value,
+                },
+            ))
+        };
+        let program =
+            |program| Event::MidiEvent(MidiEvent::try_program_change(0, program).unwrap());
+
+        // A setup track choosing the sound of channel 1, changing instrument after both notes
+        // have started
+        let mut midi = colliding();
+        midi.tracks.insert(
+            0,
+            TrackChunk::from_absolute_events([
+                (0, cc(0, 1)),
+                (0, program(40)),
+                (0, cc(10, 20)),
+                (0, cc(7, 100)),
+                (0, cc(64, 127)),
+                (600, program(41)),
+                (960, end()),
+            ])
+            .unwrap(),
+        );
+        midi.to_format0_with(MergePolicy::RechannelizeSpill)
+            .unwrap();
+
+        let moved: Vec<_> = midi.tracks[0]
+            .absolute_events()
+            .filter_map(|(tick, event)| match event {
+                Event::MidiEvent(
+                    event @ (MidiEvent::ControlChange(1, _) | MidiEvent::ProgramChange { .. }),
+                ) if event.channel() == 1 => Some((tick.get(), *event)),
+                _ => None,
+            })
+            .collect();
+        let on_channel_2 = |event: Event| match event {
+            Event::MidiEvent(mut event) => {
+                *event.channel_mut() = 1;
+                event
+            }
+            _ => unreachable!(),
+        };
+        // The bank, program, pan and volume in effect when the spilled track starts are
+        // restated, and the track's own volume still follows them
+        assert_eq!(
+            moved,
+            [
+                (0, on_channel_2(cc(0, 1))),
+                (0, on_channel_2(program(40))),
+                (0, on_channel_2(cc(7, 100))),
+                (0, on_channel_2(cc(10, 20))),
+                (0, on_channel_2(cc(7, 80))),
+            ]
+        );
+    }
+
+    #[test]
+    fn spilling_fails_when_every_channel_is_taken() {
+        let mut midi = colliding();
+        let crowd = TrackChunk::from_absolute_events(
+            (1..16)
+                .map(|channel| {
+                    let reset = ControlChange {
+                        controller_number: 121,
+                        new_value: 0,
+                    };
+                    (
+                        0,
+                        Event::MidiEvent(MidiEvent::ControlChange(channel, reset)),
+                    )
+                })
+                .chain([(960, end())]),
+        )
+        .unwrap();
+        midi.tracks.push(crowd);
+        let original = midi.clone();
+
+        assert_eq!(
+            midi.to_format0_with(MergePolicy::RechannelizeSpill),
+            Err(MergeError::NoFreeChannel {
+                track: 1,
+                channel: 0
+            })
+        );
+        assert_eq!(midi, original);
+    }
+}
@@ -0,0 +1,528 @@
+//! The static mix of a file: the volume, pan and expression each channel starts out with
+
+use std::collections::BTreeMap;
+
+use crate::{
+    chunk::track::{
+        event::{channel_nibble, ControlChange, DataOutOfRange, MidiEvent},
+        Event, MTrkEvent,
+    },
+    slice::NoteChange,
+    time::Tick,
+    Midi,
+};
+
+/// Channel Volume a General MIDI device starts with before any Control Change sets it
+const DEFAULT_VOLUME: u8 = 100;
+
+/// The controller values a channel starts out with, see [`Midi::channel_mix`]. A controller
+/// that isn't set before the channel's first note is `None`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MixSettings {
+    /// Channel Volume, CC 7
+    pub volume: Option<u8>,
+    /// Pan, CC 10, where 64 is centered
+    pub pan: Option<u8>,
+    /// Expression, CC 11
+    pub expression: Option<u8>,
+}
+
+/// How [`Midi::set_channel_volume_with`] treats the volume changes after the initial one
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct VolumeOptions {
+    /// Scales every later volume change by the same ratio as the initial volume, so automation
+    /// keeps its shape around the new level. Off by default, which leaves them untouched
+    pub scale_automation: bool,
+}
+
+impl VolumeOptions {
+    /// Sets [`VolumeOptions::scale_automation`]
+    pub fn scale_automation(mut self, scale: bool) -> Self {
+        self.scale_automation = scale;
+        self
+    }
+}
+
+/// The setup [`Midi::ensure_setup_events`] gives a channel that plays notes without it. A
+/// setting left as `None` is never inserted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ChannelDefaults {
+    /// Program to change to. Defaults to 0, Acoustic Grand Piano, or the standard kit on the
+    /// percussion channel
+    pub program: Option<u8>,
+    /// Channel Volume, CC 7. Defaults to 100, where General MIDI devices start
+    pub volume: Option<u8>,
+    /// Pan, CC 10. Defaults to 64, centered
+    pub pan: Option<u8>,
+}
+
+impl Default for ChannelDefaults {
+    fn default() -> Self {
+        Self {
+            program: Some(0),
+            volume: Some(DEFAULT_VOLUME),
+            pan: Some(64),
+        }
+    }
+}
+
+impl ChannelDefaults {
+    /// Sets [`ChannelDefaults::program`]
+    pub fn program(mut self, program: Option<u8>) -> Self {
+        self.program = program;
+        self
+    }
+
+    /// Sets [`ChannelDefaults::volume`]
+    pub fn volume(mut self, volume: Option<u8>) -> Self {
+        self.volume = volume;
+        self
+    }
+
+    /// Sets [`ChannelDefaults::pan`]
+    pub fn pan(mut self, pan: Option<u8>) -> Self {
+        self.pan = pan;
+        self
+    }
+}
+
+/// A Control Change found in a file
+struct TimedChange {
+    /// Absolute tick of the change
+    tick: Tick,
+    /// Index of the track holding it
+    track: usize,
+    /// Index of the event within its track
+    event: usize,
+    /// Channel the change is sent on
+    channel: u8,
+    /// The change itself
+    change: ControlChange,
+}
+
+impl Midi {
+    /// Every Control Change in the file, ordered by tick with simultaneous changes in track order
+    fn timed_control_changes(&self) -> Vec<TimedChange> {
+        let mut changes = vec![];
+        for (track, chunk) in self.tracks.iter().enumerate() {
+            for (event, (tick, midi_event)) in chunk.absolute_events().enumerate() {
+                if let Event::MidiEvent(MidiEvent::ControlChange(channel, change)) = midi_event {
+                    changes.push(TimedChange {
+                        tick,
+                        track,
+                        event,
+                        channel: *channel,
+                        change: *change,
+                    });
+                }
+            }
+        }
+
+        changes.sort_by_key(|change| change.tick);
+        changes
+    }
+
+    /// The tick of every channel's first sounding note across all tracks
+    fn first_notes(&self) -> BTreeMap<u8, Tick> {
+        let mut first_notes = BTreeMap::new();
+        for track in &self.tracks {
+            for (tick, event) in track.absolute_events() {
+                if let Some(NoteChange::On(channel, _)) = NoteChange::of(event) {
+                    let first = first_notes.entry(channel).or_insert(tick);
+                    *first = (*first).min(tick);
+                }
+            }
+        }
+
+        first_notes
+    }
+
+    /// Reports the volume, pan and expression every channel starts out with, for each channel
+    /// that plays a note or sets one of them. A controller's initial value is the last one sent
+    /// at or before the channel's first note, so changes made while notes are playing count as
+    /// automation rather than the mix. On a channel without notes every change counts
+    pub fn channel_mix(&self) -> BTreeMap<u8, MixSettings> {
+        let first_notes = self.first_notes();
+        let mut mix: BTreeMap<u8, MixSettings> = first_notes
+            .keys()
+            .map(|channel| (*channel, MixSettings::default()))
+            .collect();
+
+        for TimedChange {
+            tick,
+            channel,
+            change,
+            ..
+        } in self.timed_control_changes()
+        {
+            if first_notes.get(&channel).is_some_and(|note| tick > *note) {
+                continue;
+            }
+
+            let settings = mix.entry(channel).or_default();
+            let value = Some(change.value());
+            match change.controller() {
+                ControlChange::VOLUME => settings.volume = value,
+                ControlChange::PAN => settings.pan = value,
+                ControlChange::EXPRESSION => settings.expression = value,
+                _ => {}
+            }
+        }
+
+        mix.retain(|channel, settings| {
+            first_notes.contains_key(channel) || *settings != MixSettings::default()
+        });
+        mix
+    }
+
+    /// Sets the volume a channel starts out with, the one [`Midi::channel_mix`] reports, leaving
+    /// any later volume changes alone. See [`Midi::set_channel_volume_with`]
+    pub fn set_channel_volume(&mut self, channel: u8, value: u8) -> Result<(), DataOutOfRange> {
+        self.set_channel_volume_with(channel, value, VolumeOptions::default())
+    }
+
+    /// Sets the volume a channel starts out with by rewriting its initial Channel Volume (CC 7).
+    /// If the channel has none, one is inserted at tick 0 in the track holding the channel's
+    /// first message, or the first track if the channel is unused.
+    ///
+    /// When scaling automation, a channel without an initial volume is treated as starting at the
+    /// General MIDI default of 100, and nothing is scaled if it started at 0. Fails if the
+    /// channel is past 15 or the value past 127, without changing anything
+    pub fn set_channel_volume_with(
+        &mut self,
+        channel: u8,
+        value: u8,
+        options: VolumeOptions,
+    ) -> Result<(), DataOutOfRange> {
+        let channel = channel_nibble(channel)?;
+        let volume = ControlChange::new(ControlChange::VOLUME, value)?;
+
+        let first_note = self.first_notes().get(&channel).copied();
+        let changes: Vec<TimedChange> = self
+            .timed_control_changes()
+            .into_iter()
+            .filter(|timed| {
+                timed.channel == channel && timed.change.controller() == ControlChange::VOLUME
+            })
+            .collect();
+        let initial = changes
+            .iter()
+            .rposition(|timed| first_note.is_none_or(|note| timed.tick <= note));
+
+        let old = initial.map_or(DEFAULT_VOLUME, |idx| changes[idx].change.value());
+        if options.scale_automation && old != 0 {
+            for (idx, timed) in changes.iter().enumerate() {
+                if Some(idx) != initial {
+                    let scaled =
+                        (timed.change.value() as u32 * value as u32 + old as u32 / 2) / old as u32;
+                    self.set_control_value(timed.track, timed.event, scaled.min(0x7F) as u8);
+                }
+            }
+        }
+
+        match initial {
+            Some(idx) => self.set_control_value(changes[idx].track, changes[idx].event, value),
+            None => {
+                let track = self.channel_track(channel);
+                if let Some(track) = self.tracks.get_mut(track) {
+                    track.mtrk_events.insert(
+                        0,
+                        MTrkEvent::new(
+                            0,
+                            Event::MidiEvent(MidiEvent::ControlChange(channel, volume)),
+                        ),
+                    );
+                    track.forget_source();
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Index of the track holding a channel's first message, or of the first track if the
+    /// channel is unused
+    fn channel_track(&self, channel: u8) -> usize {
+        self.tracks
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, track)| {
+                track
+                    .absolute_events()
+                    .find(|(_, event)| {
+                        matches!(event, Event::MidiEvent(midi) if midi.channel() == channel)
+                    })
+                    .map(|(tick, _)| (tick, idx))
+            })
+            .min()
+            .map_or(0, |(_, idx)| idx)
+    }
+
+    /// Gives every channel that plays notes the setup it's missing, so players that start from
+    /// different states render it alike. A program change, Channel Volume (CC 7) or Pan (CC 10)
+    /// from `defaults` is inserted at tick 0 for each channel that doesn't set it at or before
+    /// its first note, the same rule [`Midi::channel_mix`] and [`Midi::program_at`] follow. They
+    /// go at the start of the track holding the channel's first message, in that order.
+    ///
+    /// Returns every inserted event with the index of its track. Running it again inserts
+    /// nothing. Fails if a default is past 127, without changing anything
+    pub fn ensure_setup_events(
+        &mut self,
+        defaults: &ChannelDefaults,
+    ) -> Result<Vec<(usize, MidiEvent)>, DataOutOfRange> {
+        let mix = self.channel_mix();
+        let mut injected = vec![];
+
+        for (channel, first_note) in self.first_notes() {
+            let settings = mix.get(&channel).copied().unwrap_or_default();
+            let track = self.channel_track(channel);
+
+            if self.program_at(channel, first_note).is_none() {
+                if let Some(program) = defaults.program {
+                    injected.push((track, MidiEvent::try_program_change(channel, program)?));
+                }
+            }
+            for (controller, set, default) in [
+                (ControlChange::VOLUME, settings.volume, defaults.volume),
+                (ControlChange::PAN, settings.pan, defaults.pan),
+            ] {
+                if let (None, Some(value)) = (set, default) {
+                    let change = ControlChange::new(controller, value)?;
+                    injected.push((track, MidiEvent::ControlChange(channel, change)));
+                }
+            }
+        }
+
+        for (idx, &(track, event)) in injected.iter().enumerate() {
+            // Earlier insertions into the same track go first
+            let at = injected[..idx]
+                .iter()
+                .filter(|(earlier, _)| *earlier == track)
+                .count();
+            let track = &mut self.tracks[track];
+            track
+                .mtrk_events
+                .insert(at, MTrkEvent::new(0, Event::MidiEvent(event)));
+            track.forget_source();
+        }
+
+        Ok(injected)
+    }
+
+    /// Sets the value of the Control Change at `event` in `track`
+    fn set_control_value(&mut self, track: usize, event: usize, value: u8) {
+        let track = &mut self.tracks[track];
+        track.forget_source();
+        if let Event::MidiEvent(MidiEvent::ControlChange(_, change)) =
+            &mut track.mtrk_events[event].event
+        {
+            change.new_value = value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChannelDefaults, MixSettings, VolumeOptions};
+    use crate::{
+        chunk::{
+            header::HeaderChunk,
+            track::{
+                event::{ControlChange, DataOutOfRange, MidiEvent, NoteMeta},
+                meta::MetaEvent,
+                Event, MTrkEvent, TrackChunk,
+            },
+        },
+        Midi,
+    };
+
+    fn cc(channel: u8, controller: u8, value: u8) -> Event {
+        Event::MidiEvent(MidiEvent::ControlChange(
+            channel,
+            ControlChange::new(controller, value).unwrap(),
+        ))
+    }
+
+    fn on(channel: u8) -> Event {
+        Event::MidiEvent(MidiEvent::NoteOn(
+            channel,
+            NoteMeta {
+                key: 60,
+                velocity: 100,
+            },
+        ))
+    }
+
+    fn track(mut events: Vec<(u64, Event)>) -> TrackChunk {
+        events.push((1920, Event::MetaEvent(MetaEvent::EndOfTrack)));
+        TrackChunk::from_absolute_events(events).unwrap()
+    }
+
+    /// Channel 0 sets its whole mix before playing, then automates its volume. Channel 1 only
+    /// sets a pan, and changes its volume after it starts playing
+    fn midi() -> Midi {
+        let tracks = vec![
+            track(vec![(0, Event::MetaEvent(MetaEvent::DEFAULT_TEMPO))]),
+            track(vec![
+                (0, cc(0, ControlChange::VOLUME, 70)),
+                (0, cc(0, ControlChange::PAN, 30)),
+                (240, cc(0, ControlChange::VOLUME, 90)),
+                (240, cc(0, ControlChange::EXPRESSION, 110)),
+                (480, on(0)),
+                (960, cc(0, ControlChange::VOLUME, 60)),
+                (1440, cc(0, ControlChange::VOLUME, 120)),
+            ]),
+            track(vec![
+                (0, cc(1, ControlChange::PAN, 64)),
+                (0, on(1)),
+                (240, cc(1, ControlChange::VOLUME, 50)),
+            ]),
+        ];
+
+        Midi {
+            header: HeaderChunk::try_from((1, 3, 480)).unwrap(),
+            tracks,
+        }
+    }
+
+    /// Every volume change on a channel, as `(tick, value)`
+    fn volumes(midi: &Midi, channel: u8) -> Vec<(u64, u8)> {
+        midi.tracks
+            .iter()
+            .flat_map(|track| track.control_changes())
+            .filter(|(_, cc_channel, cc)| {
+                *cc_channel == channel && cc.controller() == ControlChange::VOLUME
+            })
+            .map(|(tick, _, cc)| (tick.get(), cc.value()))
+            .collect()
+    }
+
+    #[test]
+    fn initial_values_ignore_automation() {
+        assert_eq!(
+            midi().channel_mix(),
+            [
+                (
+                    0,
+                    MixSettings {
+                        volume: Some(90),
+                        pan: Some(30),
+                        expression: Some(110),
+                    }
+                ),
+                (
+                    1,
+                    MixSettings {
+                        pan: Some(64),
+                        ..MixSettings::default()
+                    }
+                ),
+            ]
+            .into()
+        );
+    }
+
+    #[test]
+    fn only_the_initial_volume_changes() {
+        let mut midi = midi();
+        midi.set_channel_volume(0, 45).unwrap();
+        assert_eq!(
+            volumes(&midi, 0),
+            [(0, 70), (240, 45), (960, 60), (1440, 120)]
+        );
+
+        midi.set_channel_volume(1, 80).unwrap();
+        assert_eq!(volumes(&midi, 1), [(0, 80), (240, 50)]);
+        assert_eq!(
+            midi.tracks[2].absolute_events().next().unwrap().1,
+            &cc(1, ControlChange::VOLUME, 80)
+        );
+
+        let mix = midi.channel_mix();
+        assert_eq!(mix[&0].volume, Some(45));
+        assert_eq!(mix[&1].volume, Some(80));
+    }
+
+    #[test]
+    fn automation_can_scale_with_the_initial_volume() {
+        let mut midi = midi();
+        let scale = VolumeOptions {
+            scale_automation: true,
+        };
+        midi.set_channel_volume_with(0, 45, scale).unwrap();
+        assert_eq!(
+            volumes(&midi, 0),
+            [(0, 35), (240, 45), (960, 30), (1440, 60)]
+        );
+
+        // Channel 1 has no initial volume so it's scaled from the default of 100
+        midi.set_channel_volume_with(1, 127, scale).unwrap();
+        assert_eq!(volumes(&midi, 1), [(0, 127), (240, 64)]);
+    }
+
+    #[test]
+    fn out_of_range_values_are_rejected() {
+        let mut midi = midi();
+        assert_eq!(midi.set_channel_volume(0, 128), Err(DataOutOfRange(128)));
+        assert_eq!(midi.set_channel_volume(16, 100), Err(DataOutOfRange(16)));
+        assert_eq!(midi, self::midi());
+    }
+
+    #[test]
+    fn missing_setup_is_injected_once() {
+        let mut midi = midi();
+        midi.tracks[1].mtrk_events.insert(
+            0,
+            MTrkEvent::new(
+                0,
+                Event::MidiEvent(MidiEvent::try_program_change(0, 30).unwrap()),
+            ),
+        );
+        let configured = midi.tracks[1].clone();
+
+        let injected = midi
+            .ensure_setup_events(&ChannelDefaults::default())
+            .unwrap();
+        assert_eq!(
+            injected,
+            [
+                (2, MidiEvent::try_program_change(1, 0).unwrap()),
+                (
+                    2,
+                    MidiEvent::ControlChange(
+                        1,
+                        ControlChange::new(ControlChange::VOLUME, 100).unwrap()
+                    )
+                ),
+            ]
+        );
+        assert_eq!(midi.tracks[1], configured);
+        assert_eq!(midi.program_at(1, 0), Some(0));
+        assert_eq!(midi.channel_mix()[&1].volume, Some(100));
+        assert_eq!(midi.channel_mix()[&1].pan, Some(64));
+
+        let once = midi.clone();
+        assert_eq!(
+            midi.ensure_setup_events(&ChannelDefaults::default()),
+            Ok(vec![])
+        );
+        assert_eq!(midi, once);
+    }
+
+    #[test]
+    fn unset_defaults_are_skipped_and_bad_ones_rejected() {
+        let mut midi = midi();
+        let defaults = ChannelDefaults::default().program(None).volume(Some(90));
+        assert_eq!(midi.ensure_setup_events(&defaults).unwrap().len(), 1);
+        assert_eq!(midi.program_at(0, 480), None);
+
+        let mut midi = self::midi();
+        assert_eq!(
+            midi.ensure_setup_events(&ChannelDefaults::default().program(Some(128))),
+            Err(DataOutOfRange(128))
+        );
+        assert_eq!(midi, self::midi());
+    }
+}
@@ -0,0 +1,247 @@
+//! Canonical ordering of simultaneous events and whole file normalization
+
+use crate::{
+    chunk::track::{editor::TrackEditor, event::MidiEvent, Event, TrackChunk},
+    timeline::{BANK_SELECT_LSB, BANK_SELECT_MSB},
+    Midi,
+};
+
+/// Where an event falls in the ordering policy of [`TrackChunk::canonicalize_simultaneous`]
+fn simultaneous_rank(event: &Event) -> u8 {
+    match event {
+        Event::MetaEvent(_) | Event::SysexEvent(_) => 0,
+        Event::MidiEvent(event) => match event {
+            MidiEvent::ControlChange(_, cc)
+                if matches!(cc.controller_number, BANK_SELECT_MSB | BANK_SELECT_LSB) =>
+            {
+                1
+            }
+            MidiEvent::ProgramChange { .. } => 2,
+            MidiEvent::ControlChange(..)
+            | MidiEvent::ChannelPressure { .. }
+            | MidiEvent::PitchWheelChange(..) => 3,
+            MidiEvent::NoteOff(..) => 4,
+            MidiEvent::NoteOn(_, note) if note.velocity == 0 => 4,
+            MidiEvent::NoteOn(..) => 5,
+            MidiEvent::PolyphonicKeyPressure(..) => 6,
+        },
+    }
+}
+
+impl TrackChunk {
+    /// Reorders every group of events sharing a tick by a fixed policy, so that setup arrives
+    /// before the notes it affects:
+    ///
+    /// 1. Meta and system exclusive events
+    /// 2. Bank Select (CC 0 and CC 32), then Program Change
+    /// 3. Every other controller, channel pressure and pitch wheel change
+    /// 4. Note Off, including Note On with a velocity of 0
+    /// 5. Note On
+    /// 6. Polyphonic key pressure, which needs its note to be sounding
+    ///
+    /// Events in the same category keep their current order, and `EndOfTrack` stays last.
+    /// Exact duplicates of non-note events at the same tick are dropped, since sending the same
+    /// state twice at once has no effect. No event's absolute time changes
+    pub fn canonicalize_simultaneous(&mut self) {
+        let mut editor = TrackEditor::new(core::mem::take(self));
+        let events = editor.events_mut();
+        events.sort_by_key(|(tick, event)| (*tick, simultaneous_rank(event)));
+
+        let mut run_start = 0;
+        let mut idx = 0;
+        while idx < events.len() {
+            if events[idx].0 != events[run_start].0 {
+                run_start = idx;
+            }
+
+            let (tick, event) = &events[idx];
+            let is_note = matches!(
+                event,
+                Event::MidiEvent(MidiEvent::NoteOn(..) | MidiEvent::NoteOff(..))
+            );
+            if !is_note
+                && events[run_start..idx]
+                    .iter()
+                    .any(|(t, e)| t == tick && e == event)
+            {
+                events.remove(idx);
+            } else {
+                idx += 1;
+            }
+        }
+
+        *self = editor
+            .finish()
+            .expect("Reordering simultaneous events never widens the gap between two events");
+    }
+}
+
+/// Options for [`Midi::normalize`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct NormalizeOptions {
+    /// Also apply [`TrackChunk::canonicalize_simultaneous`] to every track
+    pub canonicalize_simultaneous: bool,
+    /// Splice complete notes found after a track's `EndOfTrack` back in before it with
+    /// [`TrackChunk::recover_trailing_notes`], rather than dropping every trailing event
+    pub recover_trailing_notes: bool,
+}
+
+impl NormalizeOptions {
+    /// Sets [`NormalizeOptions::canonicalize_simultaneous`]
+    pub fn canonicalize_simultaneous(mut self, canonicalize: bool) -> Self {
+        self.canonicalize_simultaneous = canonicalize;
+        self
+    }
+
+    /// Sets [`NormalizeOptions::recover_trailing_notes`]
+    pub fn recover_trailing_notes(mut self, recover: bool) -> Self {
+        self.recover_trailing_notes = recover;
+        self
+    }
+}
+
+impl Midi {
+    /// Puts the file into a consistent shape without changing when anything plays: every track
+    /// is sorted by tick and closed by a single `EndOfTrack`, the header is replaced by
+    /// [`Midi::consistent_header`], and simultaneous events are optionally canonicalized.
+    /// Events parsed after a track's `EndOfTrack` are dropped, or have their notes recovered
+    pub fn normalize(&mut self, options: NormalizeOptions) {
+        for track in &mut self.tracks {
+            if options.recover_trailing_notes {
+                track.recover_trailing_notes();
+            } else {
+                track.take_trailing_events();
+            }
+
+            if options.canonicalize_simultaneous {
+                track.canonicalize_simultaneous();
+            } else {
+                track.sort_stable_by_tick();
+            }
+        }
+
+        self.header = self.consistent_header();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NormalizeOptions;
+    use crate::{
+        chunk::{
+            header::{Format, HeaderChunk},
+            track::{
+                event::{ControlChange, MidiEvent, NoteMeta},
+                meta::MetaEvent,
+                Event, TrackChunk,
+            },
+        },
+        Midi,
+    };
+
+    fn cc(controller_number: u8, new_value: u8) -> Event {
+        Event::MidiEvent(MidiEvent::ControlChange(
+            0,
+            ControlChange {
+                controller_number,
+                new_value,
+            },
+        ))
+    }
+
+    fn note_on(key: u8, velocity: u8) -> Event {
+        Event::MidiEvent(MidiEvent::NoteOn(0, NoteMeta { key, velocity }))
+    }
+
+    fn note_off(key: u8) -> Event {
+        Event::MidiEvent(MidiEvent::NoteOff(0, NoteMeta { key, velocity: 0 }))
+    }
+
+    fn events(track: &TrackChunk) -> Vec<(u64, Event)> {
+        track
+            .absolute_events()
+            .map(|(tick, event)| (tick.get(), event.clone()))
+            .collect()
+    }
+
+    fn scrambled() -> TrackChunk {
+        TrackChunk::from_absolute_events([
+            (0, note_on(60, 100)),
+            (96, note_on(62, 100)),
+            (96, cc(7, 90)),
+            (96, note_off(60)),
+            (
+                96,
+                Event::MidiEvent(MidiEvent::ProgramChange {
+                    channel: 0,
+                    program: 5,
+                }),
+            ),
+            (96, cc(10, 64)),
+            (96, note_on(64, 0)),
+            (96, cc(0, 1)),
+            (96, cc(7, 90)),
+            (96, Event::MetaEvent(MetaEvent::Marker("B".into()))),
+            (96, cc(32, 2)),
+            (96, note_on(62, 100)),
+            (192, Event::MetaEvent(MetaEvent::EndOfTrack)),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn scrambled_cluster_canonicalized() {
+        let mut track = scrambled();
+        track.canonicalize_simultaneous();
+
+        assert_eq!(
+            events(&track),
+            [
+                (0, note_on(60, 100)),
+                (96, Event::MetaEvent(MetaEvent::Marker("B".into()))),
+                (96, cc(0, 1)),
+                (96, cc(32, 2)),
+                (
+                    96,
+                    Event::MidiEvent(MidiEvent::ProgramChange {
+                        channel: 0,
+                        program: 5,
+                    })
+                ),
+                (96, cc(7, 90)),
+                (96, cc(10, 64)),
+                (96, note_off(60)),
+                (96, note_on(64, 0)),
+                (96, note_on(62, 100)),
+                (96, note_on(62, 100)),
+                (192, Event::MetaEvent(MetaEvent::EndOfTrack)),
+            ]
+        );
+
+        let once = track.clone();
+        track.canonicalize_simultaneous();
+        assert_eq!(track, once);
+    }
+
+    #[test]
+    fn normalize_only_canonicalizes_when_asked() {
+        let mut midi = Midi {
+            header: HeaderChunk::try_from((0, 5, 96)).unwrap(),
+            tracks: vec![scrambled(), scrambled()],
+        };
+
+        midi.normalize(NormalizeOptions::default());
+        assert_eq!(midi.tracks[0], scrambled());
+        assert_eq!(midi.header.format(), Format::One);
+        assert_eq!(midi.header.ntrks(), 2);
+
+        midi.normalize(NormalizeOptions {
+            canonicalize_simultaneous: true,
+            ..Default::default()
+        });
+        let mut expected = scrambled();
+        expected.canonicalize_simultaneous();
+        assert_eq!(midi.tracks[0], expected);
+    }
+}
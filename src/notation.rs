@@ -0,0 +1,210 @@
+//! Reshaping notes the way notation software expects them
+
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::{
+    chunk::track::{editor::TrackEditor, TrackChunk},
+    slice::NoteChange,
+    time::Tick,
+    timeline::PositionError,
+    Midi,
+};
+
+impl TrackChunk {
+    /// Splits every note that sounds across one of the `boundaries` into a note per span, so a
+    /// note held over a bar line becomes notes retriggered at it. Each span but the last ends
+    /// `gap_ticks` before the next boundary with a copy of the note's Note Off, and each span
+    /// but the first starts at its boundary with a copy of the note's Note On, keeping its
+    /// channel, key and velocity.
+    ///
+    /// A note starting or ending exactly on a boundary isn't split there. A span left without
+    /// any length by the gap is dropped, so a note struck just before a boundary starts at it
+    /// instead. Notes are paired like [`TrackChunk::notes`], and a note that's never released
+    /// is left alone. The boundaries don't need to be sorted
+    pub fn split_notes_at(&mut self, boundaries: &[Tick], gap_ticks: u32) {
+        let mut boundaries = boundaries.to_vec();
+        boundaries.sort();
+        boundaries.dedup();
+
+        let mut editor = TrackEditor::new(core::mem::take(self));
+        let mut sounding: BTreeMap<(u8, u8), VecDeque<usize>> = BTreeMap::new();
+        let mut notes = vec![];
+        for (idx, (_, event)) in editor.events().iter().enumerate() {
+            match NoteChange::of(event) {
+                Some(NoteChange::On(channel, note)) => sounding
+                    .entry((channel, note.key))
+                    .or_default()
+                    .push_back(idx),
+                Some(NoteChange::Off(channel, note)) => {
+                    if let Some(on) = sounding
+                        .get_mut(&(channel, note.key))
+                        .and_then(|ons| ons.pop_front())
+                    {
+                        notes.push((on, idx));
+                    }
+                }
+                None => {}
+            }
+        }
+
+        let mut added = vec![];
+        for (on, off) in notes {
+            let (start, on_event) = editor.events()[on].clone();
+            let (end, off_event) = editor.events()[off].clone();
+            let crossed = boundaries.partition_point(|tick| *tick <= start)
+                ..boundaries.partition_point(|tick| *tick < end);
+            if crossed.is_empty() {
+                continue;
+            }
+
+            let mut span_start = start;
+            let mut first = true;
+            for &boundary in &boundaries[crossed] {
+                let span_end = Tick(boundary.get().saturating_sub(gap_ticks as u64));
+                if span_end > span_start {
+                    if first {
+                        editor.events_mut()[on].0 = span_start;
+                        first = false;
+                    } else {
+                        added.push((span_start, on_event.clone()));
+                    }
+                    added.push((span_end, off_event.clone()));
+                }
+                span_start = boundary;
+            }
+
+            if first {
+                editor.events_mut()[on].0 = span_start;
+            } else {
+                added.push((span_start, on_event));
+            }
+        }
+
+        for (tick, event) in added {
+            editor.insert(tick, event);
+        }
+        *self = editor
+            .finish()
+            .expect("Splitting notes only adds events between existing ones");
+    }
+}
+
+impl Midi {
+    /// Applies [`TrackChunk::split_notes_at`] to every track at every bar line, following the
+    /// time signature changes, with no gap so each retriggered note reads as tied. A time
+    /// signature change always starts a new bar, even if the bar before it is cut short.
+    ///
+    /// Fails with [`PositionError::TimeCodeDivision`] for time-code-based files, which have no
+    /// bars
+    pub fn split_notes_at_bars(&mut self) -> Result<(), PositionError> {
+        let bar_lines = self
+            .bar_lines(self.duration())
+            .ok_or(PositionError::TimeCodeDivision)?;
+
+        for track in &mut self.tracks {
+            track.split_notes_at(&bar_lines, 0);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        chunk::{
+            header::HeaderChunk,
+            track::{
+                event::{MidiEvent, NoteMeta},
+                meta::MetaEvent,
+                Event, TrackChunk,
+            },
+        },
+        time::Tick,
+        Midi,
+    };
+
+    fn on(key: u8) -> Event {
+        Event::MidiEvent(MidiEvent::NoteOn(0, NoteMeta { key, velocity: 90 }))
+    }
+
+    fn off(key: u8) -> Event {
+        Event::MidiEvent(MidiEvent::NoteOff(0, NoteMeta { key, velocity: 64 }))
+    }
+
+    fn end() -> Event {
+        Event::MetaEvent(MetaEvent::EndOfTrack)
+    }
+
+    fn events(track: &TrackChunk) -> Vec<(u64, Event)> {
+        track
+            .absolute_events()
+            .map(|(tick, event)| (tick.get(), event.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn a_tied_note_is_retriggered_at_the_bar_line() {
+        // A note held for both bars of 4/4 at 480 tpq, and one ending on the bar line
+        let track = TrackChunk::from_absolute_events([
+            (0, on(60)),
+            (0, on(64)),
+            (1920, off(64)),
+            (3840, off(60)),
+            (3840, end()),
+        ])
+        .unwrap();
+        let mut midi = Midi {
+            header: HeaderChunk::try_from((0, 1, 480)).unwrap(),
+            tracks: vec![track],
+        };
+
+        midi.split_notes_at_bars().unwrap();
+        assert_eq!(
+            events(&midi.tracks[0]),
+            [
+                (0, on(60)),
+                (0, on(64)),
+                (1920, off(64)),
+                (1920, off(60)),
+                (1920, on(60)),
+                (3840, off(60)),
+                (3840, end()),
+            ]
+        );
+        assert_eq!(midi.tracks[0].notes().len(), 3);
+
+        // Splitting again changes nothing
+        let split = midi.clone();
+        midi.split_notes_at_bars().unwrap();
+        assert_eq!(midi, split);
+    }
+
+    #[test]
+    fn gaps_leave_room_before_each_boundary() {
+        let mut track = TrackChunk::from_absolute_events([
+            (0, on(60)),
+            (1910, on(62)),
+            (2400, off(62)),
+            (4800, off(60)),
+            (4800, end()),
+        ])
+        .unwrap();
+
+        track.split_notes_at(&[Tick::new(3840), Tick::new(1920)], 30);
+        assert_eq!(
+            events(&track),
+            [
+                (0, on(60)),
+                (1890, off(60)),
+                (1920, on(62)),
+                (1920, on(60)),
+                (2400, off(62)),
+                (3810, off(60)),
+                (3840, on(60)),
+                (4800, off(60)),
+                (4800, end()),
+            ]
+        );
+    }
+}
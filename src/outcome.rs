@@ -0,0 +1,838 @@
+//! Lenient parsing straight to a [`Midi`], reporting what was parsed and everything that had to
+//! be skipped or tolerated along the way
+
+use std::collections::BTreeMap;
+
+use crate::{
+    chunk::{
+        chunk_types::{HEADER_CHUNK, TRACK_DATA_CHUNK},
+        track::{kind::MetaKind, Event},
+        ChunkParseError, ParsedChunk,
+    },
+    reader::{read_lenient, read_strict, LenientRead, ParseOptions, ParseProfile},
+    time::Tick,
+    Midi, MidiSanitizerError,
+};
+
+/// A leniently parsed file along with what it took to parse it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseOutcome {
+    /// The parsed file
+    pub midi: Midi,
+    /// Counts of what was read
+    pub stats: ParseStats,
+    /// Everything that was skipped or tolerated, in the order it was found
+    pub warnings: Vec<ParseWarning>,
+}
+
+/// Counts of what a lenient parse read
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParseStats {
+    /// Number of chunks of each type, including unknown chunks that were skipped
+    pub chunks: BTreeMap<[char; 4], usize>,
+    /// Number of MIDI channel events
+    pub midi_events: usize,
+    /// Number of system exclusive events
+    pub sysex_events: usize,
+    /// Number of meta events
+    pub meta_events: usize,
+    /// Every byte read from the stream, including skipped and trailing bytes
+    pub bytes_consumed: usize,
+}
+
+impl ParseStats {
+    /// Number of events of every kind
+    pub fn event_count(&self) -> usize {
+        self.midi_events + self.sysex_events + self.meta_events
+    }
+
+    /// Number of chunks of a given type
+    pub fn chunk_count(&self, chunk_type: [char; 4]) -> usize {
+        self.chunks.get(&chunk_type).copied().unwrap_or(0)
+    }
+}
+
+/// Something a lenient parse skipped or tolerated instead of failing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseWarning {
+    /// Bytes that couldn't start a chunk header were skipped
+    SkippedBytes {
+        /// Number of bytes skipped
+        count: usize,
+    },
+    /// A chunk of a type miami doesn't know was skipped
+    UnknownChunk {
+        /// The chunk's type
+        chunk_type: [char; 4],
+        /// Length of the skipped payload
+        length: usize,
+    },
+    /// A header after the first one was skipped
+    ExtraHeader,
+    /// The stream ended partway through a chunk, which was dropped, or kept as a
+    /// [`crate::chunk::track::TrackChunk::is_truncated`] track if tracks are salvaged
+    TruncatedChunk {
+        /// The chunk's type
+        chunk_type: [char; 4],
+        /// Payload length the chunk declared
+        expected: usize,
+        /// Payload bytes that were actually there
+        available: usize,
+    },
+    /// The header declares a different number of tracks than the file holds
+    TrackCountMismatch {
+        /// Tracks the header declares
+        declared: u16,
+        /// Tracks actually parsed
+        found: usize,
+    },
+    /// A track stopped parsing partway or was cut off, and the events before that point were
+    /// kept and closed with an `EndOfTrack`
+    TrackSalvaged {
+        /// Index of the track in the parsed file
+        track: usize,
+        /// Number of events that were parsed
+        events: usize,
+    },
+    /// A meta or system exclusive event longer than [`crate::reader::ParseOptions::max_event_payload`]
+    /// was skipped, and the track resumed at the next event that parses
+    OversizedEventSkipped {
+        /// Index of the track in the parsed file
+        track: usize,
+        /// Payload length the event declared
+        declared: u32,
+        /// The configured limit
+        limit: u32,
+    },
+    /// Notes found after a track's `EndOfTrack` were moved back into it
+    TrailingNotesRecovered {
+        /// Index of the track in the parsed file
+        track: usize,
+        /// Number of events moved back
+        events: usize,
+    },
+    /// A format 1 file has a tempo, time signature or key signature outside track 0, see
+    /// [`Midi::misplaced_conductor_events`]
+    MisplacedConductorEvent {
+        /// Index of the track holding the event
+        track: usize,
+        /// Absolute tick of the event
+        tick: Tick,
+        /// Which kind of event it is
+        kind: MetaKind,
+    },
+}
+
+impl core::fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::SkippedBytes { count } => {
+                write![f, "Skipped {count} bytes that can't start a chunk"]
+            }
+            Self::UnknownChunk { chunk_type, length } => write![
+                f,
+                "Skipped unknown {} chunk of {length} bytes",
+                String::from_iter(chunk_type)
+            ],
+            Self::ExtraHeader => write![f, "Skipped a second header chunk"],
+            Self::TruncatedChunk {
+                chunk_type,
+                expected,
+                available,
+            } => write![
+                f,
+                "{} chunk was cut off after {available} of {expected} bytes",
+                String::from_iter(chunk_type)
+            ],
+            Self::TrackCountMismatch { declared, found } => write![
+                f,
+                "Header declares {declared} tracks but {found} were found"
+            ],
+            Self::TrackSalvaged { track, events } => {
+                write![f, "Kept the first {events} events of damaged track {track}"]
+            }
+            Self::OversizedEventSkipped {
+                track,
+                declared,
+                limit,
+            } => write![
+                f,
+                "Skipped an event of {declared} bytes in track {track}, over the limit of {limit}"
+            ],
+            Self::TrailingNotesRecovered { track, events } => write![
+                f,
+                "Recovered {events} events from after the end of track {track}"
+            ],
+            Self::MisplacedConductorEvent { track, tick, kind } => write![
+                f,
+                "Track {track} has a {kind} event at tick {tick} outside the conductor track"
+            ],
+        }
+    }
+}
+
+/// Summarizes the parse in one line, such as
+/// `parsed 14 tracks, 120000 events, skipped 3 unknown chunks, 2 warnings`
+impl core::fmt::Display for ParseOutcome {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let unknown = self
+            .warnings
+            .iter()
+            .filter(|warning| matches!(warning, ParseWarning::UnknownChunk { .. }))
+            .count();
+
+        write![
+            f,
+            "parsed {} tracks, {} events, skipped {unknown} unknown chunks, {} warnings",
+            self.midi.tracks.len(),
+            self.stats.event_count(),
+            self.warnings.len()
+        ]
+    }
+}
+
+/// Error from a lenient parse that couldn't produce a file at all
+#[derive(Debug)]
+pub enum LenientParseError {
+    /// A header or track chunk failed to parse
+    Chunk(ChunkParseError),
+    /// No header was found before the first track
+    Structure(MidiSanitizerError),
+}
+
+impl core::error::Error for LenientParseError {}
+impl core::fmt::Display for LenientParseError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Chunk(err) => write![f, "{err}"],
+            Self::Structure(err) => write![f, "{err}"],
+        }
+    }
+}
+
+impl From<ChunkParseError> for LenientParseError {
+    fn from(f: ChunkParseError) -> Self {
+        Self::Chunk(f)
+    }
+}
+
+impl From<MidiSanitizerError> for LenientParseError {
+    fn from(f: MidiSanitizerError) -> Self {
+        Self::Structure(f)
+    }
+}
+
+impl Midi {
+    /// Parses a file leniently, skipping garbage between chunks, unknown chunk types, extra
+    /// headers and a truncated final chunk instead of failing on them. Each of these is reported
+    /// as a [`ParseWarning`] alongside counts of everything parsed. Text is decoded and memory
+    /// use is limited according to `options`. This is [`Midi::try_from_midi_stream_with`] using
+    /// [`ParseProfile::permissive`]
+    pub fn try_from_midi_stream_lenient<STREAM>(
+        stream: STREAM,
+        options: ParseOptions,
+    ) -> Result<ParseOutcome, LenientParseError>
+    where
+        STREAM: Iterator<Item = u8>,
+    {
+        Self::try_from_midi_stream_with(stream, ParseProfile::permissive().options(options))
+    }
+
+    /// Parses a file as forgivingly as the given [`ParseProfile`] allows, reporting everything
+    /// it skipped or tolerated as a [`ParseWarning`] alongside counts of everything parsed. A
+    /// header that declares a different number of tracks than were found is always just a
+    /// warning
+    pub fn try_from_midi_stream_with<STREAM>(
+        mut stream: STREAM,
+        profile: impl Into<ParseProfile>,
+    ) -> Result<ParseOutcome, LenientParseError>
+    where
+        STREAM: Iterator<Item = u8>,
+    {
+        let profile = profile.into();
+        let options = profile.options;
+        let mut stats = ParseStats::default();
+        let mut warnings = vec![];
+        let mut header = None;
+        let mut tracks = vec![];
+        let mut remaining = options.max_total_bytes;
+
+        loop {
+            let read = if profile.skip_garbage {
+                read_lenient(&mut stream, remaining)
+            } else {
+                read_strict(&mut stream, remaining)
+            };
+            let (skipped, chunk, data, complete) = match read {
+                LenientRead::Chunk {
+                    skipped,
+                    chunk,
+                    data,
+                } => (skipped, chunk, data, true),
+                LenientRead::Truncated {
+                    skipped,
+                    chunk,
+                    data,
+                } => (skipped, chunk, data, false),
+                LenientRead::TooLong { .. } => {
+                    return Err(options.total_bytes_exceeded().into());
+                }
+                LenientRead::End { trailing: 0 } => break,
+                LenientRead::End { trailing } if profile.skip_garbage => {
+                    warnings.push(ParseWarning::SkippedBytes { count: trailing });
+                    stats.bytes_consumed += trailing;
+                    break;
+                }
+                LenientRead::End { trailing } => {
+                    return Err(ChunkParseError::Truncated {
+                        expected: 8,
+                        available: trailing,
+                    }
+                    .into());
+                }
+            };
+
+            if skipped > 0 {
+                warnings.push(ParseWarning::SkippedBytes { count: skipped });
+            }
+            stats.bytes_consumed += skipped + 8 + data.len();
+
+            let salvaged = profile.salvage_tracks && chunk.chunk_type == TRACK_DATA_CHUNK;
+            if !complete {
+                if !profile.drop_truncated_chunks && !salvaged {
+                    return Err(ChunkParseError::Truncated {
+                        expected: chunk.len(),
+                        available: data.len(),
+                    }
+                    .into());
+                }
+                warnings.push(ParseWarning::TruncatedChunk {
+                    chunk_type: chunk.chunk_type,
+                    expected: chunk.len(),
+                    available: data.len(),
+                });
+                if !salvaged {
+                    break;
+                }
+            }
+
+            *stats.chunks.entry(chunk.chunk_type).or_default() += 1;
+            let known = matches!(chunk.chunk_type, HEADER_CHUNK | TRACK_DATA_CHUNK);
+            options.charge(&mut remaining, data.len(), known)?;
+
+            let Some(parsed) =
+                profile.parse_chunk(chunk, &data, complete, tracks.len(), &mut warnings)?
+            else {
+                continue;
+            };
+
+            match parsed {
+                ParsedChunk::Header(parsed) => match header {
+                    None => header = Some(parsed),
+                    Some(_) if profile.skip_extra_headers => {
+                        warnings.push(ParseWarning::ExtraHeader)
+                    }
+                    Some(_) => return Err(MidiSanitizerError::TooManyHeaders.into()),
+                },
+                ParsedChunk::Track(track) => {
+                    if header.is_none() {
+                        return Err(MidiSanitizerError::NoStartHeader.into());
+                    }
+
+                    for mtrk_event in track.events() {
+                        match mtrk_event.event() {
+                            Event::MidiEvent(_) => stats.midi_events += 1,
+                            Event::SysexEvent(_) => stats.sysex_events += 1,
+                            Event::MetaEvent(_) => stats.meta_events += 1,
+                        }
+                    }
+                    tracks.push(track);
+                }
+            }
+
+            if !complete {
+                break;
+            }
+        }
+
+        let header = header.ok_or(MidiSanitizerError::NoChunks)?;
+        if header.ntrks() as usize != tracks.len() {
+            warnings.push(ParseWarning::TrackCountMismatch {
+                declared: header.ntrks(),
+                found: tracks.len(),
+            });
+        }
+
+        let midi = Midi { header, tracks };
+        for (track, tick, kind) in midi.misplaced_conductor_events() {
+            if profile.reject_misplaced_conductor_events {
+                return Err(
+                    MidiSanitizerError::MisplacedConductorEvent { track, tick, kind }.into(),
+                );
+            }
+            warnings.push(ParseWarning::MisplacedConductorEvent { track, tick, kind });
+        }
+
+        Ok(ParseOutcome {
+            midi,
+            stats,
+            warnings,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LenientParseError, ParseWarning};
+    use crate::{
+        chunk::{
+            chunk_types::{HEADER_CHUNK, TRACK_DATA_CHUNK},
+            header::HeaderChunk,
+            track::{
+                event::{MidiEvent, NoteMeta},
+                kind::MetaKind,
+                meta::MetaEvent,
+                Event, TrackChunk, TrackError,
+            },
+            ChunkParseError, ParsedChunk,
+        },
+        reader::{MidiReadable, ParseOptions, ParseProfile},
+        time::Tick,
+        validate::ValidationIssue,
+        writer::MidiWriteable,
+        Chunk, Midi, MidiSanitizerError, RawMidi,
+    };
+
+    fn bytes(path: &str) -> Vec<u8> {
+        path.get_midi_bytes().unwrap().collect()
+    }
+
+    #[test]
+    fn clean_file_counts_everything_without_warnings() {
+        let bytes = bytes("test/run.mid");
+        let strict = RawMidi::try_from_midi_stream(bytes.clone().into_iter())
+            .unwrap()
+            .check_into_midi()
+            .unwrap();
+
+        let outcome =
+            Midi::try_from_midi_stream_lenient(bytes.clone().into_iter(), ParseOptions::default())
+                .unwrap();
+
+        assert_eq!(outcome.midi, strict);
+        assert!(outcome.warnings.is_empty());
+        assert_eq!(outcome.stats.chunk_count(HEADER_CHUNK), 1);
+        assert_eq!(outcome.stats.chunk_count(TRACK_DATA_CHUNK), 10);
+        assert_eq!(outcome.stats.bytes_consumed, bytes.len());
+
+        let events: usize = strict
+            .tracks
+            .iter()
+            .map(|track| track.events().count())
+            .sum();
+        assert_eq!(outcome.stats.event_count(), events);
+        assert_eq!(
+            outcome.to_string(),
+            format!("parsed 10 tracks, {events} events, skipped 0 unknown chunks, 0 warnings")
+        );
+    }
+
+    #[test]
+    fn damaged_file_reports_what_was_skipped() {
+        let clean = bytes("test/test4tracks.mid");
+        let mut damaged = clean[..14].to_vec();
+        damaged.extend([0x00, 0x00]);
+        damaged.extend(b"XFIH\0\0\0\x02\x01\x02");
+        damaged.extend(&clean[14..]);
+        damaged.extend(b"MTrk\0\0\0\x10\x00");
+
+        let outcome = Midi::try_from_midi_stream_lenient(
+            damaged.clone().into_iter(),
+            ParseOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            outcome.warnings,
+            [
+                ParseWarning::SkippedBytes { count: 2 },
+                ParseWarning::UnknownChunk {
+                    chunk_type: ['X', 'F', 'I', 'H'],
+                    length: 2
+                },
+                ParseWarning::TruncatedChunk {
+                    chunk_type: TRACK_DATA_CHUNK,
+                    expected: 16,
+                    available: 1
+                },
+            ]
+        );
+        assert_eq!(outcome.midi.tracks.len(), 1);
+        assert_eq!(outcome.stats.chunk_count(TRACK_DATA_CHUNK), 1);
+        assert_eq!(outcome.stats.chunk_count(['X', 'F', 'I', 'H']), 1);
+        assert_eq!(outcome.stats.event_count(), 17);
+        assert_eq!(outcome.stats.meta_events, 4);
+        assert_eq!(outcome.stats.bytes_consumed, damaged.len());
+        assert_eq!(
+            outcome.to_string(),
+            "parsed 1 tracks, 17 events, skipped 1 unknown chunks, 3 warnings"
+        );
+    }
+
+    #[test]
+    fn track_before_header_is_an_error() {
+        let track = b"MTrk\0\0\0\x04\x00\xFF\x2F\x00";
+        assert!(matches!(
+            Midi::try_from_midi_stream_lenient(track.iter().copied(), ParseOptions::default()),
+            Err(LenientParseError::Structure(
+                MidiSanitizerError::NoStartHeader
+            ))
+        ));
+    }
+
+    /// Padding before the header, an unknown chunk, notes after a track's End of Track, a second
+    /// header and a final track cut off partway
+    fn messy_file() -> Vec<u8> {
+        let header = b"MThd\0\0\0\x06\0\x01\0\x02\0\x60";
+        let mut bytes = vec![0x00, 0x00];
+        bytes.extend(header);
+        bytes.extend(b"XFIH\0\0\0\x02\x01\x02");
+        bytes.extend(b"MTrk\0\0\0\x14");
+        bytes.extend([
+            0x00, 0x90, 60, 100, 0x60, 0x80, 60, 0, 0x00, 0xFF, 0x2F, 0x00,
+        ]);
+        bytes.extend([0x00, 0x90, 62, 100, 0x60, 0x80, 62, 0]);
+        bytes.extend(header);
+        bytes.extend(b"MTrk\0\0\0\x10");
+        bytes.extend([0x00, 0x90, 64, 100, 0x60, 0x80, 64, 0, 0x00, 0xFF]);
+        bytes
+    }
+
+    #[test]
+    fn track_cut_off_mid_event_is_salvaged_as_truncated() {
+        let full = bytes("test/test.mid");
+        let payload = &full[22..];
+        let track = TrackChunk::parse(payload).unwrap();
+        let ParsedChunk::Track(spans) = ParsedChunk::parse_with(
+            Chunk::try_from_bytes(full[14..22].try_into().unwrap()).unwrap(),
+            payload,
+            ParseOptions::default().record_spans(true),
+        )
+        .unwrap() else {
+            panic!("test.mid's second chunk is a track");
+        };
+
+        // Cut the file off one byte before the end of a note in the middle of the track
+        let kept = track
+            .events()
+            .enumerate()
+            .skip(track.events().count() / 2)
+            .find(|(_, mtrk_event)| {
+                matches!(mtrk_event.event(), Event::MidiEvent(MidiEvent::NoteOn(..)))
+            })
+            .map(|(idx, _)| idx)
+            .unwrap();
+        let cut = 22 + spans.event_span(kept).unwrap().end - 1;
+        let truncated = &full[..cut];
+
+        let parse = |profile| Midi::try_from_midi_stream_with(truncated.iter().copied(), profile);
+        assert!(matches!(
+            parse(ParseProfile::strict()),
+            Err(LenientParseError::Chunk(ChunkParseError::Truncated { .. }))
+        ));
+
+        let outcome = parse(ParseProfile::recovering()).unwrap();
+        assert_eq!(
+            outcome.warnings,
+            [
+                ParseWarning::TruncatedChunk {
+                    chunk_type: TRACK_DATA_CHUNK,
+                    expected: payload.len(),
+                    available: cut - 22,
+                },
+                ParseWarning::TrackSalvaged {
+                    track: 0,
+                    events: kept
+                },
+            ]
+        );
+        let salvaged = &outcome.midi.tracks[0];
+        assert!(salvaged.is_truncated());
+        assert!(salvaged.events().take(kept).eq(track.events().take(kept)));
+        assert!(outcome
+            .midi
+            .validate()
+            .contains(&ValidationIssue::TruncatedTrack { track: 0 }));
+
+        // Written back out, the chunk declares the length of what was kept
+        let written = outcome.midi.clone().to_midi_bytes();
+        let reparsed = RawMidi::try_from_midi_stream(written.into_iter())
+            .unwrap()
+            .check_into_midi()
+            .unwrap();
+        assert_eq!(reparsed, outcome.midi);
+        assert!(!reparsed.tracks[0].is_truncated());
+    }
+
+    #[test]
+    fn profiles_tolerate_progressively_more() {
+        let parse = |profile| Midi::try_from_midi_stream_with(messy_file().into_iter(), profile);
+
+        assert!(matches!(
+            parse(ParseProfile::strict()),
+            Err(LenientParseError::Chunk(ChunkParseError::Truncated { .. }))
+        ));
+        assert!(matches!(
+            parse(ParseProfile::strict().skip_garbage(true)),
+            Err(LenientParseError::Chunk(ChunkParseError::UnknownType))
+        ));
+
+        let unknown = ParseWarning::UnknownChunk {
+            chunk_type: ['X', 'F', 'I', 'H'],
+            length: 2,
+        };
+        let truncated = ParseWarning::TruncatedChunk {
+            chunk_type: TRACK_DATA_CHUNK,
+            expected: 16,
+            available: 10,
+        };
+
+        let permissive = parse(ParseProfile::permissive()).unwrap();
+        assert_eq!(
+            permissive.warnings,
+            [
+                ParseWarning::SkippedBytes { count: 2 },
+                unknown,
+                ParseWarning::ExtraHeader,
+                truncated,
+                ParseWarning::TrackCountMismatch {
+                    declared: 2,
+                    found: 1
+                },
+            ]
+        );
+        assert_eq!(permissive.midi.tracks[0].trailing_events().len(), 2);
+
+        let recovering = parse(ParseProfile::recovering()).unwrap();
+        assert_eq!(
+            recovering.warnings,
+            [
+                ParseWarning::SkippedBytes { count: 2 },
+                unknown,
+                ParseWarning::TrailingNotesRecovered {
+                    track: 0,
+                    events: 2
+                },
+                ParseWarning::ExtraHeader,
+                truncated,
+                ParseWarning::TrackSalvaged {
+                    track: 1,
+                    events: 2
+                },
+            ]
+        );
+        assert_eq!(recovering.midi.tracks.len(), 2);
+        assert_eq!(recovering.stats.event_count(), 8);
+        assert_eq!(permissive.stats.event_count(), 3);
+        assert!(recovering
+            .midi
+            .tracks
+            .iter()
+            .all(|track| { track.trailing_events().is_empty() && track.ends_with_end_of_track() }));
+    }
+
+    #[test]
+    fn raw_parse_follows_the_profile() {
+        let parse = |profile| RawMidi::try_from_midi_stream_with(messy_file().into_iter(), profile);
+
+        assert!(matches!(
+            parse(ParseProfile::permissive().skip_unknown_chunks(false)),
+            Err(ChunkParseError::UnknownType)
+        ));
+
+        // The stream can't tell the cut off track from the end of the file, so it's dropped
+        let raw = parse(ParseProfile::recovering()).unwrap();
+        assert_eq!(raw.chunks.len(), 2);
+        assert_eq!(raw.check_into_midi().unwrap().tracks[0].events().count(), 5);
+    }
+
+    #[test]
+    fn oversized_events_are_skipped_when_salvaging() {
+        // A lyric whose length byte had its top bit flipped, so it runs on into its own text
+        let track = [
+            0x00, 0xFF, 0x05, 0x84, b'l', b'a', b'l', b'a', // lyric, length 0x04 became 0x84
+            0x00, 0x90, 0x3C, 0x64, // note on
+            0x60, 0x80, 0x3C, 0x40, // note off
+            0x00, 0xFF, 0x2F, 0x00, // end of track
+        ];
+        let mut file = b"MThd\0\0\0\x06\0\0\0\x01\0\x60MTrk\0\0\0\x14".to_vec();
+        file.extend(track);
+
+        let options = ParseOptions {
+            max_event_payload: 64,
+            ..ParseOptions::default()
+        };
+        let parse =
+            |profile: ParseProfile| Midi::try_from_midi_stream_with(file.iter().copied(), profile);
+
+        assert!(matches!(
+            parse(ParseProfile::permissive().options(options)),
+            Err(LenientParseError::Chunk(ChunkParseError::TrackParseError(
+                TrackError::EventTooLarge {
+                    declared: 0x26C,
+                    limit: 64
+                }
+            )))
+        ));
+
+        let outcome = parse(ParseProfile::recovering().options(options)).unwrap();
+        assert_eq!(
+            outcome.warnings,
+            [ParseWarning::OversizedEventSkipped {
+                track: 0,
+                declared: 0x26C,
+                limit: 64
+            }]
+        );
+
+        let note = |velocity| NoteMeta { key: 60, velocity };
+        let events: Vec<_> = outcome.midi.tracks[0]
+            .absolute_events()
+            .map(|(tick, event)| (tick.get(), event.clone()))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                (0, Event::MidiEvent(MidiEvent::NoteOn(0, note(100)))),
+                (0x60, Event::MidiEvent(MidiEvent::NoteOff(0, note(64)))),
+                (0x60, Event::MetaEvent(MetaEvent::EndOfTrack)),
+            ]
+        );
+    }
+
+    #[test]
+    fn oversized_sysex_resumes_after_its_end_of_exclusive() {
+        // Past the payload limit, the end of exclusive byte would otherwise read as a delta time
+        let mut track = vec![0x00, 0xF0, 0x43];
+        track.extend([0x01; 12]);
+        track.extend([
+            0xF7, // end of exclusive
+            0x00, 0x90, 0x3C, 0x64, // note on
+            0x60, 0x80, 0x3C, 0x40, // note off
+            0x00, 0xFF, 0x2F, 0x00, // end of track
+        ]);
+        let mut file = b"MThd\0\0\0\x06\0\0\0\x01\0\x60MTrk".to_vec();
+        file.extend((track.len() as u32).to_be_bytes());
+        file.extend(track);
+
+        let options = ParseOptions {
+            max_event_payload: 8,
+            ..ParseOptions::default()
+        };
+        let outcome = Midi::try_from_midi_stream_with(
+            file.into_iter(),
+            ParseProfile::recovering().options(options),
+        )
+        .unwrap();
+        assert_eq!(
+            outcome.warnings,
+            [ParseWarning::OversizedEventSkipped {
+                track: 0,
+                declared: 9,
+                limit: 8
+            }]
+        );
+
+        let note = |velocity| NoteMeta { key: 60, velocity };
+        let events: Vec<_> = outcome.midi.tracks[0]
+            .absolute_events()
+            .map(|(tick, event)| (tick.get(), event.clone()))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                (0, Event::MidiEvent(MidiEvent::NoteOn(0, note(100)))),
+                (0x60, Event::MidiEvent(MidiEvent::NoteOff(0, note(64)))),
+                (0x60, Event::MetaEvent(MetaEvent::EndOfTrack)),
+            ]
+        );
+    }
+
+    #[test]
+    fn resyncing_after_an_oversized_event_stays_linear() {
+        // An oversized text, 16,384 empty texts and a text cut off partway. Resyncing by parsing
+        // the rest of the track from every later byte would take minutes
+        let mut track = vec![0x00, 0xFF, 0x01, 0x81, 0x00];
+        track.extend([0x00, 0xFF, 0x01, 0x00].repeat(16_384));
+        track.extend([0x00, 0xFF, 0x01, 0x05, b'a']);
+        let mut file = b"MThd\0\0\0\x06\0\0\0\x01\0\x60MTrk".to_vec();
+        file.extend((track.len() as u32).to_be_bytes());
+        file.extend(track);
+
+        let options = ParseOptions {
+            max_event_payload: 64,
+            ..ParseOptions::default()
+        };
+        let started = std::time::Instant::now();
+        let outcome = Midi::try_from_midi_stream_with(
+            file.into_iter(),
+            ParseProfile::recovering().options(options),
+        )
+        .unwrap();
+        assert!(started.elapsed().as_secs() < 10);
+
+        assert!(outcome
+            .warnings
+            .contains(&ParseWarning::OversizedEventSkipped {
+                track: 0,
+                declared: 128,
+                limit: 64
+            }));
+        // The oversized text's payload holds 32 of the empty texts
+    }
+
+    #[test]
+    fn misplaced_conductor_events_warn_or_reject() {
+        let end = || (0, Event::MetaEvent(MetaEvent::EndOfTrack));
+        let file = |format| {
+            Midi {
+                header: HeaderChunk::try_from((format, 2, 480)).unwrap(),
+                tracks: vec![
+                    TrackChunk::from_absolute_events([end()]).unwrap(),
+                    TrackChunk::from_absolute_events([
+                        (0, Event::MetaEvent(MetaEvent::DEFAULT_TEMPO)),
+                        end(),
+                    ])
+                    .unwrap(),
+                ],
+            }
+            .to_midi_bytes()
+        };
+        let parse = |format, profile: ParseProfile| {
+            Midi::try_from_midi_stream_with(file(format).into_iter(), profile)
+        };
+
+        let warning = ParseWarning::MisplacedConductorEvent {
+            track: 1,
+            tick: Tick::ZERO,
+            kind: MetaKind::Tempo,
+        };
+        for profile in [ParseProfile::strict(), ParseProfile::permissive()] {
+            assert_eq!(parse(1, profile).unwrap().warnings, [warning]);
+            assert_eq!(parse(2, profile).unwrap().warnings, []);
+        }
+
+        let rejecting = ParseProfile::strict().reject_misplaced_conductor_events(true);
+        assert!(matches!(
+            parse(1, rejecting),
+            Err(LenientParseError::Structure(
+                MidiSanitizerError::MisplacedConductorEvent {
+                    track: 1,
+                    kind: MetaKind::Tempo,
+                    ..
+                }
+            ))
+        ));
+        assert!(parse(2, rejecting).is_ok());
+    }
+}
@@ -0,0 +1,390 @@
+//! Compact, reversible differences between two versions of a track, for editors that keep an
+//! undo history or sync edits between collaborators
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chunk::track::{
+        editor::{EditError, TrackEditor},
+        meta::MetaEvent,
+        Event, TrackChunk,
+    },
+    time::Tick,
+};
+
+/// A single change to a track's events at their absolute ticks, as [`TrackEditor::events`]
+/// lists them
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum PatchOp {
+    /// Inserts an event at `index`, shifting it and every event after it along
+    Insert {
+        /// Position of the new event once inserted
+        index: usize,
+        /// Absolute tick of the new event
+        tick: Tick,
+        /// The new event
+        event: Event,
+    },
+    /// Removes the event at `index`, which has to be exactly this one at this tick
+    Remove {
+        /// Position of the removed event
+        index: usize,
+        /// Absolute tick the removed event is expected at
+        tick: Tick,
+        /// The event expected at `index`
+        event: Event,
+    },
+}
+
+impl PatchOp {
+    /// The operation undoing this one, removing what it inserts or inserting what it removes
+    pub fn inverse(&self) -> Self {
+        match self.clone() {
+            Self::Insert { index, tick, event } => Self::Remove { index, tick, event },
+            Self::Remove { index, tick, event } => Self::Insert { index, tick, event },
+        }
+    }
+}
+
+/// The difference between two versions of a track, from [`TrackChunk::diff`]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TrackPatch {
+    /// The changes to apply in order, each index counting the events as they are once every
+    /// earlier operation has been applied
+    pub ops: Vec<PatchOp>,
+    /// Absolute tick the track is expected to end at before the patch
+    pub old_end: Tick,
+    /// Absolute tick the track ends at after the patch
+    pub new_end: Tick,
+}
+
+impl TrackPatch {
+    /// Returns true if the patch changes nothing
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty() && self.old_end == self.new_end
+    }
+
+    /// The patch undoing this one, taking the patched track back to where it started
+    pub fn inverse(&self) -> Self {
+        Self {
+            ops: self.ops.iter().rev().map(PatchOp::inverse).collect(),
+            old_end: self.new_end,
+            new_end: self.old_end,
+        }
+    }
+}
+
+/// Error from applying a [`TrackPatch`] to a track it doesn't fit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatchError {
+    /// The track doesn't end where the patch expects it to
+    EndMismatch {
+        /// Tick the patch expects the track to end at
+        expected: Tick,
+        /// Tick the track actually ends at
+        found: Tick,
+    },
+    /// The operation at this position in [`TrackPatch::ops`] doesn't fit the track: the event
+    /// it removes isn't there, or the event it inserts is out of range or out of tick order
+    Conflict(usize),
+    /// The patched track couldn't be rebuilt
+    Edit(EditError),
+}
+
+impl core::error::Error for PatchError {}
+impl core::fmt::Display for PatchError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::EndMismatch { expected, found } => write![
+                f,
+                "Patch expects the track to end at tick {expected}, but it ends at tick {found}"
+            ],
+            Self::Conflict(op) => write![f, "Patch operation {op} conflicts with the track"],
+            Self::Edit(err) => write![f, "{err}"],
+        }
+    }
+}
+
+impl From<EditError> for PatchError {
+    fn from(f: EditError) -> Self {
+        Self::Edit(f)
+    }
+}
+
+impl TrackChunk {
+    /// Computes the patch turning this track into `new`, as the events to remove and insert
+    /// among the events at their absolute ticks. Events only ever match others at the same
+    /// tick, so each tick is compared on its own and unchanged events cost nothing.
+    ///
+    /// The patch describes the events and where the track ends, like
+    /// [`TrackChunk::semantic_eq`], so how delta times are split up isn't part of it, and
+    /// neither are the [`TrackChunk::trailing_events`]
+    ///
+    /// ```rust
+    /// use miami::chunk::track::{editor::TrackEditor, meta::MetaEvent, Event};
+    ///
+    /// let midi = miami::open("test/test.mid").expect("Open test file");
+    /// let original = midi.tracks[0].clone();
+    ///
+    /// let mut editor = TrackEditor::new(original.clone());
+    /// editor.insert(0, Event::MetaEvent(MetaEvent::TrackName("Lead".into())));
+    /// let edited = editor.finish().expect("Rebuild the edited track");
+    ///
+    /// let patch = original.diff(&edited);
+    /// assert_eq!(patch.ops.len(), 1);
+    ///
+    /// let mut track = original.clone();
+    /// track.apply(&patch).expect("Patch the original track");
+    /// assert_eq!(track, edited);
+    /// ```
+    pub fn diff(&self, new: &TrackChunk) -> TrackPatch {
+        let old = TrackEditor::new(self.clone());
+        let new = TrackEditor::new(new.clone());
+        let (old_events, new_events) = (old.events(), new.events());
+
+        let mut ops = vec![];
+        let (mut i, mut j) = (0, 0);
+        while i < old_events.len() || j < new_events.len() {
+            let tick = match (old_events.get(i), new_events.get(j)) {
+                (Some((a, _)), Some((b, _))) => *a.min(b),
+                (Some((tick, _)), None) | (None, Some((tick, _))) => *tick,
+                (None, None) => unreachable!("Loop stops once both lists are used up"),
+            };
+            let old_len = old_events[i..]
+                .iter()
+                .take_while(|(at, _)| *at == tick)
+                .count();
+            let new_len = new_events[j..]
+                .iter()
+                .take_while(|(at, _)| *at == tick)
+                .count();
+
+            diff_tick(
+                &old_events[i..i + old_len],
+                &new_events[j..j + new_len],
+                j,
+                &mut ops,
+            );
+            i += old_len;
+            j += new_len;
+        }
+
+        TrackPatch {
+            ops,
+            old_end: old.end_of_track(),
+            new_end: new.end_of_track(),
+        }
+    }
+
+    /// Applies a patch from [`TrackChunk::diff`], so applying `old.diff(&new)` to `old` gives a
+    /// track [`TrackChunk::semantic_eq`] to `new`, and equal to it if `new` was built by a
+    /// [`TrackEditor`] or read from a file.
+    ///
+    /// Every operation is checked against the track first, so a patch made for a different
+    /// version of the track fails with [`PatchError::EndMismatch`] or [`PatchError::Conflict`]
+    /// rather than shuffling its events around. The track is only changed if the whole patch
+    /// applies
+    pub fn apply(&mut self, patch: &TrackPatch) -> Result<(), PatchError> {
+        let mut editor = TrackEditor::new(self.clone());
+        let found = editor.end_of_track();
+        if found != patch.old_end {
+            return Err(PatchError::EndMismatch {
+                expected: patch.old_end,
+                found,
+            });
+        }
+
+        let events = editor.events_mut();
+        for (idx, op) in patch.ops.iter().enumerate() {
+            match op {
+                PatchOp::Insert { index, tick, event } => {
+                    let fits = *index <= events.len()
+                        && !matches!(event, Event::MetaEvent(MetaEvent::EndOfTrack))
+                        && index
+                            .checked_sub(1)
+                            .is_none_or(|before| events[before].0 <= *tick)
+                        && events.get(*index).is_none_or(|(after, _)| *tick <= *after);
+                    if !fits {
+                        return Err(PatchError::Conflict(idx));
+                    }
+                    events.insert(*index, (*tick, event.clone()));
+                }
+                PatchOp::Remove { index, tick, event } => {
+                    if events.get(*index) != Some(&(*tick, event.clone())) {
+                        return Err(PatchError::Conflict(idx));
+                    }
+                    events.remove(*index);
+                }
+            }
+        }
+
+        editor.set_end_of_track(patch.new_end);
+        *self = editor.finish()?;
+
+        Ok(())
+    }
+}
+
+/// Adds the operations turning the `old` events at one tick into the `new` ones to `ops`,
+/// removing as few as possible by keeping their longest common subsequence. `at` is the index
+/// of the first of these events once everything before them is patched
+fn diff_tick(old: &[(Tick, Event)], new: &[(Tick, Event)], at: usize, ops: &mut Vec<PatchOp>) {
+    // kept[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut kept = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            kept[i][j] = if old[i] == new[j] {
+                kept[i + 1][j + 1] + 1
+            } else {
+                kept[i + 1][j].max(kept[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && kept[i + 1][j] >= kept[i][j + 1]) {
+            let (tick, event) = old[i].clone();
+            ops.push(PatchOp::Remove {
+                index: at + j,
+                tick,
+                event,
+            });
+            i += 1;
+        } else {
+            let (tick, event) = new[j].clone();
+            ops.push(PatchOp::Insert {
+                index: at + j,
+                tick,
+                event,
+            });
+            j += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PatchError, PatchOp};
+    use crate::{
+        chunk::track::{
+            editor::TrackEditor,
+            event::{MidiEvent, NoteMeta},
+            meta::MetaEvent,
+            Event, TrackChunk,
+        },
+        time::Tick,
+    };
+
+    fn note(key: u8) -> Event {
+        Event::MidiEvent(MidiEvent::NoteOn(0, NoteMeta { key, velocity: 100 }))
+    }
+
+    fn track(events: &[(u64, u8)], end: u64) -> TrackChunk {
+        TrackChunk::from_absolute_events(
+            events
+                .iter()
+                .map(|&(tick, key)| (tick, note(key)))
+                .chain([(end, Event::MetaEvent(MetaEvent::EndOfTrack))]),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn patches_turn_the_original_into_the_new_track_and_back() {
+        let original = crate::open("test/run.mid").unwrap().tracks[1].clone();
+
+        let mut editor = TrackEditor::new(original.clone());
+        editor.events_mut().remove(3);
+        editor.events_mut().swap(5, 6);
+        let (tick, _) = editor.events()[10].clone();
+        editor.insert(tick, note(60));
+        editor.set_end_of_track(Tick::new(editor.end_of_track().get() + 480));
+        let edited = editor.finish().unwrap();
+
+        let patch = original.diff(&edited);
+        assert!(!patch.is_empty());
+        assert!(patch.ops.len() < original.mtrk_events.len());
+
+        let mut patched = original.clone();
+        patched.apply(&patch).unwrap();
+        assert_eq!(patched, edited);
+
+        // The inverse patch undoes it
+        patched.apply(&patch.inverse()).unwrap();
+        assert_eq!(patched, original);
+
+        assert!(original.diff(&original).is_empty());
+    }
+
+    #[test]
+    fn simultaneous_events_keep_their_order() {
+        let old = track(&[(0, 60), (0, 64), (0, 67), (480, 72)], 960);
+        let new = track(&[(0, 67), (0, 60), (0, 64), (480, 72), (480, 74)], 960);
+
+        let patch = old.diff(&new);
+        assert_eq!(
+            patch.ops,
+            [
+                PatchOp::Insert {
+                    index: 0,
+                    tick: Tick::ZERO,
+                    event: note(67)
+                },
+                PatchOp::Remove {
+                    index: 3,
+                    tick: Tick::ZERO,
+                    event: note(67)
+                },
+                PatchOp::Insert {
+                    index: 4,
+                    tick: Tick::new(480),
+                    event: note(74)
+                },
+            ]
+        );
+
+        let mut patched = old.clone();
+        patched.apply(&patch).unwrap();
+        assert_eq!(patched, new);
+    }
+
+    #[test]
+    fn patches_for_another_version_conflict() {
+        let old = track(&[(0, 60), (480, 62), (960, 64)], 1920);
+        let new = track(&[(0, 60), (480, 65), (960, 64)], 1920);
+        let patch = old.diff(&new);
+
+        // Someone else already changed the note being replaced
+        let mut moved = track(&[(0, 60), (240, 62), (960, 64)], 1920);
+        let before = moved.clone();
+        assert_eq!(moved.apply(&patch), Err(PatchError::Conflict(0)));
+        assert_eq!(moved, before);
+
+        // Or lengthened the track
+        let mut longer = track(&[(0, 60), (480, 62), (960, 64)], 3840);
+        assert_eq!(
+            longer.apply(&patch),
+            Err(PatchError::EndMismatch {
+                expected: Tick::new(1920),
+                found: Tick::new(3840)
+            })
+        );
+
+        // An insert landing out of tick order is refused rather than retimed
+        let insert = track(&[(0, 60)], 1920).diff(&track(&[(0, 60), (480, 62)], 1920));
+        let mut crowded = track(&[(0, 60), (240, 61)], 1920);
+        assert_eq!(crowded.apply(&insert), Err(PatchError::Conflict(0)));
+
+        // While a change elsewhere in the track doesn't get in the way
+        let mut extended = track(&[(0, 60), (960, 64)], 1920);
+        extended.apply(&insert).unwrap();
+        assert_eq!(extended, track(&[(0, 60), (480, 62), (960, 64)], 1920));
+    }
+}
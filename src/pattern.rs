@@ -0,0 +1,301 @@
+//! The independent patterns of a format 2 file, identified by their Sequence Number meta events
+
+use crate::{
+    chunk::{
+        header::{Division, Format, HeaderChunk},
+        track::{meta::MetaEvent, Event, MTrkEvent, TrackChunk},
+    },
+    time::Tick,
+    Midi,
+};
+
+/// One track of a file viewed as a pattern, see [`Midi::patterns`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    /// The number from the track's Sequence Number meta event, if it has one
+    pub sequence_number: Option<u16>,
+    /// Index of the track in [`Midi::tracks`]
+    pub track_index: usize,
+    /// The track's name, see [`TrackChunk::name`]
+    pub name: Option<String>,
+}
+
+impl Pattern {
+    /// The number identifying the pattern. Without a Sequence Number meta event this is the
+    /// track's position in the file, as the spec defaults to
+    pub fn number(&self) -> u16 {
+        self.sequence_number
+            .unwrap_or(self.track_index.min(u16::MAX as usize) as u16)
+    }
+}
+
+impl TrackChunk {
+    /// The number from the track's Sequence Number meta event. The spec only allows it before
+    /// any time has passed, so one later in the track is ignored
+    pub fn sequence_number(&self) -> Option<u16> {
+        self.absolute_events()
+            .take_while(|(tick, _)| *tick == Tick::ZERO)
+            .find_map(|(_, event)| match event {
+                Event::MetaEvent(MetaEvent::SequenceNumber(number)) => Some(*number),
+                _ => None,
+            })
+    }
+}
+
+impl Midi {
+    /// Creates an empty format 2 file, to be filled with independent patterns by
+    /// [`Midi::add_pattern`]
+    pub fn new_format2(division: Division) -> Self {
+        Self {
+            header: HeaderChunk::new(Format::Two, 0, division),
+            tracks: vec![],
+        }
+    }
+
+    /// Appends `track` as a new pattern and returns its number, one past the highest
+    /// [`Pattern::number`] already in the file or 0 for the first. The track starts with a
+    /// Sequence Number and a Track Name meta event, replacing any it already had before its first
+    /// non-zero delta time, and the header's track count is updated to match
+    pub fn add_pattern(&mut self, name: &str, mut track: TrackChunk) -> u16 {
+        let number = self
+            .patterns()
+            .iter()
+            .map(|pattern| pattern.number().saturating_add(1))
+            .max()
+            .unwrap_or(0);
+
+        let start = track
+            .mtrk_events
+            .iter()
+            .position(|mtrk_event| mtrk_event.delta_time().get() != 0)
+            .unwrap_or(track.mtrk_events.len());
+        let mut events: Vec<MTrkEvent> = [
+            Event::MetaEvent(MetaEvent::SequenceNumber(number)),
+            Event::MetaEvent(MetaEvent::TrackName(name.into())),
+        ]
+        .into_iter()
+        .map(|event| MTrkEvent::new(0, event))
+        .collect();
+        events.extend(track.mtrk_events.drain(..start).filter(|mtrk_event| {
+            !matches!(
+                mtrk_event.event(),
+                Event::MetaEvent(MetaEvent::SequenceNumber(_) | MetaEvent::TrackName(_))
+            )
+        }));
+        events.append(&mut track.mtrk_events);
+
+        track.mtrk_events = events;
+        track.forget_source();
+        self.push_track(track);
+
+        number
+    }
+
+    /// Every track as a pattern, in file order. Patterns are what format 2 files are made of, but
+    /// this works on any format
+    pub fn patterns(&self) -> Vec<Pattern> {
+        self.tracks
+            .iter()
+            .enumerate()
+            .map(|(track_index, track)| Pattern {
+                sequence_number: track.sequence_number(),
+                track_index,
+                name: track.name().map(String::from),
+            })
+            .collect()
+    }
+
+    /// The track of the pattern numbered `number`, see [`Pattern::number`]. A pattern with an
+    /// explicit Sequence Number is preferred over one that's numbered by its position, and the
+    /// first of several with the same number is returned
+    pub fn pattern_by_number(&self, number: u16) -> Option<&TrackChunk> {
+        let patterns = self.patterns();
+        patterns
+            .iter()
+            .find(|pattern| pattern.sequence_number == Some(number))
+            .or_else(|| patterns.iter().find(|pattern| pattern.number() == number))
+            .map(|pattern| &self.tracks[pattern.track_index])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pattern;
+    use crate::{
+        chunk::{
+            header::{Division, Format, HeaderChunk},
+            track::{
+                event::{MidiEvent, NoteMeta},
+                meta::MetaEvent,
+                Event, TrackChunk,
+            },
+        },
+        validate::ValidationIssue,
+        writer::MidiWriteable,
+        Midi, RawMidi,
+    };
+
+    fn pattern(number: Option<u16>, name: &str, key: u8) -> TrackChunk {
+        let mut events = vec![];
+        if let Some(number) = number {
+            events.push((0, Event::MetaEvent(MetaEvent::SequenceNumber(number))));
+        }
+        events.extend([
+            (0, Event::MetaEvent(MetaEvent::TrackName(name.into()))),
+            (
+                0,
+                Event::MidiEvent(MidiEvent::NoteOn(0, NoteMeta { key, velocity: 90 })),
+            ),
+            (96, Event::MetaEvent(MetaEvent::EndOfTrack)),
+        ]);
+
+        TrackChunk::from_absolute_events(events).unwrap()
+    }
+
+    fn format_2(tracks: Vec<TrackChunk>) -> Midi {
+        Midi {
+            header: HeaderChunk::new(Format::Two, tracks.len() as u16, Division::TPQN_96),
+            tracks,
+        }
+    }
+
+    #[test]
+    fn patterns_are_numbered_explicitly_or_by_position() {
+        let midi = format_2(vec![
+            pattern(Some(10), "Verse", 60),
+            pattern(None, "Fill", 62),
+            pattern(Some(0), "Chorus", 64),
+        ]);
+
+        assert_eq!(
+            midi.patterns(),
+            [
+                Pattern {
+                    sequence_number: Some(10),
+                    track_index: 0,
+                    name: Some("Verse".into()),
+                },
+                Pattern {
+                    sequence_number: None,
+                    track_index: 1,
+                    name: Some("Fill".into()),
+                },
+                Pattern {
+                    sequence_number: Some(0),
+                    track_index: 2,
+                    name: Some("Chorus".into()),
+                },
+            ]
+        );
+        assert_eq!(
+            midi.patterns()
+                .iter()
+                .map(Pattern::number)
+                .collect::<Vec<_>>(),
+            [10, 1, 0]
+        );
+
+        let key = |number| {
+            midi.pattern_by_number(number)
+                .map(|track| track.notes_on().next().unwrap().2.key)
+        };
+        assert_eq!(key(10), Some(60));
+        assert_eq!(key(1), Some(62));
+        assert_eq!(key(0), Some(64));
+        assert_eq!(key(2), None);
+        assert!(midi.validate().is_empty());
+    }
+
+    #[test]
+    fn late_sequence_numbers_are_ignored() {
+        let track = TrackChunk::from_absolute_events([
+            (0, Event::MetaEvent(MetaEvent::TrackName("Late".into()))),
+            (48, Event::MetaEvent(MetaEvent::SequenceNumber(7))),
+            (96, Event::MetaEvent(MetaEvent::EndOfTrack)),
+        ])
+        .unwrap();
+
+        assert_eq!(track.sequence_number(), None);
+    }
+
+    #[test]
+    fn duplicate_sequence_numbers_are_reported() {
+        let midi = format_2(vec![
+            pattern(Some(3), "A", 60),
+            pattern(Some(4), "B", 62),
+            pattern(Some(3), "C", 64),
+        ]);
+
+        assert_eq!(
+            midi.validate(),
+            [ValidationIssue::DuplicateSequenceNumber {
+                number: 3,
+                first: 0,
+                track: 2
+            }]
+        );
+        assert_eq!(
+            midi.pattern_by_number(3).and_then(|track| track.name()),
+            Some("A".into())
+        );
+    }
+
+    #[test]
+    fn added_patterns_are_numbered_and_named() {
+        let mut midi = Midi::new_format2(Division::TPQN_96);
+        assert_eq!(midi.header.ntrks(), 0);
+
+        assert_eq!(midi.add_pattern("Kick", pattern(None, "", 36)), 0);
+        // Any name or number the track already had is replaced
+        assert_eq!(midi.add_pattern("Snare", pattern(Some(9), "Old", 38)), 1);
+
+        assert_eq!(midi.header.format(), Format::Two);
+        assert_eq!(midi.header.ntrks(), 2);
+
+        let events: Vec<_> = midi.tracks[1]
+            .absolute_events()
+            .map(|(tick, event)| (tick.get(), event.clone()))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                (0, Event::MetaEvent(MetaEvent::SequenceNumber(1))),
+                (0, Event::MetaEvent(MetaEvent::TrackName("Snare".into()))),
+                (
+                    0,
+                    Event::MidiEvent(MidiEvent::NoteOn(
+                        0,
+                        NoteMeta {
+                            key: 38,
+                            velocity: 90
+                        }
+                    ))
+                ),
+                (96, Event::MetaEvent(MetaEvent::EndOfTrack)),
+            ]
+        );
+    }
+
+    #[test]
+    fn many_patterns_round_trip_through_a_file() {
+        let mut midi = Midi::new_format2(Division::TPQN_96);
+        for idx in 0..300u16 {
+            let number = midi.add_pattern(&format!("Groove {idx}"), pattern(None, "", 36));
+            assert_eq!(number, idx);
+        }
+
+        let parsed = RawMidi::try_from_midi_stream(midi.clone().to_midi_bytes().into_iter())
+            .unwrap()
+            .check_into_midi()
+            .unwrap();
+
+        assert_eq!(parsed.header.format(), Format::Two);
+        assert_eq!(parsed.header.ntrks(), 300);
+        assert_eq!(parsed.patterns(), midi.patterns());
+        assert_eq!(
+            parsed.pattern_by_number(299).and_then(|track| track.name()),
+            Some("Groove 299".into())
+        );
+        assert!(parsed.validate().is_empty());
+    }
+}
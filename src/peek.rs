@@ -0,0 +1,238 @@
+//! Reading a file's header and chunk layout without parsing its events, for indexing many files
+//! quickly. Unlike the [`crate::reader::MidiStream`] iterators, these read through [`Read`] and
+//! [`Seek`] so every track's payload is skipped over instead of read
+
+use std::{
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::{
+    chunk::{
+        chunk_types::{HEADER_CHUNK, TRACK_DATA_CHUNK},
+        header::HeaderChunk,
+        track::{
+            meta::{MetaEvent, TextEncoding},
+            Event, MTrkEvent,
+        },
+        ChunkParseError,
+    },
+    Chunk, MidiError, MidiSanitizerError,
+};
+
+/// What [`peek_info`] learns about a file from its chunk headers
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileInfo {
+    /// The file's header, including any bytes past the standard 6
+    pub header: HeaderChunk,
+    /// Payload length in bytes of each track chunk in file order, not counting its 8 byte chunk
+    /// header. Its length is the real number of tracks, whatever the header declares
+    pub track_lengths: Vec<usize>,
+    /// Name of the first track, if it has a Track Name at tick 0
+    pub first_track_name: Option<String>,
+    /// Size of the whole file in bytes
+    pub file_size: u64,
+}
+
+/// Reads `len` bytes, failing with [`ChunkParseError::Truncated`] if fewer are left
+fn read_bytes<R: Read>(reader: R, len: usize) -> Result<Vec<u8>, MidiError> {
+    let mut bytes = Vec::with_capacity(len);
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() < len {
+        return Err(ChunkParseError::Truncated {
+            expected: len,
+            available: bytes.len(),
+        }
+        .into());
+    }
+
+    Ok(bytes)
+}
+
+/// Reads an 8 byte chunk header, or `None` if the reader is already at its end
+fn read_chunk<R: Read>(reader: R) -> Result<Option<Chunk>, MidiError> {
+    let bytes = match read_bytes(reader, 8) {
+        Err(MidiError::Parse(ChunkParseError::Truncated { available: 0, .. })) => return Ok(None),
+        bytes => bytes?,
+    };
+    // UNWRAP Safety: read_bytes returns exactly 8 bytes
+    let chunk = Chunk::try_from_bytes(bytes.try_into().unwrap())
+        .map_err(|_| ChunkParseError::UnknownType)?;
+
+    Ok(Some(chunk))
+}
+
+/// Reads the leading `MThd` chunk header and the standard 6 bytes of its payload, returning the
+/// chunk along with the header parsed from them
+fn read_header<R: Read>(mut reader: R) -> Result<(Chunk, HeaderChunk), MidiError> {
+    let chunk = read_chunk(&mut reader)?.ok_or(MidiSanitizerError::NoChunks)?;
+    if chunk.chunk_type != HEADER_CHUNK {
+        return Err(MidiSanitizerError::NoStartHeader.into());
+    }
+    if chunk.len() < 6 {
+        return Err(ChunkParseError::HeaderTooShort(chunk.len()).into());
+    }
+
+    let bytes = read_bytes(reader, 6)?;
+    // UNWRAP Safety: read_bytes returns exactly 6 bytes
+    let header = HeaderChunk::parse(&bytes.try_into().unwrap()).map_err(ChunkParseError::from)?;
+
+    Ok((chunk, header))
+}
+
+/// Reads only the first 14 bytes of the file at `path`, its `MThd` chunk header and the standard
+/// 6 byte header. See [`peek_header_from`]
+///
+/// ```rust
+/// let header = miami::peek_header("test/run.mid").expect("Read the header of `run.mid`");
+/// assert_eq!(header.ntrks(), 10);
+/// ```
+pub fn peek_header(path: impl AsRef<Path>) -> Result<HeaderChunk, MidiError> {
+    peek_header_from(File::open(path)?)
+}
+
+/// Reads only the first 14 bytes of a file, its `MThd` chunk header and the standard 6 byte
+/// header, from anything readable including a byte slice. Bytes past the standard 6 in a longer
+/// header aren't read, so [`HeaderChunk::extra`] is always empty. A file that doesn't start with
+/// a header fails with [`MidiSanitizerError::NoStartHeader`]
+pub fn peek_header_from<R: Read>(reader: R) -> Result<HeaderChunk, MidiError> {
+    read_header(reader).map(|(_, header)| header)
+}
+
+/// Reads the header and every chunk header of the file at `path`, skipping track payloads. See
+/// [`peek_info_from`]
+pub fn peek_info(path: impl AsRef<Path>) -> Result<FileInfo, MidiError> {
+    peek_info_from(File::open(path)?)
+}
+
+/// Reads the header and every chunk header of a file, seeking past each track's payload rather
+/// than reading it. Only the events at tick 0 of the first track are parsed, to find its name.
+/// Chunks of other types are skipped, and a chunk running past the end of the file fails with
+/// [`ChunkParseError::Truncated`]
+pub fn peek_info_from<R: Read + Seek>(mut reader: R) -> Result<FileInfo, MidiError> {
+    let start = reader.stream_position()?;
+    let file_size = reader.seek(SeekFrom::End(0))? - start;
+    reader.seek(SeekFrom::Start(start))?;
+
+    let (chunk, mut header) = read_header(&mut reader)?;
+    header.extra = read_bytes(&mut reader, chunk.len() - 6)?;
+
+    let mut track_lengths = vec![];
+    let mut first_track_name = None;
+    while let Some(chunk) = read_chunk(&mut reader)? {
+        let payload = reader.stream_position()?;
+        let available = start + file_size - payload;
+        if chunk.len() as u64 > available {
+            return Err(ChunkParseError::Truncated {
+                expected: chunk.len(),
+                available: available as usize,
+            }
+            .into());
+        }
+
+        if chunk.chunk_type == TRACK_DATA_CHUNK {
+            if track_lengths.is_empty() {
+                first_track_name = track_name(&mut reader, chunk.len());
+            }
+            track_lengths.push(chunk.len());
+        }
+        reader.seek(SeekFrom::Start(payload + chunk.len() as u64))?;
+    }
+
+    Ok(FileInfo {
+        header,
+        track_lengths,
+        first_track_name,
+        file_size,
+    })
+}
+
+/// Finds a Track Name among the events at tick 0 of a track payload of `len` bytes, stopping at
+/// the first later event or one that doesn't parse
+fn track_name<R: Read>(reader: R, len: usize) -> Option<String> {
+    let mut bytes = BufReader::new(reader.take(len as u64))
+        .bytes()
+        .map_while(Result::ok);
+
+    while let Ok(mtrk_event) = MTrkEvent::parse(&mut bytes, Some(TextEncoding::Raw), u32::MAX) {
+        if mtrk_event.delta_time().get() > 0 {
+            break;
+        }
+        if let Event::MetaEvent(MetaEvent::TrackName(name)) = mtrk_event.event() {
+            return Some(name.text().into_owned());
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{peek_header, peek_header_from, peek_info, peek_info_from};
+    use crate::{
+        chunk::{header::HeaderChunk, ChunkParseError},
+        MidiError, MidiSanitizerError,
+    };
+
+    #[test]
+    fn peeked_header_and_layout_match_a_full_parse() {
+        for path in ["test/run.mid", "test/test.mid", "test/test4tracks.mid"] {
+            let midi = crate::open(path).unwrap();
+            let bytes = std::fs::read(path).unwrap();
+
+            let header = peek_header(path).unwrap();
+            assert_eq!(header, midi.header, "{path}");
+            assert_eq!(peek_header_from(&bytes[..]).unwrap(), header);
+
+            let info = peek_info(path).unwrap();
+            assert_eq!(info.header, midi.header, "{path}");
+            assert_eq!(info.file_size, bytes.len() as u64);
+            assert_eq!(info.track_lengths.len(), midi.tracks.len());
+            assert_eq!(
+                info.first_track_name,
+                midi.tracks[0].name().map(|name| name.into_owned()),
+                "{path}"
+            );
+            assert_eq!(
+                14 + info.track_lengths.iter().map(|len| 8 + len).sum::<usize>(),
+                bytes.len()
+            );
+        }
+    }
+
+    #[test]
+    fn damaged_files_fail_without_reading_further() {
+        let bytes = std::fs::read("test/test.mid").unwrap();
+
+        assert!(matches!(
+            peek_header_from(&bytes[..10]),
+            Err(MidiError::Parse(ChunkParseError::Truncated {
+                expected: 6,
+                available: 2
+            }))
+        ));
+        assert!(matches!(
+            peek_header_from(&bytes[14..]),
+            Err(MidiError::Structure(MidiSanitizerError::NoStartHeader))
+        ));
+        assert!(matches!(
+            peek_header_from(&[][..]),
+            Err(MidiError::Structure(MidiSanitizerError::NoChunks))
+        ));
+
+        // Only the header is read, so a damaged track doesn't matter
+        let mut damaged = bytes.clone();
+        damaged.truncate(30);
+        assert_eq!(
+            peek_header_from(&damaged[..]).unwrap(),
+            HeaderChunk::parse(&bytes[8..14].try_into().unwrap()).unwrap()
+        );
+        assert!(matches!(
+            peek_info_from(Cursor::new(damaged)),
+            Err(MidiError::Parse(ChunkParseError::Truncated { .. }))
+        ));
+    }
+}
@@ -0,0 +1,692 @@
+//! A stable, versioned serde representation of [`Midi`] for persisting parsed files.
+//!
+//! The serde derives on the parsing types mirror their Rust shape, so any change to those enums,
+//! such as a new meta event variant, changes their serialized form as well. [`SerializableMidi`]
+//! is the compatibility-guaranteed alternative: every enum is explicitly tagged by a `kind` field
+//! with names that will never change, and tags a reader doesn't know deserialize into an
+//! `Unknown` fallback instead of failing. Values written by one version of `miami` will keep
+//! deserializing in every later version, and values written by a later version still deserialize
+//! in earlier ones, failing only at the conversion back into a [`Midi`] if they use something
+//! the reader can't represent.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chunk::{
+        header::{Format, HeaderChunk, InvalidFormat},
+        track::{
+            event::{ControlChange, MidiEvent, NoteMeta},
+            meta::{KeySignature, MetaEvent, SmpteOffset, TimeSignature},
+            sysex::{ManufactureId, SysexEvent},
+            Event, MTrkEvent, TrackChunk,
+        },
+    },
+    writer::MidiWriteable,
+    Midi,
+};
+
+/// The version of the persisted representation written by this release
+pub const PERSIST_VERSION: u32 = 1;
+
+/// Error converting a persisted representation back into a [`Midi`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PersistError {
+    /// The value was written by a newer, incompatible version of the representation
+    UnsupportedVersion(u32),
+    /// The header's format isn't 0, 1 or 2
+    InvalidHeader(InvalidFormat),
+    /// An event has a kind this version doesn't know about
+    UnknownEvent {
+        /// Index of the track containing the event
+        track: usize,
+        /// Index of the event within the track
+        index: usize,
+    },
+    /// A sysex manufacturer ID wasn't 1 or 3 bytes long
+    InvalidManufactureId,
+}
+
+impl core::error::Error for PersistError {}
+impl core::fmt::Display for PersistError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::UnsupportedVersion(version) => {
+                write![f, "Unsupported persisted MIDI version {version}"]
+            }
+            Self::InvalidHeader(_) => write![f, "Invalid header format"],
+            Self::UnknownEvent { track, index } => {
+                write![f, "Unknown event kind at event {index} of track {track}"]
+            }
+            Self::InvalidManufactureId => write![f, "Manufacturer ID must be 1 or 3 bytes"],
+        }
+    }
+}
+
+impl From<InvalidFormat> for PersistError {
+    fn from(f: InvalidFormat) -> Self {
+        Self::InvalidHeader(f)
+    }
+}
+
+/// A versioned, compatibility-guaranteed serde representation of a [`Midi`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SerializableMidi {
+    /// Version of the representation, see [`PERSIST_VERSION`]
+    pub version: u32,
+    /// The header chunk
+    pub header: SerializableHeader,
+    /// Every track chunk
+    pub tracks: Vec<SerializableTrack>,
+}
+
+/// A header chunk as its three raw 16 bit words
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SerializableHeader {
+    /// The MIDI format, 0, 1 or 2
+    pub format: u16,
+    /// Number of tracks
+    pub ntrks: u16,
+    /// The raw division word
+    pub division: u16,
+}
+
+/// A track chunk's events
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SerializableTrack {
+    /// Every event in the track
+    pub events: Vec<SerializableMTrkEvent>,
+}
+
+/// An event with the delta time before it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SerializableMTrkEvent {
+    /// Ticks since the previous event
+    pub delta_time: u32,
+    /// The event itself
+    pub event: SerializableEvent,
+}
+
+/// Every kind of event, tagged by a stable `kind` name
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SerializableEvent {
+    /// Note Off
+    NoteOff {
+        /// Channel
+        channel: u8,
+        /// Key
+        key: u8,
+        /// Release velocity
+        velocity: u8,
+    },
+    /// Note On
+    NoteOn {
+        /// Channel
+        channel: u8,
+        /// Key
+        key: u8,
+        /// Velocity
+        velocity: u8,
+    },
+    /// Polyphonic Key Pressure
+    PolyphonicKeyPressure {
+        /// Channel
+        channel: u8,
+        /// Key
+        key: u8,
+        /// Pressure
+        pressure: u8,
+    },
+    /// Control Change
+    ControlChange {
+        /// Channel
+        channel: u8,
+        /// Controller number
+        controller: u8,
+        /// New value
+        value: u8,
+    },
+    /// Program Change
+    ProgramChange {
+        /// Channel
+        channel: u8,
+        /// Program number
+        program: u8,
+    },
+    /// Channel Pressure
+    ChannelPressure {
+        /// Channel
+        channel: u8,
+        /// Pressure
+        pressure: u8,
+    },
+    /// Pitch Wheel Change
+    PitchWheelChange {
+        /// Channel
+        channel: u8,
+        /// Pitch wheel value
+        value: u16,
+    },
+    /// System exclusive message
+    Sysex {
+        /// The 1 or 3 byte manufacturer ID
+        manufacturer: Vec<u8>,
+        /// Message payload
+        payload: Vec<u8>,
+    },
+    /// Sequence Number meta event
+    SequenceNumber {
+        /// The sequence number
+        number: u16,
+    },
+    /// Text meta event
+    Text {
+        /// The text
+        text: String,
+    },
+    /// Copyright meta event
+    Copyright {
+        /// The text
+        text: String,
+    },
+    /// Track Name meta event
+    TrackName {
+        /// The text
+        text: String,
+    },
+    /// Instrument Name meta event
+    InstrumentName {
+        /// The text
+        text: String,
+    },
+    /// Lyric meta event
+    Lyric {
+        /// The text
+        text: String,
+    },
+    /// Marker meta event
+    Marker {
+        /// The text
+        text: String,
+    },
+    /// Cue Point meta event
+    CuePoint {
+        /// Raw cue point data
+        data: Vec<u8>,
+    },
+    /// MIDI Channel Prefix meta event
+    MidiChannelPrefix {
+        /// The channel
+        channel: u8,
+    },
+    /// End of Track meta event
+    EndOfTrack,
+    /// Tempo meta event
+    Tempo {
+        /// Microseconds per quarter note
+        micros_per_quarter: u32,
+    },
+    /// SMPTE Offset meta event
+    SmpteOffset {
+        /// Hours
+        hours: u8,
+        /// Minutes
+        minutes: u8,
+        /// Seconds
+        seconds: u8,
+        /// Frames
+        frames: u8,
+        /// Subframes
+        subframes: u8,
+    },
+    /// Time Signature meta event
+    TimeSignature {
+        /// Numerator
+        numerator: u8,
+        /// Denominator as a note value, such as 4 for quarter notes
+        denominator: u32,
+        /// MIDI clocks per metronome click
+        clocks_per_click: u8,
+        /// Notated 32nd notes per quarter note
+        thirty_seconds_per_quarter: u8,
+    },
+    /// Key Signature meta event
+    KeySignature {
+        /// Sharps when positive, flats when negative
+        sharps_flats: i8,
+        /// True for a minor key
+        minor: bool,
+    },
+    /// Sequencer Specific meta event
+    SequencerSpecific {
+        /// Raw data
+        data: Vec<u8>,
+    },
+    /// A meta event `miami` doesn't parse
+    UnknownMeta {
+        /// Meta event tag
+        tag: u8,
+        /// Raw data
+        data: Vec<u8>,
+    },
+    /// Any event kind written by a newer version that this version doesn't know
+    #[serde(other)]
+    Unknown,
+}
+
+impl From<Event> for SerializableEvent {
+    fn from(value: Event) -> Self {
+        match value {
+            Event::MidiEvent(event) => match event {
+                MidiEvent::NoteOff(channel, NoteMeta { key, velocity }) => Self::NoteOff {
+                    channel,
+                    key,
+                    velocity,
+                },
+                MidiEvent::NoteOn(channel, NoteMeta { key, velocity }) => Self::NoteOn {
+                    channel,
+                    key,
+                    velocity,
+                },
+                MidiEvent::PolyphonicKeyPressure(channel, NoteMeta { key, velocity }) => {
+                    Self::PolyphonicKeyPressure {
+                        channel,
+                        key,
+                        pressure: velocity,
+                    }
+                }
+                MidiEvent::ControlChange(
+                    channel,
+                    ControlChange {
+                        controller_number,
+                        new_value,
+                    },
+                ) => Self::ControlChange {
+                    channel,
+                    controller: controller_number,
+                    value: new_value,
+                },
+                MidiEvent::ProgramChange { channel, program } => {
+                    Self::ProgramChange { channel, program }
+                }
+                MidiEvent::ChannelPressure { channel, pressure } => {
+                    Self::ChannelPressure { channel, pressure }
+                }
+                MidiEvent::PitchWheelChange(channel, value) => {
+                    Self::PitchWheelChange { channel, value }
+                }
+            },
+            Event::SysexEvent(event) => Self::Sysex {
+                manufacturer: event.manufacture_id.to_midi_bytes(),
+                payload: event.payload.into(),
+            },
+            Event::MetaEvent(event) => match event {
+                MetaEvent::SequenceNumber(number) => Self::SequenceNumber { number },
+                MetaEvent::Text(text) => Self::Text {
+                    text: text.text().into_owned(),
+                },
+                MetaEvent::Copyright(text) => Self::Copyright {
+                    text: text.text().into_owned(),
+                },
+                MetaEvent::TrackName(text) => Self::TrackName {
+                    text: text.text().into_owned(),
+                },
+                MetaEvent::InstrumentName(text) => Self::InstrumentName {
+                    text: text.text().into_owned(),
+                },
+                MetaEvent::Lyric(text) => Self::Lyric {
+                    text: text.text().into_owned(),
+                },
+                MetaEvent::Marker(text) => Self::Marker {
+                    text: text.text().into_owned(),
+                },
+                MetaEvent::CuePoint(data) => Self::CuePoint { data: data.into() },
+                MetaEvent::MidiChannelPrefix(channel) => Self::MidiChannelPrefix { channel },
+                MetaEvent::EndOfTrack => Self::EndOfTrack,
+                MetaEvent::Tempo(micros_per_quarter) => Self::Tempo { micros_per_quarter },
+                MetaEvent::SmpteOffset(SmpteOffset {
+                    hours,
+                    minutes,
+                    seconds,
+                    frames,
+                    subframes,
+                }) => Self::SmpteOffset {
+                    hours,
+                    minutes,
+                    seconds,
+                    frames,
+                    subframes,
+                },
+                MetaEvent::TimeSignature(TimeSignature {
+                    numerator,
+                    denominator,
+                    clocks_per_tick,
+                    thirty_second_notes_per_quarter,
+                }) => Self::TimeSignature {
+                    numerator,
+                    denominator,
+                    clocks_per_click: clocks_per_tick,
+                    thirty_seconds_per_quarter: thirty_second_notes_per_quarter,
+                },
+                MetaEvent::KeySignature(KeySignature {
+                    sharps_flats,
+                    major_minor,
+                }) => Self::KeySignature {
+                    sharps_flats,
+                    minor: major_minor,
+                },
+                MetaEvent::SequencerSpecific(data) => Self::SequencerSpecific { data: data.into() },
+                MetaEvent::UnknownRaw(tag, data) => Self::UnknownMeta {
+                    tag,
+                    data: data.into(),
+                },
+            },
+        }
+    }
+}
+
+impl SerializableEvent {
+    /// Converts back into an [`Event`], or `None` for [`SerializableEvent::Unknown`]
+    fn into_event(self) -> Result<Option<Event>, PersistError> {
+        let midi = |event| Ok(Some(Event::MidiEvent(event)));
+        let meta = |event| Ok(Some(Event::MetaEvent(event)));
+
+        match self {
+            Self::NoteOff {
+                channel,
+                key,
+                velocity,
+            } => midi(MidiEvent::NoteOff(channel, NoteMeta { key, velocity })),
+            Self::NoteOn {
+                channel,
+                key,
+                velocity,
+            } => midi(MidiEvent::NoteOn(channel, NoteMeta { key, velocity })),
+            Self::PolyphonicKeyPressure {
+                channel,
+                key,
+                pressure,
+            } => midi(MidiEvent::PolyphonicKeyPressure(
+                channel,
+                NoteMeta {
+                    key,
+                    velocity: pressure,
+                },
+            )),
+            Self::ControlChange {
+                channel,
+                controller,
+                value,
+            } => midi(MidiEvent::ControlChange(
+                channel,
+                ControlChange {
+                    controller_number: controller,
+                    new_value: value,
+                },
+            )),
+            Self::ProgramChange { channel, program } => {
+                midi(MidiEvent::ProgramChange { channel, program })
+            }
+            Self::ChannelPressure { channel, pressure } => {
+                midi(MidiEvent::ChannelPressure { channel, pressure })
+            }
+            Self::PitchWheelChange { channel, value } => {
+                midi(MidiEvent::PitchWheelChange(channel, value))
+            }
+            Self::Sysex {
+                manufacturer,
+                payload,
+            } => {
+                let manufacture_id = match manufacturer[..] {
+                    [byte] => ManufactureId::OneByte(byte),
+                    [a, b, c] => ManufactureId::ThreeByte([a, b, c]),
+                    _ => return Err(PersistError::InvalidManufactureId),
+                };
+
+                Ok(Some(Event::SysexEvent(SysexEvent {
+                    manufacture_id,
+                    payload: payload.into(),
+                })))
+            }
+            Self::SequenceNumber { number } => meta(MetaEvent::SequenceNumber(number)),
+            Self::Text { text } => meta(MetaEvent::Text(text.into())),
+            Self::Copyright { text } => meta(MetaEvent::Copyright(text.into())),
+            Self::TrackName { text } => meta(MetaEvent::TrackName(text.into())),
+            Self::InstrumentName { text } => meta(MetaEvent::InstrumentName(text.into())),
+            Self::Lyric { text } => meta(MetaEvent::Lyric(text.into())),
+            Self::Marker { text } => meta(MetaEvent::Marker(text.into())),
+            Self::CuePoint { data } => meta(MetaEvent::CuePoint(data.into())),
+            Self::MidiChannelPrefix { channel } => meta(MetaEvent::MidiChannelPrefix(channel)),
+            Self::EndOfTrack => meta(MetaEvent::EndOfTrack),
+            Self::Tempo { micros_per_quarter } => meta(MetaEvent::Tempo(micros_per_quarter)),
+            Self::SmpteOffset {
+                hours,
+                minutes,
+                seconds,
+                frames,
+                subframes,
+            } => meta(MetaEvent::SmpteOffset(SmpteOffset {
+                hours,
+                minutes,
+                seconds,
+                frames,
+                subframes,
+            })),
+            Self::TimeSignature {
+                numerator,
+                denominator,
+                clocks_per_click,
+                thirty_seconds_per_quarter,
+            } => meta(MetaEvent::TimeSignature(TimeSignature {
+                numerator,
+                denominator,
+                clocks_per_tick: clocks_per_click,
+                thirty_second_notes_per_quarter: thirty_seconds_per_quarter,
+            })),
+            Self::KeySignature {
+                sharps_flats,
+                minor,
+            } => meta(MetaEvent::KeySignature(KeySignature {
+                sharps_flats,
+                major_minor: minor,
+            })),
+            Self::SequencerSpecific { data } => meta(MetaEvent::SequencerSpecific(data.into())),
+            Self::UnknownMeta { tag, data } => meta(MetaEvent::UnknownRaw(tag, data.into())),
+            Self::Unknown => Ok(None),
+        }
+    }
+}
+
+impl From<Midi> for SerializableMidi {
+    fn from(value: Midi) -> Self {
+        let HeaderChunk {
+            format,
+            ntrks,
+            division,
+            ..
+        } = value.header;
+
+        let format = match format {
+            Format::Zero => 0,
+            Format::One => 1,
+            Format::Two => 2,
+        };
+        let division = division.to_midi_bytes();
+
+        Self {
+            version: PERSIST_VERSION,
+            header: SerializableHeader {
+                format,
+                ntrks,
+                division: u16::from_be_bytes([division[0], division[1]]),
+            },
+            tracks: value
+                .tracks
+                .into_iter()
+                .map(|track| SerializableTrack {
+                    events: track
+                        .mtrk_events
+                        .into_iter()
+                        .map(|mtrk_event| SerializableMTrkEvent {
+                            delta_time: mtrk_event.delta_time.get(),
+                            event: mtrk_event.event.into(),
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<SerializableMidi> for Midi {
+    type Error = PersistError;
+    fn try_from(value: SerializableMidi) -> Result<Self, Self::Error> {
+        if value.version > PERSIST_VERSION {
+            return Err(PersistError::UnsupportedVersion(value.version));
+        }
+
+        let SerializableHeader {
+            format,
+            ntrks,
+            division,
+        } = value.header;
+        let header = HeaderChunk::try_from((format, ntrks, division))?;
+
+        let mut tracks = vec![];
+        for (track, chunk) in value.tracks.into_iter().enumerate() {
+            let mut mtrk_events = vec![];
+            for (index, mtrk_event) in chunk.events.into_iter().enumerate() {
+                let event = mtrk_event
+                    .event
+                    .into_event()?
+                    .ok_or(PersistError::UnknownEvent { track, index })?;
+
+                mtrk_events.push(MTrkEvent {
+                    delta_time: mtrk_event.delta_time.into(),
+                    event,
+                });
+            }
+
+            tracks.push(TrackChunk::new(mtrk_events));
+        }
+
+        Ok(Self { header, tracks })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::{PersistError, SerializableEvent, SerializableMidi, PERSIST_VERSION};
+    use crate::{reader::MidiReadable, Midi, RawMidi};
+
+    /// JSON written by version 1 of the persisted representation, which must keep loading
+    const VERSION_1_JSON: &str = r#"{
+        "version": 1,
+        "header": { "format": 1, "ntrks": 2, "division": 480 },
+        "tracks": [
+            { "events": [
+                { "delta_time": 0, "event": { "kind": "tempo", "micros_per_quarter": 500000 } },
+                { "delta_time": 0, "event": { "kind": "time_signature", "numerator": 3,
+                    "denominator": 4, "clocks_per_click": 24, "thirty_seconds_per_quarter": 8 } },
+                { "delta_time": 0, "event": { "kind": "key_signature", "sharps_flats": -2,
+                    "minor": true } },
+                { "delta_time": 0, "event": { "kind": "end_of_track" } }
+            ] },
+            { "events": [
+                { "delta_time": 0, "event": { "kind": "track_name", "text": "Lead" } },
+                { "delta_time": 0, "event": { "kind": "program_change", "channel": 1,
+                    "program": 73 } },
+                { "delta_time": 0, "event": { "kind": "note_on", "channel": 1, "key": 60,
+                    "velocity": 90 } },
+                { "delta_time": 480, "event": { "kind": "note_off", "channel": 1, "key": 60,
+                    "velocity": 0 } },
+                { "delta_time": 0, "event": { "kind": "sysex", "manufacturer": [0, 32, 41],
+                    "payload": [1, 2] } },
+                { "delta_time": 0, "event": { "kind": "unknown_meta", "tag": 96,
+                    "data": [7] } },
+                { "delta_time": 0, "event": { "kind": "end_of_track" } }
+            ] }
+        ]
+    }"#;
+
+    /// A stand-in for a future version of [`SerializableEvent`] with an extra variant
+    #[derive(Serialize, Deserialize)]
+    #[serde(tag = "kind", rename_all = "snake_case")]
+    enum FutureEvent {
+        NoteOn { channel: u8, key: u8, velocity: u8 },
+        ProbabilisticNote { key: u8, chance: f32 },
+    }
+
+    #[test]
+    fn version_1_json_still_loads() {
+        let persisted: SerializableMidi = serde_json::from_str(VERSION_1_JSON).unwrap();
+        let midi = Midi::try_from(persisted.clone()).unwrap();
+
+        assert_eq!(midi.tracks.len(), 2);
+        assert_eq!(midi.tracks[1].mtrk_events.len(), 7);
+        assert_eq!(SerializableMidi::from(midi), persisted);
+    }
+
+    #[test]
+    fn fixture_round_trips_through_json() {
+        let midi = RawMidi::try_from_midi_stream("test/run.mid".get_midi_bytes().unwrap())
+            .unwrap()
+            .check_into_midi()
+            .unwrap();
+
+        let json = serde_json::to_string(&SerializableMidi::from(midi.clone())).unwrap();
+        let persisted: SerializableMidi = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(persisted.version, PERSIST_VERSION);
+        assert_eq!(Midi::try_from(persisted).unwrap(), midi);
+    }
+
+    #[test]
+    fn future_event_kinds_fall_back_to_unknown() {
+        let future = [
+            FutureEvent::NoteOn {
+                channel: 0,
+                key: 64,
+                velocity: 100,
+            },
+            FutureEvent::ProbabilisticNote {
+                key: 64,
+                chance: 0.5,
+            },
+        ];
+        let json = serde_json::to_string(&future).unwrap();
+        let events: Vec<SerializableEvent> = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(
+            events,
+            vec![
+                SerializableEvent::NoteOn {
+                    channel: 0,
+                    key: 64,
+                    velocity: 100
+                },
+                SerializableEvent::Unknown
+            ]
+        );
+
+        let mut persisted: SerializableMidi = serde_json::from_str(VERSION_1_JSON).unwrap();
+        persisted.tracks[1].events[2].event = SerializableEvent::Unknown;
+        assert_eq!(
+            Midi::try_from(persisted),
+            Err(PersistError::UnknownEvent { track: 1, index: 2 })
+        );
+    }
+
+    #[test]
+    fn newer_versions_rejected() {
+        let mut persisted: SerializableMidi = serde_json::from_str(VERSION_1_JSON).unwrap();
+        persisted.version = PERSIST_VERSION + 1;
+
+        assert_eq!(
+            Midi::try_from(persisted),
+            Err(PersistError::UnsupportedVersion(PERSIST_VERSION + 1))
+        );
+    }
+}
@@ -0,0 +1,421 @@
+//! Chaining whole file transformations into one reusable pass, with a combined report of what
+//! each step had to drop or clamp
+
+use crate::{
+    chunk::{
+        header::Division,
+        track::{editor::EditError, event::MidiEvent, Event, TrackChunk},
+    },
+    humanize::HumanizeOptions,
+    normalize::NormalizeOptions,
+    retime::RetimeError,
+    stats::PERCUSSION_CHANNEL,
+    Midi,
+};
+
+/// One transformation queued in a [`MidiPipeline`]
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    /// [`Midi::normalize`]
+    Normalize(NormalizeOptions),
+    /// [`Midi::transpose`]
+    Transpose(i8),
+    /// [`Midi::scale_velocities`]
+    ScaleVelocities(f32),
+    /// [`TrackChunk::humanize`] on every track
+    Humanize(HumanizeOptions, u64),
+    /// [`Midi::retime_to_division`]
+    Retime(Division),
+    /// [`Midi::thin_tempo_events`]
+    ThinTempo {
+        /// Largest BPM deviation a removed change may have from the fitted ramp
+        max_deviation_bpm: f64,
+        /// Fewest ticks kept between two tempo changes
+        min_interval_ticks: u32,
+    },
+    /// [`Midi::dedup_tracks`]
+    DedupTracks,
+}
+
+/// A sequence of whole file transformations, built up from [`MidiPipeline::new`] and run in the
+/// order they were added by [`MidiPipeline::apply`]. One pipeline can be applied to any number of
+/// files
+///
+/// ```rust
+/// use miami::{normalize::NormalizeOptions, pipeline::MidiPipeline, prelude::*};
+///
+/// let mut midi = open("test/test.mid").expect("Open and parse `test.mid`");
+/// let report = MidiPipeline::new()
+///     .normalize(NormalizeOptions::default())
+///     .transpose(2)
+///     .dedup_tracks()
+///     .apply(&mut midi)
+///     .expect("Nothing here can fail");
+///
+/// for warning in &report.warnings {
+///     println!("{warning}");
+/// }
+/// ```
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MidiPipeline {
+    /// Every step in the order it runs
+    steps: Vec<Step>,
+}
+
+/// Something a [`MidiPipeline`] step dropped or clamped, along with the index of the step in the
+/// pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipelineWarning {
+    /// Events after a track's `EndOfTrack` were dropped by normalizing, rather than recovered
+    TrailingEventsDropped {
+        /// Index of the step
+        step: usize,
+        /// Index of the track
+        track: usize,
+        /// Number of events dropped
+        events: usize,
+    },
+    /// Transposing would have taken note events past key 0 or 127, so they were clamped
+    KeysClamped {
+        /// Index of the step
+        step: usize,
+        /// Number of note events clamped across the file
+        events: usize,
+    },
+    /// Tempo changes close enough to a ramp were removed
+    TempoEventsThinned {
+        /// Index of the step
+        step: usize,
+        /// Number of tempo events removed
+        removed: usize,
+    },
+    /// Tracks playing the same thing as an earlier track were removed
+    DuplicateTracksRemoved {
+        /// Index of the step
+        step: usize,
+        /// Number of tracks removed
+        removed: usize,
+    },
+}
+
+impl core::fmt::Display for PipelineWarning {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::TrailingEventsDropped {
+                step,
+                track,
+                events,
+            } => write![
+                f,
+                "Step {step}: Dropped {events} events after the end of track {track}"
+            ],
+            Self::KeysClamped { step, events } => {
+                write![f, "Step {step}: Clamped the key of {events} note events"]
+            }
+            Self::TempoEventsThinned { step, removed } => {
+                write![f, "Step {step}: Removed {removed} tempo events"]
+            }
+            Self::DuplicateTracksRemoved { step, removed } => {
+                write![f, "Step {step}: Removed {removed} duplicate tracks"]
+            }
+        }
+    }
+}
+
+/// Everything a successful [`MidiPipeline::apply`] reports
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PipelineReport {
+    /// Number of steps run
+    pub steps: usize,
+    /// Warnings from every step, in the order the steps ran
+    pub warnings: Vec<PipelineWarning>,
+}
+
+/// Error from a [`MidiPipeline`] step, along with the index of the step that failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipelineError {
+    /// [`Midi::retime_to_division`] failed
+    Retime {
+        /// Index of the step
+        step: usize,
+        /// Why retiming failed
+        error: RetimeError,
+    },
+    /// [`Midi::thin_tempo_events`] failed
+    ThinTempo {
+        /// Index of the step
+        step: usize,
+        /// Why thinning failed
+        error: EditError,
+    },
+}
+
+impl core::error::Error for PipelineError {}
+impl core::fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Retime { step, error } => write![f, "Step {step}: {error}"],
+            Self::ThinTempo { step, error } => write![f, "Step {step}: {error}"],
+        }
+    }
+}
+
+/// Counts the note events of a track that transposing by `semitones` would clamp, following
+/// [`TrackChunk::transpose`] in leaving percussion alone
+fn clamped_keys(track: &TrackChunk, semitones: i8) -> usize {
+    track
+        .events()
+        .filter(|mtrk_event| match mtrk_event.event() {
+            Event::MidiEvent(
+                MidiEvent::NoteOn(channel, note)
+                | MidiEvent::NoteOff(channel, note)
+                | MidiEvent::PolyphonicKeyPressure(channel, note),
+            ) => {
+                *channel != PERCUSSION_CHANNEL
+                    && !(0..=127).contains(&(note.key as i16 + semitones as i16))
+            }
+            _ => false,
+        })
+        .count()
+}
+
+impl MidiPipeline {
+    /// A pipeline without any steps
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds [`Midi::normalize`], reporting [`PipelineWarning::TrailingEventsDropped`] for every
+    /// track that loses events after its `EndOfTrack`
+    pub fn normalize(mut self, options: NormalizeOptions) -> Self {
+        self.steps.push(Step::Normalize(options));
+        self
+    }
+
+    /// Adds [`Midi::transpose`], reporting [`PipelineWarning::KeysClamped`] if any key leaves the
+    /// valid range
+    pub fn transpose(mut self, semitones: i8) -> Self {
+        self.steps.push(Step::Transpose(semitones));
+        self
+    }
+
+    /// Adds [`Midi::scale_velocities`]
+    pub fn scale_velocities(mut self, factor: f32) -> Self {
+        self.steps.push(Step::ScaleVelocities(factor));
+        self
+    }
+
+    /// Adds [`TrackChunk::humanize`] on every track, seeded with `seed` plus the track's index so
+    /// tracks don't move in lockstep. The seed is fixed, so the same file is always humanized
+    /// the same way
+    pub fn humanize(mut self, opts: HumanizeOptions, seed: u64) -> Self {
+        self.steps.push(Step::Humanize(opts, seed));
+        self
+    }
+
+    /// Adds [`Midi::retime_to_division`], failing the pipeline with [`PipelineError::Retime`] if
+    /// the file can't be retimed
+    pub fn retime(mut self, division: Division) -> Self {
+        self.steps.push(Step::Retime(division));
+        self
+    }
+
+    /// Adds [`Midi::thin_tempo_events`], reporting [`PipelineWarning::TempoEventsThinned`] if any
+    /// are removed and failing the pipeline with [`PipelineError::ThinTempo`] if they can't be
+    pub fn thin_tempo_events(mut self, max_deviation_bpm: f64, min_interval_ticks: u32) -> Self {
+        self.steps.push(Step::ThinTempo {
+            max_deviation_bpm,
+            min_interval_ticks,
+        });
+        self
+    }
+
+    /// Adds [`Midi::dedup_tracks`], reporting [`PipelineWarning::DuplicateTracksRemoved`] if any
+    /// are removed
+    pub fn dedup_tracks(mut self) -> Self {
+        self.steps.push(Step::DedupTracks);
+        self
+    }
+
+    /// Runs every step on the file in the order they were added. If a step fails, the file is
+    /// left as it was before the pipeline started
+    pub fn apply(&self, midi: &mut Midi) -> Result<PipelineReport, PipelineError> {
+        let mut working = midi.clone();
+        let mut warnings = vec![];
+
+        for (step, kind) in self.steps.iter().enumerate() {
+            match kind {
+                Step::Normalize(options) => {
+                    for (track, chunk) in working.tracks.iter_mut().enumerate() {
+                        let trailing = chunk.trailing_events().len();
+                        let events = if options.recover_trailing_notes && trailing > 0 {
+                            let before = chunk.mtrk_events.len();
+                            chunk.recover_trailing_notes();
+                            trailing - (chunk.mtrk_events.len() - before)
+                        } else {
+                            trailing
+                        };
+                        if events > 0 {
+                            warnings.push(PipelineWarning::TrailingEventsDropped {
+                                step,
+                                track,
+                                events,
+                            });
+                        }
+                    }
+                    working.normalize(*options);
+                }
+                Step::Transpose(semitones) => {
+                    let events = working
+                        .tracks
+                        .iter()
+                        .map(|track| clamped_keys(track, *semitones))
+                        .sum();
+                    if events > 0 {
+                        warnings.push(PipelineWarning::KeysClamped { step, events });
+                    }
+                    working.transpose(*semitones);
+                }
+                Step::ScaleVelocities(factor) => working.scale_velocities(*factor),
+                Step::Humanize(opts, seed) => {
+                    for (idx, track) in working.tracks.iter_mut().enumerate() {
+                        track.humanize(*opts, seed.wrapping_add(idx as u64));
+                    }
+                }
+                Step::Retime(division) => working
+                    .retime_to_division(*division)
+                    .map_err(|error| PipelineError::Retime { step, error })?,
+                Step::ThinTempo {
+                    max_deviation_bpm,
+                    min_interval_ticks,
+                } => {
+                    let removed = working
+                        .thin_tempo_events(*max_deviation_bpm, *min_interval_ticks)
+                        .map_err(|error| PipelineError::ThinTempo { step, error })?;
+                    if removed > 0 {
+                        warnings.push(PipelineWarning::TempoEventsThinned { step, removed });
+                    }
+                }
+                Step::DedupTracks => {
+                    let before = working.tracks.len();
+                    working.dedup_tracks();
+                    let removed = before - working.tracks.len();
+                    if removed > 0 {
+                        warnings.push(PipelineWarning::DuplicateTracksRemoved { step, removed });
+                    }
+                }
+            }
+        }
+
+        *midi = working;
+        Ok(PipelineReport {
+            steps: self.steps.len(),
+            warnings,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MidiPipeline, PipelineError, PipelineWarning};
+    use crate::{
+        chunk::{
+            header::Division,
+            track::{
+                event::{MidiEvent, NoteMeta},
+                Event, MTrkEvent,
+            },
+        },
+        normalize::NormalizeOptions,
+        reader::MidiReadable,
+        retime::RetimeError,
+        Midi, RawMidi,
+    };
+
+    fn fixture() -> Midi {
+        RawMidi::try_from_midi_stream("test/test4tracks.mid".get_midi_bytes().unwrap())
+            .unwrap()
+            .check_into_midi()
+            .unwrap()
+    }
+
+    #[test]
+    fn steps_report_in_order() {
+        let mut midi = fixture();
+        let mut copy = midi.tracks[0].clone();
+        copy.trailing_events.push(MTrkEvent::new(
+            0,
+            Event::MidiEvent(MidiEvent::NoteOn(
+                0,
+                NoteMeta {
+                    key: 60,
+                    velocity: 90,
+                },
+            )),
+        ));
+        midi.push_track(copy);
+
+        let high_notes = midi.tracks[0]
+            .events()
+            .filter(|mtrk_event| {
+                matches!(
+                    mtrk_event.event(),
+                    Event::MidiEvent(MidiEvent::NoteOn(channel, note) | MidiEvent::NoteOff(channel, note))
+                        if *channel != 9 && note.key > 27
+                )
+            })
+            .count();
+        assert!(high_notes > 0);
+
+        let mut expected = fixture();
+        expected.normalize(NormalizeOptions::default());
+        expected.transpose(100);
+
+        let report = MidiPipeline::new()
+            .normalize(NormalizeOptions::default())
+            .transpose(100)
+            .dedup_tracks()
+            .apply(&mut midi)
+            .unwrap();
+
+        assert_eq!(report.steps, 3);
+        assert_eq!(
+            report.warnings,
+            [
+                PipelineWarning::TrailingEventsDropped {
+                    step: 0,
+                    track: 1,
+                    events: 1
+                },
+                PipelineWarning::KeysClamped {
+                    step: 1,
+                    events: 2 * high_notes
+                },
+                PipelineWarning::DuplicateTracksRemoved {
+                    step: 2,
+                    removed: 1
+                },
+            ]
+        );
+        assert_eq!(midi.tracks, expected.tracks);
+    }
+
+    #[test]
+    fn failed_step_leaves_the_file_alone() {
+        let mut midi = fixture();
+        let before = midi.clone();
+
+        let error = MidiPipeline::new()
+            .transpose(2)
+            .retime(Division::from(0xE728))
+            .apply(&mut midi);
+        assert_eq!(
+            error,
+            Err(PipelineError::Retime {
+                step: 1,
+                error: RetimeError::TimeCodeDivision
+            })
+        );
+        assert_eq!(midi, before);
+    }
+}
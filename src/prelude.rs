@@ -0,0 +1,54 @@
+//! The types and traits most programs reading, editing or writing files need, for glob importing:
+//!
+//! ```rust
+//! use miami::prelude::*;
+//!
+//! let data = "test/test.mid"
+//!     .get_midi_bytes()
+//!     .expect("Get `test.mid` file and read bytes");
+//! let midi: Midi = RawMidi::try_from_midi_stream(data)
+//!     .expect("Parse data as a MIDI stream")
+//!     .check_into_midi()
+//!     .expect("Sanitize MIDI into formatted MIDI");
+//!
+//! let mut editor = TrackEditor::new(midi.tracks[0].clone());
+//! editor.insert(0, Event::MetaEvent(MetaEvent::Marker("Start".into())));
+//! let track: TrackChunk = editor.finish().expect("Inserting only shrinks delta times");
+//!
+//! let bytes = Midi {
+//!     header: HeaderChunk::new(Format::Zero, 1, Division::TPQN_480),
+//!     tracks: vec![track],
+//! }
+//! .to_midi_bytes();
+//! # assert!(!bytes.is_empty());
+//! ```
+//!
+//! This covers:
+//!
+//! - Opening and saving files: [`Midi`], [`RawMidi`], [`open`], [`save`] and their options
+//! - Parsing and writing traits: [`MidiReadable`], [`MidiStream`] and [`MidiWriteable`]
+//! - Building tracks: the header, track and event types, [`TrackEditor`] and the [`Tick`] and
+//!   [`DeltaTime`] time types
+//!
+//! Error enums such as [`crate::MidiError`] and [`crate::chunk::track::TrackError`] are left out
+//! so they don't clash with the errors of the program using them, and so are the low level
+//! [`crate::Chunk`] and [`crate::chunk::ParsedChunk`] that only matter when reading a file's raw
+//! layout. Analysis, query and transform types stay in their own modules
+
+pub use crate::{
+    chunk::{
+        header::{Division, Format, HeaderChunk},
+        track::{
+            editor::TrackEditor,
+            event::{Channel, ControlChange, MidiEvent, NoteMeta, PitchBend, U7},
+            meta::{MetaEvent, TimeSignature},
+            sysex::SysexEvent,
+            Event, MTrkEvent, TrackChunk,
+        },
+    },
+    file::{open, open_with, save, save_with, OpenOptions, SaveOptions},
+    reader::{MidiReadable, MidiStream, ParseOptions, ParseProfile},
+    time::{DeltaTime, Tick},
+    writer::{MidiFileWriter, MidiWriteable, WriteOptions},
+    Midi, RawMidi,
+};
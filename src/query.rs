@@ -0,0 +1,281 @@
+//! Searching a file for events matching a combination of filters, such as every sustain pedal
+//! change on one channel within a span of ticks
+
+use core::ops::RangeInclusive;
+
+use crate::{
+    chunk::track::{event::MidiEvent, kind::EventKind, Event},
+    time::Tick,
+    Midi,
+};
+
+/// Filters for [`Midi::find_events`], built up from [`EventQuery::new`]. Every filter that's set
+/// has to match, and a query without any matches every event
+///
+/// ```rust
+/// use miami::{chunk::track::kind::EventKind, prelude::*, query::EventQuery};
+///
+/// // Every sustain pedal change on channel 0 from tick 4800 up to 9600
+/// let query = EventQuery::new()
+///     .channel(0)
+///     .kind(EventKind::ControlChange)
+///     .controller(ControlChange::SUSTAIN)
+///     .ticks(4800, 9600);
+/// # let _ = query;
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EventQuery {
+    /// See [`EventQuery::track`]
+    track: Option<usize>,
+    /// See [`EventQuery::channel`]
+    channel: Option<u8>,
+    /// See [`EventQuery::kind`]
+    kind: Option<EventKind>,
+    /// See [`EventQuery::controller`]
+    controller: Option<u8>,
+    /// See [`EventQuery::keys`]
+    keys: Option<RangeInclusive<u8>>,
+    /// See [`EventQuery::ticks`], as `(start, end)`
+    ticks: Option<(Tick, Tick)>,
+    /// See [`EventQuery::meta_tag`]
+    meta_tag: Option<u8>,
+}
+
+/// An event found by [`Midi::find_events`], with where it is in the file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EventRef<'a> {
+    /// Index of the track holding the event
+    pub track: usize,
+    /// Index of the event within its track
+    pub index: usize,
+    /// Absolute tick of the event
+    pub tick: Tick,
+    /// The event itself
+    pub event: &'a Event,
+}
+
+impl EventQuery {
+    /// A query matching every event
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only matches events in the track at this index
+    pub fn track(mut self, track: usize) -> Self {
+        self.track = Some(track);
+        self
+    }
+
+    /// Only matches channel messages sent on this channel
+    pub fn channel(mut self, channel: u8) -> Self {
+        self.channel = Some(channel);
+        self
+    }
+
+    /// Only matches events of this kind
+    pub fn kind(mut self, kind: EventKind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    /// Only matches Control Changes to this controller number
+    pub fn controller(mut self, controller: u8) -> Self {
+        self.controller = Some(controller);
+        self
+    }
+
+    /// Only matches Note Ons, Note Offs and polyphonic key pressure on a key within the range
+    pub fn keys(mut self, keys: RangeInclusive<u8>) -> Self {
+        self.keys = Some(keys);
+        self
+    }
+
+    /// Only matches events from the `start` tick up to but not including the `end` tick
+    pub fn ticks(mut self, start: impl Into<Tick>, end: impl Into<Tick>) -> Self {
+        self.ticks = Some((start.into(), end.into()));
+        self
+    }
+
+    /// Only matches meta events with this tag, including ones this crate doesn't interpret
+    pub fn meta_tag(mut self, tag: u8) -> Self {
+        self.meta_tag = Some(tag);
+        self
+    }
+
+    /// Whether an event at the given tick in the given track passes every filter
+    fn matches(&self, track: usize, tick: Tick, event: &Event) -> bool {
+        let midi = match event {
+            Event::MidiEvent(midi) => Some(midi),
+            _ => None,
+        };
+        let key = midi.and_then(|midi| match midi {
+            MidiEvent::NoteOn(_, note)
+            | MidiEvent::NoteOff(_, note)
+            | MidiEvent::PolyphonicKeyPressure(_, note) => Some(note.key),
+            _ => None,
+        });
+        let controller = midi.and_then(|midi| match midi {
+            MidiEvent::ControlChange(_, change) => Some(change.controller()),
+            _ => None,
+        });
+        let meta_tag = match event {
+            Event::MetaEvent(meta) => Some(meta.get_tag()),
+            _ => None,
+        };
+
+        self.track.is_none_or(|wanted| wanted == track)
+            && self
+                .channel
+                .is_none_or(|wanted| midi.is_some_and(|midi| midi.channel() == wanted))
+            && self.kind.is_none_or(|wanted| event.kind() == wanted)
+            && self
+                .controller
+                .is_none_or(|wanted| controller == Some(wanted))
+            && self
+                .keys
+                .as_ref()
+                .is_none_or(|wanted| key.is_some_and(|key| wanted.contains(&key)))
+            && self
+                .ticks
+                .is_none_or(|(start, end)| start <= tick && tick < end)
+            && self.meta_tag.is_none_or(|wanted| meta_tag == Some(wanted))
+    }
+}
+
+impl Midi {
+    /// Every event matching the query, in track order and then in order within each track
+    pub fn find_events(&self, query: EventQuery) -> Vec<EventRef<'_>> {
+        let query = &query;
+        self.tracks
+            .iter()
+            .enumerate()
+            .filter(|(track, _)| query.track.is_none_or(|wanted| wanted == *track))
+            .flat_map(|(track, chunk)| {
+                chunk
+                    .absolute_events()
+                    .enumerate()
+                    .filter(move |(_, (tick, event))| query.matches(track, *tick, event))
+                    .map(move |(index, (tick, event))| EventRef {
+                        track,
+                        index,
+                        tick,
+                        event,
+                    })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EventQuery;
+    use crate::{
+        chunk::{
+            header::HeaderChunk,
+            track::{
+                event::{ControlChange, MidiEvent, NoteMeta},
+                kind::{EventKind, MetaKind},
+                meta::MetaEvent,
+                Event, TrackChunk,
+            },
+        },
+        Midi,
+    };
+
+    fn sustain(channel: u8, value: u8) -> Event {
+        Event::MidiEvent(MidiEvent::ControlChange(
+            channel,
+            ControlChange::new(ControlChange::SUSTAIN, value).unwrap(),
+        ))
+    }
+
+    fn note(channel: u8, key: u8) -> Event {
+        Event::MidiEvent(MidiEvent::NoteOn(channel, NoteMeta { key, velocity: 90 }))
+    }
+
+    fn midi() -> Midi {
+        let end = || Event::MetaEvent(MetaEvent::EndOfTrack);
+        let tracks = vec![
+            TrackChunk::from_absolute_events([
+                (0, Event::MetaEvent(MetaEvent::DEFAULT_TEMPO)),
+                (4800, Event::MetaEvent(MetaEvent::Marker("Bridge".into()))),
+                (
+                    4800,
+                    Event::MetaEvent(MetaEvent::UnknownRaw(0x60, vec![1].into())),
+                ),
+                (9600, end()),
+            ])
+            .unwrap(),
+            TrackChunk::from_absolute_events([
+                (0, sustain(0, 127)),
+                (0, note(0, 60)),
+                (4800, sustain(0, 0)),
+                (4800, note(0, 72)),
+                (7200, sustain(1, 127)),
+                (7200, sustain(0, 127)),
+                (9600, sustain(0, 0)),
+                (9600, end()),
+            ])
+            .unwrap(),
+        ];
+
+        Midi {
+            header: HeaderChunk::try_from((1, 2, 480)).unwrap(),
+            tracks,
+        }
+    }
+
+    #[test]
+    fn combined_filters_narrow_the_search() {
+        let midi = midi();
+        let query = EventQuery::new()
+            .channel(0)
+            .controller(ControlChange::SUSTAIN)
+            .ticks(4800, 9600);
+
+        let found: Vec<_> = midi
+            .find_events(query)
+            .iter()
+            .map(|found| {
+                (
+                    found.track,
+                    found.index,
+                    found.tick.get(),
+                    found.event.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [(1, 2, 4800, sustain(0, 0)), (1, 5, 7200, sustain(0, 127))]
+        );
+
+        let keys = EventQuery::new().kind(EventKind::NoteOn).keys(61..=127);
+        assert_eq!(midi.find_events(keys.clone())[0].event, &note(0, 72));
+        assert_eq!(midi.find_events(keys).len(), 1);
+
+        let markers = EventQuery::new()
+            .kind(EventKind::Meta(MetaKind::Marker))
+            .track(0);
+        assert_eq!(midi.find_events(markers)[0].tick.get(), 4800);
+        assert_eq!(midi.find_events(EventQuery::new().meta_tag(0x60)).len(), 1);
+        assert_eq!(midi.find_events(EventQuery::new()).len(), 12);
+    }
+
+    #[test]
+    fn contradictory_filters_find_nothing() {
+        let midi = midi();
+
+        for query in [
+            EventQuery::new().channel(2),
+            EventQuery::new().track(0).kind(EventKind::ControlChange),
+            EventQuery::new()
+                .controller(ControlChange::SUSTAIN)
+                .keys(0..=127),
+            EventQuery::new().ticks(9601, 20_000),
+            EventQuery::new().track(5),
+        ] {
+            assert!(midi.find_events(query.clone()).is_empty(), "{query:?}");
+        }
+    }
+}
@@ -7,7 +7,15 @@ use std::{
     path::Path,
 };
 
-use crate::Chunk;
+use crate::{
+    chunk::{
+        chunk_types::{HEADER_CHUNK, TRACK_DATA_CHUNK},
+        track::{meta::MetaEvent, meta::TextEncoding, Event, MTrkEvent, TrackChunk, TrackError},
+        ChunkParseError, ParsedChunk, ResourceLimit,
+    },
+    outcome::ParseWarning,
+    Chunk, InvalidChunkHeader, Midi, MidiError, RawMidi,
+};
 
 /// Trait that allows certain amount of bytes to be yielded by an iterator
 pub trait Yieldable<T> {
@@ -21,7 +29,9 @@ where
 {
     #[allow(if_let_rescope)]
     fn get(&mut self, n: usize) -> Vec<ITER::Item> {
-        let mut elements = Vec::with_capacity(n);
+        // A length read from the input can be far larger than what's actually left, so don't
+        // trust it for the allocation
+        let mut elements = Vec::with_capacity(n.min(self.size_hint().0));
         for _ in 0..n {
             if let Some(item) = self.next() {
                 elements.push(item);
@@ -33,7 +43,12 @@ where
     }
 }
 
-/// Trait for reading sequential chunks from a MIDI stream
+/// Trait for reading sequential chunks from a MIDI stream.
+///
+/// Every byte iterator is a stream, including `&mut` references to one. The trait is object safe,
+/// so sources of different types can be stored as `Box<dyn MidiStream>` and parsed from directly
+/// or through a `&mut dyn MidiStream`. New methods must keep it that way, taking `&mut self` and
+/// no type parameters
 pub trait MidiStream {
     /// Reads the next chunk from the sequence along with its associated data.
     ///
@@ -45,6 +60,77 @@ pub trait MidiStream {
     /// This method will fail silently by returning `None` if the stream does not contain enough
     /// data to read a full chunk header or its associated payload.
     fn read_chunk_data_pair(&mut self) -> Option<(Chunk, Vec<u8>)>;
+
+    /// Reads the next chunk like [`MidiStream::read_chunk_data_pair`], but rejects chunk headers
+    /// whose type isn't printable ASCII instead of trusting their length.
+    ///
+    /// # Returns
+    /// - `Some(Ok((Chunk, Vec<u8>)))`: If a plausible chunk and its data are successfully read.
+    /// - `Some(Err(InvalidChunkHeader))`: If the next 8 bytes can't be a chunk header.
+    /// - `None`: If there isn't enough data left to read a full chunk or its payload.
+    fn read_chunk_data_pair_checked(
+        &mut self,
+    ) -> Option<Result<(Chunk, Vec<u8>), InvalidChunkHeader>> {
+        let (chunk, data) = self.read_chunk_data_pair()?;
+        let mut bytes = [0; 8];
+        for (byte, c) in bytes.iter_mut().zip(chunk.chunk_type) {
+            *byte = c as u8;
+        }
+        bytes[4..].copy_from_slice(&chunk.length.to_be_bytes());
+
+        Some(Chunk::try_from_bytes(bytes).map(|chunk| (chunk, data)))
+    }
+
+    /// Reads the next chunk, skipping over any bytes that can't start a chunk header (such as
+    /// alignment padding between chunks) until a printable chunk type is found. Streams that
+    /// can't resync fall back to [`MidiStream::read_chunk_data_pair`]
+    ///
+    /// # Returns
+    /// - `Some((Chunk, Vec<u8>))`: If a plausible chunk and its data are successfully read.
+    /// - `None`: If the stream ends before a full chunk and its payload are found.
+    fn read_chunk_data_pair_lenient(&mut self) -> Option<(Chunk, Vec<u8>)> {
+        self.read_chunk_data_pair()
+    }
+
+    /// Reads the next chunk like [`MidiStream::read_chunk_data_pair_lenient`], but refuses to
+    /// read a payload longer than `max_len` bytes.
+    ///
+    /// # Returns
+    /// - `Some(Ok((Chunk, Vec<u8>)))`: If a chunk no longer than `max_len` is read.
+    /// - `Some(Err(Chunk))`: If the next chunk is longer than `max_len`. Streams that can't check
+    ///   the length before reading fall back to reading the payload and then discarding it.
+    /// - `None`: If the stream ends before a full chunk and its payload are found.
+    fn read_chunk_data_pair_lenient_within(
+        &mut self,
+        max_len: usize,
+    ) -> Option<Result<(Chunk, Vec<u8>), Chunk>> {
+        let (chunk, data) = self.read_chunk_data_pair_lenient()?;
+        Some(if data.len() > max_len {
+            Err(chunk)
+        } else {
+            Ok((chunk, data))
+        })
+    }
+
+    /// Reads the next chunk like [`MidiStream::read_chunk_data_pair`], but refuses to read a
+    /// payload longer than `max_len` bytes.
+    ///
+    /// # Returns
+    /// - `Some(Ok((Chunk, Vec<u8>)))`: If a chunk no longer than `max_len` is read.
+    /// - `Some(Err(Chunk))`: If the next chunk is longer than `max_len`. Streams that can't check
+    ///   the length before reading fall back to reading the payload and then discarding it.
+    /// - `None`: If there isn't enough data left to read a full chunk or its payload.
+    fn read_chunk_data_pair_within(
+        &mut self,
+        max_len: usize,
+    ) -> Option<Result<(Chunk, Vec<u8>), Chunk>> {
+        let (chunk, data) = self.read_chunk_data_pair()?;
+        Some(if data.len() > max_len {
+            Err(chunk)
+        } else {
+            Ok((chunk, data))
+        })
+    }
 }
 
 impl<MIDI> MidiStream for MIDI
@@ -65,11 +151,907 @@ where
         let data = self.get(chunk.len());
 
         if data.len() != chunk.len() {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(
+                expected = chunk.len(),
+                available = data.len(),
+                "stream ended partway through a chunk"
+            );
             return None;
         }
 
         Some((chunk, data))
     }
+
+    fn read_chunk_data_pair_checked(
+        &mut self,
+    ) -> Option<Result<(Chunk, Vec<u8>), InvalidChunkHeader>> {
+        let chunk_packet = self.get(8);
+
+        // Fewer than 8 bytes left means the stream is done, same as the unchecked read
+        let chunk = match Chunk::try_from_bytes(chunk_packet.try_into().ok()?) {
+            Ok(chunk) => chunk,
+            Err(e) => return Some(Err(e)),
+        };
+
+        let data = self.get(chunk.len());
+
+        if data.len() != chunk.len() {
+            return None;
+        }
+
+        Some(Ok((chunk, data)))
+    }
+
+    fn read_chunk_data_pair_lenient(&mut self) -> Option<(Chunk, Vec<u8>)> {
+        self.read_chunk_data_pair_lenient_within(usize::MAX)?.ok()
+    }
+
+    fn read_chunk_data_pair_lenient_within(
+        &mut self,
+        max_len: usize,
+    ) -> Option<Result<(Chunk, Vec<u8>), Chunk>> {
+        match read_lenient(self, max_len) {
+            LenientRead::Chunk { chunk, data, .. } => Some(Ok((chunk, data))),
+            LenientRead::TooLong { chunk } => Some(Err(chunk)),
+            LenientRead::Truncated { .. } | LenientRead::End { .. } => None,
+        }
+    }
+
+    fn read_chunk_data_pair_within(
+        &mut self,
+        max_len: usize,
+    ) -> Option<Result<(Chunk, Vec<u8>), Chunk>> {
+        match read_strict(self, max_len) {
+            LenientRead::Chunk { chunk, data, .. } => Some(Ok((chunk, data))),
+            LenientRead::TooLong { chunk } => Some(Err(chunk)),
+            LenientRead::Truncated { .. } | LenientRead::End { .. } => None,
+        }
+    }
+}
+
+/// Forwards every method to the boxed stream, so its own overrides are kept
+impl MidiStream for Box<dyn MidiStream + '_> {
+    fn read_chunk_data_pair(&mut self) -> Option<(Chunk, Vec<u8>)> {
+        (**self).read_chunk_data_pair()
+    }
+
+    fn read_chunk_data_pair_checked(
+        &mut self,
+    ) -> Option<Result<(Chunk, Vec<u8>), InvalidChunkHeader>> {
+        (**self).read_chunk_data_pair_checked()
+    }
+
+    fn read_chunk_data_pair_lenient(&mut self) -> Option<(Chunk, Vec<u8>)> {
+        (**self).read_chunk_data_pair_lenient()
+    }
+
+    fn read_chunk_data_pair_lenient_within(
+        &mut self,
+        max_len: usize,
+    ) -> Option<Result<(Chunk, Vec<u8>), Chunk>> {
+        (**self).read_chunk_data_pair_lenient_within(max_len)
+    }
+
+    fn read_chunk_data_pair_within(
+        &mut self,
+        max_len: usize,
+    ) -> Option<Result<(Chunk, Vec<u8>), Chunk>> {
+        (**self).read_chunk_data_pair_within(max_len)
+    }
+}
+
+/// Forwards every method to the borrowed stream, so its own overrides are kept
+impl MidiStream for &mut (dyn MidiStream + '_) {
+    fn read_chunk_data_pair(&mut self) -> Option<(Chunk, Vec<u8>)> {
+        (**self).read_chunk_data_pair()
+    }
+
+    fn read_chunk_data_pair_checked(
+        &mut self,
+    ) -> Option<Result<(Chunk, Vec<u8>), InvalidChunkHeader>> {
+        (**self).read_chunk_data_pair_checked()
+    }
+
+    fn read_chunk_data_pair_lenient(&mut self) -> Option<(Chunk, Vec<u8>)> {
+        (**self).read_chunk_data_pair_lenient()
+    }
+
+    fn read_chunk_data_pair_lenient_within(
+        &mut self,
+        max_len: usize,
+    ) -> Option<Result<(Chunk, Vec<u8>), Chunk>> {
+        (**self).read_chunk_data_pair_lenient_within(max_len)
+    }
+
+    fn read_chunk_data_pair_within(
+        &mut self,
+        max_len: usize,
+    ) -> Option<Result<(Chunk, Vec<u8>), Chunk>> {
+        (**self).read_chunk_data_pair_within(max_len)
+    }
+}
+
+/// Outcome of reading one chunk, including how many bytes had to be passed over to find it
+pub(crate) enum LenientRead {
+    /// A complete chunk, found after skipping `skipped` bytes that couldn't start a header
+    Chunk {
+        /// Bytes skipped before the chunk header
+        skipped: usize,
+        /// The chunk header
+        chunk: Chunk,
+        /// The chunk's payload
+        data: Vec<u8>,
+    },
+    /// The stream ended partway through a chunk's payload
+    Truncated {
+        /// Bytes skipped before the chunk header
+        skipped: usize,
+        /// The chunk header
+        chunk: Chunk,
+        /// The part of the payload that was available
+        data: Vec<u8>,
+    },
+    /// The chunk's payload is longer than the most the caller would read, so it was left unread
+    TooLong {
+        /// The chunk header
+        chunk: Chunk,
+    },
+    /// The stream ended before another chunk header was found
+    End {
+        /// Bytes read while looking for a header
+        trailing: usize,
+    },
+}
+
+/// Reads the next chunk, skipping over any bytes that can't start a chunk header. A payload
+/// longer than `max_len` isn't read at all
+pub(crate) fn read_lenient<ITER: Iterator<Item = u8>>(
+    iter: &mut ITER,
+    max_len: usize,
+) -> LenientRead {
+    let start = iter.get(8);
+    let Ok(mut window) = <[u8; 8]>::try_from(start.as_slice()) else {
+        return LenientRead::End {
+            trailing: start.len(),
+        };
+    };
+
+    let mut skipped = 0;
+    let chunk = loop {
+        match Chunk::try_from_bytes(window) {
+            Ok(chunk) => break chunk,
+            Err(_) => {
+                let Some(next) = iter.next() else {
+                    return LenientRead::End {
+                        trailing: skipped + 8,
+                    };
+                };
+                window.rotate_left(1);
+                window[7] = next;
+                skipped += 1;
+            }
+        }
+    };
+
+    #[cfg(feature = "tracing")]
+    if skipped > 0 {
+        tracing::warn!(skipped, "skipped bytes that can't start a chunk header");
+    }
+
+    read_payload(iter, skipped, chunk, max_len)
+}
+
+/// Reads the next chunk, trusting the next 8 bytes to be its header. A payload longer than
+/// `max_len` isn't read at all
+pub(crate) fn read_strict<ITER: Iterator<Item = u8>>(
+    iter: &mut ITER,
+    max_len: usize,
+) -> LenientRead {
+    let start = iter.get(8);
+    let Ok(bytes) = <[u8; 8]>::try_from(start.as_slice()) else {
+        return LenientRead::End {
+            trailing: start.len(),
+        };
+    };
+
+    read_payload(iter, 0, u64::from_be_bytes(bytes).into(), max_len)
+}
+
+/// Reads the payload of a chunk whose header has been read
+fn read_payload<ITER: Iterator<Item = u8>>(
+    iter: &mut ITER,
+    skipped: usize,
+    chunk: Chunk,
+    max_len: usize,
+) -> LenientRead {
+    if chunk.len() > max_len {
+        return LenientRead::TooLong { chunk };
+    }
+
+    let data = iter.get(chunk.len());
+    if data.len() != chunk.len() {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(
+            expected = chunk.len(),
+            available = data.len(),
+            "stream ended partway through a chunk"
+        );
+        return LenientRead::Truncated {
+            skipped,
+            chunk,
+            data,
+        };
+    }
+
+    LenientRead::Chunk {
+        skipped,
+        chunk,
+        data,
+    }
+}
+
+/// Options for the lenient parsing entry points, such as
+/// [`crate::RawMidi::try_from_midi_stream_lenient_with`].
+///
+/// Like every options struct in the crate, it can't be built with a struct literal outside the
+/// crate so that new fields can be added without breaking anyone. Start from the default and
+/// chain the builder methods instead:
+///
+/// ```rust
+/// use miami::reader::ParseOptions;
+///
+/// let options = ParseOptions::default()
+///     .keep_raw(true)
+///     .max_events_per_track(1 << 16)
+///     .max_event_payload(1 << 10);
+/// assert!(options.keep_raw);
+/// assert_eq!(options.max_total_bytes, usize::MAX);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ParseOptions {
+    /// Encoding text meta events are decoded with. Their original bytes are always kept, so text
+    /// that isn't valid in this encoding never fails the parse
+    pub text_encoding: TextEncoding,
+    /// Keeps a copy of every chunk's original payload, available through
+    /// [`crate::chunk::ParsedChunk::raw_payload`], at the cost of holding the file in memory twice
+    pub keep_raw: bool,
+    /// Most bytes of chunk payloads the parse may hold in memory across the whole file, counting
+    /// kept raw payloads a second time. A chunk that would cross it is never read, and the parse
+    /// fails with [`crate::chunk::ChunkParseError::ResourceLimitExceeded`]. Unlimited by default
+    pub max_total_bytes: usize,
+    /// Most events a single track may hold. Parsing stops at the first event past it with
+    /// [`crate::chunk::ChunkParseError::ResourceLimitExceeded`]. Unlimited by default
+    pub max_events_per_track: usize,
+    /// Most payload bytes a single meta or system exclusive event may hold. Lengths far beyond
+    /// what any real event needs are nearly always a corrupted length, so a larger event fails
+    /// with [`TrackError::EventTooLarge`] unless [`ParseProfile::salvage_tracks`] skips it.
+    /// 1 MiB by default
+    pub max_event_payload: u32,
+    /// Records the range of bytes every track event was parsed from, available through
+    /// [`TrackChunk::event_span`], for tools that point back into the file. Off by default
+    pub record_spans: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            text_encoding: TextEncoding::Utf8,
+            keep_raw: false,
+            max_total_bytes: usize::MAX,
+            max_events_per_track: usize::MAX,
+            max_event_payload: 1 << 20,
+            record_spans: false,
+        }
+    }
+}
+
+impl ParseOptions {
+    /// Sets [`ParseOptions::text_encoding`]
+    pub fn text_encoding(mut self, encoding: TextEncoding) -> Self {
+        self.text_encoding = encoding;
+        self
+    }
+
+    /// Sets [`ParseOptions::keep_raw`]
+    pub fn keep_raw(mut self, keep: bool) -> Self {
+        self.keep_raw = keep;
+        self
+    }
+
+    /// Sets [`ParseOptions::max_total_bytes`]
+    pub fn max_total_bytes(mut self, limit: usize) -> Self {
+        self.max_total_bytes = limit;
+        self
+    }
+
+    /// Sets [`ParseOptions::max_events_per_track`]
+    pub fn max_events_per_track(mut self, limit: usize) -> Self {
+        self.max_events_per_track = limit;
+        self
+    }
+
+    /// Sets [`ParseOptions::max_event_payload`]
+    pub fn max_event_payload(mut self, limit: u32) -> Self {
+        self.max_event_payload = limit;
+        self
+    }
+
+    /// Sets [`ParseOptions::record_spans`]
+    pub fn record_spans(mut self, record: bool) -> Self {
+        self.record_spans = record;
+        self
+    }
+}
+
+impl ParseOptions {
+    /// Takes a chunk's payload out of `remaining`, the bytes left of
+    /// [`ParseOptions::max_total_bytes`], charging it twice if raw payloads are kept and `kept`
+    /// is set. Fails if the budget doesn't cover it
+    pub(crate) fn charge(
+        &self,
+        remaining: &mut usize,
+        len: usize,
+        kept: bool,
+    ) -> Result<(), ChunkParseError> {
+        let cost = if kept && self.keep_raw { 2 * len } else { len };
+        *remaining = remaining
+            .checked_sub(cost)
+            .ok_or_else(|| self.total_bytes_exceeded())?;
+        Ok(())
+    }
+
+    /// The error for a file that needs more than [`ParseOptions::max_total_bytes`]
+    pub(crate) fn total_bytes_exceeded(&self) -> ChunkParseError {
+        ChunkParseError::ResourceLimitExceeded {
+            which: ResourceLimit::TotalBytes,
+            limit: self.max_total_bytes,
+        }
+    }
+}
+
+/// How forgiving a parse is of damaged or non-standard files. Start from one of the presets and
+/// override single behaviors with the builder methods, each of which documents what it changes
+/// and the [`ParseWarning`] it reports instead of failing:
+///
+/// ```rust
+/// use miami::prelude::*;
+///
+/// let profile = ParseProfile::strict()
+///     .skip_unknown_chunks(true)
+///     .options(ParseOptions::default().keep_raw(true));
+/// assert_ne!(profile, ParseProfile::strict());
+/// ```
+///
+/// Profiles are used by [`crate::Midi::try_from_midi_stream_with`], which reports the warnings,
+/// [`crate::RawMidi::try_from_midi_stream_with`] and [`crate::Midi::from_path_with`]. The default
+/// is [`ParseProfile::strict`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ParseProfile {
+    /// Text decoding and memory limits
+    pub(crate) options: ParseOptions,
+    /// See [`ParseProfile::skip_garbage`]
+    pub(crate) skip_garbage: bool,
+    /// See [`ParseProfile::skip_unknown_chunks`]
+    pub(crate) skip_unknown_chunks: bool,
+    /// See [`ParseProfile::skip_extra_headers`]
+    pub(crate) skip_extra_headers: bool,
+    /// See [`ParseProfile::drop_truncated_chunks`]
+    pub(crate) drop_truncated_chunks: bool,
+    /// See [`ParseProfile::salvage_tracks`]
+    pub(crate) salvage_tracks: bool,
+    /// See [`ParseProfile::recover_trailing_notes`]
+    pub(crate) recover_trailing_notes: bool,
+    /// See [`ParseProfile::reject_misplaced_conductor_events`]
+    pub(crate) reject_misplaced_conductor_events: bool,
+}
+
+impl ParseProfile {
+    /// Fails on anything that doesn't follow the spec: bytes between chunks, unknown chunk types,
+    /// a second header, a truncated chunk or an event that doesn't parse. Nothing is ever
+    /// skipped, so a strict parse only warns about things that don't stop the file being used:
+    /// a wrong track count, and conductor events outside track 0 unless
+    /// [`ParseProfile::reject_misplaced_conductor_events`] is set
+    pub fn strict() -> Self {
+        Self::default()
+    }
+
+    /// Skips whatever doesn't belong in the file, reporting it as a warning: bytes between
+    /// chunks, unknown chunk types, extra headers and a truncated final chunk. Tracks are still
+    /// required to parse completely
+    pub fn permissive() -> Self {
+        Self::strict()
+            .skip_garbage(true)
+            .skip_unknown_chunks(true)
+            .skip_extra_headers(true)
+            .drop_truncated_chunks(true)
+    }
+
+    /// Like [`ParseProfile::permissive`], but also keeps the events of a track that stops parsing
+    /// partway or is cut off by the end of the stream, and moves notes found after a track's
+    /// `EndOfTrack` back into it
+    pub fn recovering() -> Self {
+        Self::permissive()
+            .salvage_tracks(true)
+            .recover_trailing_notes(true)
+    }
+
+    /// Sets the text decoding and memory limits. Running past a limit fails the parse whatever
+    /// the profile
+    pub fn options(mut self, options: ParseOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Skips bytes that can't start a chunk header, such as alignment padding, reporting
+    /// [`ParseWarning::SkippedBytes`]. Otherwise the next 8 bytes are always read as a chunk
+    /// header, so garbage usually fails as an unknown chunk type, and a few bytes left over at
+    /// the end fail with [`ChunkParseError::Truncated`]
+    pub fn skip_garbage(mut self, skip: bool) -> Self {
+        self.skip_garbage = skip;
+        self
+    }
+
+    /// Skips chunks of a type other than `MThd` and `MTrk`, reporting
+    /// [`ParseWarning::UnknownChunk`]. Otherwise they fail with [`ChunkParseError::UnknownType`]
+    pub fn skip_unknown_chunks(mut self, skip: bool) -> Self {
+        self.skip_unknown_chunks = skip;
+        self
+    }
+
+    /// Skips every header after the first, reporting [`ParseWarning::ExtraHeader`]. Otherwise a
+    /// [`crate::Midi`] can't be built and fails with
+    /// [`crate::MidiSanitizerError::TooManyHeaders`], while a [`crate::RawMidi`] keeps them and
+    /// can be split into separate files with [`crate::RawMidi::split_on_headers`]
+    pub fn skip_extra_headers(mut self, skip: bool) -> Self {
+        self.skip_extra_headers = skip;
+        self
+    }
+
+    /// Drops a chunk the stream ends partway through, reporting [`ParseWarning::TruncatedChunk`].
+    /// Otherwise it fails with [`ChunkParseError::Truncated`]. With
+    /// [`ParseProfile::salvage_tracks`] a truncated track's events are kept instead.
+    ///
+    /// Streams parsed into a [`crate::RawMidi`] through the [`MidiStream`] trait can't tell a
+    /// truncated chunk from the end of the stream, so they always drop it
+    pub fn drop_truncated_chunks(mut self, drop: bool) -> Self {
+        self.drop_truncated_chunks = drop;
+        self
+    }
+
+    /// Keeps every event of a track parsed before one that fails, or before the end of a
+    /// truncated track, closing it with an `EndOfTrack` and reporting
+    /// [`ParseWarning::TrackSalvaged`]. Otherwise the event's error fails the parse. Resource
+    /// limits are never salvaged.
+    ///
+    /// An event over [`ParseOptions::max_event_payload`] is skipped instead, reporting
+    /// [`ParseWarning::OversizedEventSkipped`], and the track carries on from the first later byte
+    /// the rest of it parses from
+    pub fn salvage_tracks(mut self, salvage: bool) -> Self {
+        self.salvage_tracks = salvage;
+        self
+    }
+
+    /// Runs [`TrackChunk::recover_trailing_notes`] on every track with events after its
+    /// `EndOfTrack`, reporting [`ParseWarning::TrailingNotesRecovered`]. Otherwise they're kept
+    /// aside in [`TrackChunk::trailing_events`] without a warning
+    pub fn recover_trailing_notes(mut self, recover: bool) -> Self {
+        self.recover_trailing_notes = recover;
+        self
+    }
+
+    /// Fails a format 1 file with a tempo, time signature or key signature outside track 0 (see
+    /// [`crate::Midi::misplaced_conductor_events`]) with
+    /// [`crate::MidiSanitizerError::MisplacedConductorEvent`]. Otherwise each one is
+    /// reported as [`ParseWarning::MisplacedConductorEvent`], whatever the profile, since plenty
+    /// of files in the wild do this. Only [`crate::Midi::try_from_midi_stream_with`] and the
+    /// functions built on it check, as a [`crate::RawMidi`] isn't checked for structure
+    pub fn reject_misplaced_conductor_events(mut self, reject: bool) -> Self {
+        self.reject_misplaced_conductor_events = reject;
+        self
+    }
+
+    /// Parses a chunk's payload, returning `None` for an unknown chunk that's skipped. `track` is
+    /// the index the chunk gets if it's a track, and `complete` is false for a truncated chunk
+    pub(crate) fn parse_chunk(
+        &self,
+        chunk: Chunk,
+        data: &[u8],
+        complete: bool,
+        track: usize,
+        warnings: &mut Vec<ParseWarning>,
+    ) -> Result<Option<ParsedChunk>, ChunkParseError> {
+        match chunk.chunk_type {
+            HEADER_CHUNK => {}
+            TRACK_DATA_CHUNK if self.salvage_tracks => {
+                let (mut parsed, error, skipped) = TrackChunk::parse_skipping_oversized(
+                    data,
+                    Some(self.options.text_encoding),
+                    self.options.max_events_per_track,
+                    self.options.max_event_payload,
+                );
+                if let Some((_, error @ TrackError::TooManyEvents(_))) = error {
+                    return Err(error.into());
+                }
+                for skipped in &skipped {
+                    if let TrackError::EventTooLarge { declared, limit } = *skipped {
+                        warnings.push(ParseWarning::OversizedEventSkipped {
+                            track,
+                            declared,
+                            limit,
+                        });
+                    }
+                }
+
+                if error.is_some() || !complete || !parsed.ends_with_end_of_track() {
+                    parsed.truncated = !complete;
+                    let events = parsed.mtrk_events.len();
+                    if !parsed.ends_with_end_of_track() {
+                        parsed
+                            .mtrk_events
+                            .push(MTrkEvent::new(0, Event::MetaEvent(MetaEvent::EndOfTrack)));
+                    }
+                    warnings.push(ParseWarning::TrackSalvaged { track, events });
+                    return Ok(Some(self.recover(
+                        ParsedChunk::Track(parsed),
+                        track,
+                        warnings,
+                    )));
+                }
+                // Parsing again would fail on the skipped events
+                if !skipped.is_empty() {
+                    return Ok(Some(self.recover(
+                        ParsedChunk::Track(parsed),
+                        track,
+                        warnings,
+                    )));
+                }
+            }
+            TRACK_DATA_CHUNK => {}
+            chunk_type if self.skip_unknown_chunks => {
+                warnings.push(ParseWarning::UnknownChunk {
+                    chunk_type,
+                    length: data.len(),
+                });
+                return Ok(None);
+            }
+            _ => return Err(ChunkParseError::UnknownType),
+        }
+
+        let parsed = ParsedChunk::parse_with(chunk, data, self.options)?;
+        Ok(Some(self.recover(parsed, track, warnings)))
+    }
+
+    /// Recovers a parsed track's trailing notes if the profile asks for it
+    fn recover(
+        &self,
+        mut parsed: ParsedChunk,
+        track: usize,
+        warnings: &mut Vec<ParseWarning>,
+    ) -> ParsedChunk {
+        if let ParsedChunk::Track(parsed) = &mut parsed {
+            if self.recover_trailing_notes && !parsed.trailing_events.is_empty() {
+                let before = parsed.mtrk_events.len();
+                parsed.recover_trailing_notes();
+                warnings.push(ParseWarning::TrailingNotesRecovered {
+                    track,
+                    events: parsed.mtrk_events.len() - before,
+                });
+            }
+        }
+
+        parsed
+    }
+}
+
+/// Parses strictly with the given options
+impl From<ParseOptions> for ParseProfile {
+    fn from(options: ParseOptions) -> Self {
+        Self::strict().options(options)
+    }
+}
+
+/// Error yielded while walking the chunk boundaries of a byte slice
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamError {
+    /// Fewer than 8 bytes remained where a chunk header was expected
+    TruncatedHeader {
+        /// Offset of the partial chunk header
+        offset: usize,
+    },
+    /// The chunk header declared more payload than the slice holds
+    TruncatedPayload {
+        /// Offset of the chunk's header
+        offset: usize,
+        /// Payload length declared by the chunk header
+        expected: usize,
+        /// Payload bytes actually left in the slice
+        available: usize,
+    },
+}
+
+impl core::error::Error for StreamError {}
+impl core::fmt::Display for StreamError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::TruncatedHeader { offset } => {
+                write![f, "Truncated chunk header at byte {offset}"]
+            }
+            Self::TruncatedPayload {
+                offset,
+                expected,
+                available,
+            } => write![
+                f,
+                "Chunk at byte {offset} declares {expected} bytes but only {available} remain"
+            ],
+        }
+    }
+}
+
+/// Walks the chunk boundaries of an in memory MIDI file without parsing or copying any payloads.
+/// Each item is a chunk header alongside a borrowed slice of its data, which can be handed to
+/// [`crate::chunk::ParsedChunk::parse`] or re-emitted verbatim. A truncated final chunk yields a
+/// single error, after which the iterator ends
+pub fn chunks(bytes: &[u8]) -> impl Iterator<Item = Result<(Chunk, &[u8]), StreamError>> {
+    let mut offset = 0;
+    let mut done = false;
+
+    core::iter::from_fn(move || {
+        if done || offset == bytes.len() {
+            return None;
+        }
+
+        let Some(header) = bytes.get(offset..offset + 8) else {
+            done = true;
+            return Some(Err(StreamError::TruncatedHeader { offset }));
+        };
+
+        // UNWRAP Safety: The header slice is exactly 8 bytes
+        let chunk: Chunk = u64::from_be_bytes(header.try_into().unwrap()).into();
+        let start = offset + 8;
+        let available = bytes.len() - start;
+
+        if chunk.len() > available {
+            done = true;
+            return Some(Err(StreamError::TruncatedPayload {
+                offset,
+                expected: chunk.len(),
+                available,
+            }));
+        }
+
+        let data = &bytes[start..start + chunk.len()];
+        offset = start + chunk.len();
+        Some(Ok((chunk, data)))
+    })
+}
+
+/// The type bytes of the chunks a file is made of, which a layout scan resynchronizes on
+const SIGNATURES: [&[u8; 4]; 2] = [b"MThd", b"MTrk"];
+
+/// Offset of the first `MThd` or `MTrk` signature in `bytes[from..end]`
+pub(crate) fn find_signature(bytes: &[u8], from: usize, end: usize) -> Option<usize> {
+    bytes
+        .get(from..end)?
+        .windows(4)
+        .position(|window| SIGNATURES.iter().any(|signature| window == *signature))
+        .map(|idx| from + idx)
+}
+
+/// One region of a file found by [`inspect`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayoutEntry {
+    /// Something shaped like a chunk: a header with a printable type
+    Chunk {
+        /// Offset of the chunk header
+        offset: usize,
+        /// The chunk type exactly as stored
+        chunk_type: [u8; 4],
+        /// Payload length declared by the header
+        declared_len: usize,
+        /// Payload bytes actually present, less than `declared_len` if the file ends first
+        available: usize,
+        /// Why the parser rejects the chunk, or `None` if it accepts it
+        error: Option<ChunkParseError>,
+    },
+    /// Bytes that can't start a chunk header, up to the next `MThd` or `MTrk` signature
+    Unrecognized {
+        /// Offset of the first byte
+        offset: usize,
+        /// How many bytes were passed over
+        len: usize,
+    },
+}
+
+impl LayoutEntry {
+    /// Offset of the region in the file
+    pub fn offset(&self) -> usize {
+        match self {
+            Self::Chunk { offset, .. } | Self::Unrecognized { offset, .. } => *offset,
+        }
+    }
+
+    /// Whether the region is a chunk the parser accepts
+    pub fn accepted(&self) -> bool {
+        matches!(self, Self::Chunk { error: None, .. })
+    }
+}
+
+/// The chunk layout of a file as found by [`inspect`], which displays as a table
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LayoutReport {
+    /// Every region found, in file order
+    pub entries: Vec<LayoutEntry>,
+}
+
+impl core::fmt::Display for LayoutReport {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        writeln![
+            f,
+            "{:>10}  {:<16}  {:>10}  {:>10}  status",
+            "offset", "type", "declared", "available"
+        ]?;
+
+        for entry in &self.entries {
+            match entry {
+                LayoutEntry::Chunk {
+                    offset,
+                    chunk_type,
+                    declared_len,
+                    available,
+                    error,
+                } => {
+                    let chunk_type = chunk_type.escape_ascii().to_string();
+                    write![
+                        f,
+                        "{offset:>10}  {chunk_type:<16}  {declared_len:>10}  {available:>10}  "
+                    ]?;
+                    match error {
+                        None => writeln![f, "ok"]?,
+                        Some(error) => writeln![f, "{error}"]?,
+                    }
+                }
+                LayoutEntry::Unrecognized { offset, len } => writeln![
+                    f,
+                    "{offset:>10}  {:<16}  {:>10}  {len:>10}  unrecognized bytes",
+                    "-", "-"
+                ]?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Reports the chunk layout of a file for debugging one that won't parse: where each chunk
+/// starts, its type, how long it claims to be, how much of it is really there and whether the
+/// parser accepts it. This never fails and is separate from parsing.
+///
+/// Declared lengths are followed as long as they fit in the file. When one doesn't, or a
+/// rejected chunk has an `MThd` or `MTrk` signature inside its payload, or the bytes where a
+/// header should be can't start one, the scan resynchronizes on the next signature
+///
+/// ```rust
+/// use miami::reader::{inspect, LayoutEntry};
+///
+/// let mut bytes = std::fs::read("test/test.mid").unwrap();
+/// bytes[18] = 0x7F;
+///
+/// let report = inspect(&bytes);
+/// assert!(report.entries[0].accepted());
+/// assert!(matches!(report.entries[1], LayoutEntry::Chunk { offset: 14, error: Some(_), .. }));
+/// println!("{report}");
+/// ```
+pub fn inspect(bytes: &[u8]) -> LayoutReport {
+    let mut report = LayoutReport::default();
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        let header = bytes
+            .get(offset..offset + 8)
+            .and_then(|header| Chunk::try_from_bytes(header.try_into().ok()?).ok());
+
+        let Some(chunk) = header else {
+            let next = find_signature(bytes, offset + 1, bytes.len()).unwrap_or(bytes.len());
+            report.entries.push(LayoutEntry::Unrecognized {
+                offset,
+                len: next - offset,
+            });
+            offset = next;
+            continue;
+        };
+
+        let start = offset + 8;
+        let available = chunk.len().min(bytes.len() - start);
+        let data = &bytes[start..start + available];
+
+        let (error, next) = if available < chunk.len() {
+            let error = ChunkParseError::Truncated {
+                expected: chunk.len(),
+                available,
+            };
+            (Some(error), find_signature(bytes, start, bytes.len()))
+        } else {
+            match ParsedChunk::parse(chunk, data) {
+                Ok(_) => (None, None),
+                Err(error) => (Some(error), find_signature(bytes, start, start + available)),
+            }
+        };
+
+        report.entries.push(LayoutEntry::Chunk {
+            offset,
+            // UNWRAP Safety: The header was read from these 8 bytes
+            chunk_type: bytes[offset..offset + 4].try_into().unwrap(),
+            declared_len: chunk.len(),
+            available,
+            error,
+        });
+        offset = next.unwrap_or(start + available);
+    }
+
+    report
+}
+
+/// Whether a chunk at `offset` declaring `len` bytes of payload fits in `bytes` and is followed
+/// by another chunk header or the end of the data. A final fragment too short to be a header
+/// counts as the end
+fn plausible_chunk(bytes: &[u8], offset: usize, len: usize) -> bool {
+    let end = offset + 8 + len;
+    match bytes.get(end..) {
+        None => false,
+        Some(rest) if rest.len() < 8 => true,
+        // UNWRAP Safety: The slice is exactly 8 bytes
+        Some(rest) => Chunk::try_from_bytes(rest[..8].try_into().unwrap()).is_ok(),
+    }
+}
+
+/// Splits data holding several complete MIDI files back to back, such as a capture dump, into
+/// the bytes of each file. Anything before the first `MThd` is dropped.
+///
+/// Chunk lengths are followed from one header to the next, so the bytes `MThd` inside a payload
+/// aren't mistaken for a new file; only an `MThd` chunk header starts one. When a length runs
+/// past the data or doesn't land on another chunk header, the split falls back to scanning for
+/// the next `MThd` or `MTrk` signature
+pub fn split_concatenated(bytes: &[u8]) -> Vec<&[u8]> {
+    let mut files = vec![];
+    let Some(mut start) = bytes.windows(4).position(|window| window == SIGNATURES[0]) else {
+        return files;
+    };
+
+    let mut offset = start;
+    while let Some(header) = bytes.get(offset..offset + 8) {
+        if header[..4] == *SIGNATURES[0] && offset != start {
+            files.push(&bytes[start..offset]);
+            start = offset;
+        }
+
+        // UNWRAP Safety: The header slice is exactly 8 bytes
+        let chunk: Chunk = u64::from_be_bytes(header.try_into().unwrap()).into();
+        offset = if plausible_chunk(bytes, offset, chunk.len()) {
+            offset + 8 + chunk.len()
+        } else {
+            find_signature(bytes, offset + 1, bytes.len()).unwrap_or(bytes.len())
+        };
+    }
+
+    files.push(&bytes[start..]);
+    files
+}
+
+impl Midi {
+    /// Parses every file in data holding several back to back, as split by
+    /// [`split_concatenated`]. Each file succeeds or fails on its own
+    pub fn parse_all(bytes: &[u8]) -> Vec<Result<Midi, MidiError>> {
+        split_concatenated(bytes)
+            .into_iter()
+            .map(|file| {
+                let raw = RawMidi::try_from_midi_stream(file.iter().copied())?;
+                Ok(Midi::try_from(raw)?)
+            })
+            .collect()
+    }
 }
 
 /// Trait that allows for different types to be translated to a MIDI parseable format
@@ -105,7 +1087,213 @@ where
 
 #[cfg(test)]
 mod tests {
-    use super::MidiReadable;
+    use std::io::{Cursor, Read};
+
+    use super::{
+        chunks, inspect, split_concatenated, LayoutEntry, MidiReadable, MidiStream, StreamError,
+    };
+    use crate::{
+        chunk::{
+            chunk_types::{HEADER_CHUNK, TRACK_DATA_CHUNK},
+            header::Division,
+            track::{meta::MetaEvent, Event, MTrkEvent, TrackChunk},
+            ChunkParseError, ParsedChunk,
+        },
+        writer::MidiWriteable,
+        InvalidChunkHeader, Midi, RawMidi,
+    };
+
+    #[test]
+    fn boxed_streams_parse_like_their_contents() {
+        let bytes: Vec<u8> = "test/test4tracks.mid".get_midi_bytes().unwrap().collect();
+        let expected = RawMidi::try_from_midi_stream(bytes.clone().into_iter()).unwrap();
+
+        let sources: Vec<Box<dyn MidiStream>> = vec![
+            Box::new("test/test4tracks.mid".get_midi_bytes().unwrap()),
+            Box::new(Cursor::new(bytes.clone()).bytes().map_while(Result::ok)),
+            Box::new(bytes.iter().copied()),
+        ];
+
+        for mut source in sources {
+            let mut garbage = vec![0x00, 0x00];
+            garbage.extend(&bytes);
+            let mut padded: Box<dyn MidiStream> = Box::new(garbage.into_iter());
+
+            assert_eq!(
+                RawMidi::try_from_midi_stream_lenient(&mut *padded).unwrap(),
+                expected
+            );
+            assert_eq!(
+                RawMidi::try_from_midi_stream(&mut *source).unwrap(),
+                expected
+            );
+            assert_eq!(source.read_chunk_data_pair(), None);
+        }
+
+        let boxed: Box<dyn MidiStream> = Box::new(Cursor::new(bytes).bytes().map_while(Result::ok));
+        assert_eq!(RawMidi::try_from_midi_stream(boxed).unwrap(), expected);
+    }
+
+    #[test]
+    fn checked_read_rejects_garbage_header() {
+        let mut garbage = [0x00, 0x01, 0x02, 0x03, 0xFF, 0xFF, 0xFF, 0xFF, 0x00].into_iter();
+        assert_eq!(
+            garbage.read_chunk_data_pair_checked(),
+            Some(Err(InvalidChunkHeader {
+                bytes: [0x00, 0x01, 0x02, 0x03, 0xFF, 0xFF, 0xFF, 0xFF]
+            }))
+        );
+
+        let mut midi = "test/test.mid".get_midi_bytes().unwrap();
+        let (chunk, data) = midi.read_chunk_data_pair_checked().unwrap().unwrap();
+        assert_eq!(chunk.chunk_type, HEADER_CHUNK);
+        assert_eq!(data.len(), 6);
+    }
+
+    #[test]
+    fn chunk_slices_match_fixture_offsets() {
+        let bytes: Vec<u8> = "test/test4tracks.mid".get_midi_bytes().unwrap().collect();
+        let pairs: Vec<_> = chunks(&bytes).collect::<Result<_, _>>().unwrap();
+
+        assert_eq!(pairs.len(), 2);
+
+        let (header, header_data) = pairs[0];
+        assert_eq!(header.chunk_type, HEADER_CHUNK);
+        assert_eq!(header_data, &bytes[8..14]);
+
+        let (track, track_data) = pairs[1];
+        assert_eq!(track.chunk_type, TRACK_DATA_CHUNK);
+        assert_eq!(track.len(), 0x59);
+        assert_eq!(track_data, &bytes[22..111]);
+
+        assert!(matches!(
+            ParsedChunk::parse(track, track_data),
+            Ok(ParsedChunk::Track(_))
+        ));
+    }
+
+    #[test]
+    fn truncated_final_chunk_yields_one_error() {
+        let bytes: Vec<u8> = "test/test4tracks.mid".get_midi_bytes().unwrap().collect();
+        let truncated = &bytes[..bytes.len() - 10];
+
+        let items: Vec<_> = chunks(truncated).collect();
+        assert_eq!(items.len(), 2);
+        assert!(items[0].is_ok());
+        assert_eq!(
+            items[1],
+            Err(StreamError::TruncatedPayload {
+                offset: 14,
+                expected: 0x59,
+                available: 0x59 - 10,
+            })
+        );
+
+        let items: Vec<_> = chunks(&bytes[..18]).collect();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1], Err(StreamError::TruncatedHeader { offset: 14 }));
+    }
+
+    #[test]
+    fn layout_scan_resynchronizes_after_bad_regions() {
+        let bytes: Vec<u8> = "test/test4tracks.mid".get_midi_bytes().unwrap().collect();
+        let track = &bytes[14..];
+
+        // Header, 3 bytes of garbage, a track claiming far more than the file holds, then an
+        // intact track
+        let mut corrupt = bytes[..14].to_vec();
+        corrupt.extend([0x00, 0xFF, 0x00]);
+        corrupt.extend(&track[..4]);
+        corrupt.extend(0x00FF_FFFFu32.to_be_bytes());
+        corrupt.extend(&track[8..]);
+        corrupt.extend(track);
+
+        let report = inspect(&corrupt);
+        assert_eq!(report.entries.len(), 4);
+        assert!(report.entries[0].accepted());
+        assert_eq!(
+            report.entries[1],
+            LayoutEntry::Unrecognized { offset: 14, len: 3 }
+        );
+        assert_eq!(
+            report.entries[2],
+            LayoutEntry::Chunk {
+                offset: 17,
+                chunk_type: *b"MTrk",
+                declared_len: 0x00FF_FFFF,
+                available: corrupt.len() - 25,
+                error: Some(ChunkParseError::Truncated {
+                    expected: 0x00FF_FFFF,
+                    available: corrupt.len() - 25,
+                }),
+            }
+        );
+        assert_eq!(
+            report.entries[3],
+            LayoutEntry::Chunk {
+                offset: 17 + track.len(),
+                chunk_type: *b"MTrk",
+                declared_len: 0x59,
+                available: 0x59,
+                error: None,
+            }
+        );
+
+        let table = report.to_string();
+        assert_eq!(table.lines().count(), 5);
+        assert!(table.contains("unrecognized bytes"));
+
+        let mut odd_type = bytes.clone();
+        odd_type[14..18].copy_from_slice(b"MT\"k");
+        let report = inspect(&odd_type);
+        assert_eq!(report.entries.len(), 2);
+        assert!(report.to_string().contains(r#"MT\"k"#));
+    }
+
+    #[test]
+    fn concatenated_files_split_on_chunk_boundaries() {
+        let first: Vec<u8> = "test/test.mid".get_midi_bytes().unwrap().collect();
+        let second: Vec<u8> = "test/test4tracks.mid".get_midi_bytes().unwrap().collect();
+        let mut bytes = vec![0x00, 0x00];
+        bytes.extend(&first);
+        bytes.extend(&second);
+
+        assert_eq!(split_concatenated(&bytes), [&first[..], &second[..]]);
+
+        let parsed = Midi::parse_all(&bytes);
+        assert_eq!(parsed.len(), 2);
+        for (midi, file) in parsed.into_iter().zip([first, second]) {
+            let expected = RawMidi::try_from_midi_stream(file.into_iter()).unwrap();
+            assert_eq!(midi.unwrap(), Midi::try_from(expected).unwrap());
+        }
+    }
+
+    #[test]
+    fn signatures_inside_payloads_dont_start_files() {
+        let track = TrackChunk::new(vec![
+            MTrkEvent::new(
+                0,
+                Event::MetaEvent(MetaEvent::TrackName("MThd\0\0\0\x06 in a name".into())),
+            ),
+            MTrkEvent::new(0, Event::MetaEvent(MetaEvent::EndOfTrack)),
+        ]);
+        let named = Midi::new(vec![track], Division::TPQN_480).to_midi_bytes();
+        let plain: Vec<u8> = "test/test.mid".get_midi_bytes().unwrap().collect();
+
+        let mut bytes = named.clone();
+        bytes.extend(&plain);
+        assert_eq!(split_concatenated(&bytes), [&named[..], &plain[..]]);
+        assert!(Midi::parse_all(&bytes).iter().all(Result::is_ok));
+
+        // A track length running past the data falls back to scanning for the next signature
+        let mut corrupt = plain.clone();
+        corrupt[18] = 0x7F;
+        corrupt.extend(&named);
+        let split = split_concatenated(&corrupt);
+        assert_eq!(split, [&corrupt[..plain.len()], &named[..]]);
+        assert!(Midi::parse_all(&corrupt)[1].is_ok());
+        assert!(split_concatenated(&[0x00; 16]).is_empty());
+    }
 
     #[test]
     fn midi_files_stream() {
@@ -114,4 +1302,184 @@ mod tests {
 
         assert!(data.is_ok())
     }
+
+    /// Memory limits, checked with an allocator that records the largest allocation each thread
+    /// makes
+    mod limits {
+        use std::{
+            alloc::{GlobalAlloc, Layout, System},
+            cell::Cell,
+        };
+
+        use crate::{
+            chunk::{ChunkParseError, ResourceLimit},
+            reader::{ParseOptions, ParseProfile},
+            Midi, RawMidi,
+        };
+
+        /// Records the size of the largest allocation made on the current thread
+        struct Recording;
+
+        thread_local! {
+            static LARGEST: Cell<usize> = const { Cell::new(0) };
+        }
+
+        /// Notes an allocation of `size` bytes
+        fn record(size: usize) {
+            let _ = LARGEST.try_with(|largest| largest.set(largest.get().max(size)));
+        }
+
+        unsafe impl GlobalAlloc for Recording {
+            unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+                record(layout.size());
+                unsafe { System.alloc(layout) }
+            }
+
+            unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+                unsafe { System.dealloc(ptr, layout) }
+            }
+
+            unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+                record(new_size);
+                unsafe { System.realloc(ptr, layout, new_size) }
+            }
+        }
+
+        #[global_allocator]
+        static ALLOCATOR: Recording = Recording;
+
+        /// Runs `f`, returning its result and the largest allocation it made
+        fn largest_allocation<T>(f: impl FnOnce() -> T) -> (T, usize) {
+            LARGEST.with(|largest| largest.set(0));
+            let result = f();
+            (result, LARGEST.with(Cell::get))
+        }
+
+        const HEADER: [u8; 14] = [
+            b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0x00, 0x01, 0x00, 0x01, 0x00, 0x60,
+        ];
+
+        fn track(payload: &[u8]) -> Vec<u8> {
+            let mut bytes = b"MTrk".to_vec();
+            bytes.extend((payload.len() as u32).to_be_bytes());
+            bytes.extend(payload);
+            bytes
+        }
+
+        fn limited(max_total_bytes: usize, max_events_per_track: usize) -> ParseOptions {
+            ParseOptions {
+                max_total_bytes,
+                max_events_per_track,
+                ..Default::default()
+            }
+        }
+
+        #[test]
+        fn huge_chunk_is_never_read() {
+            // Claims a 256 MiB track and has the bytes to back it up, but is never asked for them
+            let stream = || {
+                HEADER
+                    .into_iter()
+                    .chain(*b"MTrk\x10\x00\x00\x00")
+                    .chain(std::iter::repeat(0x00))
+            };
+
+            let (result, largest) = largest_allocation(|| {
+                RawMidi::try_from_midi_stream_lenient_with(stream(), limited(4096, usize::MAX))
+            });
+            assert!(matches!(
+                result,
+                Err(ChunkParseError::ResourceLimitExceeded {
+                    which: ResourceLimit::TotalBytes,
+                    limit: 4096
+                })
+            ));
+            assert!(largest < 4096, "allocated {largest} bytes");
+
+            let (result, largest) = largest_allocation(|| {
+                Midi::try_from_midi_stream_lenient(stream(), limited(4096, usize::MAX))
+            });
+            assert!(result.is_err());
+            assert!(largest < 4096, "allocated {largest} bytes");
+
+            // Strict parsing trusts the chunk header, but still checks its length first
+            let (result, largest) = largest_allocation(|| {
+                RawMidi::try_from_midi_stream_with(
+                    stream(),
+                    ParseProfile::strict().options(limited(4096, usize::MAX)),
+                )
+            });
+            assert!(matches!(
+                result,
+                Err(ChunkParseError::ResourceLimitExceeded {
+                    which: ResourceLimit::TotalBytes,
+                    limit: 4096
+                })
+            ));
+            assert!(largest < 4096, "allocated {largest} bytes");
+        }
+
+        #[test]
+        fn many_small_chunks_add_up() {
+            let end_of_track = [0x00, 0xFF, 0x2F, 0x00];
+            let mut file = HEADER.to_vec();
+            for _ in 0..100 {
+                file.extend(track(&end_of_track));
+            }
+
+            // 6 header bytes and 100 tracks of 4
+            let parse = |max_total_bytes, keep_raw| {
+                RawMidi::try_from_midi_stream_lenient_with(
+                    file.clone().into_iter(),
+                    ParseOptions {
+                        keep_raw,
+                        ..limited(max_total_bytes, usize::MAX)
+                    },
+                )
+            };
+            assert_eq!(parse(406, false).unwrap().chunks.len(), 101);
+            assert!(matches!(
+                parse(405, false),
+                Err(ChunkParseError::ResourceLimitExceeded {
+                    which: ResourceLimit::TotalBytes,
+                    ..
+                })
+            ));
+            assert!(parse(811, true).is_err());
+            assert!(parse(812, true).is_ok());
+        }
+
+        #[test]
+        fn event_limit_stops_the_track_early() {
+            // 100,000 Note Ons followed by an End of Track
+            let mut payload = [0x00, 0x90, 0x3C, 0x64].repeat(100_000);
+            payload.extend([0x00, 0xFF, 0x2F, 0x00]);
+            let mut file = HEADER.to_vec();
+            file.extend(track(&payload));
+
+            let parse = |max_events_per_track| {
+                largest_allocation(|| {
+                    RawMidi::try_from_midi_stream_lenient_with(
+                        file.iter().copied(),
+                        limited(usize::MAX, max_events_per_track),
+                    )
+                })
+            };
+
+            let (result, unlimited) = parse(100_001);
+            assert!(result.is_ok());
+
+            let (result, largest) = parse(1000);
+            assert!(matches!(
+                result,
+                Err(ChunkParseError::ResourceLimitExceeded {
+                    which: ResourceLimit::EventsPerTrack,
+                    limit: 1000
+                })
+            ));
+            // The payload itself is still read, but the events parsed from it stay small
+            assert!(largest <= payload.len(), "allocated {largest} bytes");
+            assert!(unlimited > 4 * payload.len());
+        }
+    }
 }
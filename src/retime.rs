@@ -0,0 +1,198 @@
+//! Rescaling a file's ticks to a different division
+
+use crate::{
+    chunk::{
+        header::Division,
+        track::{MTrkEvent, TrackChunk},
+    },
+    time::Tick,
+    Midi,
+};
+
+/// Error from [`Midi::retime_to_division`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetimeError {
+    /// One of the divisions is time-code-based. Converting between time codes and beats depends
+    /// on the tempo, so only metrical divisions can be retimed
+    TimeCodeDivision,
+    /// One of the divisions has 0 ticks per quarter note
+    ZeroTicksPerQuarter,
+    /// The gap before the event at this rescaled tick is larger than a delta time can express
+    DeltaOverflow(Tick),
+}
+
+impl core::error::Error for RetimeError {}
+impl core::fmt::Display for RetimeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::TimeCodeDivision => {
+                write![f, "Only metrical divisions can be retimed without a tempo"]
+            }
+            Self::ZeroTicksPerQuarter => write![f, "Division has 0 ticks per quarter note"],
+            Self::DeltaOverflow(tick) => write![
+                f,
+                "Delta time before the event at rescaled tick {tick} is too large"
+            ],
+        }
+    }
+}
+
+/// Rescales a track's delta times by `new / old`. Each event's absolute tick is rounded to the
+/// nearest rescaled tick rather than each delta time on its own, so rounding errors never add up
+fn retime_track(track: &TrackChunk, old: u64, new: u64) -> Result<TrackChunk, RetimeError> {
+    let mut tick = 0u64;
+    let mut rescaled = Tick::ZERO;
+    let mut retime = |events: &[MTrkEvent]| {
+        events
+            .iter()
+            .map(|mtrk_event| {
+                tick += mtrk_event.delta_time().get() as u64;
+                let next = (tick as u128 * new as u128 + old as u128 / 2) / old as u128;
+                let next = Tick::new(next as u64);
+
+                let delta_time = next
+                    .delta_since(rescaled)
+                    .ok_or(RetimeError::DeltaOverflow(next))?;
+                rescaled = next;
+
+                Ok(MTrkEvent::new(delta_time, mtrk_event.event().clone()))
+            })
+            .collect::<Result<Vec<_>, _>>()
+    };
+
+    let mtrk_events = retime(&track.mtrk_events)?;
+    // Trailing delta times carry on from the end of the track
+    let trailing_events = retime(&track.trailing_events)?;
+
+    Ok(TrackChunk {
+        trailing_events,
+        id: track.id,
+        truncated: track.truncated,
+        ..TrackChunk::new(mtrk_events)
+    })
+}
+
+impl Midi {
+    /// Rescales every delta time so the file plays the same with a different number of ticks
+    /// per quarter note, then sets the header's division to `new`. Every event lands within half
+    /// a tick of its exact rescaled position, however long the track.
+    ///
+    /// Both the current and new division must be [`Division::Metrical`], since converting to or
+    /// from time-code-based ticks would depend on the tempo; anything else fails with
+    /// [`RetimeError::TimeCodeDivision`] unless the division is unchanged. Nothing is modified if
+    /// an error is returned
+    pub fn retime_to_division(&mut self, new: Division) -> Result<(), RetimeError> {
+        let old = self.header.division();
+        if old == new {
+            return Ok(());
+        }
+
+        let (Some(old_tpq), Some(new_tpq)) = (old.ticks_per_quarter(), new.ticks_per_quarter())
+        else {
+            return Err(RetimeError::TimeCodeDivision);
+        };
+        if old_tpq == 0 || new_tpq == 0 {
+            return Err(RetimeError::ZeroTicksPerQuarter);
+        }
+
+        let tracks = self
+            .tracks
+            .iter()
+            .map(|track| retime_track(track, old_tpq as u64, new_tpq as u64))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.tracks = tracks;
+        self.header.division = new;
+        self.header.raw_payload = None;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RetimeError;
+    use crate::{
+        chunk::{
+            header::{Division, HeaderChunk},
+            track::{
+                event::{MidiEvent, NoteMeta},
+                meta::MetaEvent,
+                Event, TrackChunk,
+            },
+        },
+        Midi,
+    };
+
+    /// Ticks chosen so that neither 96 nor 480 ticks per quarter divides them evenly
+    const TICKS: [u64; 12] = [0, 1, 2, 7, 13, 50, 97, 101, 479, 481, 1003, 4099];
+
+    fn midi(division: u16) -> Midi {
+        let notes = TICKS.iter().enumerate().map(|(idx, &tick)| {
+            let note = NoteMeta {
+                key: 60 + idx as u8,
+                velocity: 100,
+            };
+            (tick, Event::MidiEvent(MidiEvent::NoteOn(0, note)))
+        });
+        let track = TrackChunk::from_absolute_events(
+            notes.chain([(4099, Event::MetaEvent(MetaEvent::EndOfTrack))]),
+        )
+        .unwrap();
+
+        Midi {
+            header: HeaderChunk::try_from((0, 1, division)).unwrap(),
+            tracks: vec![track.clone(), track],
+        }
+    }
+
+    /// Asserts every event moved to within a tick of its exact rescaled position
+    fn assert_within_a_tick(old: u64, new: u64) {
+        let mut midi = midi(old as u16);
+        midi.retime_to_division(Division::Metrical(new as u16))
+            .unwrap();
+
+        assert_eq!(midi.header.division(), Division::Metrical(new as u16));
+        for track in &midi.tracks {
+            let ticks: Vec<u64> = track
+                .absolute_events()
+                .map(|(tick, _)| tick.get())
+                .collect();
+            assert_eq!(ticks.len(), TICKS.len() + 1);
+
+            for (rescaled, original) in ticks.into_iter().zip(TICKS) {
+                let error = (rescaled * old).abs_diff(original * new);
+                assert!(error <= old, "{original} became {rescaled}");
+            }
+        }
+    }
+
+    #[test]
+    fn upscale_stays_within_a_tick() {
+        assert_within_a_tick(96, 480);
+    }
+
+    #[test]
+    fn downscale_stays_within_a_tick() {
+        assert_within_a_tick(480, 96);
+    }
+
+    #[test]
+    fn time_codes_and_empty_divisions_are_rejected() {
+        let mut midi = midi(96);
+        let original = midi.clone();
+
+        assert_eq!(
+            midi.retime_to_division(Division::from(0xE728)),
+            Err(RetimeError::TimeCodeDivision)
+        );
+        assert_eq!(
+            midi.retime_to_division(Division::Metrical(0)),
+            Err(RetimeError::ZeroTicksPerQuarter)
+        );
+        assert_eq!(midi, original);
+
+        midi.retime_to_division(Division::Metrical(96)).unwrap();
+        assert_eq!(midi, original);
+    }
+}
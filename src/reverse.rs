@@ -0,0 +1,399 @@
+//! Playing a file backwards by mirroring its events around the end of the file
+
+use std::collections::BTreeMap;
+
+use crate::{
+    chunk::{
+        header::Format,
+        track::{
+            editor::{EditError, TrackEditor},
+            event::{MidiEvent, NoteMeta},
+            meta::MetaEvent,
+            Event, TrackChunk,
+        },
+    },
+    slice::NoteChange,
+    time::Tick,
+    timeline::{BANK_SELECT_LSB, BANK_SELECT_MSB},
+    Midi,
+};
+
+/// How [`Midi::reverse_with`] treats the conductor events
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ReverseOptions {
+    /// Mirrors tempo, time signature and key signature events like controllers, rather than
+    /// keeping them at their original ticks. Off by default
+    pub mirror_conductor: bool,
+}
+
+impl ReverseOptions {
+    /// Sets [`ReverseOptions::mirror_conductor`]
+    pub fn mirror_conductor(mut self, mirror: bool) -> Self {
+        self.mirror_conductor = mirror;
+        self
+    }
+}
+
+/// Where an event lands among the others at its tick once reversed. Notes that end at a tick
+/// release before the events and notes that start there, so a key struck again right away isn't
+/// cut off, while a note with no length keeps its Note On before its Note Off
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Slot {
+    /// The Note Off of a note with a length
+    Release,
+    /// Any event other than a note, in its original order
+    Other,
+    /// A Note On
+    Strike,
+    /// The Note Off of a note without a length
+    EmptyRelease,
+}
+
+impl Midi {
+    /// Reverses the file in time with the default [`ReverseOptions`], see
+    /// [`Midi::reverse_with`]
+    pub fn reverse(&mut self) -> Result<(), EditError> {
+        self.reverse_with(ReverseOptions::default())
+    }
+
+    /// Mirrors every track around the end of the file, so it plays backwards:
+    ///
+    /// - Each note from [`TrackChunk::notes`] is struck at `duration - end` and released at
+    ///   `duration - start`. Release velocities aren't kept, and a Note Off without a Note On is
+    ///   dropped
+    /// - Control changes, pitch bends and aftertouch move from `tick` to `duration - tick`, in
+    ///   their original order at each tick. The sustain pedal is mirrored point-wise like any
+    ///   other controller, so its presses and lifts trade places
+    /// - Program changes with the bank selects before them move with the stretch of the track
+    ///   they apply to: a change at `tick` that lasts until the channel's next change at `next`
+    ///   moves to `duration - next`, and the channel's last change moves to the start. Every
+    ///   note keeps the instrument it was played with, except notes before a channel's first
+    ///   change, which now follow its first instrument. Each track's changes are mirrored on
+    ///   their own, so changes in one track don't move another's
+    /// - System exclusive and meta events stay where they are. Tempo, time signature and key
+    ///   signature events are only mirrored with [`ReverseOptions::mirror_conductor`]
+    ///
+    /// The duration is [`Midi::duration`], and every track ends there once reversed. Format 2
+    /// patterns are independent, so each is mirrored around its own end instead. Reversing twice
+    /// gives back the same notes and controller curves. If any track can't be rebuilt, nothing
+    /// is changed
+    pub fn reverse_with(&mut self, options: ReverseOptions) -> Result<(), EditError> {
+        let duration = self.duration();
+        let independent = self.header.format() == Format::Two;
+
+        let reversed = self
+            .tracks
+            .iter()
+            .map(|track| {
+                let end = if independent {
+                    track.duration()
+                } else {
+                    duration
+                };
+                reverse_track(track.clone(), end, options)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.tracks = reversed;
+
+        Ok(())
+    }
+}
+
+/// Mirrors a track's events around `end`, see [`Midi::reverse_with`]
+fn reverse_track(
+    track: TrackChunk,
+    end: Tick,
+    options: ReverseOptions,
+) -> Result<TrackChunk, EditError> {
+    let mirror = |tick: Tick| Tick(end.get() - tick.get());
+    let mut events: Vec<(Tick, Slot, Event)> = vec![];
+
+    for note in track.notes() {
+        let release = if note.start == note.end {
+            Slot::EmptyRelease
+        } else {
+            Slot::Release
+        };
+        let meta = |velocity| NoteMeta {
+            key: note.key,
+            velocity,
+        };
+
+        events.push((
+            mirror(note.end),
+            Slot::Strike,
+            Event::MidiEvent(MidiEvent::NoteOn(note.channel, meta(note.velocity))),
+        ));
+        events.push((
+            mirror(note.start),
+            release,
+            Event::MidiEvent(MidiEvent::NoteOff(note.channel, meta(0))),
+        ));
+    }
+
+    // Each channel's instrument changes in order, a program change with the bank selects
+    // before it, and the bank selects still waiting for their program change
+    let mut instruments: BTreeMap<u8, Vec<(Tick, Vec<Event>)>> = BTreeMap::new();
+    let mut pending: BTreeMap<u8, (Tick, Vec<Event>)> = BTreeMap::new();
+
+    let mut editor = TrackEditor::new(track);
+    for (tick, event) in core::mem::take(editor.events_mut()) {
+        if NoteChange::of(&event).is_some() {
+            continue;
+        }
+
+        if let Some((channel, is_program)) = instrument_change(&event) {
+            let (_, mut change) = pending.remove(&channel).unwrap_or_default();
+            change.push(event);
+            if is_program {
+                instruments.entry(channel).or_default().push((tick, change));
+            } else {
+                pending.insert(channel, (tick, change));
+            }
+            continue;
+        }
+
+        let tick = if mirrors(&event, options) {
+            mirror(tick)
+        } else {
+            tick
+        };
+        events.push((tick, Slot::Other, event));
+    }
+
+    // Bank selects no program change follows count as a change of their own
+    for (channel, change) in pending {
+        instruments.entry(channel).or_default().push(change);
+    }
+
+    // A change lasts until the channel's next one, so once mirrored it starts where that one
+    // was. Later changes are gathered first, so a change that lasted no time at all at the end
+    // of the track is overridden by the one before it
+    for changes in instruments.into_values() {
+        let nexts = changes.iter().skip(1).map(|(tick, _)| *tick).chain([end]);
+        let mirrored: Vec<_> = nexts
+            .zip(&changes)
+            .map(|(next, (_, change))| (mirror(next), change))
+            .collect();
+        for (tick, change) in mirrored.into_iter().rev() {
+            events.extend(
+                change
+                    .iter()
+                    .map(|event| (tick, Slot::Other, event.clone())),
+            );
+        }
+    }
+
+    // Stable, so events sharing a tick and slot keep the order they were gathered in
+    events.sort_by_key(|(tick, slot, _)| (*tick, *slot));
+    for (tick, _, event) in events {
+        editor.insert(tick, event);
+    }
+    editor.set_end_of_track(end);
+
+    editor.finish()
+}
+
+/// The channel of a program change or bank select, and whether it's the program change that
+/// completes an instrument change
+fn instrument_change(event: &Event) -> Option<(u8, bool)> {
+    match event {
+        Event::MidiEvent(MidiEvent::ProgramChange { channel, .. }) => Some((*channel, true)),
+        Event::MidiEvent(MidiEvent::ControlChange(channel, change))
+            if matches!(change.controller(), BANK_SELECT_MSB | BANK_SELECT_LSB) =>
+        {
+            Some((*channel, false))
+        }
+        _ => None,
+    }
+}
+
+/// Whether an event other than a note or an instrument change moves to its mirrored tick
+fn mirrors(event: &Event, options: ReverseOptions) -> bool {
+    match event {
+        Event::MidiEvent(_) => true,
+        Event::MetaEvent(
+            MetaEvent::Tempo(_) | MetaEvent::TimeSignature(_) | MetaEvent::KeySignature(_),
+        ) => options.mirror_conductor,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ReverseOptions;
+    use crate::{
+        analysis::Note,
+        chunk::{
+            header::HeaderChunk,
+            track::{
+                event::{ControlChange, MidiEvent, NoteMeta},
+                meta::MetaEvent,
+                Event, TrackChunk,
+            },
+        },
+        Midi,
+    };
+
+    fn note_on(key: u8, velocity: u8) -> Event {
+        Event::MidiEvent(MidiEvent::NoteOn(0, NoteMeta { key, velocity }))
+    }
+
+    fn note_off(key: u8) -> Event {
+        Event::MidiEvent(MidiEvent::NoteOff(0, NoteMeta { key, velocity: 0 }))
+    }
+
+    fn volume(value: u8) -> Event {
+        Event::MidiEvent(MidiEvent::ControlChange(
+            0,
+            ControlChange {
+                controller_number: 7,
+                new_value: value,
+            },
+        ))
+    }
+
+    /// Every track's notes in start order and every other event with its tick, sorted
+    fn contents(midi: &Midi) -> Vec<(Vec<Note>, Vec<String>)> {
+        midi.tracks
+            .iter()
+            .map(|track| {
+                let mut notes = track.notes();
+                notes.sort_by_key(|note| (note.start, note.end, note.channel, note.key));
+
+                let mut others: Vec<_> = track
+                    .absolute_events()
+                    .filter(|(_, event)| {
+                        !matches!(
+                            event,
+                            Event::MidiEvent(MidiEvent::NoteOn(..) | MidiEvent::NoteOff(..))
+                                | Event::MetaEvent(MetaEvent::EndOfTrack)
+                        )
+                    })
+                    .map(|(tick, event)| format!("{tick} {event:?}"))
+                    .collect();
+                others.sort();
+
+                (notes, others)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn notes_and_controllers_are_mirrored() {
+        let track = TrackChunk::from_absolute_events([
+            (0, Event::MetaEvent(MetaEvent::TrackName("Lead".into()))),
+            (0, volume(100)),
+            (0, note_on(60, 90)),
+            (480, note_off(60)),
+            (480, note_on(62, 80)),
+            (600, volume(50)),
+            (1440, note_off(62)),
+            (1920, Event::MetaEvent(MetaEvent::EndOfTrack)),
+        ])
+        .unwrap();
+        let mut midi = Midi {
+            header: HeaderChunk::try_from((0, 1, 480)).unwrap(),
+            tracks: vec![track],
+        };
+
+        midi.reverse().unwrap();
+        let events: Vec<_> = midi.tracks[0]
+            .absolute_events()
+            .map(|(tick, event)| (tick.get(), event.clone()))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                (0, Event::MetaEvent(MetaEvent::TrackName("Lead".into()))),
+                (480, note_on(62, 80)),
+                (1320, volume(50)),
+                (1440, note_off(62)),
+                (1440, note_on(60, 90)),
+                (1920, note_off(60)),
+                (1920, volume(100)),
+                (1920, Event::MetaEvent(MetaEvent::EndOfTrack)),
+            ]
+        );
+    }
+
+    #[test]
+    fn notes_keep_their_instruments() {
+        let bank = |bank| {
+            Event::MidiEvent(MidiEvent::ControlChange(
+                0,
+                ControlChange {
+                    controller_number: 0,
+                    new_value: bank,
+                },
+            ))
+        };
+        let program =
+            |program| Event::MidiEvent(MidiEvent::try_program_change(0, program).unwrap());
+        let track = TrackChunk::from_absolute_events([
+            (0, bank(0)),
+            (0, program(1)),
+            (0, note_on(60, 90)),
+            (480, note_off(60)),
+            (960, bank(1)),
+            (960, program(2)),
+            (960, note_on(64, 90)),
+            (1440, note_off(64)),
+            (1920, Event::MetaEvent(MetaEvent::EndOfTrack)),
+        ])
+        .unwrap();
+        let original = Midi {
+            header: HeaderChunk::try_from((0, 1, 480)).unwrap(),
+            tracks: vec![track],
+        };
+
+        let mut midi = original.clone();
+        midi.reverse().unwrap();
+        let instruments: Vec<_> = midi.tracks[0]
+            .absolute_events()
+            .filter(|(_, event)| {
+                matches!(
+                    event,
+                    Event::MidiEvent(
+                        MidiEvent::ProgramChange { .. } | MidiEvent::ControlChange(..)
+                    )
+                )
+            })
+            .map(|(tick, event)| (tick.get(), event.clone()))
+            .collect();
+        assert_eq!(
+            instruments,
+            [
+                (0, bank(1)),
+                (0, program(2)),
+                (960, bank(0)),
+                (960, program(1))
+            ]
+        );
+        // The note from 960 to 1440 now plays from 480 to 960, still with program 2
+        assert_eq!(midi.program_at(0, 480), Some(2));
+        assert_eq!(midi.program_at(0, 1440), Some(1));
+
+        midi.reverse().unwrap();
+        assert_eq!(contents(&midi), contents(&original));
+    }
+
+    #[test]
+    fn reversing_twice_restores_the_file() {
+        for path in ["test/test.mid", "test/run.mid"] {
+            let original = crate::open(path).unwrap();
+            for options in [
+                ReverseOptions::default(),
+                ReverseOptions::default().mirror_conductor(true),
+            ] {
+                let mut midi = original.clone();
+                midi.reverse_with(options).unwrap();
+                assert_eq!(midi.duration(), original.duration());
+                midi.reverse_with(options).unwrap();
+
+                assert_eq!(contents(&midi), contents(&original), "{path}");
+            }
+        }
+    }
+}
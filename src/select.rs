@@ -0,0 +1,170 @@
+//! Looking up a file's tracks by position, name or channel.
+//!
+//! Indexing a [`Midi`] panics when nothing matches, like indexing a `Vec` or a `HashMap`: `midi[2]`
+//! panics if there are fewer than 3 tracks and `midi["Bass"]` if no track is named `Bass`. The
+//! `track_by_*` methods return `None` instead
+
+use std::{
+    borrow::Cow,
+    ops::{Index, IndexMut},
+};
+
+use crate::{
+    chunk::track::{meta::MetaEvent, Event, TrackChunk},
+    Midi,
+};
+
+impl TrackChunk {
+    /// The text of the track's first Track Name meta event, if it has one
+    pub fn name(&self) -> Option<Cow<'_, str>> {
+        self.events()
+            .find_map(|mtrk_event| match mtrk_event.event() {
+                Event::MetaEvent(MetaEvent::TrackName(name)) => Some(name.text()),
+                _ => None,
+            })
+    }
+}
+
+impl Midi {
+    /// Position of the first track named `name`
+    fn track_position_by_name(&self, name: &str) -> Option<usize> {
+        self.tracks
+            .iter()
+            .position(|track| track.name().is_some_and(|track_name| track_name == name))
+    }
+
+    /// The first track whose [`TrackChunk::name`] is exactly `name`
+    pub fn track_by_name(&self, name: &str) -> Option<&TrackChunk> {
+        self.track_position_by_name(name)
+            .map(|idx| &self.tracks[idx])
+    }
+
+    /// Mutable access to the first track whose [`TrackChunk::name`] is exactly `name`
+    pub fn track_mut_by_name(&mut self, name: &str) -> Option<&mut TrackChunk> {
+        self.track_position_by_name(name)
+            .map(|idx| &mut self.tracks[idx])
+    }
+
+    /// The first track that sends any channel message on `channel`, counted from 0
+    pub fn track_by_channel(&self, channel: u8) -> Option<&TrackChunk> {
+        self.tracks.iter().find(|track| {
+            track.events().any(|mtrk_event| {
+                matches!(mtrk_event.event(), Event::MidiEvent(event) if event.channel() == channel)
+            })
+        })
+    }
+}
+
+/// The track at a position, panicking if it's out of range
+impl Index<usize> for Midi {
+    type Output = TrackChunk;
+
+    fn index(&self, index: usize) -> &TrackChunk {
+        &self.tracks[index]
+    }
+}
+
+impl IndexMut<usize> for Midi {
+    fn index_mut(&mut self, index: usize) -> &mut TrackChunk {
+        &mut self.tracks[index]
+    }
+}
+
+/// The first track with a name, panicking if there is none. See [`Midi::track_by_name`]
+impl Index<&str> for Midi {
+    type Output = TrackChunk;
+
+    fn index(&self, name: &str) -> &TrackChunk {
+        self.track_by_name(name)
+            .unwrap_or_else(|| panic!("No track is named {name:?}"))
+    }
+}
+
+impl IndexMut<&str> for Midi {
+    fn index_mut(&mut self, name: &str) -> &mut TrackChunk {
+        self.track_mut_by_name(name)
+            .unwrap_or_else(|| panic!("No track is named {name:?}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        chunk::{
+            header::Division,
+            track::{
+                event::{MidiEvent, NoteMeta},
+                meta::MetaEvent,
+                Event, TrackChunk,
+            },
+        },
+        Midi,
+    };
+
+    fn track(name: &str, channel: u8, key: u8) -> TrackChunk {
+        TrackChunk::from_absolute_events([
+            (0, Event::MetaEvent(MetaEvent::TrackName(name.into()))),
+            (
+                0,
+                Event::MidiEvent(MidiEvent::NoteOn(channel, NoteMeta { key, velocity: 90 })),
+            ),
+            (96, Event::MetaEvent(MetaEvent::EndOfTrack)),
+        ])
+        .unwrap()
+    }
+
+    fn key(track: &TrackChunk) -> u8 {
+        track.notes_on().next().unwrap().2.key
+    }
+
+    fn midi() -> Midi {
+        Midi::new(
+            vec![
+                TrackChunk::from_absolute_events([(0, Event::MetaEvent(MetaEvent::EndOfTrack))])
+                    .unwrap(),
+                track("Lead", 0, 72),
+                track("Bass", 1, 48),
+            ],
+            Division::TPQN_96,
+        )
+    }
+
+    #[test]
+    fn lookups_return_none_when_nothing_matches() {
+        let mut midi = midi();
+
+        assert_eq!(midi[0].name(), None);
+        assert_eq!(midi[1].name().as_deref(), Some("Lead"));
+        assert_eq!(midi.track_by_name("Bass").map(key), Some(48));
+        assert_eq!(midi.track_by_name("Drums"), None);
+        assert_eq!(midi.track_by_name("bass"), None);
+        assert_eq!(midi.track_by_channel(0).map(key), Some(72));
+        assert_eq!(midi.track_by_channel(9), None);
+
+        midi.track_mut_by_name("Bass").unwrap().transpose(-12);
+        assert_eq!(key(&midi.tracks[2]), 36);
+        assert!(midi.track_mut_by_name("Drums").is_none());
+    }
+
+    #[test]
+    fn indexing_reads_and_mutates() {
+        let mut midi = midi();
+
+        midi["Bass"].transpose(-12);
+        midi[1].transpose(12);
+        assert_eq!(key(&midi["Bass"]), 36);
+        assert_eq!(key(&midi[1]), 84);
+    }
+
+    #[test]
+    #[should_panic(expected = "No track is named \"Drums\"")]
+    fn indexing_a_missing_name_panics() {
+        let _ = &midi()["Drums"];
+    }
+
+    #[test]
+    #[should_panic]
+    fn indexing_past_the_last_track_panics() {
+        let _ = &midi()[3];
+    }
+}
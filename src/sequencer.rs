@@ -0,0 +1,202 @@
+//! Carrying sequencer specific meta events, such as the track colors and folders DAWs store
+//! behind their own manufacturer IDs, from one track to another without interpreting them.
+//!
+//! Every tick based conversion keeps these events where they are, including
+//! [`crate::Midi::to_format0`], [`crate::Midi::retime_to_division`] and [`crate::Midi::normalize`].
+//! [`crate::Midi::skeleton`] drops them along with every other non-timing meta event, and
+//! [`crate::Midi::with_tracks`] drops those of the tracks it leaves out
+
+use crate::{
+    chunk::track::{editor::TrackEditor, meta::MetaEvent, sysex::ManufactureId, Event, TrackChunk},
+    time::Tick,
+};
+
+/// Where [`TrackChunk::copy_sequencer_specific_from_with`] places the copied events
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SequencerCopyOptions {
+    /// Places every copied event at tick 0 instead of its original tick, for metadata that
+    /// describes the whole track. Off by default
+    pub at_start: bool,
+}
+
+impl SequencerCopyOptions {
+    /// Sets [`SequencerCopyOptions::at_start`]
+    pub fn at_start(mut self, at_start: bool) -> Self {
+        self.at_start = at_start;
+        self
+    }
+}
+
+/// Splits a Sequencer Specific payload into its manufacturer ID and the vendor's data, or `None`
+/// for an empty payload. A leading 0 starts a three byte ID, like in system exclusive events
+fn split_manufacturer(payload: &[u8]) -> Option<(ManufactureId, &[u8])> {
+    match payload {
+        [0x00, second, third, data @ ..] => {
+            Some((ManufactureId::ThreeByte([0x00, *second, *third]), data))
+        }
+        [first, data @ ..] if *first != 0x00 => Some((ManufactureId::OneByte(*first), data)),
+        _ => None,
+    }
+}
+
+impl TrackChunk {
+    /// Every Sequencer Specific meta event in the track as its absolute tick, manufacturer ID and
+    /// the data after the ID. Events too short to hold an ID are skipped
+    pub fn sequencer_specific(&self) -> Vec<(Tick, ManufactureId, &[u8])> {
+        self.meta_events()
+            .filter_map(|(tick, meta)| match meta {
+                MetaEvent::SequencerSpecific(payload) => {
+                    let (id, data) = split_manufacturer(payload)?;
+                    Some((tick, id, data))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Copies every Sequencer Specific meta event from another track into this one at its
+    /// original tick. See [`TrackChunk::copy_sequencer_specific_from_with`]
+    pub fn copy_sequencer_specific_from(&mut self, other: &TrackChunk) {
+        self.copy_sequencer_specific_from_with(other, SequencerCopyOptions::default());
+    }
+
+    /// Copies every Sequencer Specific meta event from another track into this one, after any
+    /// events already at the same tick. No other event moves: an event past the end of this
+    /// track is placed at its end instead of extending it, and an identical event already at the
+    /// same tick isn't copied again, so copying twice changes nothing
+    pub fn copy_sequencer_specific_from_with(
+        &mut self,
+        other: &TrackChunk,
+        options: SequencerCopyOptions,
+    ) {
+        let mut editor = TrackEditor::new(core::mem::take(self));
+        let end = editor.end_of_track();
+
+        for (tick, meta) in other.meta_events() {
+            if !matches!(meta, MetaEvent::SequencerSpecific(_)) {
+                continue;
+            }
+
+            let tick = if options.at_start {
+                Tick::ZERO
+            } else {
+                tick.min(end)
+            };
+            let event = Event::MetaEvent(meta.clone());
+            if !editor
+                .events()
+                .iter()
+                .any(|(existing_tick, existing)| *existing_tick == tick && *existing == event)
+            {
+                editor.insert(tick, event);
+            }
+        }
+
+        *self = editor
+            .finish()
+            .expect("Copied events land within the track, so no gap between events widens");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SequencerCopyOptions;
+    use crate::chunk::track::{
+        event::{MidiEvent, NoteMeta},
+        meta::MetaEvent,
+        sysex::ManufactureId,
+        Event, TrackChunk,
+    };
+
+    fn vendor(payload: &[u8]) -> Event {
+        Event::MetaEvent(MetaEvent::SequencerSpecific(payload.into()))
+    }
+
+    fn note(key: u8, velocity: u8) -> Event {
+        Event::MidiEvent(MidiEvent::NoteOn(0, NoteMeta { key, velocity }))
+    }
+
+    fn end() -> Event {
+        Event::MetaEvent(MetaEvent::EndOfTrack)
+    }
+
+    /// A track with a three byte ID color blob at the start and a one byte ID folder blob later
+    fn source() -> TrackChunk {
+        TrackChunk::from_absolute_events([
+            (0, vendor(&[0x00, 0x20, 0x29, 0xFF, 0x80, 0x00])),
+            (0, note(60, 90)),
+            (480, note(60, 0)),
+            (960, vendor(&[0x41, 0x01, 0x02])),
+            (960, vendor(&[])),
+            (1920, end()),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn blobs_are_listed_by_manufacturer() {
+        let track = source();
+        let found: Vec<_> = track
+            .sequencer_specific()
+            .into_iter()
+            .map(|(tick, id, data)| (tick.get(), id, data.to_vec()))
+            .collect();
+
+        assert_eq!(
+            found,
+            [
+                (
+                    0,
+                    ManufactureId::ThreeByte([0x00, 0x20, 0x29]),
+                    vec![0xFF, 0x80, 0x00]
+                ),
+                (960, ManufactureId::OneByte(0x41), vec![0x01, 0x02]),
+            ]
+        );
+    }
+
+    #[test]
+    fn copying_keeps_every_other_event_in_place() {
+        let target = || {
+            TrackChunk::from_absolute_events([(0, note(48, 80)), (720, note(48, 0)), (720, end())])
+                .unwrap()
+        };
+
+        let mut copied = target();
+        copied.copy_sequencer_specific_from(&source());
+        let events: Vec<_> = copied
+            .absolute_events()
+            .map(|(tick, event)| (tick.get(), event.clone()))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                (0, note(48, 80)),
+                (0, vendor(&[0x00, 0x20, 0x29, 0xFF, 0x80, 0x00])),
+                (720, note(48, 0)),
+                (720, vendor(&[0x41, 0x01, 0x02])),
+                (720, vendor(&[])),
+                (720, end()),
+            ]
+        );
+
+        let again = copied.clone();
+        copied.copy_sequencer_specific_from(&source());
+        assert_eq!(copied, again);
+
+        let mut at_start = target();
+        at_start
+            .copy_sequencer_specific_from_with(&source(), SequencerCopyOptions { at_start: true });
+        assert!(at_start
+            .sequencer_specific()
+            .iter()
+            .all(|(tick, _, _)| tick.get() == 0));
+        assert_eq!(at_start.duration().get(), 720);
+
+        // The blobs survive merging the tracks down to format 0
+        let mut merged = target();
+        merged.merge(&copied);
+        assert_eq!(merged.sequencer_specific(), copied.sequencer_specific());
+    }
+}
@@ -0,0 +1,212 @@
+//! Copyright notices and generator signatures stamped at the start of a file
+
+use crate::{
+    chunk::track::{
+        editor::{EditError, TrackEditor},
+        meta::MetaEvent,
+        Event, TrackChunk,
+    },
+    time::Tick,
+    Midi,
+};
+
+/// Text every generator signature starts with, followed by the generator's name
+pub const GENERATOR_PREFIX: &str = "generated by ";
+
+/// The generator [`crate::writer::WriteOptions::stamp_generator`] signs files with
+pub const GENERATOR: &str = concat!("miami ", env!("CARGO_PKG_VERSION"));
+
+impl Midi {
+    /// Sets the file's copyright notice, replacing any Copyright event already in the first
+    /// track with one at tick 0, ahead of every other event as the spec asks. A file without
+    /// tracks gets one to hold it.
+    ///
+    /// Fails without changing anything if removing the old notice joins the gaps around it into
+    /// one too large for a delta time
+    pub fn set_copyright(&mut self, text: &str) -> Result<(), EditError> {
+        self.edit_first_track(|editor| {
+            editor.retain(|_, event| !matches!(event, Event::MetaEvent(MetaEvent::Copyright(_))));
+            editor
+                .events_mut()
+                .insert(0, (Tick::new(0), meta(MetaEvent::Copyright(text.into()))));
+        })
+    }
+
+    /// Signs the file with a `generated by <text>` Text event at tick 0 of the first track,
+    /// replacing any signature already there. It follows the copyright notice if there is one,
+    /// and otherwise comes first. A file without tracks gets one to hold it.
+    ///
+    /// Fails without changing anything like [`Midi::set_copyright`]
+    pub fn set_generator(&mut self, text: &str) -> Result<(), EditError> {
+        self.edit_first_track(|editor| {
+            editor.retain(|_, event| !is_signature(event));
+            let after_copyright = editor.events().first().is_some_and(|(tick, event)| {
+                tick.get() == 0 && matches!(event, Event::MetaEvent(MetaEvent::Copyright(_)))
+            });
+            let signature = format!("{GENERATOR_PREFIX}{text}");
+            editor.events_mut().insert(
+                after_copyright as usize,
+                (Tick::new(0), meta(MetaEvent::Text(signature.into()))),
+            );
+        })
+    }
+
+    /// Edits a copy of the first track, replacing the track only if the edit finishes. A file
+    /// without tracks gets an empty one first
+    fn edit_first_track(&mut self, edit: impl FnOnce(&mut TrackEditor)) -> Result<(), EditError> {
+        if self.tracks.is_empty() {
+            self.push_track(TrackChunk::default());
+        }
+
+        let mut editor = TrackEditor::new(self.tracks[0].clone());
+        edit(&mut editor);
+        self.tracks[0] = editor.finish()?;
+
+        Ok(())
+    }
+}
+
+/// Wraps a meta event
+fn meta(event: MetaEvent) -> Event {
+    Event::MetaEvent(event)
+}
+
+/// Whether the event is a Text meta event holding a generator signature
+fn is_signature(event: &Event) -> bool {
+    matches!(event, Event::MetaEvent(MetaEvent::Text(text)) if text.as_bytes().starts_with(GENERATOR_PREFIX.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        chunk::track::editor::EditError,
+        chunk::{
+            header::HeaderChunk,
+            track::{
+                event::{MidiEvent, NoteMeta},
+                meta::MetaEvent,
+                Event, TrackChunk,
+            },
+        },
+        file::{save_with, SaveOptions},
+        time::Tick,
+        writer::{MidiWriteable, WriteError, WriteOptions},
+        Midi, MidiError, RawMidi,
+    };
+
+    fn meta(event: MetaEvent) -> Event {
+        Event::MetaEvent(event)
+    }
+
+    fn events(track: &TrackChunk) -> Vec<(u64, Event)> {
+        track
+            .absolute_events()
+            .map(|(tick, event)| (tick.get(), event.clone()))
+            .collect()
+    }
+
+    fn parse(bytes: Vec<u8>) -> Midi {
+        Midi::try_from(RawMidi::try_from_midi_stream(bytes.into_iter()).unwrap()).unwrap()
+    }
+
+    fn midi() -> Midi {
+        let note = Event::MidiEvent(MidiEvent::NoteOn(
+            0,
+            NoteMeta {
+                key: 60,
+                velocity: 90,
+            },
+        ));
+        Midi {
+            header: HeaderChunk::try_from((0, 1, 480)).unwrap(),
+            tracks: vec![TrackChunk::from_absolute_events([
+                (0, meta(MetaEvent::TrackName("Piano".into()))),
+                (0, note),
+                (240, meta(MetaEvent::Copyright("© Old".into()))),
+                (480, meta(MetaEvent::EndOfTrack)),
+            ])
+            .unwrap()],
+        }
+    }
+
+    #[test]
+    fn stamping_twice_leaves_one_of_each() {
+        let mut midi = midi();
+        midi.set_generator("Tool 1.0").unwrap();
+        midi.set_copyright("© New").unwrap();
+        let stamped = midi.clone();
+
+        midi.set_copyright("© New").unwrap();
+        midi.set_generator("Tool 1.0").unwrap();
+        assert_eq!(midi, stamped);
+
+        let events = events(&midi.tracks[0]);
+        assert_eq!(
+            events[..3],
+            [
+                (0, meta(MetaEvent::Copyright("© New".into()))),
+                (0, meta(MetaEvent::Text("generated by Tool 1.0".into()))),
+                (0, meta(MetaEvent::TrackName("Piano".into()))),
+            ]
+        );
+        // The old copyright is replaced, not kept alongside
+        assert_eq!(events.len(), 5);
+    }
+
+    #[test]
+    fn stamping_fails_rather_than_overflow_a_delta_time() {
+        // Removing the old event would leave a gap of two maximal delta times
+        let midi = |old: MetaEvent| {
+            let note = Event::MidiEvent(MidiEvent::NoteOn(
+                0,
+                NoteMeta {
+                    key: 60,
+                    velocity: 90,
+                },
+            ));
+            Midi {
+                header: HeaderChunk::try_from((0, 1, 480)).unwrap(),
+                tracks: vec![TrackChunk::from_absolute_events([
+                    (0, note.clone()),
+                    (0x0FFF_FFFF, meta(old)),
+                    (0x1FFF_FFFE, note),
+                    (0x1FFF_FFFE, meta(MetaEvent::EndOfTrack)),
+                ])
+                .unwrap()],
+            }
+        };
+        let overflow = Err(EditError::DeltaOverflow(Tick::new(0x1FFF_FFFE)));
+
+        let mut copyrighted = midi(MetaEvent::Copyright("© Old".into()));
+        let original = copyrighted.clone();
+        assert_eq!(copyrighted.set_copyright("© New"), overflow);
+        assert_eq!(copyrighted, original);
+
+        let mut signed = midi(MetaEvent::Text("generated by Tool 0.9".into()));
+        let original = signed.clone();
+        assert_eq!(signed.set_generator("Tool 1.0"), overflow);
+        assert_eq!(signed, original);
+
+        let path = std::env::temp_dir().join(format!("miami_stamp_{}.mid", std::process::id()));
+        let options = SaveOptions::default().write(WriteOptions::default().stamp_generator(true));
+        assert!(matches!(
+            save_with(&signed, &path, options),
+            Err(MidiError::Write(WriteError::Stamp(
+                EditError::DeltaOverflow(_)
+            )))
+        ));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn writing_can_stamp_the_generator() {
+        let written =
+            parse(midi().to_midi_bytes_with(WriteOptions::default().stamp_generator(true)));
+
+        let mut expected = midi();
+        expected.set_generator(super::GENERATOR).unwrap();
+        assert_eq!(written.tracks, expected.tracks);
+
+        assert_eq!(parse(midi().to_midi_bytes()).tracks, midi().tracks);
+    }
+}
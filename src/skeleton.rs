@@ -0,0 +1,213 @@
+//! Stripped down copies of a file that keep its timing but not its arrangement, for sharing a
+//! rhythm reference
+
+use crate::{
+    chunk::track::{
+        event::{MidiEvent, NoteMeta},
+        meta::MetaEvent,
+        Event,
+    },
+    time::DeltaTimeOverflow,
+    Midi,
+};
+
+/// What [`Midi::skeleton`] keeps of a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkeletonKind {
+    /// Only notes, tempo changes, time signatures and the end of each track
+    NotesOnly,
+    /// Like [`SkeletonKind::NotesOnly`], with every note moved to the same key
+    RhythmOnly {
+        /// The key every note is played on
+        key: u8,
+    },
+}
+
+/// Whether a skeleton keeps an event
+fn is_skeletal(event: &Event) -> bool {
+    matches!(
+        event,
+        Event::MidiEvent(MidiEvent::NoteOn(..) | MidiEvent::NoteOff(..))
+            | Event::MetaEvent(
+                MetaEvent::Tempo(_) | MetaEvent::TimeSignature(_) | MetaEvent::EndOfTrack
+            )
+    )
+}
+
+impl Midi {
+    /// A copy of the file with everything but its notes and timing removed: controllers, pitch
+    /// bends, aftertouch, program changes, system exclusive events and every meta event other
+    /// than tempo changes, time signatures and `EndOfTrack`. Every remaining event keeps its
+    /// absolute tick, and the header is unchanged.
+    ///
+    /// Fails if removing events leaves a gap between two remaining events too large for a delta
+    /// time
+    pub fn skeleton(&self, keep: SkeletonKind) -> Result<Midi, DeltaTimeOverflow> {
+        let mut skeleton = self.clone();
+
+        for track in &mut skeleton.tracks {
+            track.retain_events(is_skeletal)?;
+            track.trailing_events.clear();
+
+            if let SkeletonKind::RhythmOnly { key } = keep {
+                for mtrk_event in track.events_mut() {
+                    if let Event::MidiEvent(
+                        MidiEvent::NoteOn(_, note) | MidiEvent::NoteOff(_, note),
+                    ) = mtrk_event.event_mut()
+                    {
+                        *note = NoteMeta {
+                            key: key & 0x7F,
+                            ..*note
+                        };
+                    }
+                }
+            }
+        }
+
+        Ok(skeleton)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SkeletonKind;
+    use crate::{
+        chunk::{
+            header::HeaderChunk,
+            track::{
+                event::{ControlChange, MidiEvent, NoteMeta},
+                meta::MetaEvent,
+                sysex::{ManufactureId, SysexEvent},
+                Event, MTrkEvent, TrackChunk,
+            },
+        },
+        time::DeltaTimeOverflow,
+        Midi,
+    };
+
+    fn on(key: u8) -> Event {
+        Event::MidiEvent(MidiEvent::NoteOn(0, NoteMeta { key, velocity: 100 }))
+    }
+
+    fn off(key: u8) -> Event {
+        Event::MidiEvent(MidiEvent::NoteOff(0, NoteMeta { key, velocity: 0 }))
+    }
+
+    fn midi() -> Midi {
+        let track = TrackChunk::from_absolute_events([
+            (0, Event::MetaEvent(MetaEvent::TrackName("Lead".into()))),
+            (0, Event::MetaEvent(MetaEvent::DEFAULT_TEMPO)),
+            (
+                0,
+                Event::MidiEvent(MidiEvent::ProgramChange {
+                    channel: 0,
+                    program: 80,
+                }),
+            ),
+            (
+                0,
+                Event::SysexEvent(SysexEvent {
+                    manufacture_id: ManufactureId::OneByte(0x41),
+                    payload: vec![0x10, 0x42].into(),
+                }),
+            ),
+            (120, on(60)),
+            (
+                200,
+                Event::MidiEvent(MidiEvent::ControlChange(
+                    0,
+                    ControlChange::new(1, 64).unwrap(),
+                )),
+            ),
+            (
+                220,
+                Event::MidiEvent(MidiEvent::PitchWheelChange(0, 0x3000)),
+            ),
+            (240, off(60)),
+            (
+                240,
+                Event::MidiEvent(MidiEvent::ChannelPressure {
+                    channel: 0,
+                    pressure: 90,
+                }),
+            ),
+            (300, Event::MetaEvent(MetaEvent::Marker("Solo".into()))),
+            (360, on(67)),
+            (360, on(64)),
+            (480, off(67)),
+            (480, off(64)),
+            (960, Event::MetaEvent(MetaEvent::EndOfTrack)),
+        ])
+        .unwrap();
+
+        Midi {
+            header: HeaderChunk::try_from((0, 1, 480)).unwrap(),
+            tracks: vec![track],
+        }
+    }
+
+    fn onsets(midi: &Midi) -> Vec<u64> {
+        midi.tracks[0]
+            .notes_on()
+            .map(|(tick, _, _)| tick.get())
+            .collect()
+    }
+
+    #[test]
+    fn notes_only_keeps_notes_and_timing() {
+        let original = midi();
+        let skeleton = original.skeleton(SkeletonKind::NotesOnly).unwrap();
+
+        assert_eq!(onsets(&skeleton), onsets(&original));
+        assert_eq!(skeleton.duration(), original.duration());
+        assert_eq!(
+            skeleton.tracks[0]
+                .absolute_events()
+                .map(|(tick, event)| (tick.get(), event.clone()))
+                .collect::<Vec<_>>(),
+            [
+                (0, Event::MetaEvent(MetaEvent::DEFAULT_TEMPO)),
+                (120, on(60)),
+                (240, off(60)),
+                (360, on(67)),
+                (360, on(64)),
+                (480, off(67)),
+                (480, off(64)),
+                (960, Event::MetaEvent(MetaEvent::EndOfTrack)),
+            ]
+        );
+    }
+
+    #[test]
+    fn rhythm_only_collapses_keys() {
+        let original = midi();
+        let skeleton = original
+            .skeleton(SkeletonKind::RhythmOnly { key: 37 })
+            .unwrap();
+
+        assert_eq!(onsets(&skeleton), onsets(&original));
+        assert!(skeleton.tracks[0]
+            .notes_on()
+            .all(|(_, _, note)| note.key == 37));
+        assert_eq!(skeleton.tracks[0].notes().len(), 3);
+    }
+
+    #[test]
+    fn oversized_gaps_are_rejected() {
+        let marker = || Event::MetaEvent(MetaEvent::Marker("x".into()));
+        let track = TrackChunk::new(vec![
+            MTrkEvent::new(0x0FFF_FFFF, marker()),
+            MTrkEvent::new(0x0FFF_FFFF, marker()),
+            MTrkEvent::new(1, Event::MetaEvent(MetaEvent::EndOfTrack)),
+        ]);
+        let midi = Midi {
+            header: HeaderChunk::try_from((0, 1, 480)).unwrap(),
+            tracks: vec![track],
+        };
+
+        assert_eq!(
+            midi.skeleton(SkeletonKind::NotesOnly),
+            Err(DeltaTimeOverflow(0x1FFF_FFFF))
+        );
+    }
+}
@@ -0,0 +1,728 @@
+//! Extracting and repeating ranges of absolute time within a MIDI file
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    chunk::track::{
+        event::{MidiEvent, NoteMeta},
+        meta::MetaEvent,
+        Event, TrackChunk,
+    },
+    time::Tick,
+    Midi,
+};
+
+/// Error from repeating a section of a MIDI file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepeatError {
+    /// The section's end tick is not after its start tick
+    EmptySection,
+    /// Repeating the section would place events further apart than a delta time can express
+    DeltaOverflow,
+}
+
+impl core::error::Error for RepeatError {}
+impl core::fmt::Display for RepeatError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::EmptySection => write![f, "Section end must be after its start"],
+            Self::DeltaOverflow => write![f, "Repeated section overflows a delta time"],
+        }
+    }
+}
+
+/// How [`Midi::slice_with`] ends the tracks it extracts
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SliceOptions {
+    /// Ends every track with an All Notes Off and Reset All Controllers for each channel it
+    /// uses, see [`TrackChunk::append_all_notes_off`]. Off by default
+    pub all_notes_off: bool,
+}
+
+impl SliceOptions {
+    /// Sets [`SliceOptions::all_notes_off`]
+    pub fn all_notes_off(mut self, all_notes_off: bool) -> Self {
+        self.all_notes_off = all_notes_off;
+        self
+    }
+}
+
+/// How [`Midi::truncate_at_with`] treats the tracks it cuts
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TruncateOptions {
+    /// Removes every track left with nothing but its `EndOfTrack`, updating the header's track
+    /// count. Off by default, which keeps the file's track layout
+    pub drop_empty_tracks: bool,
+    /// Ends every track the cut shortens with an All Notes Off and Reset All Controllers for
+    /// each channel it uses, see [`TrackChunk::append_all_notes_off`]. Off by default
+    pub all_notes_off: bool,
+}
+
+impl TruncateOptions {
+    /// Sets [`TruncateOptions::drop_empty_tracks`]
+    pub fn drop_empty_tracks(mut self, drop: bool) -> Self {
+        self.drop_empty_tracks = drop;
+        self
+    }
+
+    /// Sets [`TruncateOptions::all_notes_off`]
+    pub fn all_notes_off(mut self, all_notes_off: bool) -> Self {
+        self.all_notes_off = all_notes_off;
+        self
+    }
+}
+
+/// How an event changes the set of sounding notes
+pub(crate) enum NoteChange {
+    /// A note starts sounding
+    On(u8, NoteMeta),
+    /// A note stops sounding
+    Off(u8, NoteMeta),
+}
+
+impl NoteChange {
+    /// Classifies an event as a note change, treating a zero velocity NoteOn as a NoteOff
+    pub(crate) fn of(event: &Event) -> Option<Self> {
+        match event {
+            Event::MidiEvent(MidiEvent::NoteOn(channel, note)) if note.velocity > 0 => {
+                Some(Self::On(*channel, *note))
+            }
+            Event::MidiEvent(
+                MidiEvent::NoteOn(channel, note) | MidiEvent::NoteOff(channel, note),
+            ) => Some(Self::Off(*channel, *note)),
+            _ => None,
+        }
+    }
+}
+
+/// The notes sounding at some point in a track, by channel and key, with the velocity of every
+/// unmatched NoteOn in the order they were struck
+#[derive(Default)]
+struct SoundingNotes(BTreeMap<(u8, u8), Vec<u8>>);
+
+impl SoundingNotes {
+    /// Applies an event to the set, returning false for a NoteOff that matches no sounding note
+    fn apply(&mut self, event: &Event) -> bool {
+        match NoteChange::of(event) {
+            Some(NoteChange::On(channel, note)) => {
+                self.0
+                    .entry((channel, note.key))
+                    .or_default()
+                    .push(note.velocity);
+                true
+            }
+            Some(NoteChange::Off(channel, note)) => match self.0.get_mut(&(channel, note.key)) {
+                Some(velocities) if !velocities.is_empty() => {
+                    velocities.remove(0);
+                    true
+                }
+                _ => false,
+            },
+            None => true,
+        }
+    }
+
+    /// NoteOn events restriking every sounding note
+    fn note_ons(&self) -> Vec<Event> {
+        self.0
+            .iter()
+            .flat_map(|(&(channel, key), velocities)| {
+                velocities.iter().map(move |&velocity| {
+                    Event::MidiEvent(MidiEvent::NoteOn(channel, NoteMeta { key, velocity }))
+                })
+            })
+            .collect()
+    }
+
+    /// NoteOff events silencing every sounding note
+    fn note_offs(&self) -> Vec<Event> {
+        self.0
+            .iter()
+            .flat_map(|(&(channel, key), velocities)| {
+                velocities.iter().map(move |_| {
+                    Event::MidiEvent(MidiEvent::NoteOff(channel, NoteMeta { key, velocity: 0 }))
+                })
+            })
+            .collect()
+    }
+}
+
+/// Returns the tag of the conductor meta events whose state carries into a slice
+fn conductor_tag(event: &Event) -> Option<u8> {
+    match event {
+        Event::MetaEvent(
+            meta @ (MetaEvent::Tempo(_) | MetaEvent::TimeSignature(_) | MetaEvent::KeySignature(_)),
+        ) => Some(meta.get_tag()),
+        _ => None,
+    }
+}
+
+/// Every channel the track sends channel messages on
+fn channels_used(track: &TrackChunk) -> BTreeSet<u8> {
+    track
+        .events()
+        .filter_map(|mtrk_event| match mtrk_event.event() {
+            Event::MidiEvent(event) => Some(event.channel()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns true for NoteOff events, including zero velocity NoteOns
+fn is_note_off(event: &Event) -> bool {
+    matches!(NoteChange::of(event), Some(NoteChange::Off(..)))
+}
+
+impl TrackChunk {
+    /// Extracts the events in `[start_tick, end_tick)` as a standalone track starting at tick 0.
+    ///
+    /// Notes already sounding at `start_tick` are restruck at the start of the slice and notes
+    /// still sounding at `end_tick` are released there, so the slice never leaves hanging notes.
+    /// The tempo, time signature and key signature in effect at `start_tick` are restated at the
+    /// start, and the slice ends with an `EndOfTrack` at `end_tick - start_tick`. An `end_tick`
+    /// past the end of the track is clamped to [`TrackChunk::duration`], so the slice never runs
+    /// longer than its source
+    pub fn slice(&self, start_tick: impl Into<Tick>, end_tick: impl Into<Tick>) -> TrackChunk {
+        let start_tick = start_tick.into().get();
+        let end_tick = end_tick.into().min(self.duration()).get();
+        let events = self.slice_events(start_tick, end_tick);
+        let len = end_tick.saturating_sub(start_tick);
+
+        let events = events
+            .into_iter()
+            .chain([(len, Event::MetaEvent(MetaEvent::EndOfTrack))]);
+
+        TrackChunk::from_absolute_events(events)
+            .expect("Slice events are ordered and no further apart than the source's")
+    }
+
+    /// Cuts the track off at `tick`, keeping the events in `[0, tick)` and releasing any notes
+    /// still sounding at `tick` there, like [`TrackChunk::slice`] from the start. The track then
+    /// ends at `tick`. A track that already ends by `tick` is left unchanged
+    pub fn truncate_at(&mut self, tick: impl Into<Tick>) {
+        let tick = tick.into().get();
+        if self.duration().get() <= tick {
+            return;
+        }
+
+        let events = self
+            .slice_events(0, tick)
+            .into_iter()
+            .chain([(tick, Event::MetaEvent(MetaEvent::EndOfTrack))]);
+        *self = TrackChunk {
+            id: self.id,
+            ..TrackChunk::from_absolute_events(events)
+                .expect("Truncated events are ordered and no further apart than the source's")
+        };
+    }
+
+    /// The events of a slice relative to its start, without the closing `EndOfTrack`
+    fn slice_events(&self, start_tick: u64, end_tick: u64) -> Vec<(u64, Event)> {
+        let mut sounding = SoundingNotes::default();
+        let mut conductor: Vec<(u8, Event)> = vec![];
+        let mut events = vec![];
+        let mut reached = false;
+
+        for (tick, event) in self.absolute_events() {
+            let tick = tick.get();
+            if matches!(event, Event::MetaEvent(MetaEvent::EndOfTrack)) {
+                continue;
+            }
+
+            if tick < start_tick {
+                sounding.apply(event);
+                if let Some(tag) = conductor_tag(event) {
+                    conductor.retain(|(state, _)| *state != tag);
+                    conductor.push((tag, event.clone()));
+                }
+                continue;
+            }
+
+            if !reached {
+                reached = true;
+                events.extend(conductor.drain(..).map(|(_, event)| (0, event)));
+                events.extend(sounding.note_ons().into_iter().map(|event| (0, event)));
+            }
+
+            if tick > end_tick || (tick == end_tick && !is_note_off(event)) {
+                break;
+            }
+
+            // A conductor event right at the start replaces the state restated there
+            if let Some(tag) = conductor_tag(event).filter(|_| tick == start_tick) {
+                events.retain(|(tick, state)| *tick != 0 || conductor_tag(state) != Some(tag));
+            }
+
+            if sounding.apply(event) {
+                events.push((tick - start_tick, event.clone()));
+            }
+        }
+
+        let len = end_tick.saturating_sub(start_tick);
+        if !reached {
+            events.extend(conductor.into_iter().map(|(_, event)| (0, event)));
+            events.extend(sounding.note_ons().into_iter().map(|event| (0, event)));
+        }
+        events.extend(sounding.note_offs().into_iter().map(|event| (len, event)));
+
+        events
+    }
+
+    /// Repeats the events in `[start_tick, end_tick)` back to back `times` times starting at
+    /// `end_tick`, pushing everything after the section later by the inserted duration
+    fn repeat_section(
+        &self,
+        start_tick: u64,
+        end_tick: u64,
+        times: u32,
+    ) -> Result<TrackChunk, RepeatError> {
+        let len = end_tick - start_tick;
+        let shift = len
+            .checked_mul(times as u64)
+            .ok_or(RepeatError::DeltaOverflow)?;
+        let shifted = |tick: u64| tick.checked_add(shift).ok_or(RepeatError::DeltaOverflow);
+        // Every copy ends by here, so nothing inside them can overflow either
+        let resume = shifted(end_tick)?;
+
+        let section = self.slice_events(start_tick, end_tick);
+
+        let mut sounding = SoundingNotes::default();
+        let mut events = vec![];
+        let mut suffix = vec![];
+        let mut end_of_track = 0;
+
+        for (tick, event) in self.absolute_events() {
+            let tick = tick.get();
+            if matches!(event, Event::MetaEvent(MetaEvent::EndOfTrack)) {
+                end_of_track = if tick >= end_tick {
+                    shifted(tick)?
+                } else {
+                    tick
+                };
+            } else if tick < end_tick || (tick == end_tick && is_note_off(event)) {
+                sounding.apply(event);
+                events.push((tick, event.clone()));
+            } else {
+                suffix.push((shifted(tick)?, event.clone()));
+            }
+        }
+
+        events.extend(
+            sounding
+                .note_offs()
+                .into_iter()
+                .map(|event| (end_tick, event)),
+        );
+        for copy in 0..times as u64 {
+            let offset = end_tick + copy * len;
+            events.extend(
+                section
+                    .iter()
+                    .map(|(tick, event)| (tick + offset, event.clone())),
+            );
+        }
+        events.extend(sounding.note_ons().into_iter().map(|event| (resume, event)));
+        events.extend(suffix);
+
+        let last = events.last().map_or(0, |(tick, _)| *tick);
+        events.push((
+            end_of_track.max(last),
+            Event::MetaEvent(MetaEvent::EndOfTrack),
+        ));
+
+        TrackChunk::from_absolute_events(events)
+            .map(|track| TrackChunk {
+                id: self.id,
+                ..track
+            })
+            .ok_or(RepeatError::DeltaOverflow)
+    }
+}
+
+impl Midi {
+    /// Extracts `[start_tick, end_tick)` of every track as a new file, see [`TrackChunk::slice`].
+    /// Tracks that end before `end_tick` end where they do in the source
+    pub fn slice(&self, start_tick: impl Into<Tick>, end_tick: impl Into<Tick>) -> Midi {
+        self.slice_with(start_tick, end_tick, SliceOptions::default())
+    }
+
+    /// Extracts `[start_tick, end_tick)` of every track like [`Midi::slice`], following the given
+    /// [`SliceOptions`]
+    pub fn slice_with(
+        &self,
+        start_tick: impl Into<Tick>,
+        end_tick: impl Into<Tick>,
+        options: SliceOptions,
+    ) -> Midi {
+        let (start_tick, end_tick) = (start_tick.into(), end_tick.into());
+        Midi {
+            header: self.header.clone(),
+            tracks: self
+                .tracks
+                .iter()
+                .map(|track| {
+                    let mut slice = track.slice(start_tick, end_tick);
+                    if options.all_notes_off {
+                        slice.append_all_notes_off(channels_used(&slice));
+                    }
+                    slice
+                })
+                .collect(),
+        }
+    }
+
+    /// Cuts every track off at `tick`, see [`TrackChunk::truncate_at`]. Unlike [`Midi::slice`]
+    /// this changes the file in place, and tempo or time signature changes after the cut are
+    /// simply dropped. Afterwards the file lasts at most `tick` ticks
+    pub fn truncate_at(&mut self, tick: impl Into<Tick>) {
+        self.truncate_at_with(tick, TruncateOptions::default());
+    }
+
+    /// Cuts every track off at `tick` like [`Midi::truncate_at`], following the given
+    /// [`TruncateOptions`]
+    pub fn truncate_at_with(&mut self, tick: impl Into<Tick>, options: TruncateOptions) {
+        let tick = tick.into();
+        for track in &mut self.tracks {
+            let cut = track.duration() > tick;
+            track.truncate_at(tick);
+            if cut && options.all_notes_off {
+                track.append_all_notes_off(channels_used(track));
+            }
+        }
+
+        if options.drop_empty_tracks {
+            self.tracks.retain(|track| {
+                track.events().any(|mtrk_event| {
+                    !matches!(mtrk_event.event(), Event::MetaEvent(MetaEvent::EndOfTrack))
+                })
+            });
+            self.header = self.consistent_header();
+        }
+    }
+
+    /// Duplicates every event in `[start_tick, end_tick)` `times` times, appending the copies back
+    /// to back starting at `end_tick` and shifting every later event by the inserted duration.
+    ///
+    /// Each copy is built the same way as [`Midi::slice`]: notes crossing the section boundaries
+    /// are released at the end of a copy and restruck at the start of the next, and notes
+    /// sounding across `end_tick` resume after the final copy. Repeating a section zero times
+    /// leaves the file unchanged
+    pub fn repeat_section(
+        &mut self,
+        start_tick: impl Into<Tick>,
+        end_tick: impl Into<Tick>,
+        times: u32,
+    ) -> Result<(), RepeatError> {
+        let (start_tick, end_tick) = (start_tick.into().get(), end_tick.into().get());
+        if end_tick <= start_tick {
+            return Err(RepeatError::EmptySection);
+        }
+        if times == 0 {
+            return Ok(());
+        }
+
+        let tracks = self
+            .tracks
+            .iter()
+            .map(|track| track.repeat_section(start_tick, end_tick, times))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.tracks = tracks;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RepeatError, SliceOptions, TruncateOptions};
+    use crate::{
+        chunk::{
+            header::HeaderChunk,
+            track::{
+                event::{ControlChange, MidiEvent, NoteMeta},
+                meta::MetaEvent,
+                Event, TrackChunk,
+            },
+        },
+        time::Tick,
+        Midi,
+    };
+
+    fn on(channel: u8, key: u8) -> Event {
+        Event::MidiEvent(MidiEvent::NoteOn(channel, NoteMeta { key, velocity: 100 }))
+    }
+
+    fn off(channel: u8, key: u8) -> Event {
+        Event::MidiEvent(MidiEvent::NoteOff(channel, NoteMeta { key, velocity: 0 }))
+    }
+
+    fn end() -> Event {
+        Event::MetaEvent(MetaEvent::EndOfTrack)
+    }
+
+    fn track(events: Vec<(u64, Event)>) -> TrackChunk {
+        TrackChunk::from_absolute_events(events).unwrap()
+    }
+
+    fn events(track: &TrackChunk) -> Vec<(u64, Event)> {
+        track
+            .absolute_events()
+            .map(|(tick, event)| (tick.get(), event.clone()))
+            .collect()
+    }
+
+    /// Four bars of 4/4 at 480 tpq, a quarter note on every beat and a held note from the
+    /// middle of bar 2 into bar 3
+    fn fixture() -> Midi {
+        let mut melody = vec![];
+        for beat in 0..16 {
+            melody.push((beat * 480, on(0, 60 + beat as u8)));
+            melody.push((beat * 480 + 240, off(0, 60 + beat as u8)));
+        }
+        melody.push((7 * 240 + 1920, on(1, 40)));
+        melody.push((3840 + 960, off(1, 40)));
+        melody.sort_by_key(|(tick, _)| *tick);
+        melody.push((7680, end()));
+
+        let conductor = vec![
+            (0, Event::MetaEvent(MetaEvent::Tempo(500_000))),
+            (1920, Event::MetaEvent(MetaEvent::Tempo(400_000))),
+            (7680, end()),
+        ];
+
+        Midi {
+            header: HeaderChunk::try_from((1, 2, 480)).unwrap(),
+            tracks: vec![track(conductor), track(melody)],
+        }
+    }
+
+    #[test]
+    fn slice_clamps_end_to_track_duration() {
+        let midi = fixture();
+        let slice = midi.slice(7200, 1u64 << 40);
+
+        assert_eq!(
+            events(&slice.tracks[1]),
+            vec![(0, on(0, 75)), (240, off(0, 75)), (480, end()),]
+        );
+        assert_eq!(slice.tracks[0].duration(), Tick::new(480));
+        assert_eq!(midi.slice(0, u64::MAX), midi.slice(0, 7680));
+    }
+
+    #[test]
+    fn slice_closes_and_restrikes_crossing_notes() {
+        let midi = fixture();
+        let slice = midi.slice(3840, 4320);
+
+        assert_eq!(
+            events(&slice.tracks[1]),
+            vec![
+                (0, on(1, 40)),
+                (0, on(0, 68)),
+                (240, off(0, 68)),
+                (480, off(1, 40)),
+                (480, end()),
+            ]
+        );
+        assert_eq!(
+            events(&slice.tracks[0]),
+            vec![
+                (0, Event::MetaEvent(MetaEvent::Tempo(400_000))),
+                (480, end())
+            ]
+        );
+    }
+
+    #[test]
+    fn repeated_section_aligns_to_bars() {
+        let mut midi = fixture();
+        midi.repeat_section(1920, 3840, 2).unwrap();
+
+        let duration = midi
+            .tracks
+            .iter()
+            .filter_map(|track| track.absolute_events().last().map(|(tick, _)| tick))
+            .max();
+        assert_eq!(duration, Some(Tick::new(7680 + 2 * 1920)));
+
+        let onsets: Vec<(u64, u8)> = midi.tracks[1]
+            .absolute_events()
+            .filter_map(|(tick, event)| match event {
+                Event::MidiEvent(MidiEvent::NoteOn(0, note)) => Some((tick.get(), note.key)),
+                _ => None,
+            })
+            .collect();
+
+        // Bar 2 plays three times, then bars 3 and 4 follow
+        let mut expected = vec![];
+        for beat in 0..8 {
+            expected.push((beat * 480, 60 + beat as u8));
+        }
+        for copy in 0..2 {
+            for beat in 4..8 {
+                expected.push((copy * 1920 + 3840 + (beat - 4) * 480, 60 + beat as u8));
+            }
+        }
+        for beat in 8..16 {
+            expected.push((3840 + beat * 480, 60 + beat as u8));
+        }
+        assert_eq!(onsets, expected);
+
+        let tempos: Vec<(u64, Event)> = events(&midi.tracks[0]);
+        assert_eq!(
+            tempos,
+            vec![
+                (0, Event::MetaEvent(MetaEvent::Tempo(500_000))),
+                (1920, Event::MetaEvent(MetaEvent::Tempo(400_000))),
+                (3840, Event::MetaEvent(MetaEvent::Tempo(400_000))),
+                (5760, Event::MetaEvent(MetaEvent::Tempo(400_000))),
+                (11520, end()),
+            ]
+        );
+
+        let held: Vec<(u64, Event)> = events(&midi.tracks[1])
+            .into_iter()
+            .filter(|(_, event)| matches!(event, Event::MidiEvent(e) if e.channel() == 1))
+            .collect();
+        assert_eq!(
+            held,
+            vec![
+                (3600, on(1, 40)),
+                (3840, off(1, 40)),
+                (5520, on(1, 40)),
+                (5760, off(1, 40)),
+                (7440, on(1, 40)),
+                (7680, off(1, 40)),
+                (7680, on(1, 40)),
+                (8640, off(1, 40)),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_section_rejected() {
+        let mut midi = fixture();
+        assert_eq!(
+            midi.repeat_section(960, 960, 1),
+            Err(RepeatError::EmptySection)
+        );
+    }
+
+    #[test]
+    fn repeating_overflow_is_an_error_and_zero_times_a_no_op() {
+        let mut midi = fixture();
+        let original = midi.clone();
+
+        assert_eq!(midi.repeat_section(0, 1920, 0), Ok(()));
+        assert_eq!(midi, original);
+
+        assert_eq!(
+            midi.repeat_section(0, 1u64 << 40, u32::MAX),
+            Err(RepeatError::DeltaOverflow)
+        );
+        assert_eq!(
+            midi.repeat_section(0, u64::MAX, 2),
+            Err(RepeatError::DeltaOverflow)
+        );
+        assert_eq!(
+            midi.repeat_section(u64::MAX - 1, u64::MAX, 1),
+            Err(RepeatError::DeltaOverflow)
+        );
+        assert_eq!(midi, original);
+    }
+
+    #[test]
+    fn truncation_releases_notes_at_the_cut() {
+        let mut midi = fixture();
+        midi.truncate_at(3720u64);
+
+        assert_eq!(midi.duration(), Tick::new(3720));
+        assert_eq!(
+            events(&midi.tracks[1])[14..],
+            [
+                (3360, on(0, 67)),
+                (3600, off(0, 67)),
+                (3600, on(1, 40)),
+                (3720, off(1, 40)),
+                (3720, end()),
+            ]
+        );
+        assert_eq!(
+            events(&midi.tracks[0]),
+            [
+                (0, Event::MetaEvent(MetaEvent::Tempo(500_000))),
+                (1920, Event::MetaEvent(MetaEvent::Tempo(400_000))),
+                (3720, end()),
+            ]
+        );
+
+        // Cutting past the end changes nothing
+        let before = midi.clone();
+        midi.truncate_at(10_000u64);
+        assert_eq!(midi, before);
+    }
+
+    #[test]
+    fn truncation_can_drop_emptied_tracks() {
+        let mut midi = fixture();
+        midi.tracks[0] = track(vec![
+            (1920, Event::MetaEvent(MetaEvent::Tempo(400_000))),
+            (7680, end()),
+        ]);
+
+        let mut kept = midi.clone();
+        kept.truncate_at(960u64);
+        assert_eq!(kept.tracks.len(), 2);
+        assert_eq!(events(&kept.tracks[0]), [(960, end())]);
+
+        midi.truncate_at_with(960u64, TruncateOptions::default().drop_empty_tracks(true));
+        assert_eq!(midi.tracks.len(), 1);
+        assert_eq!(midi.header.ntrks(), 1);
+        assert_eq!(midi.duration(), Tick::new(960));
+    }
+
+    #[test]
+    fn slicing_and_truncating_can_silence_every_channel() {
+        let panic = |channel, controller_number| {
+            Event::MidiEvent(MidiEvent::ControlChange(
+                channel,
+                ControlChange {
+                    controller_number,
+                    new_value: 0,
+                },
+            ))
+        };
+        let silenced = |channels: &[u8], tick| {
+            let mut events = vec![];
+            for &channel in channels {
+                events.push((tick, panic(channel, ControlChange::ALL_NOTES_OFF)));
+                events.push((tick, panic(channel, ControlChange::RESET_ALL_CONTROLLERS)));
+            }
+            events.push((tick, end()));
+            events
+        };
+        let midi = fixture();
+
+        let slice = midi.slice_with(3840, 4320, SliceOptions::default().all_notes_off(true));
+        assert_eq!(events(&slice.tracks[0])[1..], silenced(&[], 480));
+        let melody = events(&slice.tracks[1]);
+        assert_eq!(melody[melody.len() - 5..], silenced(&[0, 1], 480));
+        assert_eq!(
+            midi.slice(3840, 4320).tracks[1].events().count() + 4,
+            melody.len()
+        );
+
+        let mut truncated = midi.clone();
+        truncated.truncate_at_with(3720u64, TruncateOptions::default().all_notes_off(true));
+        let melody = events(&truncated.tracks[1]);
+        assert_eq!(melody[melody.len() - 5..], silenced(&[0, 1], 3720));
+        assert_eq!(events(&truncated.tracks[0])[2..], silenced(&[], 3720));
+
+        // Tracks the cut doesn't shorten are left alone
+        let mut uncut = midi.clone();
+        uncut.truncate_at_with(10_000u64, TruncateOptions::default().all_notes_off(true));
+        assert_eq!(uncut, midi);
+    }
+}
@@ -0,0 +1,189 @@
+//! Event statistics over tracks and whole files
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    chunk::track::{event::MidiEvent, kind::EventKind, sysex::ManufactureId, Event, TrackChunk},
+    Midi,
+};
+
+/// The MIDI channel General MIDI reserves for percussion, channel 10 when counting from 1
+pub const PERCUSSION_CHANNEL: u8 = 9;
+
+/// Counts of what a track or file contains
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TrackStats {
+    /// Total number of events, including meta and sysex events
+    pub event_count: usize,
+    /// Number of Note On events with a non-zero velocity
+    pub note_count: usize,
+    /// Every channel a channel message was sent on
+    pub channels: BTreeSet<u8>,
+    /// Every program selected by a Program Change
+    pub programs: BTreeSet<u8>,
+    /// Number of events of each kind, leaving out kinds that don't occur
+    pub kinds: BTreeMap<EventKind, usize>,
+}
+
+impl TrackStats {
+    /// Folds another set of statistics into this one
+    pub fn merge(&mut self, other: &TrackStats) {
+        self.event_count += other.event_count;
+        self.note_count += other.note_count;
+        self.channels.extend(&other.channels);
+        self.programs.extend(&other.programs);
+        for (kind, count) in &other.kinds {
+            *self.kinds.entry(*kind).or_default() += count;
+        }
+    }
+}
+
+impl TrackChunk {
+    /// Counts the events, notes, channels, programs and kinds of event used by the track
+    pub fn stats(&self) -> TrackStats {
+        let mut stats = TrackStats::default();
+
+        for (_, event) in self.absolute_events() {
+            stats.event_count += 1;
+            *stats.kinds.entry(event.kind()).or_default() += 1;
+
+            let Event::MidiEvent(event) = event else {
+                continue;
+            };
+
+            stats.channels.insert(event.channel());
+            match event {
+                MidiEvent::NoteOn(_, note) if note.velocity > 0 => stats.note_count += 1,
+                MidiEvent::ProgramChange { program, .. } => {
+                    stats.programs.insert(*program);
+                }
+                _ => {}
+            }
+        }
+
+        stats
+    }
+}
+
+impl Midi {
+    /// Combined [`TrackChunk::stats`] of every track
+    pub fn stats(&self) -> TrackStats {
+        self.tracks
+            .iter()
+            .fold(TrackStats::default(), |mut stats, track| {
+                stats.merge(&track.stats());
+                stats
+            })
+    }
+
+    /// Guesses whether the file targets General MIDI. This is true if it sends the GM System On
+    /// message, or if it plays percussion on channel 10 with only GM drum keys (35 to 81) and
+    /// never switches to a non-zero bank
+    pub fn looks_general_midi(&self) -> bool {
+        let mut percussion = false;
+
+        for track in &self.tracks {
+            for (_, event) in track.absolute_events() {
+                match event {
+                    Event::SysexEvent(sysex)
+                        if sysex.manufacture_id == ManufactureId::OneByte(0x7E)
+                            && sysex.payload.get(1..3) == Some(&[0x09, 0x01]) =>
+                    {
+                        return true;
+                    }
+                    Event::MidiEvent(MidiEvent::NoteOn(PERCUSSION_CHANNEL, note)) => {
+                        if !(35..=81).contains(&note.key) {
+                            return false;
+                        }
+                        percussion = true;
+                    }
+                    Event::MidiEvent(MidiEvent::ControlChange(_, cc))
+                        if cc.controller_number == 0 && cc.new_value != 0 =>
+                    {
+                        return false;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        percussion
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        chunk::{
+            header::HeaderChunk,
+            track::{
+                event::{MidiEvent, NoteMeta},
+                kind::{EventKind, MetaKind},
+                meta::MetaEvent,
+                sysex::{ManufactureId, SysexEvent},
+                Event, TrackChunk,
+            },
+        },
+        Midi,
+    };
+
+    fn midi(events: Vec<Event>) -> Midi {
+        let track = TrackChunk::from_absolute_events(
+            events
+                .into_iter()
+                .chain([Event::MetaEvent(MetaEvent::EndOfTrack)])
+                .map(|event| (0, event)),
+        )
+        .unwrap();
+
+        Midi {
+            header: HeaderChunk::try_from((0, 1, 96)).unwrap(),
+            tracks: vec![track],
+        }
+    }
+
+    fn note_on(channel: u8, key: u8, velocity: u8) -> Event {
+        Event::MidiEvent(MidiEvent::NoteOn(channel, NoteMeta { key, velocity }))
+    }
+
+    #[test]
+    fn stats_count_notes_channels_and_programs() {
+        let stats = midi(vec![
+            Event::MidiEvent(MidiEvent::ProgramChange {
+                channel: 2,
+                program: 33,
+            }),
+            note_on(2, 40, 100),
+            note_on(2, 40, 0),
+            note_on(9, 36, 90),
+        ])
+        .stats();
+
+        assert_eq!(stats.event_count, 5);
+        assert_eq!(stats.note_count, 2);
+        assert_eq!(stats.channels.into_iter().collect::<Vec<_>>(), [2, 9]);
+        assert_eq!(stats.programs.into_iter().collect::<Vec<_>>(), [33]);
+        assert_eq!(
+            stats.kinds,
+            [
+                (EventKind::NoteOn, 3),
+                (EventKind::ProgramChange, 1),
+                (EventKind::Meta(MetaKind::EndOfTrack), 1),
+            ]
+            .into()
+        );
+    }
+
+    #[test]
+    fn general_midi_detection() {
+        let gm_on = Event::SysexEvent(SysexEvent {
+            manufacture_id: ManufactureId::OneByte(0x7E),
+            payload: vec![0x7F, 0x09, 0x01].into(),
+        });
+
+        assert!(midi(vec![gm_on, note_on(9, 20, 90)]).looks_general_midi());
+        assert!(midi(vec![note_on(9, 36, 90)]).looks_general_midi());
+        assert!(!midi(vec![note_on(9, 20, 90)]).looks_general_midi());
+        assert!(!midi(vec![note_on(0, 60, 90)]).looks_general_midi());
+    }
+}
@@ -0,0 +1,158 @@
+//! A one call overview of a MIDI file, for CLIs and quick inspection
+
+use crate::{
+    chunk::{
+        header::{Division, Format},
+        track::meta::TimeSignature,
+    },
+    time::Tick,
+    Midi,
+};
+
+/// Header level overview of a MIDI file, see [`Midi::summary`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MidiSummary {
+    /// The header's format
+    pub format: Format,
+    /// Number of tracks actually present
+    pub track_count: usize,
+    /// The header's division
+    pub division: Division,
+    /// Length of the file in ticks
+    pub duration_ticks: Tick,
+    /// Length of the file in seconds
+    pub duration_seconds: f64,
+    /// Smallest and largest tempo in microseconds per quarter note
+    pub tempo_range: (u32, u32),
+    /// Every distinct time signature in effect at some point, in order of first use
+    pub time_signatures: Vec<TimeSignature>,
+    /// Every program selected by a Program Change, ascending
+    pub instruments: Vec<u8>,
+    /// Number of sounding Note On events
+    pub note_count: usize,
+    /// Every channel used, ascending and counted from 0
+    pub channels: Vec<u8>,
+    /// Whether the file looks like it targets General MIDI
+    pub general_midi: bool,
+}
+
+impl Midi {
+    /// Computes a [`MidiSummary`] of the file
+    pub fn summary(&self) -> MidiSummary {
+        let stats = self.stats();
+        let tempo_map = self.tempo_map();
+        let duration_ticks = self.duration();
+
+        let signatures = self.time_signature_map();
+        let initial = match signatures.changes().first() {
+            Some((Tick::ZERO, _)) => None,
+            _ => Some(signatures.default_value()),
+        };
+        let mut time_signatures = vec![];
+        for signature in initial
+            .into_iter()
+            .chain(signatures.changes().iter().map(|(_, signature)| *signature))
+        {
+            if !time_signatures.contains(&signature) {
+                time_signatures.push(signature);
+            }
+        }
+
+        MidiSummary {
+            format: self.header.format(),
+            track_count: self.tracks.len(),
+            division: self.header.division(),
+            duration_ticks,
+            duration_seconds: tempo_map.seconds_at(duration_ticks),
+            tempo_range: tempo_map.tempo_range(),
+            time_signatures,
+            instruments: stats.programs.into_iter().collect(),
+            note_count: stats.note_count,
+            channels: stats.channels.into_iter().collect(),
+            general_midi: self.looks_general_midi(),
+        }
+    }
+}
+
+/// Joins displayable items with commas, or `none` if there are none
+fn list<T: core::fmt::Display>(items: impl IntoIterator<Item = T>) -> String {
+    let items: Vec<String> = items.into_iter().map(|item| item.to_string()).collect();
+    if items.is_empty() {
+        "none".to_string()
+    } else {
+        items.join(", ")
+    }
+}
+
+impl core::fmt::Display for MidiSummary {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let bpm = |tempo: u32| 60_000_000.0 / tempo.max(1) as f64;
+        let (fastest, slowest) = (bpm(self.tempo_range.0), bpm(self.tempo_range.1));
+
+        writeln!(f, "Format: {:?}", self.format)?;
+        writeln!(f, "Tracks: {}", self.track_count)?;
+        match self.division {
+            Division::Metrical(tpq) => writeln!(f, "Division: {tpq} ticks per quarter")?,
+            Division::TimeCodeBased(smpte) => writeln!(
+                f,
+                "Division: {:.2} fps, {} ticks per frame",
+                smpte.frames_per_second(),
+                smpte.tpf
+            )?,
+        }
+        writeln!(
+            f,
+            "Duration: {} ticks ({:.2}s)",
+            self.duration_ticks, self.duration_seconds
+        )?;
+        if self.tempo_range.0 == self.tempo_range.1 {
+            writeln!(f, "Tempo: {fastest:.2} BPM")?;
+        } else {
+            writeln!(f, "Tempo: {slowest:.2} to {fastest:.2} BPM")?;
+        }
+        writeln!(
+            f,
+            "Time signatures: {}",
+            list(
+                self.time_signatures
+                    .iter()
+                    .map(|ts| format!("{}/{}", ts.numerator, ts.denominator))
+            )
+        )?;
+        writeln!(f, "Instruments: {}", list(&self.instruments))?;
+        writeln!(f, "Notes: {}", self.note_count)?;
+        writeln!(f, "Channels: {}", list(&self.channels))?;
+        write!(
+            f,
+            "General MIDI: {}",
+            if self.general_midi { "yes" } else { "no" }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{reader::MidiReadable, RawMidi};
+
+    #[test]
+    fn run_mid_summary_golden_output() {
+        let midi = RawMidi::try_from_midi_stream("test/run.mid".get_midi_bytes().unwrap())
+            .unwrap()
+            .check_into_midi()
+            .unwrap();
+
+        let expected = "\
+Format: One
+Tracks: 10
+Division: 384 ticks per quarter
+Duration: 173664 ticks (301.50s)
+Tempo: 90.00 BPM
+Time signatures: 4/4
+Instruments: 0, 13, 40, 80, 87, 107
+Notes: 11457
+Channels: 0, 1, 2, 3, 4, 5, 6, 7, 9
+General MIDI: yes";
+
+        assert_eq!(midi.summary().to_string(), expected);
+    }
+}
@@ -0,0 +1,344 @@
+//! Tempo maps for converting ticks into seconds
+
+use std::collections::BTreeSet;
+
+use crate::{
+    chunk::{
+        header::Division,
+        track::{
+            editor::{EditError, TrackEditor},
+            meta::MetaEvent,
+            Event, TrackChunk,
+        },
+    },
+    time::Tick,
+    timeline::SignatureMap,
+    Midi,
+};
+
+/// The tempo assumed before any tempo event, 120 BPM in microseconds per quarter note
+pub const DEFAULT_TEMPO: u32 = 500_000;
+
+/// Every tempo change in a file alongside the division, for converting absolute ticks into
+/// seconds. Files with a time-code-based division ignore tempo events for timing
+#[derive(Debug, Clone, PartialEq)]
+pub struct TempoMap {
+    /// The file's division
+    division: Division,
+    /// Tempo changes in microseconds per quarter note
+    tempos: SignatureMap<u32>,
+    /// Seconds elapsed at each tempo change, in the same order as `tempos.changes()`
+    seconds: Vec<f64>,
+}
+
+impl TempoMap {
+    /// Returns the tempo in microseconds per quarter note active at the given tick
+    pub fn tempo_at(&self, tick: impl Into<Tick>) -> u32 {
+        self.tempos.at(tick)
+    }
+
+    /// Every `(tick, tempo)` change in ascending tick order
+    pub fn changes(&self) -> &[(Tick, u32)] {
+        self.tempos.changes()
+    }
+
+    /// The smallest and largest tempo in effect at any point, in microseconds per quarter note
+    pub fn tempo_range(&self) -> (u32, u32) {
+        let changes = self.changes();
+        let initial = match changes.first() {
+            Some((Tick::ZERO, _)) => None,
+            _ => Some(self.tempos.default_value()),
+        };
+
+        initial
+            .into_iter()
+            .chain(changes.iter().map(|(_, tempo)| *tempo))
+            .fold((u32::MAX, u32::MIN), |(min, max), tempo| {
+                (min.min(tempo), max.max(tempo))
+            })
+    }
+
+    /// Converts an absolute tick into seconds from the start of the file
+    pub fn seconds_at(&self, tick: impl Into<Tick>) -> f64 {
+        let tick = tick.into();
+        match self.division {
+            Division::Metrical(tpq) => {
+                let tpq = tpq.max(1) as f64;
+                let idx = self
+                    .changes()
+                    .partition_point(|(change, _)| *change <= tick);
+
+                let (start, seconds) = match idx.checked_sub(1) {
+                    Some(idx) => (self.changes()[idx].0, self.seconds[idx]),
+                    None => (Tick::ZERO, 0.0),
+                };
+                let tempo = self.tempo_at(tick) as f64;
+
+                seconds + (tick.get() - start.get()) as f64 * tempo / tpq / 1_000_000.0
+            }
+            Division::TimeCodeBased(smpte) => tick.get() as f64 / smpte.ticks_per_second(),
+        }
+    }
+}
+
+impl Midi {
+    /// Gathers every tempo change across all tracks into a [`TempoMap`]. Simultaneous tempo
+    /// changes resolve like [`Midi::time_signature_map`]
+    pub fn tempo_map(&self) -> TempoMap {
+        let tempos = self.signature_map(DEFAULT_TEMPO, |event| match event {
+            MetaEvent::Tempo(tempo) => Some(*tempo),
+            _ => None,
+        });
+
+        let tpq = self
+            .header
+            .division()
+            .ticks_per_quarter()
+            .unwrap_or(1)
+            .max(1) as f64;
+        let mut seconds = Vec::with_capacity(tempos.changes().len());
+        let (mut last_tick, mut last_tempo, mut elapsed) =
+            (Tick::ZERO, tempos.default_value(), 0.0);
+        for &(tick, tempo) in tempos.changes() {
+            elapsed +=
+                (tick.get() - last_tick.get()) as f64 * last_tempo as f64 / tpq / 1_000_000.0;
+            seconds.push(elapsed);
+            (last_tick, last_tempo) = (tick, tempo);
+        }
+
+        TempoMap {
+            division: self.header.division(),
+            tempos,
+            seconds,
+        }
+    }
+
+    /// The length of the file in ticks, which is the tick of the last event in the longest track
+    pub fn duration(&self) -> Tick {
+        self.tracks
+            .iter()
+            .map(TrackChunk::duration)
+            .max()
+            .unwrap_or(Tick::ZERO)
+    }
+
+    /// The length of the file in seconds
+    pub fn duration_seconds(&self) -> f64 {
+        self.tempo_map().seconds_at(self.duration())
+    }
+
+    /// Removes redundant tempo events from dense tempo automation, returning how many were
+    /// removed. Working through the tempo changes of [`Midi::tempo_map`] in order, a change is
+    /// dropped if it's less than `min_interval_ticks` after the last kept change, or if it and
+    /// every change dropped since the last kept one are within `max_deviation_bpm` of a straight
+    /// line from the last kept change to the one after it. The first and last changes and any
+    /// change at the same tick as a time signature change are always kept.
+    ///
+    /// Every tempo event at a dropped tick is removed from whichever track holds it. Fails
+    /// without changing anything if removing them would leave a gap too large for a delta time
+    pub fn thin_tempo_events(
+        &mut self,
+        max_deviation_bpm: f64,
+        min_interval_ticks: u32,
+    ) -> Result<usize, EditError> {
+        let bpm = |tempo: u32| 60_000_000.0 / tempo.max(1) as f64;
+        let changes: Vec<(u64, f64)> = self
+            .tempo_map()
+            .changes()
+            .iter()
+            .map(|(tick, tempo)| (tick.get(), bpm(*tempo)))
+            .collect();
+        let boundaries: BTreeSet<u64> = self
+            .time_signature_map()
+            .changes()
+            .iter()
+            .map(|(tick, _)| tick.get())
+            .collect();
+
+        let pinned = |idx: usize| idx + 1 == changes.len() || boundaries.contains(&changes[idx].0);
+        // Whether every change after `anchor` up to `last` lies near the line from `anchor` to
+        // the change after `last`
+        let on_line = |anchor: usize, last: usize| {
+            let ((start, from), (end, to)) = (changes[anchor], changes[last + 1]);
+            changes[anchor + 1..=last].iter().all(|&(tick, value)| {
+                let expected = from + (to - from) * (tick - start) as f64 / (end - start) as f64;
+                (value - expected).abs() <= max_deviation_bpm
+            })
+        };
+
+        let mut kept = BTreeSet::new();
+        let mut anchor = 0;
+        while anchor < changes.len() {
+            kept.insert(changes[anchor].0);
+
+            let mut next = anchor + 1;
+            while next < changes.len()
+                && !pinned(next)
+                && (changes[next].0 - changes[anchor].0 < min_interval_ticks as u64
+                    || on_line(anchor, next))
+            {
+                next += 1;
+            }
+            anchor = next;
+        }
+
+        let mut removed = 0;
+        let mut tracks = Vec::with_capacity(self.tracks.len());
+        for track in &self.tracks {
+            let mut editor = TrackEditor::new(track.clone());
+            editor.retain(|tick, event| {
+                let drop = matches!(event, Event::MetaEvent(MetaEvent::Tempo(_)))
+                    && !kept.contains(&tick.get());
+                removed += drop as usize;
+                !drop
+            });
+
+            tracks.push(editor.finish()?);
+        }
+
+        self.tracks = tracks;
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DEFAULT_TEMPO;
+    use crate::{
+        chunk::{
+            header::HeaderChunk,
+            track::{
+                editor::EditError,
+                meta::{MetaEvent, TimeSignature},
+                Event, TrackChunk,
+            },
+        },
+        time::Tick,
+        Midi,
+    };
+
+    fn tempo_midi(division: u16) -> Midi {
+        let track = TrackChunk::from_absolute_events([
+            (96, Event::MetaEvent(MetaEvent::Tempo(1_000_000))),
+            (192, Event::MetaEvent(MetaEvent::Tempo(250_000))),
+            (1000, Event::MetaEvent(MetaEvent::EndOfTrack)),
+        ])
+        .unwrap();
+
+        Midi {
+            header: HeaderChunk::try_from((0, 1, division)).unwrap(),
+            tracks: vec![track],
+        }
+    }
+
+    #[test]
+    fn seconds_follow_tempo_changes() {
+        let midi = tempo_midi(96);
+        let map = midi.tempo_map();
+
+        assert_eq!(map.tempo_at(0), DEFAULT_TEMPO);
+        assert_eq!(map.seconds_at(96), 0.5);
+        assert_eq!(map.seconds_at(144), 1.0);
+        assert_eq!(map.seconds_at(192), 1.5);
+        assert_eq!(map.seconds_at(288), 1.75);
+        assert_eq!(map.tempo_range(), (250_000, 1_000_000));
+        assert_eq!(midi.duration(), Tick::new(1000));
+    }
+
+    #[test]
+    fn tempo_ramp_is_thinned() {
+        // A one tick per step accelerando from 100 to 120 BPM, then a few minutes at 120 with
+        // a change of meter part way through the ramp
+        let mut events: Vec<(u64, Event)> = (0..1000)
+            .map(|step| {
+                let bpm = 100.0 + 20.0 * step as f64 / 999.0;
+                let tempo = (60_000_000.0 / bpm).round() as u32;
+                (step, Event::MetaEvent(MetaEvent::Tempo(tempo)))
+            })
+            .collect();
+        events.push((
+            500,
+            Event::MetaEvent(MetaEvent::TimeSignature(TimeSignature {
+                numerator: 3,
+                denominator: 4,
+                clocks_per_tick: 24,
+                thirty_second_notes_per_quarter: 8,
+            })),
+        ));
+        events.push((192_000, Event::MetaEvent(MetaEvent::EndOfTrack)));
+        events.sort_by_key(|(tick, _)| *tick);
+        let original = Midi {
+            header: HeaderChunk::try_from((0, 1, 480)).unwrap(),
+            tracks: vec![TrackChunk::from_absolute_events(events).unwrap()],
+        };
+
+        let mut thinned = original.clone();
+        let removed = thinned.thin_tempo_events(0.5, 10).unwrap();
+        let kept: Vec<u64> = thinned
+            .tempo_map()
+            .changes()
+            .iter()
+            .map(|(tick, _)| tick.get())
+            .collect();
+
+        assert_eq!(removed + kept.len(), 1000);
+        assert!(kept.len() <= 5, "{kept:?}");
+        assert_eq!(kept.first(), Some(&0));
+        assert_eq!(kept.last(), Some(&999));
+        assert!(kept.contains(&500));
+
+        let (before, after) = (original.duration_seconds(), thinned.duration_seconds());
+        assert!((after - before).abs() / before < 0.001);
+
+        // Anything off the line between its neighbors stays, unless it's too close to them
+        let mut spiked = original;
+        let Event::MetaEvent(MetaEvent::Tempo(tempo)) =
+            spiked.tracks[0].events_mut().nth(250).unwrap().event_mut()
+        else {
+            unreachable!()
+        };
+        *tempo = 300_000;
+        let mut kept = spiked.clone();
+        kept.thin_tempo_events(0.5, 0).unwrap();
+        assert_eq!(kept.tempo_map().tempo_at(250), 300_000);
+        spiked.thin_tempo_events(0.5, 10).unwrap();
+        assert_ne!(spiked.tempo_map().tempo_at(250), 300_000);
+    }
+
+    #[test]
+    fn thinning_fails_rather_than_overflow_a_delta_time() {
+        const MAX: u64 = 0x0FFF_FFFF;
+        let mut midi = Midi {
+            header: HeaderChunk::try_from((0, 1, 480)).unwrap(),
+            tracks: vec![TrackChunk::from_absolute_events([
+                (0, Event::MetaEvent(MetaEvent::Tempo(500_000))),
+                (MAX, Event::MetaEvent(MetaEvent::Tempo(545_454))),
+                (2 * MAX, Event::MetaEvent(MetaEvent::Tempo(600_000))),
+                (2 * MAX, Event::MetaEvent(MetaEvent::EndOfTrack)),
+            ])
+            .unwrap()],
+        };
+        let original = midi.clone();
+
+        assert_eq!(
+            midi.thin_tempo_events(1.0, 0),
+            Err(EditError::DeltaOverflow(Tick::new(2 * MAX)))
+        );
+        assert_eq!(midi, original);
+    }
+
+    #[test]
+    fn timecode_division_ignores_tempo() {
+        // 25 fps, 40 ticks per frame
+        let midi = tempo_midi(0xE728);
+        assert_eq!(midi.tempo_map().seconds_at(1000), 1.0);
+        assert_eq!(midi.tempo_map().seconds_at(500), 0.5);
+        assert_eq!(midi.duration_seconds(), 1.0);
+        assert_eq!(
+            midi.smpte_time_at(1000).map(|time| time.to_string()),
+            Some("00:00:01:00.00".to_string())
+        );
+
+        assert_eq!(tempo_midi(96).smpte_time_at(1000), None);
+    }
+}
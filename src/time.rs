@@ -0,0 +1,421 @@
+//! Distinct types for relative delta times and absolute tick positions, so the two can't be mixed
+//! up with each other or with other integer quantities like microseconds
+
+use core::ops::{Add, AddAssign};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chunk::{header::SmpteTicks, track::MAX_DELTA_TIME},
+    vlq,
+};
+
+/// Ticks waited after the previous event in a track before the next one.
+///
+/// Converts cheaply to and from `u32` with `.into()`, clamping values above [`DeltaTime::MAX`],
+/// the largest delta time a file can store. Use `DeltaTime::try_from` with a `u64` to reject them
+/// instead, as deserializing does. Arithmetic is checked or saturating against the same limit
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(try_from = "u64", into = "u32")
+)]
+pub struct DeltaTime(pub(crate) u32);
+
+/// An absolute position in ticks from the start of a track
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
+pub struct Tick(pub(crate) u64);
+
+/// Error converting a tick count too large for the 4 byte variable length quantity a file stores
+/// delta times in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeltaTimeOverflow(pub u64);
+
+impl core::error::Error for DeltaTimeOverflow {}
+impl core::fmt::Display for DeltaTimeOverflow {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write![
+            f,
+            "{} ticks is larger than the maximum delta time of {MAX_DELTA_TIME}",
+            self.0
+        ]
+    }
+}
+
+impl DeltaTime {
+    /// No wait at all, the event happens together with the previous one
+    pub const ZERO: Self = Self(0);
+    /// The largest delta time that can be stored in a file, see [`MAX_DELTA_TIME`]
+    pub const MAX: Self = Self(MAX_DELTA_TIME);
+
+    /// Creates a delta time of `ticks`, clamped to [`DeltaTime::MAX`] so it always encodes as a
+    /// valid variable length quantity
+    pub const fn new(ticks: u32) -> Self {
+        if ticks > MAX_DELTA_TIME {
+            Self::MAX
+        } else {
+            Self(ticks)
+        }
+    }
+
+    /// The number of ticks
+    pub const fn get(self) -> u32 {
+        self.0
+    }
+
+    /// Adds two delta times, returning `None` if the sum is larger than [`DeltaTime::MAX`]
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
+        self.0
+            .checked_add(rhs.0)
+            .filter(|ticks| *ticks <= MAX_DELTA_TIME)
+            .map(Self)
+    }
+
+    /// Adds two delta times, stopping at [`DeltaTime::MAX`]
+    pub fn saturating_add(self, rhs: Self) -> Self {
+        self.checked_add(rhs).unwrap_or(Self::MAX)
+    }
+
+    /// Subtracts a delta time, returning `None` if it's larger than `self`
+    pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+        self.0.checked_sub(rhs.0).map(Self)
+    }
+
+    /// Subtracts a delta time, stopping at zero
+    pub fn saturating_sub(self, rhs: Self) -> Self {
+        Self(self.0.saturating_sub(rhs.0))
+    }
+
+    /// Encodes the delta time as a variable length quantity, the way it's stored in a file
+    pub fn to_vlq(self) -> Vec<u8> {
+        vlq::encode(self.0).to_vec()
+    }
+
+    /// Decodes a variable length quantity from the start of `bytes`, returning the delta time and
+    /// how many bytes it took up. Returns `None` if `bytes` ends partway through the quantity or
+    /// it runs longer than the 4 bytes the spec allows
+    pub fn from_vlq_bytes(bytes: &[u8]) -> Option<(Self, usize)> {
+        vlq::decode(bytes)
+            .ok()
+            .map(|(ticks, len)| (Self(ticks), len))
+    }
+}
+
+impl Tick {
+    /// The start of the track
+    pub const ZERO: Self = Self(0);
+
+    /// Creates a position `ticks` from the start of the track
+    pub const fn new(ticks: u64) -> Self {
+        Self(ticks)
+    }
+
+    /// The number of ticks from the start of the track
+    pub const fn get(self) -> u64 {
+        self.0
+    }
+
+    /// Moves the position later by a delta time, returning `None` on overflow
+    pub fn checked_add(self, delta_time: DeltaTime) -> Option<Self> {
+        self.0.checked_add(delta_time.0 as u64).map(Self)
+    }
+
+    /// Moves the position later by a delta time, stopping at the largest possible tick
+    pub fn saturating_add(self, delta_time: DeltaTime) -> Self {
+        Self(self.0.saturating_add(delta_time.0 as u64))
+    }
+
+    /// The number of ticks from `earlier` to `self`, or `None` if `earlier` is actually later
+    pub fn checked_sub(self, earlier: Self) -> Option<u64> {
+        self.0.checked_sub(earlier.0)
+    }
+
+    /// The delta time an event at `self` needs when following an event at `earlier`. Returns
+    /// `None` if `earlier` is actually later, or the gap is larger than [`DeltaTime::MAX`]
+    pub fn delta_since(self, earlier: Self) -> Option<DeltaTime> {
+        self.checked_sub(earlier)
+            .and_then(|ticks| DeltaTime::try_from(ticks).ok())
+    }
+}
+
+/// Saturates rather than overflowing, which no real track can get anywhere near
+impl Add<DeltaTime> for Tick {
+    type Output = Tick;
+
+    fn add(self, rhs: DeltaTime) -> Tick {
+        self.saturating_add(rhs)
+    }
+}
+
+impl AddAssign<DeltaTime> for Tick {
+    fn add_assign(&mut self, rhs: DeltaTime) {
+        *self = *self + rhs;
+    }
+}
+
+/// Clamps to [`DeltaTime::MAX`] like [`DeltaTime::new`], so a value too large to store silently
+/// becomes the largest one. Use [`DeltaTime::try_from`] with a `u64` to catch it instead
+impl From<u32> for DeltaTime {
+    fn from(value: u32) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<DeltaTime> for u32 {
+    fn from(value: DeltaTime) -> Self {
+        value.0
+    }
+}
+
+impl From<DeltaTime> for u64 {
+    fn from(value: DeltaTime) -> Self {
+        value.0 as u64
+    }
+}
+
+impl TryFrom<u64> for DeltaTime {
+    type Error = DeltaTimeOverflow;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        if value > MAX_DELTA_TIME as u64 {
+            Err(DeltaTimeOverflow(value))
+        } else {
+            Ok(Self(value as u32))
+        }
+    }
+}
+
+impl From<u64> for Tick {
+    fn from(value: u64) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Tick> for u64 {
+    fn from(value: Tick) -> Self {
+        value.0
+    }
+}
+
+/// A delta time measured from the start of the track
+impl From<DeltaTime> for Tick {
+    fn from(value: DeltaTime) -> Self {
+        Self(value.0 as u64)
+    }
+}
+
+impl core::fmt::Display for DeltaTime {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write![f, "{}", self.0]
+    }
+}
+
+impl core::fmt::Display for Tick {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write![f, "{}", self.0]
+    }
+}
+
+/// A position in a file with a time-code-based division, as SMPTE timecode. Formats as
+/// `hh:mm:ss:ff.sf`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SmpteTime {
+    /// Whole hours, which keep counting past 24
+    pub hours: u64,
+    /// Minutes into the hour
+    pub minutes: u8,
+    /// Seconds into the minute
+    pub seconds: u8,
+    /// Frames into the second
+    pub frames: u8,
+    /// Hundredths of a frame, like an SMPTE offset meta event's fractional frames
+    pub subframes: u8,
+}
+
+impl SmpteTime {
+    /// Frames in ten minutes of 29.97 fps drop-frame timecode
+    const DROP_FRAMES_PER_TEN_MINUTES: u64 = 17_982;
+    /// Frames in a minute of 29.97 fps drop-frame timecode that isn't a multiple of ten
+    const DROP_FRAMES_PER_MINUTE: u64 = 1_798;
+
+    /// The timecode of an absolute tick in a file with the given division. Frames are counted
+    /// exactly in whole ticks, so no rounding error builds up over long files. The 29.97 fps
+    /// format is labelled as drop-frame timecode, which skips frame numbers 0 and 1 at the start
+    /// of every minute except each tenth
+    pub fn from_tick(tick: impl Into<Tick>, division: SmpteTicks) -> Self {
+        let tick = tick.into().get();
+        let tpf = division.tpf.max(1) as u64;
+        let subframes = ((tick % tpf) * 100 / tpf) as u8;
+
+        let (frame, fps) = match -(division.smpte as i16) {
+            29 => (Self::drop_frame_label(tick / tpf), 30),
+            fps => (tick / tpf, fps.max(1) as u64),
+        };
+        let seconds = frame / fps;
+
+        Self {
+            hours: seconds / 3600,
+            minutes: (seconds / 60 % 60) as u8,
+            seconds: (seconds % 60) as u8,
+            frames: (frame % fps) as u8,
+            subframes,
+        }
+    }
+
+    /// Maps a count of 29.97 fps frames to the 30 fps frame number drop-frame timecode labels
+    /// it with
+    fn drop_frame_label(frame: u64) -> u64 {
+        let tens = frame / Self::DROP_FRAMES_PER_TEN_MINUTES;
+        let within = frame % Self::DROP_FRAMES_PER_TEN_MINUTES;
+        let minutes = within.saturating_sub(2) / Self::DROP_FRAMES_PER_MINUTE;
+
+        frame + 18 * tens + 2 * minutes
+    }
+}
+
+impl core::fmt::Display for SmpteTime {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write![
+            f,
+            "{:02}:{:02}:{:02}:{:02}.{:02}",
+            self.hours, self.minutes, self.seconds, self.frames, self.subframes
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeltaTime, DeltaTimeOverflow, SmpteTime, Tick};
+    use crate::chunk::header::{Division, SmpteTicks};
+
+    fn smpte(division: u16) -> SmpteTicks {
+        match Division::from(division) {
+            Division::TimeCodeBased(smpte) => smpte,
+            Division::Metrical(_) => unreachable!("Only time-code-based divisions are used"),
+        }
+    }
+
+    #[test]
+    fn smpte_time_counts_whole_frames() {
+        // 25 fps, 40 ticks per frame
+        let division = smpte(0xE728);
+        let at = |tick: u64| SmpteTime::from_tick(tick, division);
+
+        assert_eq!(at(0), SmpteTime::default());
+        assert_eq!(
+            at(1000),
+            SmpteTime {
+                seconds: 1,
+                ..SmpteTime::default()
+            }
+        );
+        assert_eq!(at(1020).to_string(), "00:00:01:00.50");
+        assert_eq!(at(1039).to_string(), "00:00:01:00.97");
+        assert_eq!(at(999).to_string(), "00:00:00:24.97");
+        assert_eq!(at(1000 * 3723 + 40 * 7).to_string(), "01:02:03:07.00");
+        assert_eq!(at(1000 * 3600 * 30).hours, 30);
+    }
+
+    #[test]
+    fn smpte_time_drops_frame_numbers_at_29_97() {
+        // 29.97 fps drop-frame, 1 tick per frame
+        let division = smpte(0xE301);
+        let at = |frame: u64| SmpteTime::from_tick(frame, division).to_string();
+
+        assert_eq!(at(1799), "00:00:59:29.00");
+        assert_eq!(at(1800), "00:01:00:02.00");
+        assert_eq!(at(17_981), "00:09:59:29.00");
+        assert_eq!(at(17_982), "00:10:00:00.00");
+        assert_eq!(at(17_982 + 1800), "00:11:00:02.00");
+        // An hour of drop-frame timecode is exactly 107,892 frames
+        assert_eq!(at(107_892), "01:00:00:00.00");
+    }
+
+    #[test]
+    fn vlq_boundaries_round_trip() {
+        for (ticks, encoded) in [
+            (0, vec![0x00]),
+            (0x7F, vec![0x7F]),
+            (0x80, vec![0x81, 0x00]),
+            (0x3FFF, vec![0xFF, 0x7F]),
+            (0x4000, vec![0x81, 0x80, 0x00]),
+            (0x0FFF_FFFF, vec![0xFF, 0xFF, 0xFF, 0x7F]),
+        ] {
+            let delta_time = DeltaTime::new(ticks);
+            assert_eq!(delta_time.to_vlq(), encoded);
+
+            let mut trailing = encoded.clone();
+            trailing.push(0x42);
+            assert_eq!(
+                DeltaTime::from_vlq_bytes(&trailing),
+                Some((delta_time, encoded.len()))
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_vlq_is_rejected() {
+        assert_eq!(DeltaTime::from_vlq_bytes(&[]), None);
+        assert_eq!(DeltaTime::from_vlq_bytes(&[0x81, 0x80]), None);
+        assert_eq!(
+            DeltaTime::from_vlq_bytes(&[0xFF, 0xFF, 0xFF, 0xFF, 0x7F]),
+            None
+        );
+    }
+
+    #[test]
+    fn arithmetic_stops_at_the_largest_delta_time() {
+        let almost = DeltaTime::new(0x0FFF_FFFE);
+        assert_eq!(almost.checked_add(1.into()), Some(DeltaTime::MAX));
+        assert_eq!(almost.checked_add(2.into()), None);
+        assert_eq!(
+            DeltaTime::MAX.saturating_add(DeltaTime::MAX),
+            DeltaTime::MAX
+        );
+        assert_eq!(DeltaTime::new(u32::MAX).checked_add(1.into()), None);
+
+        // Too large to store, so clamped rather than written as a 5 byte quantity
+        assert_eq!(DeltaTime::new(u32::MAX), DeltaTime::MAX);
+        assert_eq!(DeltaTime::from(0x1000_0000u32), DeltaTime::MAX);
+        assert_eq!(DeltaTime::new(u32::MAX).to_vlq(), [0xFF, 0xFF, 0xFF, 0x7F]);
+
+        assert_eq!(DeltaTime::new(3).checked_sub(4.into()), None);
+        assert_eq!(DeltaTime::new(3).saturating_sub(4.into()), DeltaTime::ZERO);
+
+        assert_eq!(DeltaTime::try_from(0x0FFF_FFFFu64), Ok(DeltaTime::MAX));
+        assert_eq!(
+            DeltaTime::try_from(0x1000_0000u64),
+            Err(DeltaTimeOverflow(0x1000_0000))
+        );
+    }
+
+    #[test]
+    fn ticks_advance_by_delta_times() {
+        let mut tick = Tick::ZERO + DeltaTime::new(96);
+        tick += 32.into();
+        assert_eq!(tick, Tick::new(128));
+
+        assert_eq!(Tick::new(u64::MAX).checked_add(1.into()), None);
+        assert_eq!(Tick::new(u64::MAX) + DeltaTime::MAX, Tick::new(u64::MAX));
+
+        assert_eq!(tick.delta_since(Tick::new(100)), Some(DeltaTime::new(28)));
+        assert_eq!(Tick::new(100).delta_since(tick), None);
+        assert_eq!(Tick::new(0x1000_0000).delta_since(Tick::ZERO), None);
+        assert_eq!(tick.checked_sub(Tick::new(28)), Some(100));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserializing_rejects_oversized_delta_times() {
+        assert_eq!(serde_json::to_string(&DeltaTime::MAX).unwrap(), "268435455");
+        assert_eq!(
+            serde_json::from_str::<DeltaTime>("268435455").unwrap(),
+            DeltaTime::MAX
+        );
+        assert!(serde_json::from_str::<DeltaTime>("268435456").is_err());
+    }
+}
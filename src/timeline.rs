@@ -0,0 +1,840 @@
+//! Timelines of channel state over the absolute time of a MIDI file
+
+use std::collections::BTreeMap;
+
+use crate::{
+    chunk::{
+        header::Division,
+        track::{
+            editor::EditError,
+            event::MidiEvent,
+            meta::{KeySignature, MetaEvent, TimeSignature},
+            Event, TrackChunk,
+        },
+    },
+    time::{SmpteTime, Tick},
+    Midi,
+};
+
+/// Controller number of the Bank Select MSB control change
+pub(crate) const BANK_SELECT_MSB: u8 = 0;
+/// Controller number of the Bank Select LSB control change
+pub(crate) const BANK_SELECT_LSB: u8 = 32;
+
+/// Controller number of the Data Entry MSB control change
+pub(crate) const DATA_ENTRY_MSB: u8 = 6;
+/// Controller number of the Data Entry LSB control change
+pub(crate) const DATA_ENTRY_LSB: u8 = 38;
+/// Controller number of the Non-Registered Parameter Number LSB control change
+pub(crate) const NRPN_LSB: u8 = 98;
+/// Controller number of the Non-Registered Parameter Number MSB control change
+pub(crate) const NRPN_MSB: u8 = 99;
+/// Controller number of the Registered Parameter Number LSB control change
+pub(crate) const RPN_LSB: u8 = 100;
+/// Controller number of the Registered Parameter Number MSB control change
+pub(crate) const RPN_MSB: u8 = 101;
+
+/// The pitch bend range a channel starts with, in semitones either way
+pub const DEFAULT_BEND_RANGE: f32 = 2.0;
+
+/// A program change with its preceding bank select, as `(bank_msb, bank_lsb, program)`
+pub type BankProgram = (u8, u8, u8);
+
+/// An ordered list of `(tick, value)` changes to a piece of conductor state, such as the key or
+/// time signature, with a default that applies before the first change
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignatureMap<T> {
+    /// Each change in ascending tick order, at most one per tick
+    changes: Vec<(Tick, T)>,
+    /// The value in effect before any change
+    default: T,
+    /// Ticks where different tracks disagreed on the value
+    conflicts: Vec<Tick>,
+}
+
+impl<T: Copy + PartialEq> SignatureMap<T> {
+    /// Builds a map from `(tick, track index, value)` entries listed in per-track order.
+    /// Simultaneous changes resolve to the lowest track index, and within that track the last
+    /// change at the tick wins
+    fn from_entries(mut entries: Vec<(Tick, usize, T)>, default: T) -> Self {
+        entries.sort_by_key(|(tick, track, _)| (*tick, *track));
+
+        let mut changes: Vec<(Tick, T)> = vec![];
+        let mut conflicts = vec![];
+        let mut winning_track = 0;
+
+        for (tick, track, value) in entries {
+            match changes.last_mut() {
+                Some((last_tick, last)) if *last_tick == tick => {
+                    if track == winning_track {
+                        *last = value;
+                    } else if *last != value && conflicts.last() != Some(&tick) {
+                        conflicts.push(tick);
+                    }
+                }
+                _ => {
+                    changes.push((tick, value));
+                    winning_track = track;
+                }
+            }
+        }
+
+        Self {
+            changes,
+            default,
+            conflicts,
+        }
+    }
+
+    /// Returns the value active at the given tick
+    pub fn at(&self, tick: impl Into<Tick>) -> T {
+        let tick = tick.into();
+        let idx = self
+            .changes
+            .partition_point(|(change_tick, _)| *change_tick <= tick);
+
+        idx.checked_sub(1)
+            .map(|idx| self.changes[idx].1)
+            .unwrap_or(self.default)
+    }
+
+    /// All changes in ascending tick order
+    pub fn changes(&self) -> &[(Tick, T)] {
+        &self.changes
+    }
+
+    /// The value in effect before the first change
+    pub fn default_value(&self) -> T {
+        self.default
+    }
+
+    /// Ticks where multiple tracks set conflicting values. The lowest track index wins at each
+    pub fn conflicts(&self) -> &[Tick] {
+        &self.conflicts
+    }
+}
+
+/// Error from converting a bar:beat:tick position into an absolute tick
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PositionError {
+    /// Bars and beats are 1-indexed, but 0 was given
+    ZeroIndexed,
+    /// The beat doesn't exist in the bar's time signature
+    BeatOutOfRange,
+    /// The tick is past the end of the beat
+    TickOutOfRange,
+    /// The file's division is time-code-based, so it has no bars or beats
+    TimeCodeDivision,
+    /// The position lies past the largest tick that can be represented
+    TickOverflow,
+}
+
+impl core::error::Error for PositionError {}
+impl core::fmt::Display for PositionError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::ZeroIndexed => write![f, "Bars and beats are 1-indexed"],
+            Self::BeatOutOfRange => write![f, "Beat is outside of the bar's time signature"],
+            Self::TickOutOfRange => write![f, "Tick is past the end of the beat"],
+            Self::TimeCodeDivision => write![f, "Time-code-based files have no bars or beats"],
+            Self::TickOverflow => write![f, "Position is past the largest representable tick"],
+        }
+    }
+}
+
+/// A span of the file that shares a single time signature
+pub(crate) struct MeterSegment {
+    /// Absolute tick the segment starts at
+    pub(crate) start: u64,
+    /// Absolute tick the segment ends at, `None` for the final segment
+    pub(crate) end: Option<u64>,
+    /// Number of bars that came before this segment
+    pub(crate) bars_before: u64,
+    /// Length of one beat in ticks
+    pub(crate) beat_len: u64,
+    /// Beats per bar
+    pub(crate) beats: u64,
+}
+
+impl MeterSegment {
+    /// Length of a bar in ticks
+    fn bar_len(&self) -> u64 {
+        self.beat_len * self.beats
+    }
+}
+
+impl Midi {
+    /// Formats an absolute tick as a `bar:beat:tick` position, with 1-indexed bars and beats and
+    /// the tick counted from the start of the beat. Time signature changes are walked, with each
+    /// change starting a new bar. Files with a time-code-based division have no notion of beats,
+    /// so their positions are formatted as a plain tick count; see [`Midi::smpte_time_at`] for
+    /// those
+    pub fn format_position(&self, tick: impl Into<Tick>) -> String {
+        let tick = tick.into().get();
+        let Some(segments) = self.meter_segments() else {
+            return tick.to_string();
+        };
+
+        let segment = segments
+            .iter()
+            .rev()
+            .find(|segment| segment.start <= tick)
+            .expect("The first segment always starts at tick 0");
+
+        let offset = tick - segment.start;
+        let bar = segment.bars_before + offset / segment.bar_len() + 1;
+        let within_bar = offset % segment.bar_len();
+        let beat = within_bar / segment.beat_len + 1;
+        let tick_in_beat = within_bar % segment.beat_len;
+
+        format!("{bar}:{beat}:{tick_in_beat}")
+    }
+
+    /// The SMPTE timecode of an absolute tick, or `None` if the file's division is metrical. Tempo
+    /// events don't affect time-code-based files, so this depends only on the division
+    pub fn smpte_time_at(&self, tick: impl Into<Tick>) -> Option<SmpteTime> {
+        match self.header.division() {
+            Division::TimeCodeBased(smpte) => Some(SmpteTime::from_tick(tick, smpte)),
+            Division::Metrical(_) => None,
+        }
+    }
+
+    /// Converts a 1-indexed `bar:beat:tick` position back into an absolute tick, walking any time
+    /// signature changes along the way
+    pub fn position_to_tick(
+        &self,
+        bar: u64,
+        beat: u64,
+        tick_in_beat: u64,
+    ) -> Result<Tick, PositionError> {
+        if bar == 0 || beat == 0 {
+            return Err(PositionError::ZeroIndexed);
+        }
+
+        let segments = self
+            .meter_segments()
+            .ok_or(PositionError::TimeCodeDivision)?;
+        let segment = segments
+            .iter()
+            .rev()
+            .find(|segment| segment.bars_before < bar)
+            .expect("The first segment never has bars before it");
+
+        if beat > segment.beats {
+            return Err(PositionError::BeatOutOfRange);
+        }
+        if tick_in_beat >= segment.beat_len {
+            return Err(PositionError::TickOutOfRange);
+        }
+
+        let tick = (bar - segment.bars_before - 1)
+            .checked_mul(segment.bar_len())
+            .and_then(|offset| offset.checked_add(segment.start))
+            .and_then(|tick| tick.checked_add((beat - 1) * segment.beat_len + tick_in_beat))
+            .ok_or(PositionError::TickOverflow)?;
+
+        match segment.end {
+            Some(end) if tick >= end => Err(PositionError::BeatOutOfRange),
+            _ => Ok(Tick(tick)),
+        }
+    }
+
+    /// Pads every track with [`TrackChunk::pad_to`] so they all end on the first bar boundary at
+    /// or after the end of the longest track, returning that tick. A bar cut short by a time
+    /// signature change counts as a boundary where the change happens. Time-code-based files
+    /// have no bars and are left unchanged. If any track can't reach the boundary, no track is
+    /// padded
+    pub fn pad_all_tracks_to_bar(&mut self) -> Result<Tick, EditError> {
+        let end = self
+            .tracks
+            .iter()
+            .map(TrackChunk::duration)
+            .max()
+            .unwrap_or(Tick::ZERO);
+        let Some(segments) = self.meter_segments() else {
+            return Ok(end);
+        };
+
+        let segment = segments
+            .iter()
+            .rev()
+            .find(|segment| segment.start <= end.get())
+            .expect("The first segment always starts at tick 0");
+        let bars = (end.get() - segment.start).div_ceil(segment.bar_len());
+        let mut boundary = segment.start + bars * segment.bar_len();
+        if let Some(segment_end) = segment.end {
+            boundary = boundary.min(segment_end);
+        }
+        let boundary = Tick(boundary);
+
+        for track in &self.tracks {
+            track.padding_delta(boundary)?;
+        }
+        for track in &mut self.tracks {
+            track.pad_to(boundary)?;
+        }
+
+        Ok(boundary)
+    }
+
+    /// Splits the file into spans of constant meter, or `None` for time-code-based files
+    pub(crate) fn meter_segments(&self) -> Option<Vec<MeterSegment>> {
+        let tpq = self.header.division().ticks_per_quarter()? as u64;
+        let map = self.time_signature_map();
+
+        let mut starts = vec![(0, map.default_value())];
+        for &(tick, signature) in map.changes() {
+            let tick = tick.get();
+            match starts.last_mut() {
+                Some(last) if last.0 == tick => last.1 = signature,
+                _ => starts.push((tick, signature)),
+            }
+        }
+
+        let mut segments: Vec<MeterSegment> = vec![];
+        for (idx, &(start, signature)) in starts.iter().enumerate() {
+            let end = starts.get(idx + 1).map(|(end, _)| *end);
+            let bars_before = segments.last().map_or(0, |last| {
+                let len = start - last.start;
+                last.bars_before + len.div_ceil(last.bar_len())
+            });
+
+            segments.push(MeterSegment {
+                start,
+                end,
+                bars_before,
+                beat_len: (tpq * 4 / signature.denominator.max(1) as u64).max(1),
+                beats: (signature.numerator as u64).max(1),
+            });
+        }
+
+        Some(segments)
+    }
+
+    /// The tick of every bar line before `until`, each time signature change starting a new bar,
+    /// or `None` for time-code-based files
+    pub(crate) fn bar_lines(&self, until: Tick) -> Option<Vec<Tick>> {
+        let mut lines = vec![];
+        for segment in self.meter_segments()? {
+            let end = segment.end.map_or(until.get(), |end| end.min(until.get()));
+            lines.extend(
+                (segment.start..end)
+                    .step_by(segment.bar_len() as usize)
+                    .map(Tick),
+            );
+        }
+
+        Some(lines)
+    }
+
+    /// Collects every key signature change across all tracks. Defaults to C major
+    pub fn key_signature_map(&self) -> SignatureMap<KeySignature> {
+        self.signature_map(KeySignature::default(), |event| match event {
+            MetaEvent::KeySignature(key) => Some(*key),
+            _ => None,
+        })
+    }
+
+    /// Collects every time signature change across all tracks. Defaults to 4/4
+    pub fn time_signature_map(&self) -> SignatureMap<TimeSignature> {
+        self.signature_map(TimeSignature::default(), |event| match event {
+            MetaEvent::TimeSignature(time) => Some(*time),
+            _ => None,
+        })
+    }
+
+    /// Builds a signature map from the meta events picked out by `extract`
+    pub(crate) fn signature_map<T: Copy + PartialEq>(
+        &self,
+        default: T,
+        extract: impl Fn(&MetaEvent) -> Option<T>,
+    ) -> SignatureMap<T> {
+        let mut entries = vec![];
+
+        for (idx, track) in self.tracks.iter().enumerate() {
+            for (tick, meta) in track.meta_events() {
+                if let Some(value) = extract(meta) {
+                    entries.push((tick, idx, value));
+                }
+            }
+        }
+
+        SignatureMap::from_entries(entries, default)
+    }
+
+    /// Maps every channel to the ordered `(tick, program)` pairs of its program changes, gathered
+    /// from all tracks. Simultaneous program changes keep their track order
+    pub fn program_timeline(&self) -> BTreeMap<u8, Vec<(Tick, u8)>> {
+        self.program_bank_timeline()
+            .into_iter()
+            .map(|(channel, changes)| {
+                let changes = changes
+                    .into_iter()
+                    .map(|(tick, (_, _, program))| (tick, program))
+                    .collect();
+                (channel, changes)
+            })
+            .collect()
+    }
+
+    /// Like [`Midi::program_timeline`], but folds the Bank Select MSB (CC 0) and LSB (CC 32)
+    /// messages immediately preceding each program change into a `(bank_msb, bank_lsb, program)`
+    /// triple. A bank select that isn't sent before the program change is reported as 0
+    pub fn program_bank_timeline(&self) -> BTreeMap<u8, Vec<(Tick, BankProgram)>> {
+        let mut timeline: BTreeMap<u8, Vec<(Tick, BankProgram)>> = BTreeMap::new();
+
+        for track in &self.tracks {
+            let mut banks: BTreeMap<u8, (u8, u8)> = BTreeMap::new();
+
+            for (tick, event) in track.absolute_events() {
+                let Event::MidiEvent(event) = event else {
+                    continue;
+                };
+
+                match event {
+                    MidiEvent::ControlChange(channel, cc)
+                        if cc.controller_number == BANK_SELECT_MSB =>
+                    {
+                        banks.entry(*channel).or_default().0 = cc.new_value;
+                    }
+                    MidiEvent::ControlChange(channel, cc)
+                        if cc.controller_number == BANK_SELECT_LSB =>
+                    {
+                        banks.entry(*channel).or_default().1 = cc.new_value;
+                    }
+                    MidiEvent::ProgramChange { channel, program } => {
+                        let (msb, lsb) = banks.remove(channel).unwrap_or_default();
+                        timeline
+                            .entry(*channel)
+                            .or_default()
+                            .push((tick, (msb, lsb, *program)));
+                    }
+                    other => {
+                        banks.remove(&other.channel());
+                    }
+                }
+            }
+        }
+
+        for changes in timeline.values_mut() {
+            changes.sort_by_key(|(tick, _)| *tick);
+        }
+
+        timeline
+    }
+
+    /// Maps every channel to the ordered `(tick, range)` changes of its pitch bend range in
+    /// semitones either way, gathered from all tracks. The range is set by Data Entry (CC 6 for
+    /// semitones, CC 38 for cents) while RPN 0 is selected with CC 101 and CC 100 in the same
+    /// track, and each channel starts at [`DEFAULT_BEND_RANGE`]. Selecting an NRPN deselects the
+    /// RPN. Simultaneous changes keep their track order
+    pub fn bend_range_timeline(&self) -> BTreeMap<u8, Vec<(Tick, f32)>> {
+        let mut timeline: BTreeMap<u8, Vec<(Tick, f32)>> = BTreeMap::new();
+
+        for track in &self.tracks {
+            // Selected RPN as (MSB, LSB), and the range as (semitones, cents), for each channel
+            let mut selected: BTreeMap<u8, (Option<u8>, Option<u8>)> = BTreeMap::new();
+            let mut ranges: BTreeMap<u8, (u8, u8)> = BTreeMap::new();
+
+            for (tick, event) in track.absolute_events() {
+                let Event::MidiEvent(MidiEvent::ControlChange(channel, cc)) = event else {
+                    continue;
+                };
+
+                let rpn = selected.entry(*channel).or_default();
+                match cc.controller_number {
+                    RPN_MSB => rpn.0 = Some(cc.new_value),
+                    RPN_LSB => rpn.1 = Some(cc.new_value),
+                    NRPN_MSB | NRPN_LSB => *rpn = (None, None),
+                    DATA_ENTRY_MSB | DATA_ENTRY_LSB if *rpn == (Some(0), Some(0)) => {
+                        let range = ranges
+                            .entry(*channel)
+                            .or_insert((DEFAULT_BEND_RANGE as u8, 0));
+                        if cc.controller_number == DATA_ENTRY_MSB {
+                            range.0 = cc.new_value;
+                        } else {
+                            range.1 = cc.new_value;
+                        }
+
+                        timeline
+                            .entry(*channel)
+                            .or_default()
+                            .push((tick, range.0 as f32 + range.1 as f32 / 100.0));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        for changes in timeline.values_mut() {
+            changes.sort_by_key(|(tick, _)| *tick);
+        }
+
+        timeline
+    }
+
+    /// The pitch bend range of a channel at the given tick in semitones either way, see
+    /// [`Midi::bend_range_timeline`]
+    pub fn bend_range_at(&self, channel: u8, tick: impl Into<Tick>) -> f32 {
+        let tick = tick.into();
+        let timeline = self.bend_range_timeline();
+        let Some(changes) = timeline.get(&channel) else {
+            return DEFAULT_BEND_RANGE;
+        };
+
+        let idx = changes.partition_point(|(change_tick, _)| *change_tick <= tick);
+        idx.checked_sub(1)
+            .map_or(DEFAULT_BEND_RANGE, |idx| changes[idx].1)
+    }
+
+    /// Returns the program active on a channel at the given tick, if any program change has
+    /// occurred on that channel by then
+    pub fn program_at(&self, channel: u8, tick: impl Into<Tick>) -> Option<u8> {
+        let tick = tick.into();
+        let timeline = self.program_timeline();
+        let changes = timeline.get(&channel)?;
+
+        let idx = changes.partition_point(|(change_tick, _)| *change_tick <= tick);
+        idx.checked_sub(1).map(|idx| changes[idx].1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PositionError, DEFAULT_BEND_RANGE};
+    use crate::{
+        chunk::{
+            header::HeaderChunk,
+            track::{
+                event::{ControlChange, MidiEvent},
+                meta::{KeySignature, MetaEvent, TimeSignature},
+                Event, MTrkEvent, TrackChunk,
+            },
+        },
+        time::Tick,
+        Midi,
+    };
+
+    fn event(delta_time: u32, event: MidiEvent) -> MTrkEvent {
+        MTrkEvent {
+            delta_time: delta_time.into(),
+            event: Event::MidiEvent(event),
+        }
+    }
+
+    fn cc(channel: u8, controller_number: u8, new_value: u8) -> MidiEvent {
+        MidiEvent::ControlChange(
+            channel,
+            ControlChange {
+                controller_number,
+                new_value,
+            },
+        )
+    }
+
+    fn midi() -> Midi {
+        let first = TrackChunk::new(vec![
+            event(
+                0,
+                MidiEvent::ProgramChange {
+                    channel: 2,
+                    program: 5,
+                },
+            ),
+            event(480, cc(2, 0, 1)),
+            event(0, cc(2, 32, 3)),
+            event(
+                0,
+                MidiEvent::ProgramChange {
+                    channel: 2,
+                    program: 40,
+                },
+            ),
+            MTrkEvent {
+                delta_time: 0.into(),
+                event: Event::MetaEvent(MetaEvent::EndOfTrack),
+            },
+        ]);
+        let second = TrackChunk::new(vec![
+            event(
+                240,
+                MidiEvent::ProgramChange {
+                    channel: 2,
+                    program: 19,
+                },
+            ),
+            event(
+                0,
+                MidiEvent::ProgramChange {
+                    channel: 9,
+                    program: 0,
+                },
+            ),
+            MTrkEvent {
+                delta_time: 0.into(),
+                event: Event::MetaEvent(MetaEvent::EndOfTrack),
+            },
+        ]);
+
+        Midi {
+            header: HeaderChunk::try_from((1, 2, 480)).unwrap(),
+            tracks: vec![first, second],
+        }
+    }
+
+    #[test]
+    fn program_timeline_merges_tracks_in_order() {
+        let timeline = midi().program_timeline();
+
+        assert_eq!(
+            timeline[&2],
+            vec![(Tick::ZERO, 5), (Tick::new(240), 19), (Tick::new(480), 40)]
+        );
+        assert_eq!(timeline[&9], vec![(Tick::new(240), 0)]);
+    }
+
+    #[test]
+    fn program_at_finds_active_program() {
+        let midi = midi();
+
+        assert_eq!(midi.program_at(2, 0), Some(5));
+        assert_eq!(midi.program_at(2, 239), Some(5));
+        assert_eq!(midi.program_at(2, 240), Some(19));
+        assert_eq!(midi.program_at(2, 10_000), Some(40));
+        assert_eq!(midi.program_at(9, 0), None);
+        assert_eq!(midi.program_at(4, 480), None);
+    }
+
+    #[test]
+    fn bank_select_folded_into_program_changes() {
+        let timeline = midi().program_bank_timeline();
+
+        assert_eq!(
+            timeline[&2],
+            vec![
+                (Tick::ZERO, (0, 0, 5)),
+                (Tick::new(240), (0, 0, 19)),
+                (Tick::new(480), (1, 3, 40))
+            ]
+        );
+    }
+
+    fn meta(delta_time: u32, event: MetaEvent) -> MTrkEvent {
+        MTrkEvent {
+            delta_time: delta_time.into(),
+            event: Event::MetaEvent(event),
+        }
+    }
+
+    fn time_signature(numerator: u8, denominator: u32) -> TimeSignature {
+        TimeSignature {
+            numerator,
+            denominator,
+            clocks_per_tick: 24,
+            thirty_second_notes_per_quarter: 8,
+        }
+    }
+
+    #[test]
+    fn signature_maps_default_before_first_change() {
+        let midi = midi();
+
+        assert_eq!(midi.time_signature_map().at(0), time_signature(4, 4));
+        assert_eq!(midi.key_signature_map().at(100), KeySignature::default());
+        assert!(midi.time_signature_map().changes().is_empty());
+    }
+
+    #[test]
+    fn time_signature_map_looks_up_active_value() {
+        let mut midi = midi();
+        midi.tracks[1] = TrackChunk::new(vec![
+            meta(960, MetaEvent::TimeSignature(time_signature(3, 4))),
+            meta(960, MetaEvent::TimeSignature(time_signature(7, 8))),
+            meta(0, MetaEvent::EndOfTrack),
+        ]);
+
+        let map = midi.time_signature_map();
+        assert_eq!(map.at(959), time_signature(4, 4));
+        assert_eq!(map.at(960), time_signature(3, 4));
+        assert_eq!(map.at(1919), time_signature(3, 4));
+        assert_eq!(map.at(5000), time_signature(7, 8));
+        assert!(map.conflicts().is_empty());
+    }
+
+    #[test]
+    fn conflicting_signatures_resolve_to_lowest_track() {
+        let minor = KeySignature {
+            sharps_flats: -3,
+            major_minor: true,
+        };
+        let major = KeySignature {
+            sharps_flats: 2,
+            major_minor: false,
+        };
+
+        let mut midi = midi();
+        midi.tracks[1] = TrackChunk::new(vec![
+            meta(480, MetaEvent::KeySignature(minor)),
+            meta(0, MetaEvent::EndOfTrack),
+        ]);
+        midi.tracks.push(TrackChunk::new(vec![
+            meta(480, MetaEvent::KeySignature(major)),
+            meta(0, MetaEvent::EndOfTrack),
+        ]));
+
+        let map = midi.key_signature_map();
+        assert_eq!(map.changes(), &[(Tick::new(480), minor)]);
+        assert_eq!(map.conflicts(), &[Tick::new(480)]);
+    }
+
+    fn meter_change_midi() -> Midi {
+        let mut midi = midi();
+        // 2 bars of 4/4 at 480 tpq, then 7/8
+        midi.tracks[1] = TrackChunk::new(vec![
+            meta(3840, MetaEvent::TimeSignature(time_signature(7, 8))),
+            meta(0, MetaEvent::EndOfTrack),
+        ]);
+        midi
+    }
+
+    #[test]
+    fn positions_default_to_common_time() {
+        let midi = midi();
+
+        assert_eq!(midi.format_position(0), "1:1:0");
+        assert_eq!(midi.format_position(479), "1:1:479");
+        assert_eq!(midi.format_position(480 * 4 + 960 + 240), "2:3:240");
+        assert_eq!(midi.position_to_tick(2, 3, 240), Ok(Tick::new(3120)));
+    }
+
+    #[test]
+    fn positions_walk_meter_changes() {
+        let midi = meter_change_midi();
+
+        assert_eq!(midi.format_position(3839), "2:4:479");
+        assert_eq!(midi.format_position(3840), "3:1:0");
+        // A 7/8 bar is 7 eighth notes of 240 ticks
+        assert_eq!(midi.format_position(3840 + 1680), "4:1:0");
+        assert_eq!(midi.format_position(3840 + 1680 + 6 * 240 + 10), "4:7:10");
+
+        assert_eq!(midi.position_to_tick(3, 1, 0), Ok(Tick::new(3840)));
+        assert_eq!(
+            midi.position_to_tick(4, 7, 10),
+            Ok(Tick::new(3840 + 1680 + 1450))
+        );
+        assert_eq!(midi.position_to_tick(1, 4, 479), Ok(Tick::new(1919)));
+    }
+
+    #[test]
+    fn positions_round_trip() {
+        let midi = meter_change_midi();
+
+        for tick in (0..12_000).step_by(37) {
+            let position = midi.format_position(tick);
+            let parts: Vec<u64> = position.split(':').map(|p| p.parse().unwrap()).collect();
+
+            assert_eq!(
+                midi.position_to_tick(parts[0], parts[1], parts[2]),
+                Ok(Tick::new(tick))
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_positions_error() {
+        let midi = meter_change_midi();
+
+        assert_eq!(
+            midi.position_to_tick(0, 1, 0),
+            Err(PositionError::ZeroIndexed)
+        );
+        assert_eq!(
+            midi.position_to_tick(3, 8, 0),
+            Err(PositionError::BeatOutOfRange)
+        );
+        assert_eq!(
+            midi.position_to_tick(3, 1, 240),
+            Err(PositionError::TickOutOfRange)
+        );
+        assert_eq!(
+            midi.position_to_tick(u64::MAX, 1, 0),
+            Err(PositionError::TickOverflow)
+        );
+    }
+
+    #[test]
+    fn bend_range_follows_rpn_zero() {
+        let cc = |controller, value| {
+            MidiEvent::ControlChange(1, ControlChange::new(controller, value).unwrap())
+        };
+        let track = TrackChunk::new(vec![
+            // An NRPN's data entry doesn't change the range
+            event(0, cc(99, 0)),
+            event(0, cc(98, 0)),
+            event(0, cc(6, 24)),
+            // RPN 0 set to 12 semitones
+            event(0, cc(101, 0)),
+            event(0, cc(100, 0)),
+            event(480, cc(6, 12)),
+            // Then to 12 semitones and 50 cents once the RPN is reselected
+            event(480, cc(101, 127)),
+            event(0, cc(100, 127)),
+            event(0, cc(38, 50)),
+            event(0, cc(101, 0)),
+            event(0, cc(100, 0)),
+            event(0, cc(38, 50)),
+            MTrkEvent::new(0, Event::MetaEvent(MetaEvent::EndOfTrack)),
+        ]);
+        let midi = Midi {
+            header: HeaderChunk::try_from((0, 1, 480)).unwrap(),
+            tracks: vec![track],
+        };
+
+        assert_eq!(
+            midi.bend_range_timeline(),
+            [(1, vec![(Tick::new(480), 12.0), (Tick::new(960), 12.5)])].into()
+        );
+        assert_eq!(midi.bend_range_at(1, 0), DEFAULT_BEND_RANGE);
+        assert_eq!(midi.bend_range_at(1, 500), 12.0);
+        assert_eq!(midi.bend_range_at(0, 1000), DEFAULT_BEND_RANGE);
+    }
+
+    #[test]
+    fn tracks_pad_to_the_next_bar() {
+        let mut midi = midi();
+        // 3.5 bars of 4/4 at 480 tpq, and a second track without an EndOfTrack
+        midi.tracks[0] = TrackChunk::new(vec![
+            event(6720 - 1, cc(0, 7, 100)),
+            meta(1, MetaEvent::EndOfTrack),
+        ]);
+        midi.tracks[1] = TrackChunk::new(vec![event(960, cc(1, 7, 100))]);
+
+        assert_eq!(midi.pad_all_tracks_to_bar(), Ok(Tick::new(7680)));
+        for track in &midi.tracks {
+            assert_eq!(track.duration(), Tick::new(7680));
+            assert!(track.ends_with_end_of_track());
+        }
+        assert_eq!(midi.tracks[0].events().count(), 2);
+
+        // Already on a boundary, and padding never shortens a track
+        assert_eq!(midi.pad_all_tracks_to_bar(), Ok(Tick::new(7680)));
+        midi.tracks[0].pad_to(480).unwrap();
+        assert_eq!(midi.tracks[0].duration(), Tick::new(7680));
+    }
+
+    #[test]
+    fn padding_stops_at_a_meter_change() {
+        let mut midi = meter_change_midi();
+        // Into the first 7/8 bar, and a track ending halfway into the 4/4 section
+        midi.tracks[0] = TrackChunk::new(vec![meta(3840 + 100, MetaEvent::EndOfTrack)]);
+        assert_eq!(midi.pad_all_tracks_to_bar(), Ok(Tick::new(3840 + 1680)));
+
+        midi.tracks[0] = TrackChunk::new(vec![meta(2000, MetaEvent::EndOfTrack)]);
+        midi.tracks[1] = TrackChunk::new(vec![
+            meta(2400, MetaEvent::TimeSignature(time_signature(7, 8))),
+            meta(0, MetaEvent::EndOfTrack),
+        ]);
+        assert_eq!(midi.pad_all_tracks_to_bar(), Ok(Tick::new(2400)));
+        assert_eq!(midi.format_position(2400), "3:1:0");
+    }
+}
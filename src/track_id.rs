@@ -0,0 +1,340 @@
+//! Identities for tracks that follow them through edits which add, remove, split or merge tracks.
+//!
+//! An id lives on the [`TrackChunk`] itself rather than in the MIDI data, so it's never written
+//! to a file, serialized, or compared by `==`. Parsed tracks start without one; they're handed
+//! out by [`Midi::push_track`] and [`Midi::assign_track_ids`] as one more than the highest id in
+//! the file, so the same edits always produce the same ids. An id no longer in the file, because
+//! its track was removed or merged away, can be handed out again
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    chunk::{
+        header::{Format, HeaderChunk},
+        track::{
+            editor::{EditError, TrackEditor},
+            Event, TrackChunk,
+        },
+    },
+    merge::{MergeError, MergePolicy},
+    Midi,
+};
+
+/// Identifies a track within a file, see [`crate::track_id`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TrackId(u32);
+
+impl TrackId {
+    /// The id as a number
+    pub fn get(self) -> u32 {
+        self.0
+    }
+}
+
+impl core::fmt::Display for TrackId {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write![f, "#{}", self.0]
+    }
+}
+
+impl TrackChunk {
+    /// The track's id, if it was given one
+    pub fn id(&self) -> Option<TrackId> {
+        self.id
+    }
+}
+
+impl Midi {
+    /// The id after the highest one in the file
+    fn next_track_id(&self) -> TrackId {
+        let next = self
+            .tracks
+            .iter()
+            .filter_map(|track| track.id)
+            .map(|id| id.0 + 1)
+            .max()
+            .unwrap_or(0);
+
+        TrackId(next)
+    }
+
+    /// Appends a track with a fresh id, replacing any id it already had, and syncs the header
+    /// with [`Midi::consistent_header`]
+    pub fn push_track(&mut self, mut track: TrackChunk) -> TrackId {
+        let id = self.next_track_id();
+        track.id = Some(id);
+        self.tracks.push(track);
+        self.header = self.consistent_header();
+
+        id
+    }
+
+    /// Gives every track without an id a fresh one, in file order
+    pub fn assign_track_ids(&mut self) {
+        for idx in 0..self.tracks.len() {
+            if self.tracks[idx].id.is_none() {
+                self.tracks[idx].id = Some(self.next_track_id());
+            }
+        }
+    }
+
+    /// The track with the given id
+    pub fn track_by_id(&self, id: TrackId) -> Option<&TrackChunk> {
+        self.tracks.iter().find(|track| track.id == Some(id))
+    }
+
+    /// Mutable access to the track with the given id
+    pub fn track_by_id_mut(&mut self, id: TrackId) -> Option<&mut TrackChunk> {
+        self.tracks.iter_mut().find(|track| track.id == Some(id))
+    }
+
+    /// Removes the track with the given id and syncs the header's track count. Every other
+    /// track keeps its id
+    pub fn remove_track(&mut self, id: TrackId) -> Option<TrackChunk> {
+        let idx = self.tracks.iter().position(|track| track.id == Some(id))?;
+        let track = self.tracks.remove(idx);
+        self.header = self.consistent_header();
+
+        Some(track)
+    }
+
+    /// Splits every track holding channel messages for more than one channel into one track per
+    /// channel, as when converting format 0 to format 1. The split track keeps its place, its id
+    /// and its meta and system exclusive events, even if nothing else is left in it, and is
+    /// followed by a track for each of its channels in ascending order, each with a fresh id and
+    /// ending at the same tick. Tracks using one channel or none are left as they are, and a
+    /// format 0 header becomes format 1 if there's more than one track afterwards.
+    ///
+    /// Returns the ids of the tracks each identified track's events ended up in, starting with
+    /// its own. Fails if removing another channel's events from a track leaves a gap too large
+    /// for a delta time, without changing anything
+    pub fn split_by_channel(&mut self) -> Result<BTreeMap<TrackId, Vec<TrackId>>, EditError> {
+        let mut tracks = Vec::with_capacity(self.tracks.len());
+        let mut mapping = BTreeMap::new();
+        let mut next = self.next_track_id().0;
+
+        for track in &self.tracks {
+            let channels: BTreeSet<u8> = track
+                .absolute_events()
+                .filter_map(|(_, event)| match event {
+                    Event::MidiEvent(midi) => Some(midi.channel()),
+                    _ => None,
+                })
+                .collect();
+
+            if channels.len() < 2 {
+                if let Some(id) = track.id {
+                    mapping.insert(id, vec![id]);
+                }
+                tracks.push(track.clone());
+                continue;
+            }
+
+            let editor = TrackEditor::new(track.clone());
+            let mut rest = editor.clone();
+            rest.retain(|_, event| !matches!(event, Event::MidiEvent(_)));
+            tracks.push(rest.finish()?);
+
+            let mut ids = track.id.into_iter().collect::<Vec<_>>();
+            for channel in channels {
+                let mut split = editor.clone();
+                split.retain(
+                    |_, event| matches!(event, Event::MidiEvent(midi) if midi.channel() == channel),
+                );
+
+                let mut split = split.finish()?;
+                split.id = Some(TrackId(next));
+                split.trailing_events.clear();
+                ids.push(TrackId(next));
+                next += 1;
+                tracks.push(split);
+            }
+
+            if let Some(id) = track.id {
+                mapping.insert(id, ids);
+            }
+        }
+
+        self.tracks = tracks;
+        self.header = self.consistent_header();
+
+        Ok(mapping)
+    }
+
+    /// Merges every track into one with [`TrackChunk::merge`] and makes the file format 0.
+    /// Events keep their absolute ticks, simultaneous events stay in track order, and the merged
+    /// track ends when the last track did. It's named after the first track named at tick 0 and
+    /// takes the id of the first track that has one, and every identified track is mapped to it
+    /// in the returned map. Events found after the `EndOfTrack` of any track but the first are
+    /// dropped.
+    ///
+    /// The patterns of a format 2 file are independent of each other, so merging them plays
+    /// them all at once
+    pub fn to_format0(&mut self) -> BTreeMap<TrackId, TrackId> {
+        self.to_format0_with(MergePolicy::Keep)
+            .expect("Keeping collisions never fails")
+    }
+
+    /// Merges every track into one like [`Midi::to_format0`], handling notes that collide
+    /// across tracks according to the [`MergePolicy`]. On error the file is left unchanged
+    pub fn to_format0_with(
+        &mut self,
+        policy: MergePolicy,
+    ) -> Result<BTreeMap<TrackId, TrackId>, MergeError> {
+        match policy {
+            MergePolicy::Keep => {}
+            MergePolicy::RechannelizeSpill => self.spill_collisions()?,
+            MergePolicy::ErrorOnCollision => {
+                let collisions = self.note_collisions();
+                if !collisions.is_empty() {
+                    return Err(MergeError::Collisions(collisions));
+                }
+            }
+        }
+
+        let id = self.tracks.iter().find_map(|track| track.id);
+        let mapping = id
+            .map(|merged| {
+                self.tracks
+                    .iter()
+                    .filter_map(|track| track.id)
+                    .map(|id| (id, merged))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut tracks = core::mem::take(&mut self.tracks).into_iter();
+        let mut merged = tracks.next().unwrap_or_default();
+        for track in tracks {
+            merged.merge(&track);
+        }
+
+        self.header = HeaderChunk::new(Format::Zero, 1, self.header.division);
+        self.tracks = vec![TrackChunk { id, ..merged }];
+
+        Ok(mapping)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TrackId;
+    use crate::{
+        chunk::{
+            header::{Division, Format},
+            track::{
+                event::{MidiEvent, NoteMeta},
+                meta::MetaEvent,
+                Event, TrackChunk,
+            },
+        },
+        normalize::NormalizeOptions,
+        writer::MidiWriteable,
+        Midi,
+    };
+
+    fn note(channel: u8, key: u8, velocity: u8) -> Event {
+        Event::MidiEvent(MidiEvent::NoteOn(channel, NoteMeta { key, velocity }))
+    }
+
+    fn track(mut events: Vec<(u64, Event)>) -> TrackChunk {
+        events.push((960, Event::MetaEvent(MetaEvent::EndOfTrack)));
+        TrackChunk::from_absolute_events(events).unwrap()
+    }
+
+    /// A conductor track and a track playing on two channels, pushed in that order
+    fn midi() -> (Midi, TrackId, TrackId) {
+        let mut midi = Midi::new(vec![], Division::TPQN_480);
+        let conductor =
+            midi.push_track(track(vec![(0, Event::MetaEvent(MetaEvent::DEFAULT_TEMPO))]));
+        let band = midi.push_track(track(vec![
+            (0, Event::MetaEvent(MetaEvent::TrackName("Band".into()))),
+            (0, note(1, 40, 100)),
+            (0, note(0, 60, 100)),
+            (480, note(0, 60, 0)),
+            (480, note(1, 40, 0)),
+        ]));
+
+        (midi, conductor, band)
+    }
+
+    fn events(track: &TrackChunk) -> Vec<(u64, Event)> {
+        track
+            .absolute_events()
+            .map(|(tick, event)| (tick.get(), event.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn ids_follow_tracks_but_not_bytes() {
+        let (mut midi, conductor, band) = midi();
+        assert_eq!((conductor.get(), band.get()), (0, 1));
+        assert_eq!(midi.header.ntrks, 2);
+
+        let anonymous = Midi {
+            header: midi.header.clone(),
+            tracks: midi
+                .tracks
+                .iter()
+                .map(|track| TrackChunk::new(track.mtrk_events.clone()))
+                .collect(),
+        };
+        assert_eq!(midi, anonymous);
+        assert_eq!(
+            midi.clone().to_midi_bytes(),
+            anonymous.clone().to_midi_bytes()
+        );
+
+        let removed = midi.remove_track(conductor).unwrap();
+        assert_eq!(removed.id(), Some(conductor));
+        assert_eq!(midi.header.ntrks, 1);
+        assert_eq!(midi.track_by_id(conductor), None);
+        assert_eq!(
+            midi.track_by_id(band).unwrap().name().as_deref(),
+            Some("Band")
+        );
+        assert_eq!(midi.push_track(removed), TrackId(2));
+
+        let mut parsed = anonymous;
+        parsed.push_track(TrackChunk::default());
+        parsed.assign_track_ids();
+        let ids: Vec<_> = parsed.tracks.iter().map(|track| track.id()).collect();
+        assert_eq!(ids, [Some(TrackId(1)), Some(TrackId(2)), Some(TrackId(0))]);
+    }
+
+    #[test]
+    fn ids_survive_normalizing_and_a_format_round_trip() {
+        let (mut midi, conductor, band) = midi();
+        let original = midi.clone();
+        midi.normalize(NormalizeOptions::default());
+        assert_eq!(midi.tracks[1].id(), Some(band));
+
+        let merged = midi.to_format0();
+        assert_eq!(merged, [(conductor, conductor), (band, conductor)].into());
+        assert_eq!(midi.header.format, Format::Zero);
+        assert_eq!(midi.tracks.len(), 1);
+
+        let split = midi.split_by_channel().unwrap();
+        let ids = &split[&conductor];
+        assert_eq!(ids.len(), 3);
+        assert_eq!(midi.header.format, Format::One);
+        assert_eq!(midi.header.ntrks, 3);
+
+        let meta = midi.track_by_id(ids[0]).unwrap();
+        assert_eq!(meta.name().as_deref(), Some("Band"));
+        assert!(meta.notes().is_empty());
+        for (channel, id) in [(0, ids[1]), (1, ids[2])] {
+            let notes = midi.track_by_id(id).unwrap().notes();
+            assert_eq!(notes.len(), 1);
+            assert_eq!(notes[0].channel, channel);
+            assert_eq!(midi.track_by_id(id).unwrap().duration().get(), 960);
+        }
+
+        // Tracks with a single channel are left alone
+        let mut unsplit = original.clone();
+        let split = unsplit.split_by_channel().unwrap();
+        assert_eq!(split[&conductor], [conductor]);
+        assert_eq!(split[&band], [band, TrackId(2), TrackId(3)]);
+        assert_eq!(events(&unsplit.tracks[0]), events(&original.tracks[0]));
+    }
+}
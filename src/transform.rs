@@ -0,0 +1,301 @@
+//! In place transformations of note and aftertouch events, built on the public mutation accessors
+
+use std::collections::BTreeMap;
+
+use crate::{
+    chunk::track::{
+        event::{ControlChange, MidiEvent, U7},
+        Event, TrackChunk,
+    },
+    slice::NoteChange,
+    stats::PERCUSSION_CHANNEL,
+    time::DeltaTimeOverflow,
+    Midi,
+};
+
+/// How [`TrackChunk::convert_aftertouch_to_cc_with`] turns the pressure of every key held on a
+/// channel into a single controller value
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PolyPressure {
+    /// The mean pressure of the held keys
+    #[default]
+    Average,
+    /// The pressure of the hardest pressed key
+    Max,
+}
+
+impl TrackChunk {
+    /// Shifts the key of every note event by the given number of semitones, clamping to the
+    /// valid 0 to 127 range. Percussion on channel 10 is left alone, since its keys pick drums
+    /// rather than pitches
+    pub fn transpose(&mut self, semitones: i8) {
+        for mtrk_event in self.events_mut() {
+            if let Event::MidiEvent(
+                MidiEvent::NoteOn(channel, note)
+                | MidiEvent::NoteOff(channel, note)
+                | MidiEvent::PolyphonicKeyPressure(channel, note),
+            ) = mtrk_event.event_mut()
+            {
+                if *channel != PERCUSSION_CHANNEL {
+                    note.key = (note.key as i16 + semitones as i16).clamp(0, 127) as u8;
+                }
+            }
+        }
+    }
+
+    /// Multiplies the velocity of every sounding Note On by `factor`, clamping to 1 to 127 so no
+    /// note is silenced or turned into a Note Off
+    pub fn scale_velocities(&mut self, factor: f32) {
+        for mtrk_event in self.events_mut() {
+            if let Event::MidiEvent(MidiEvent::NoteOn(_, note)) = mtrk_event.event_mut() {
+                if note.velocity > 0 {
+                    note.velocity = (note.velocity as f32 * factor).round().clamp(1.0, 127.0) as u8;
+                }
+            }
+        }
+    }
+
+    /// Converts aftertouch into `target_controller` changes, averaging polyphonic key pressure
+    /// across the held keys. See [`TrackChunk::convert_aftertouch_to_cc_with`]
+    pub fn convert_aftertouch_to_cc(
+        &mut self,
+        target_controller: U7,
+        scale: f32,
+    ) -> Result<(), DeltaTimeOverflow> {
+        self.convert_aftertouch_to_cc_with(target_controller, scale, PolyPressure::Average)
+    }
+
+    /// Replaces every Channel Pressure and Polyphonic Key Pressure event with a Control Change
+    /// of `target_controller` on the same channel at the same tick, for synths that ignore
+    /// aftertouch. Usually that's CC 1, the mod wheel, or CC 11, expression. Each value is the
+    /// pressure multiplied by `scale`, clamped to 0 to 127.
+    ///
+    /// A key's pressure counts until the key is released, and `poly` decides how the keys held
+    /// on a channel combine. A change that would repeat the controller's last value on its
+    /// channel, including one already in the track, is dropped rather than converted, with its
+    /// delta time carried over like [`TrackChunk::retain_events`]. Fails without changing
+    /// anything if that makes a gap too large for a delta time
+    pub fn convert_aftertouch_to_cc_with(
+        &mut self,
+        target_controller: U7,
+        scale: f32,
+        poly: PolyPressure,
+    ) -> Result<(), DeltaTimeOverflow> {
+        let controller_number = target_controller.get();
+        let mut converted = self.clone();
+        let mut pressures: BTreeMap<(u8, u8), u8> = BTreeMap::new();
+        let mut last: BTreeMap<u8, u8> = BTreeMap::new();
+        let mut keep = vec![];
+
+        for mtrk_event in converted.events_mut() {
+            let event = mtrk_event.event_mut();
+            if let Some(NoteChange::Off(channel, note)) = NoteChange::of(event) {
+                pressures.remove(&(channel, note.key));
+            }
+
+            let (channel, pressure) = match *event {
+                Event::MidiEvent(MidiEvent::ControlChange(channel, cc))
+                    if cc.controller_number == controller_number =>
+                {
+                    last.insert(channel, cc.new_value);
+                    keep.push(true);
+                    continue;
+                }
+                Event::MidiEvent(MidiEvent::ChannelPressure { channel, pressure }) => {
+                    (channel, pressure as f32)
+                }
+                Event::MidiEvent(MidiEvent::PolyphonicKeyPressure(channel, note)) => {
+                    pressures.insert((channel, note.key), note.velocity);
+                    let held = pressures
+                        .range((channel, 0)..=(channel, u8::MAX))
+                        .map(|(_, pressure)| *pressure as f32);
+                    let pressure = match poly {
+                        PolyPressure::Average => {
+                            let (sum, count) = held.fold((0.0, 0.0), |(sum, count), pressure| {
+                                (sum + pressure, count + 1.0)
+                            });
+                            sum / count
+                        }
+                        PolyPressure::Max => held.fold(0.0, f32::max),
+                    };
+                    (channel, pressure)
+                }
+                _ => {
+                    keep.push(true);
+                    continue;
+                }
+            };
+
+            let new_value = (pressure * scale).round().clamp(0.0, 127.0) as u8;
+            keep.push(last.insert(channel, new_value) != Some(new_value));
+            *event = Event::MidiEvent(MidiEvent::ControlChange(
+                channel,
+                ControlChange {
+                    controller_number,
+                    new_value,
+                },
+            ));
+        }
+
+        let mut keep = keep.into_iter();
+        converted.retain_events(|_| keep.next().unwrap_or(true))?;
+        *self = converted;
+
+        Ok(())
+    }
+}
+
+impl Midi {
+    /// Applies [`TrackChunk::transpose`] to every track
+    pub fn transpose(&mut self, semitones: i8) {
+        for track in &mut self.tracks {
+            track.transpose(semitones);
+        }
+    }
+
+    /// Applies [`TrackChunk::scale_velocities`] to every track
+    pub fn scale_velocities(&mut self, factor: f32) {
+        for track in &mut self.tracks {
+            track.scale_velocities(factor);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PolyPressure;
+    use crate::chunk::track::{
+        event::{ControlChange, MidiEvent, NoteMeta, U7},
+        meta::MetaEvent,
+        Event, TrackChunk,
+    };
+
+    fn notes(track: &TrackChunk) -> Vec<(u8, u8, u8)> {
+        track
+            .events()
+            .filter_map(|mtrk_event| match mtrk_event.event() {
+                Event::MidiEvent(MidiEvent::NoteOn(channel, note)) => {
+                    Some((*channel, note.key, note.velocity))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn track() -> TrackChunk {
+        let note_on = |channel, key, velocity| {
+            Event::MidiEvent(MidiEvent::NoteOn(channel, NoteMeta { key, velocity }))
+        };
+
+        TrackChunk::from_absolute_events([
+            (0, note_on(0, 60, 100)),
+            (0, note_on(0, 120, 0)),
+            (0, note_on(9, 36, 10)),
+            (96, Event::MetaEvent(MetaEvent::EndOfTrack)),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn transpose_clamps_and_skips_percussion() {
+        let mut track = track();
+        track.transpose(12);
+        assert_eq!(notes(&track), [(0, 72, 100), (0, 127, 0), (9, 36, 10)]);
+
+        track.transpose(-100);
+        assert_eq!(notes(&track), [(0, 0, 100), (0, 27, 0), (9, 36, 10)]);
+    }
+
+    #[test]
+    fn scale_velocities_keeps_notes_sounding() {
+        let mut track = track();
+        track.scale_velocities(1.5);
+        assert_eq!(notes(&track), [(0, 60, 127), (0, 120, 0), (9, 36, 15)]);
+
+        track.scale_velocities(0.0);
+        assert_eq!(notes(&track), [(0, 60, 1), (0, 120, 0), (9, 36, 1)]);
+    }
+
+    fn cc_values(track: &TrackChunk) -> Vec<(u64, u8)> {
+        track
+            .absolute_events()
+            .filter_map(|(tick, event)| match event {
+                Event::MidiEvent(MidiEvent::ControlChange(0, cc)) if cc.controller() == 11 => {
+                    Some((tick.get(), cc.value()))
+                }
+                Event::MidiEvent(
+                    MidiEvent::ChannelPressure { .. } | MidiEvent::PolyphonicKeyPressure(..),
+                ) => panic!("Aftertouch left at tick {tick}"),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pressure_ramps_become_controller_ramps() {
+        let pressure = |pressure| {
+            Event::MidiEvent(MidiEvent::ChannelPressure {
+                channel: 0,
+                pressure,
+            })
+        };
+        let mut events: Vec<_> = (0..=127u8)
+            .map(|step| (step as u64 * 10, pressure(step)))
+            .collect();
+        events.push((1280, Event::MetaEvent(MetaEvent::EndOfTrack)));
+        let mut track = TrackChunk::from_absolute_events(events).unwrap();
+
+        track
+            .convert_aftertouch_to_cc(U7::try_from(11).unwrap(), 0.5)
+            .unwrap();
+        let ramp = cc_values(&track);
+        assert_eq!(ramp.first(), Some(&(0, 0)));
+        assert_eq!(ramp.last(), Some(&(1270, 64)));
+        assert_eq!(ramp.len(), 65);
+        // Every value rises, so none repeats, and each lands where its pressure was
+        assert!(ramp.windows(2).all(|pair| pair[0].1 < pair[1].1));
+        assert!(ramp
+            .iter()
+            .all(|(tick, value)| { (*value as f32 - *tick as f32 / 20.0).abs() <= 0.5 }));
+        assert_eq!(track.duration().get(), 1280);
+    }
+
+    #[test]
+    fn poly_pressure_combines_held_keys() {
+        let key = |key, velocity| {
+            Event::MidiEvent(MidiEvent::PolyphonicKeyPressure(
+                0,
+                NoteMeta { key, velocity },
+            ))
+        };
+        let note_off = |key| Event::MidiEvent(MidiEvent::NoteOff(0, NoteMeta { key, velocity: 0 }));
+        let track = TrackChunk::from_absolute_events([
+            (
+                0,
+                Event::MidiEvent(MidiEvent::ControlChange(
+                    0,
+                    ControlChange {
+                        controller_number: 11,
+                        new_value: 40,
+                    },
+                )),
+            ),
+            (10, key(60, 40)),
+            (20, key(64, 80)),
+            (30, note_off(64)),
+            (40, key(60, 40)),
+            (50, Event::MetaEvent(MetaEvent::EndOfTrack)),
+        ])
+        .unwrap();
+
+        let expression = U7::try_from(11).unwrap();
+        let mut average = track.clone();
+        average.convert_aftertouch_to_cc(expression, 1.0).unwrap();
+        assert_eq!(cc_values(&average), [(0, 40), (20, 60), (40, 40)]);
+
+        let mut max = track.clone();
+        max.convert_aftertouch_to_cc_with(expression, 1.0, PolyPressure::Max)
+            .unwrap();
+        assert_eq!(cc_values(&max), [(0, 40), (20, 80), (40, 40)]);
+    }
+}
@@ -0,0 +1,267 @@
+//! Structural validation of parsed MIDI files
+
+use std::collections::BTreeMap;
+
+use crate::{chunk::track::kind::MetaKind, time::Tick, Midi};
+
+/// A problem found while validating a MIDI file. None of these stop the file from being written,
+/// but players may handle them inconsistently
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationIssue {
+    /// A track doesn't end with an `EndOfTrack` event
+    MissingEndOfTrack {
+        /// Index of the track
+        track: usize,
+    },
+    /// Events follow a track's first `EndOfTrack`
+    EventsAfterEndOfTrack {
+        /// Index of the track
+        track: usize,
+        /// Number of events after the `EndOfTrack`
+        count: usize,
+    },
+    /// Events were found after a track's first `EndOfTrack` when it was parsed. They're kept in
+    /// [`crate::chunk::track::TrackChunk::trailing_events`] but won't be written unless recovered
+    TrailingEvents {
+        /// Index of the track
+        track: usize,
+        /// Number of trailing events
+        count: usize,
+    },
+    /// The track was salvaged from a chunk the file ended partway through, so its last events are
+    /// probably missing. See [`crate::chunk::track::TrackChunk::is_truncated`]
+    TruncatedTrack {
+        /// Index of the track
+        track: usize,
+    },
+    /// Two tracks have the same Sequence Number, so patterns can't be told apart by number
+    DuplicateSequenceNumber {
+        /// The repeated number
+        number: u16,
+        /// Index of the first track with the number
+        first: usize,
+        /// Index of the track repeating it
+        track: usize,
+    },
+    /// Different tracks set conflicting time signatures at the same tick
+    ConflictingTimeSignatures {
+        /// Absolute tick of the conflict
+        tick: Tick,
+    },
+    /// Different tracks set conflicting key signatures at the same tick
+    ConflictingKeySignatures {
+        /// Absolute tick of the conflict
+        tick: Tick,
+    },
+    /// A tempo, time signature or key signature outside track 0 of a format 1 file, see
+    /// [`Midi::misplaced_conductor_events`]
+    MisplacedConductorEvent {
+        /// Index of the track holding the event
+        track: usize,
+        /// Absolute tick of the event
+        tick: Tick,
+        /// Which kind of event it is
+        kind: MetaKind,
+    },
+}
+
+impl core::fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::MissingEndOfTrack { track } => {
+                write![f, "Track {track} doesn't end with an End of Track event"]
+            }
+            Self::EventsAfterEndOfTrack { track, count } => {
+                write![f, "Track {track} has {count} events after its End of Track"]
+            }
+            Self::TrailingEvents { track, count } => write![
+                f,
+                "Track {track} had {count} events after its End of Track that won't be written"
+            ],
+            Self::TruncatedTrack { track } => {
+                write![f, "Track {track} was cut off by the end of the file"]
+            }
+            Self::DuplicateSequenceNumber {
+                number,
+                first,
+                track,
+            } => write![
+                f,
+                "Track {track} repeats sequence number {number} from track {first}"
+            ],
+            Self::ConflictingTimeSignatures { tick } => {
+                write![f, "Tracks set conflicting time signatures at tick {tick}"]
+            }
+            Self::ConflictingKeySignatures { tick } => {
+                write![f, "Tracks set conflicting key signatures at tick {tick}"]
+            }
+            Self::MisplacedConductorEvent { track, tick, kind } => write![
+                f,
+                "Track {track} has a {kind} event at tick {tick} outside the conductor track"
+            ],
+        }
+    }
+}
+
+impl Midi {
+    /// Checks the file for structural problems, returning every issue found in track order
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        let mut issues = vec![];
+
+        for (track, chunk) in self.tracks.iter().enumerate() {
+            if !chunk.ends_with_end_of_track() {
+                issues.push(ValidationIssue::MissingEndOfTrack { track });
+            }
+
+            let count = chunk.events_after_end_of_track();
+            if count > 0 {
+                issues.push(ValidationIssue::EventsAfterEndOfTrack { track, count });
+            }
+
+            let count = chunk.trailing_events().len();
+            if count > 0 {
+                issues.push(ValidationIssue::TrailingEvents { track, count });
+            }
+
+            if chunk.is_truncated() {
+                issues.push(ValidationIssue::TruncatedTrack { track });
+            }
+        }
+
+        let mut numbered: BTreeMap<u16, usize> = BTreeMap::new();
+        for pattern in self.patterns() {
+            let Some(number) = pattern.sequence_number else {
+                continue;
+            };
+            match numbered.get(&number) {
+                Some(&first) => issues.push(ValidationIssue::DuplicateSequenceNumber {
+                    number,
+                    first,
+                    track: pattern.track_index,
+                }),
+                None => {
+                    numbered.insert(number, pattern.track_index);
+                }
+            }
+        }
+
+        issues.extend(
+            self.time_signature_map()
+                .conflicts()
+                .iter()
+                .map(|&tick| ValidationIssue::ConflictingTimeSignatures { tick }),
+        );
+        issues.extend(
+            self.key_signature_map()
+                .conflicts()
+                .iter()
+                .map(|&tick| ValidationIssue::ConflictingKeySignatures { tick }),
+        );
+        issues.extend(
+            self.misplaced_conductor_events()
+                .into_iter()
+                .map(
+                    |(track, tick, kind)| ValidationIssue::MisplacedConductorEvent {
+                        track,
+                        tick,
+                        kind,
+                    },
+                ),
+        );
+
+        issues
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ValidationIssue;
+    use crate::{
+        chunk::{
+            header::HeaderChunk,
+            track::{
+                kind::MetaKind,
+                meta::{MetaEvent, TimeSignature},
+                Event, TrackChunk,
+            },
+        },
+        normalize::NormalizeOptions,
+        reader::MidiReadable,
+        time::Tick,
+        Midi, RawMidi,
+    };
+
+    #[test]
+    fn fixture_validates_cleanly() {
+        let midi = RawMidi::try_from_midi_stream("test/run.mid".get_midi_bytes().unwrap())
+            .unwrap()
+            .check_into_midi()
+            .unwrap();
+
+        assert_eq!(midi.validate(), vec![]);
+    }
+
+    #[test]
+    fn trailing_events_and_conflicts_reported() {
+        let end = || Event::MetaEvent(MetaEvent::EndOfTrack);
+        let time_signature = |numerator| {
+            Event::MetaEvent(MetaEvent::TimeSignature(TimeSignature {
+                numerator,
+                ..Default::default()
+            }))
+        };
+
+        let tracks = vec![
+            vec![(0, time_signature(3)), (0, end())],
+            vec![(0, time_signature(5)), (0, end()), (10, end())],
+            vec![(0, time_signature(3))],
+        ];
+
+        let midi = Midi {
+            header: HeaderChunk::try_from((1, 3, 96)).unwrap(),
+            tracks: tracks
+                .into_iter()
+                .map(|events| TrackChunk::from_absolute_events(events).unwrap())
+                .collect(),
+        };
+
+        assert_eq!(
+            midi.validate(),
+            vec![
+                ValidationIssue::EventsAfterEndOfTrack { track: 1, count: 1 },
+                ValidationIssue::MissingEndOfTrack { track: 2 },
+                ValidationIssue::ConflictingTimeSignatures { tick: Tick::ZERO },
+                ValidationIssue::MisplacedConductorEvent {
+                    track: 1,
+                    tick: Tick::ZERO,
+                    kind: MetaKind::TimeSignature
+                },
+                ValidationIssue::MisplacedConductorEvent {
+                    track: 2,
+                    tick: Tick::ZERO,
+                    kind: MetaKind::TimeSignature
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parsed_trailing_events_reported() {
+        let track = TrackChunk::parse(&[
+            0x00, 0xFF, 0x2F, 0x00, 0x00, 0xFF, 0x2F, 0x00, 0x10, 0x90, 0x3C, 0x64,
+        ])
+        .unwrap();
+        let mut midi = Midi {
+            header: HeaderChunk::try_from((0, 1, 96)).unwrap(),
+            tracks: vec![track],
+        };
+
+        assert_eq!(
+            midi.validate(),
+            vec![ValidationIssue::TrailingEvents { track: 0, count: 2 }]
+        );
+
+        midi.normalize(NormalizeOptions::default());
+        assert_eq!(midi.validate(), vec![]);
+    }
+}
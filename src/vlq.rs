@@ -0,0 +1,174 @@
+//! Variable length quantities, the big-endian base 128 integers MIDI files store delta times and
+//! event lengths in. Each byte holds 7 bits of the value, with the top bit set on every byte but
+//! the last
+
+use core::ops::Deref;
+
+/// The largest value the spec allows, the most that fits in 4 bytes
+pub const MAX: u32 = 0x0FFF_FFFF;
+
+/// Most bytes a quantity within [`MAX`] takes up
+pub const MAX_LEN: usize = 4;
+
+/// Error decoding a variable length quantity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VlqError {
+    /// There were no bytes to decode at all
+    Empty,
+    /// The bytes ended while the top bit was still set
+    Truncated,
+    /// The quantity runs longer than the 4 bytes the spec allows
+    TooLong,
+}
+
+impl core::error::Error for VlqError {}
+impl core::fmt::Display for VlqError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Empty => write![f, "No bytes to decode a variable length quantity from"],
+            Self::Truncated => write![f, "Variable length quantity ends partway through"],
+            Self::TooLong => write![f, "Variable length quantity is longer than {MAX_LEN} bytes"],
+        }
+    }
+}
+
+/// An encoded quantity, held inline without allocating
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Encoded {
+    /// The encoded bytes, left aligned
+    bytes: [u8; 5],
+    /// How many of `bytes` are used
+    len: u8,
+}
+
+impl Encoded {
+    /// The encoded bytes
+    pub fn as_slice(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+impl Deref for Encoded {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl AsRef<[u8]> for Encoded {
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+/// Encodes a value in as few bytes as possible. Values above [`MAX`] take 5 bytes, which
+/// [`decode`] rejects, so they can't be stored in a file
+pub fn encode(value: u32) -> Encoded {
+    let len = (1..5).find(|len| value >> (7 * len) == 0).unwrap_or(5);
+    let mut bytes = [0; 5];
+
+    for (idx, byte) in bytes[..len].iter_mut().enumerate() {
+        let shift = 7 * (len - 1 - idx);
+        let continuation = if idx + 1 < len { 0x80 } else { 0 };
+        *byte = ((value >> shift) & 0x7F) as u8 | continuation;
+    }
+
+    Encoded {
+        bytes,
+        len: len as u8,
+    }
+}
+
+/// Decodes a quantity from the start of `bytes`, returning its value and how many bytes it took
+/// up. Anything after the quantity is ignored
+pub fn decode(bytes: &[u8]) -> Result<(u32, usize), VlqError> {
+    let mut consumed = 0;
+    let value = read(&mut bytes.iter().copied().inspect(|_| consumed += 1))?;
+
+    Ok((value, consumed))
+}
+
+/// Decodes a quantity from the front of an iterator, leaving it just past the last byte read
+pub fn read<ITER: Iterator<Item = u8>>(iter: &mut ITER) -> Result<u32, VlqError> {
+    let mut value = 0;
+
+    for idx in 0..MAX_LEN {
+        let byte = iter.next().ok_or(match idx {
+            0 => VlqError::Empty,
+            _ => VlqError::Truncated,
+        })?;
+
+        value = (value << 7) | (byte & 0x7F) as u32;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(VlqError::TooLong)
+}
+
+/// Appends the encoding of `value` to `bytes`
+pub fn write(value: u32, bytes: &mut Vec<u8>) {
+    bytes.extend_from_slice(&encode(value));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, read, VlqError, MAX};
+
+    #[test]
+    fn boundaries_round_trip() {
+        for (value, encoded) in [
+            (0, &[0x00][..]),
+            (0x40, &[0x40]),
+            (0x7F, &[0x7F]),
+            (0x80, &[0x81, 0x00]),
+            (0x2000, &[0xC0, 0x00]),
+            (0x3FFF, &[0xFF, 0x7F]),
+            (0x4000, &[0x81, 0x80, 0x00]),
+            (0x1F_FFFF, &[0xFF, 0xFF, 0x7F]),
+            (0x20_0000, &[0x81, 0x80, 0x80, 0x00]),
+            (MAX, &[0xFF, 0xFF, 0xFF, 0x7F]),
+        ] {
+            assert_eq!(&*encode(value), encoded);
+            assert_eq!(decode(encoded), Ok((value, encoded.len())));
+        }
+
+        assert_eq!(&*encode(u32::MAX), [0x8F, 0xFF, 0xFF, 0xFF, 0x7F]);
+        assert_eq!(
+            decode(&encode(u32::MAX)),
+            Err(VlqError::TooLong),
+            "Values past MAX don't fit in a file"
+        );
+    }
+
+    #[test]
+    fn random_values_round_trip() {
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        for _ in 0..10_000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+
+            // Spread values over every encoded length rather than mostly 4 byte ones
+            let value = (state as u32 & MAX) >> ((state >> 32) % 28);
+            let encoded = encode(value);
+            assert_eq!(decode(&encoded), Ok((value, encoded.len())));
+        }
+    }
+
+    #[test]
+    fn malformed_quantities_rejected() {
+        assert_eq!(decode(&[]), Err(VlqError::Empty));
+        assert_eq!(decode(&[0x81, 0x80]), Err(VlqError::Truncated));
+        assert_eq!(
+            decode(&[0xFF, 0xFF, 0xFF, 0xFF, 0x7F]),
+            Err(VlqError::TooLong)
+        );
+
+        let mut iter = [0x81, 0x00, 0x42].into_iter();
+        assert_eq!(read(&mut iter), Ok(0x80));
+        assert_eq!(iter.next(), Some(0x42));
+    }
+}
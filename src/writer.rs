@@ -4,7 +4,22 @@
 //! into the canonical MIDI byte format. This is particularly useful when you have manipulated
 //! or inspected MIDI data in your application and need to write it back to a file or stream.
 
-use crate::Chunk;
+use std::io::{self, Seek, SeekFrom, Write};
+
+use crate::{
+    chunk::{
+        chunk_types::TRACK_DATA_CHUNK,
+        header::{Division, Format, HeaderChunk},
+        track::{
+            editor::{EditError, TrackEditor},
+            meta::TextEncoding,
+            Event, TrackChunk,
+        },
+        ParsedChunk,
+    },
+    reader::{ParseOptions, ParseProfile},
+    Chunk, Midi,
+};
 
 /// A trait for types that can be encoded as MIDI-format bytes.
 ///
@@ -17,6 +32,317 @@ pub trait MidiWriteable {
     fn to_midi_bytes(self) -> Vec<u8>;
 }
 
+/// Options controlling how a [`crate::Midi`] is laid out when written with
+/// [`crate::Midi::to_midi_bytes_with`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct WriteOptions {
+    /// Appends a 0x00 byte after every chunk with an odd length payload, for players and RIFF
+    /// style containers that expect even alignment. The pad byte is not counted in the chunk's
+    /// declared length, so it has to be skipped with a lenient reader
+    pub pad_odd_chunks: bool,
+    /// Encoding text meta events are written in. The default, [`TextEncoding::Raw`], writes
+    /// every text's bytes exactly as they were parsed or constructed
+    pub encoding: TextEncoding,
+    /// Signs the written file with [`crate::Midi::set_generator`] as
+    /// [`crate::signature::GENERATOR`]. Applies to [`crate::Midi::to_midi_bytes_with`] and to
+    /// saving, while [`MidiFileWriter`] writes its tracks as they're given. Saving a file that
+    /// can't be signed fails with [`WriteError::Stamp`], while `to_midi_bytes_with` can't fail
+    /// and writes it unsigned. Off by default
+    pub stamp_generator: bool,
+    /// Parses the written bytes back before saving them, failing with
+    /// [`WriteError::RoundTripMismatch`] instead of saving a file that doesn't read back as the
+    /// same music. Tracks are compared with [`TrackChunk::semantic_eq`]. Applies to saving, while
+    /// [`MidiFileWriter`] and [`crate::Midi::to_midi_bytes_with`] never check. On by default in
+    /// debug builds, and worth turning off where saving speed matters
+    pub verify_roundtrip: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self {
+            pad_odd_chunks: false,
+            encoding: TextEncoding::Raw,
+            stamp_generator: false,
+            verify_roundtrip: cfg!(debug_assertions),
+        }
+    }
+}
+
+impl WriteOptions {
+    /// Sets [`WriteOptions::pad_odd_chunks`]
+    pub fn pad_odd_chunks(mut self, pad: bool) -> Self {
+        self.pad_odd_chunks = pad;
+        self
+    }
+
+    /// Sets [`WriteOptions::encoding`]
+    pub fn encoding(mut self, encoding: TextEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Sets [`WriteOptions::stamp_generator`]
+    pub fn stamp_generator(mut self, stamp: bool) -> Self {
+        self.stamp_generator = stamp;
+        self
+    }
+
+    /// Sets [`WriteOptions::verify_roundtrip`]
+    pub fn verify_roundtrip(mut self, verify: bool) -> Self {
+        self.verify_roundtrip = verify;
+        self
+    }
+}
+
+impl WriteOptions {
+    /// Re-encodes a track's text meta events into the configured encoding
+    pub(crate) fn encode_track(&self, mut track: TrackChunk) -> TrackChunk {
+        if self.encoding != TextEncoding::Raw {
+            for mtrk_event in track.events_mut() {
+                if let Event::MetaEvent(meta) = &mut mtrk_event.event {
+                    if let Some(text) = meta.text_mut() {
+                        *text = text.encode(self.encoding);
+                    }
+                }
+            }
+        }
+
+        track
+    }
+
+    /// Writes a single chunk and its payload according to these options
+    pub(crate) fn write_chunk(&self, chunk: (Chunk, Vec<u8>)) -> Vec<u8> {
+        let odd = chunk.1.len() % 2 == 1;
+        let mut bytes = chunk.to_midi_bytes();
+        if self.pad_odd_chunks && odd {
+            bytes.push(0x00);
+        }
+
+        bytes
+    }
+
+    /// Parses `bytes` back and checks they hold `midi` as these options write it: the header
+    /// [`Midi::consistent_header`] describes and every track with its text re-encoded
+    pub(crate) fn check_roundtrip(&self, midi: &Midi, bytes: &[u8]) -> Result<(), WriteError> {
+        let mismatch = |detail: String| WriteError::RoundTripMismatch { detail };
+        // Whatever was written has to be read back, however large, so nothing is limited
+        let limits = ParseOptions::default()
+            .max_total_bytes(usize::MAX)
+            .max_events_per_track(usize::MAX)
+            .max_event_payload(u32::MAX);
+        let profile = ParseProfile::strict()
+            .options(limits)
+            .skip_garbage(self.pad_odd_chunks);
+        let read = Midi::try_from_midi_stream_with(bytes.iter().copied(), profile)
+            .map_err(|err| mismatch(format!("the written bytes don't parse: {err}")))?
+            .midi;
+
+        let header = midi.consistent_header();
+        if (read.header.format(), read.header.division()) != (header.format(), header.division()) {
+            return Err(mismatch(format!(
+                "wrote a {:?} header with {:?}, read back {:?} with {:?}",
+                header.format(),
+                header.division(),
+                read.header.format(),
+                read.header.division()
+            )));
+        }
+        if read.tracks.len() != midi.tracks.len() {
+            return Err(mismatch(format!(
+                "wrote {} tracks, read back {}",
+                midi.tracks.len(),
+                read.tracks.len()
+            )));
+        }
+
+        for (idx, (track, read)) in midi.tracks.iter().zip(&read.tracks).enumerate() {
+            let track = self.encode_track(track.clone());
+            if track.semantic_eq(read) {
+                continue;
+            }
+
+            let wrote = TrackEditor::new(track);
+            let read = TrackEditor::new(read.clone());
+            let first_difference = wrote
+                .events()
+                .iter()
+                .zip(read.events())
+                .position(|(wrote, read)| wrote != read);
+            let detail = match first_difference {
+                Some(event) => {
+                    let ((wrote_tick, wrote), (read_tick, read)) =
+                        (&wrote.events()[event], &read.events()[event]);
+                    format!(
+                        "track {idx}, event {event}: wrote {wrote:?} at tick {}, read back \
+                         {read:?} at tick {}",
+                        wrote_tick.get(),
+                        read_tick.get()
+                    )
+                }
+                None if wrote.events().len() != read.events().len() => format!(
+                    "track {idx}: wrote {} events, read back {}",
+                    wrote.events().len(),
+                    read.events().len()
+                ),
+                None => format!(
+                    "track {idx}: wrote its end at tick {}, read back {}",
+                    wrote.end_of_track().get(),
+                    read.end_of_track().get()
+                ),
+            };
+
+            return Err(mismatch(detail));
+        }
+
+        Ok(())
+    }
+
+    /// Number of bytes [`WriteOptions::write_chunk`] writes for a payload of the given length,
+    /// counting the 8 byte chunk header and any pad byte
+    pub(crate) fn chunk_len(&self, payload: usize) -> usize {
+        let pad = usize::from(self.pad_odd_chunks && payload % 2 == 1);
+        8 + payload + pad
+    }
+}
+
+/// An error from preparing or checking a written file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WriteError {
+    /// [`WriteOptions::stamp_generator`] couldn't sign the file, see [`crate::Midi::set_generator`]
+    Stamp(EditError),
+    /// The written bytes don't read back as the file they were written from, see
+    /// [`WriteOptions::verify_roundtrip`]
+    RoundTripMismatch {
+        /// What differs, naming the first event that doesn't match
+        detail: String,
+    },
+}
+
+impl core::error::Error for WriteError {}
+impl core::fmt::Display for WriteError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Stamp(err) => write![f, "Couldn't sign the file: {err}"],
+            Self::RoundTripMismatch { detail } => {
+                write![f, "The written file doesn't read back the same: {detail}"]
+            }
+        }
+    }
+}
+
+/// Writes a MIDI file to a seekable sink one track at a time, so only the track currently being
+/// written has to be in memory. The header's track count and each track's length are written as
+/// placeholders and patched in once they're known
+#[derive(Debug)]
+pub struct MidiFileWriter<W> {
+    /// Where the file is written
+    sink: W,
+    /// Position of the header chunk in the sink
+    start: u64,
+    /// The format the header declares
+    format: Format,
+    /// Number of tracks written so far
+    ntrks: u16,
+    /// How each track is laid out
+    options: WriteOptions,
+}
+
+impl<W: Write + Seek> MidiFileWriter<W> {
+    /// Starts a file at the sink's current position by writing a header with no tracks
+    pub fn new(sink: W, format: Format, division: Division) -> io::Result<Self> {
+        Self::with_options(sink, format, division, WriteOptions::default())
+    }
+
+    /// Starts a file like [`MidiFileWriter::new`], laying out every track according to the
+    /// given [`WriteOptions`]
+    pub fn with_options(
+        mut sink: W,
+        format: Format,
+        division: Division,
+        options: WriteOptions,
+    ) -> io::Result<Self> {
+        let start = sink.stream_position()?;
+        let header = HeaderChunk::new(format, 0, division);
+        sink.write_all(&ParsedChunk::Header(header).to_midi_bytes())?;
+
+        Ok(Self {
+            sink,
+            start,
+            format,
+            ntrks: 0,
+            options,
+        })
+    }
+
+    /// Number of tracks written so far
+    pub fn track_count(&self) -> u16 {
+        self.ntrks
+    }
+
+    /// Writes a track chunk, encoding its events one at a time
+    pub fn write_track(&mut self, track: &TrackChunk) -> io::Result<()> {
+        if self.ntrks == u16::MAX || (self.format == Format::Zero && self.ntrks == 1) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The header's format can't hold another track",
+            ));
+        }
+
+        let encoded;
+        let track = if self.options.encoding == TextEncoding::Raw {
+            track
+        } else {
+            encoded = self.options.encode_track(track.clone());
+            &encoded
+        };
+
+        let chunk_start = self.sink.stream_position()?;
+        let placeholder = Chunk {
+            chunk_type: TRACK_DATA_CHUNK,
+            length: 0,
+        };
+        self.sink.write_all(&placeholder.to_midi_bytes())?;
+
+        let mut length = 0u64;
+        for mtrk_event in track.events() {
+            let bytes = mtrk_event.clone().to_midi_bytes();
+            self.sink.write_all(&bytes)?;
+            length += bytes.len() as u64;
+        }
+
+        let length = u32::try_from(length).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Track is too long for a chunk's 32 bit length",
+            )
+        })?;
+
+        let chunk_end = self.sink.stream_position()?;
+        self.sink.seek(SeekFrom::Start(chunk_start + 4))?;
+        self.sink.write_all(&length.to_midi_bytes())?;
+        self.sink.seek(SeekFrom::Start(chunk_end))?;
+        if self.options.pad_odd_chunks && length % 2 == 1 {
+            self.sink.write_all(&[0x00])?;
+        }
+
+        self.ntrks += 1;
+        Ok(())
+    }
+
+    /// Patches the header's track count and returns the sink, positioned after the last track
+    pub fn finish(mut self) -> io::Result<W> {
+        let end = self.sink.stream_position()?;
+        // Chunk type, chunk length and format come before the track count
+        self.sink.seek(SeekFrom::Start(self.start + 10))?;
+        self.sink.write_all(&self.ntrks.to_midi_bytes())?;
+        self.sink.seek(SeekFrom::Start(end))?;
+        self.sink.flush()?;
+
+        Ok(self.sink)
+    }
+}
+
 impl MidiWriteable for u8 {
     fn to_midi_bytes(self) -> Vec<u8> {
         vec![self]
@@ -88,13 +414,113 @@ impl MidiWriteable for String {
 
 #[cfg(test)]
 mod tests {
+    use std::io::Cursor;
+
     use crate::{
-        chunk::ParsedChunk,
+        chunk::{
+            header::{Division, Format, HeaderChunk},
+            track::{
+                event::{MidiEvent, NoteMeta},
+                meta::{MetaEvent, TextEncoding},
+                Event, TrackChunk,
+            },
+            ParsedChunk,
+        },
         reader::{MidiReadable, MidiStream},
-        Chunk,
+        Chunk, Midi, RawMidi,
     };
 
-    use super::MidiWriteable;
+    use super::{MidiFileWriter, MidiWriteable, WriteOptions};
+
+    fn track(key: u8) -> TrackChunk {
+        let note = |velocity| Event::MidiEvent(MidiEvent::NoteOn(0, NoteMeta { key, velocity }));
+        TrackChunk::from_absolute_events(vec![
+            (
+                0,
+                Event::MetaEvent(MetaEvent::TrackName(format!("Track {key}").into())),
+            ),
+            (0, note(100)),
+            (480 + key as u64, note(0)),
+            (960, Event::MetaEvent(MetaEvent::EndOfTrack)),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn file_writer_matches_in_memory_writer() {
+        let tracks: Vec<_> = (0..5).map(track).collect();
+
+        let mut writer =
+            MidiFileWriter::new(Cursor::new(vec![]), Format::One, Division::Metrical(480)).unwrap();
+        for track in &tracks {
+            writer.write_track(track).unwrap();
+        }
+        assert_eq!(writer.track_count(), 5);
+        let bytes = writer.finish().unwrap().into_inner();
+
+        let midi = Midi {
+            header: HeaderChunk::try_from((1, 5, 480)).unwrap(),
+            tracks,
+        };
+        assert_eq!(bytes, midi.clone().to_midi_bytes());
+
+        let parsed = RawMidi::try_from_midi_stream(bytes.into_iter())
+            .unwrap()
+            .check_into_midi()
+            .unwrap();
+        assert_eq!(parsed, midi);
+    }
+
+    #[test]
+    fn file_writer_follows_write_options() {
+        let options = WriteOptions {
+            pad_odd_chunks: true,
+            encoding: TextEncoding::Latin1,
+            stamp_generator: false,
+            verify_roundtrip: false,
+        };
+        let tracks: Vec<_> = [60, 100].map(track).into();
+        assert!(tracks
+            .iter()
+            .any(|track| ParsedChunk::Track(track.clone()).to_midi_bytes().len() % 2 == 1));
+
+        let mut writer = MidiFileWriter::with_options(
+            Cursor::new(vec![]),
+            Format::One,
+            Division::Metrical(480),
+            options,
+        )
+        .unwrap();
+        for track in &tracks {
+            writer.write_track(track).unwrap();
+        }
+        let bytes = writer.finish().unwrap().into_inner();
+
+        let midi = Midi {
+            header: HeaderChunk::try_from((1, 2, 480)).unwrap(),
+            tracks,
+        };
+        assert_eq!(bytes, midi.to_midi_bytes_with(options));
+    }
+
+    #[test]
+    fn file_writer_starts_at_sink_position() {
+        let mut sink = Cursor::new(b"prefix".to_vec());
+        sink.set_position(6);
+
+        let mut writer = MidiFileWriter::new(sink, Format::Zero, Division::Metrical(96)).unwrap();
+        writer.write_track(&track(60)).unwrap();
+        assert!(writer.write_track(&track(61)).is_err());
+        let bytes = writer.finish().unwrap().into_inner();
+
+        assert_eq!(&bytes[..6], b"prefix");
+        let parsed = RawMidi::try_from_midi_stream(bytes[6..].iter().copied())
+            .unwrap()
+            .check_into_midi()
+            .unwrap();
+        assert_eq!(parsed.header.ntrks, 1);
+        assert_eq!(parsed.tracks, vec![track(60)]);
+    }
 
     #[test]
     fn header_chunk_saves_as_proper_bytes() {
@@ -103,7 +529,7 @@ mod tests {
             .expect("Get MIDI bytes from source");
         let expected = stream
             .read_chunk_data_pair()
-            .map(|val| ParsedChunk::try_from(val))
+            .map(ParsedChunk::try_from)
             .unwrap()
             .unwrap();
 
@@ -113,7 +539,7 @@ mod tests {
         let mut new_stream = bytes.into_iter();
         let new_header = new_stream
             .read_chunk_data_pair()
-            .map(|val| ParsedChunk::try_from(val))
+            .map(ParsedChunk::try_from)
             .unwrap()
             .unwrap();
 